@@ -0,0 +1,40 @@
+//! Captures the git commit and compiler version for the `solana_exporter_build_info` metric.
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// Returns the trimmed standard output of `program args`, or `None` if it could not be run.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let stdout = stdout.trim();
+    (!stdout.is_empty()).then(|| stdout.to_string())
+}
+
+fn main() {
+    // Builds outside a git checkout (e.g. from a source archive) have no commit to report.
+    let commit = command_output("git", &["rev-parse", "--short=12", "HEAD"]);
+    println!(
+        "cargo:rustc-env=SOLANA_EXPORTER_GIT_COMMIT={}",
+        commit.as_deref().unwrap_or("unknown")
+    );
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]);
+    println!(
+        "cargo:rustc-env=SOLANA_EXPORTER_RUSTC_VERSION={}",
+        rustc_version.as_deref().unwrap_or("unknown")
+    );
+
+    // A path that does not exist would make cargo rerun this script on every build.
+    for path in [".git/HEAD", ".git/refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+    println!("cargo:rerun-if-env-changed=RUSTC");
+}
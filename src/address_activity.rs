@@ -0,0 +1,267 @@
+//! Transactions involving the addresses of the watchlist.
+
+use crate::config::AddressWatchlist;
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::persistent_database::counters::CounterBaselines;
+use crate::rpc_api::RpcApi;
+use anyhow::Context;
+use async_trait::async_trait;
+use log::warn;
+use prometheus_exporter::prometheus::IntCounterVec;
+use solana_client::rpc_config::RpcSignaturesForAddressConfig;
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+use solana_pubkey::Pubkey;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Time between two queries of the signatures of the watched addresses.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Maximum number of signatures `getSignaturesForAddress` returns at once.
+const MAX_SIGNATURES: usize = 1_000;
+
+/// Maximum number of pages of `MAX_SIGNATURES` signatures queried per address and query.
+const MAX_PAGES: usize = 10;
+
+/// The monitor of the transactions involving each watched address, and those of them that failed.
+///
+/// Only transactions newer than the newest one seen by the previous query are counted, so the
+/// first query of a running exporter only sets where counting starts, and transactions made while
+/// the exporter is down are not counted.
+pub struct AddressActivityMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Prometheus counter.
+    transactions: &'a IntCounterVec,
+    /// Prometheus counter.
+    failed_transactions: &'a IntCounterVec,
+    /// Persisted counter totals.
+    baselines: &'a CounterBaselines,
+    watchlist: AddressWatchlist,
+    /// The newest signature seen of every address.
+    last_signatures: HashMap<String, Option<String>>,
+}
+
+impl<'a> AddressActivityMonitor<'a> {
+    /// Constructs a monitor of `watchlist` given `rpc`.
+    pub fn new(
+        rpc: &'a dyn RpcApi,
+        transactions: &'a IntCounterVec,
+        failed_transactions: &'a IntCounterVec,
+        baselines: &'a CounterBaselines,
+        watchlist: AddressWatchlist,
+    ) -> Self {
+        Self {
+            rpc,
+            transactions,
+            failed_transactions,
+            baselines,
+            watchlist,
+            last_signatures: HashMap::new(),
+        }
+    }
+
+    /// Counts the transactions involving `address`, named `name`, since the last query, paging
+    /// through them `MAX_SIGNATURES` at a time. At most `MAX_PAGES` pages are counted per query.
+    async fn count_transactions(&mut self, name: &str, address: &str) -> anyhow::Result<()> {
+        let pubkey = address
+            .parse::<Pubkey>()
+            .with_context(|| format!("invalid watched address {}: {}", name, address))?;
+        let last_signature = self.last_signatures.get(address).cloned();
+        let limit = match last_signature {
+            Some(_) => MAX_SIGNATURES,
+            None => 1,
+        };
+        let mut signatures: Vec<RpcConfirmedTransactionStatusWithSignature> = Vec::new();
+        for _ in 0..MAX_PAGES {
+            let config = RpcSignaturesForAddressConfig {
+                before: signatures.last().map(|s| s.signature.clone()),
+                until: last_signature.clone().flatten(),
+                limit: Some(limit),
+                ..RpcSignaturesForAddressConfig::default()
+            };
+            let page = self
+                .rpc
+                .get_signatures_for_address_with_config(&pubkey, config)
+                .await?;
+            let last_page = page.len() < limit || last_signature.is_none();
+            signatures.extend(page);
+            if last_page {
+                break;
+            }
+        }
+        if let Some(newest) = signatures.first() {
+            self.last_signatures
+                .insert(address.to_string(), Some(newest.signature.clone()));
+        } else {
+            self.last_signatures.entry(address.to_string()).or_default();
+        }
+        if last_signature.is_none() {
+            return Ok(());
+        }
+
+        if signatures.len() == MAX_PAGES * MAX_SIGNATURES {
+            warn!(
+                "More than {} transactions involving {} since the last query, counting {}",
+                signatures.len(),
+                name,
+                signatures.len()
+            );
+        }
+        let failed = signatures.iter().filter(|s| s.err.is_some()).count();
+        let labels = [name, address];
+        self.baselines
+            .inc_by(self.transactions, &labels, signatures.len() as u64)?;
+        self.baselines
+            .inc_by(self.failed_transactions, &labels, failed as u64)
+    }
+
+    /// Counts the transactions involving every watched address since the last query. Addresses
+    /// whose transactions cannot be counted are logged and skipped.
+    async fn count_watchlist(&mut self) {
+        for (name, address) in self.watchlist.0.clone() {
+            if let Err(e) = self.count_transactions(&name, &address).await {
+                warn!("Could not count the transactions involving {}: {e:#}", name);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Monitor for AddressActivityMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "watched address activity"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(self.transactions),
+            family_name(self.failed_transactions),
+        ]
+    }
+
+    /// Restores the transaction counter totals.
+    async fn setup(&mut self) -> anyhow::Result<()> {
+        self.baselines.restore(self.transactions)?;
+        self.baselines.restore(self.failed_transactions)
+    }
+
+    fn interval(&self) -> Duration {
+        REFRESH_INTERVAL
+    }
+
+    async fn collect(&mut self, _cycle: &Cycle) -> anyhow::Result<()> {
+        self.count_watchlist().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_api::mock::MockRpc;
+    use prometheus_exporter::prometheus::Opts;
+
+    fn signature(signature: &str, failed: bool) -> RpcConfirmedTransactionStatusWithSignature {
+        RpcConfirmedTransactionStatusWithSignature {
+            signature: signature.to_string(),
+            slot: 1,
+            err: failed.then(|| serde_json::from_value("AccountInUse".into()).unwrap()),
+            memo: None,
+            block_time: None,
+            confirmation_status: None,
+            transaction_index: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_transactions_since_the_last_query() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let baselines = CounterBaselines::new(db.open_tree("counters").unwrap());
+        let labels = &["name", "pubkey"];
+        let transactions =
+            IntCounterVec::new(Opts::new("transactions", "Transactions"), labels).unwrap();
+        let failed_transactions =
+            IntCounterVec::new(Opts::new("failed", "Failed transactions"), labels).unwrap();
+        let address = Pubkey::new_unique();
+        let watchlist = AddressWatchlist(
+            std::iter::once(("treasury".to_string(), address.to_string())).collect(),
+        );
+
+        let mut rpc = MockRpc::default();
+        rpc.signatures
+            .insert(address, vec![signature("b", false), signature("a", false)]);
+        let mut monitor = AddressActivityMonitor::new(
+            &rpc,
+            &transactions,
+            &failed_transactions,
+            &baselines,
+            watchlist.clone(),
+        );
+        monitor
+            .count_transactions("treasury", &address.to_string())
+            .await
+            .unwrap();
+        let labels = ["treasury".to_string(), address.to_string()];
+        let labels = [labels[0].as_str(), labels[1].as_str()];
+        assert_eq!(transactions.with_label_values(&labels).get(), 0);
+
+        let last_signatures = monitor.last_signatures;
+        let mut rpc = MockRpc::default();
+        rpc.signatures.insert(
+            address,
+            vec![
+                signature("d", true),
+                signature("c", false),
+                signature("b", false),
+                signature("a", false),
+            ],
+        );
+        let mut monitor = AddressActivityMonitor::new(
+            &rpc,
+            &transactions,
+            &failed_transactions,
+            &baselines,
+            watchlist,
+        );
+        monitor.last_signatures = last_signatures;
+        monitor
+            .count_transactions("treasury", &address.to_string())
+            .await
+            .unwrap();
+        assert_eq!(transactions.with_label_values(&labels).get(), 2);
+        assert_eq!(failed_transactions.with_label_values(&labels).get(), 1);
+        assert_eq!(
+            monitor.last_signatures[&address.to_string()].as_deref(),
+            Some("d")
+        );
+        let last_signatures = monitor.last_signatures;
+
+        // Beyond a page of signatures, and with an invalid address in the watchlist.
+        let mut rpc = MockRpc::default();
+        let mut signatures: Vec<_> = (0..2_500)
+            .map(|i| signature(&format!("e{}", i), false))
+            .collect();
+        signatures.push(signature("d", true));
+        rpc.signatures.insert(address, signatures);
+        let watchlist = AddressWatchlist(
+            vec![
+                ("invalid".to_string(), "not-an-address".to_string()),
+                ("treasury".to_string(), address.to_string()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let mut monitor = AddressActivityMonitor::new(
+            &rpc,
+            &transactions,
+            &failed_transactions,
+            &baselines,
+            watchlist,
+        );
+        monitor.last_signatures = last_signatures;
+        monitor.count_watchlist().await;
+        assert_eq!(transactions.with_label_values(&labels).get(), 2_502);
+        assert_eq!(failed_transactions.with_label_values(&labels).get(), 1);
+    }
+}
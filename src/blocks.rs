@@ -0,0 +1,239 @@
+//! Compute-unit utilization of blocks produced by whitelisted validators.
+
+use crate::config::Whitelist;
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::rpc_api::RpcApi;
+use crate::rpc_extra::leader_slots;
+use crate::slots::caching::LeaderScheduleCache;
+use async_trait::async_trait;
+use log::debug;
+use prometheus_exporter::prometheus::GaugeVec;
+use solana_client::rpc_config::RpcBlockConfig;
+use solana_clock::{Epoch, Slot};
+use solana_epoch_info::EpochInfo;
+use solana_transaction_status_client_types::{
+    option_serializer::OptionSerializer, TransactionDetails, UiTransactionEncoding,
+};
+use std::collections::{BTreeMap, HashMap};
+
+/// Percentiles of the compute units distribution that are exported.
+const PERCENTILES: [f64; 3] = [50.0, 90.0, 99.0];
+
+/// Compute units consumed and number of transactions in a single block.
+#[derive(Copy, Clone, Debug, Default)]
+struct BlockUsage {
+    compute_units: u64,
+    transactions: u64,
+}
+
+/// The monitor of compute units used by blocks that whitelisted validators produced in the
+/// current epoch.
+///
+/// The leader slots of each whitelisted identity are fetched once per epoch, and persisted so that
+/// a restart does not fetch them again. Every cycle, the scheduled slots that have passed since
+/// the last cycle are checked against `getBlocks`, and the produced blocks are fetched with full
+/// transaction details to sum up their compute units. When started in the middle of an epoch,
+/// only blocks produced from then on are sampled, since backfilling would mean downloading every
+/// full block produced so far.
+pub struct BlockComputeUnitsMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Prometheus gauge.
+    compute_units_average: &'a GaugeVec,
+    /// Prometheus gauge.
+    compute_units_percentile: &'a GaugeVec,
+    /// Prometheus gauge.
+    transactions_average: &'a GaugeVec,
+    /// Caching database for the leader slots.
+    cache: &'a LeaderScheduleCache,
+    /// The epoch that the schedule and the samples belong to.
+    epoch: Option<Epoch>,
+    /// Leader slots of whitelisted identities in `epoch`, mapped to the identity.
+    schedule: BTreeMap<Slot, String>,
+    /// The first slot that has not been checked yet.
+    next_slot: Slot,
+    /// Usage of the blocks produced in `epoch` so far, per identity.
+    samples: HashMap<String, Vec<BlockUsage>>,
+}
+
+impl<'a> BlockComputeUnitsMonitor<'a> {
+    /// Constructs a monitor given `rpc`.
+    pub fn new(
+        rpc: &'a dyn RpcApi,
+        compute_units_average: &'a GaugeVec,
+        compute_units_percentile: &'a GaugeVec,
+        transactions_average: &'a GaugeVec,
+        cache: &'a LeaderScheduleCache,
+    ) -> Self {
+        Self {
+            rpc,
+            compute_units_average,
+            compute_units_percentile,
+            transactions_average,
+            cache,
+            epoch: None,
+            schedule: BTreeMap::new(),
+            next_slot: 0,
+            samples: HashMap::new(),
+        }
+    }
+
+    /// Exports compute-unit statistics of the blocks produced by whitelisted identities. Only
+    /// exported if a whitelist is set.
+    pub async fn export_block_compute_units(
+        &mut self,
+        epoch_info: &EpochInfo,
+        node_whitelist: &Whitelist,
+    ) -> anyhow::Result<()> {
+        if node_whitelist.0.is_empty() {
+            return Ok(());
+        }
+
+        let first_slot = epoch_info.absolute_slot - epoch_info.slot_index;
+        if self.epoch != Some(epoch_info.epoch) {
+            self.schedule = leader_slots(self.rpc, self.cache, epoch_info, node_whitelist).await?;
+            // Sample from the start of the epoch only if the previous epoch was followed too.
+            self.next_slot = if self.epoch.is_some() {
+                first_slot
+            } else {
+                epoch_info.absolute_slot
+            };
+            self.epoch = Some(epoch_info.epoch);
+            self.samples.clear();
+            self.compute_units_average.reset();
+            self.compute_units_percentile.reset();
+            self.transactions_average.reset();
+            debug!(
+                "BlockComputeUnitsMonitor loaded {} leader slots for epoch {}",
+                self.schedule.len(),
+                epoch_info.epoch
+            );
+        }
+
+        let last_slot = epoch_info.absolute_slot;
+        if last_slot < self.next_slot
+            || self
+                .schedule
+                .range(self.next_slot..=last_slot)
+                .next()
+                .is_none()
+        {
+            self.next_slot = self.next_slot.max(last_slot + 1);
+            return Ok(());
+        }
+
+        let produced = self.rpc.get_blocks(self.next_slot, Some(last_slot)).await?;
+        for slot in produced {
+            if let Some(identity) = self.schedule.get(&slot).cloned() {
+                let usage = self.block_usage(slot).await?;
+                self.samples.entry(identity).or_default().push(usage);
+                // Advance per block so that a failed fetch is retried without re-sampling.
+                self.next_slot = slot + 1;
+            }
+        }
+        self.next_slot = last_slot + 1;
+
+        for (identity, samples) in &self.samples {
+            let mut compute_units: Vec<_> = samples.iter().map(|s| s.compute_units).collect();
+            compute_units.sort_unstable();
+            let count = samples.len() as f64;
+            let total_transactions: u64 = samples.iter().map(|s| s.transactions).sum();
+
+            self.compute_units_average
+                .get_metric_with_label_values(&[identity])
+                .map(|c| c.set(compute_units.iter().sum::<u64>() as f64 / count))?;
+            self.transactions_average
+                .get_metric_with_label_values(&[identity])
+                .map(|c| c.set(total_transactions as f64 / count))?;
+            for p in PERCENTILES {
+                self.compute_units_percentile
+                    .get_metric_with_label_values(&[identity, &p.to_string()])
+                    .map(|c| c.set(percentile(&compute_units, p) as f64))?;
+            }
+        }
+
+        debug!("Exported block compute units");
+        Ok(())
+    }
+
+    /// Fetches the block at `slot` and measures its usage.
+    async fn block_usage(&self, slot: Slot) -> anyhow::Result<BlockUsage> {
+        let block = self
+            .rpc
+            .get_block_with_config(
+                slot,
+                RpcBlockConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    transaction_details: Some(TransactionDetails::Full),
+                    rewards: Some(false),
+                    commitment: None,
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await?;
+
+        let transactions = block.transactions.unwrap_or_default();
+        let compute_units = transactions
+            .iter()
+            .filter_map(|tx| tx.meta.as_ref())
+            .map(|meta| match meta.compute_units_consumed {
+                OptionSerializer::Some(units) => units,
+                _ => 0,
+            })
+            .sum();
+
+        Ok(BlockUsage {
+            compute_units,
+            transactions: transactions.len() as u64,
+        })
+    }
+}
+
+#[async_trait]
+impl Monitor for BlockComputeUnitsMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "block compute units"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(self.compute_units_average),
+            family_name(self.compute_units_percentile),
+            family_name(self.transactions_average),
+        ]
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.export_block_compute_units(&cycle.epoch_info, &cycle.node_whitelist)
+            .await
+    }
+}
+
+/// Returns the `p`-th percentile of `sorted` using the nearest-rank method, or zero if `sorted`
+/// is empty.
+pub(crate) fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::percentile;
+
+    #[test]
+    fn percentile_nearest_rank() {
+        let sorted = [10, 20, 30, 40, 50, 60, 70, 80, 90, 100];
+        assert_eq!(percentile(&sorted, 50.0), 50);
+        assert_eq!(percentile(&sorted, 90.0), 90);
+        assert_eq!(percentile(&sorted, 99.0), 100);
+        assert_eq!(percentile(&sorted, 0.0), 10);
+    }
+
+    #[test]
+    fn percentile_of_empty_is_zero() {
+        assert_eq!(percentile(&[], 50.0), 0);
+    }
+}
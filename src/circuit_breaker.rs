@@ -0,0 +1,234 @@
+//! Backing off collection while the RPC endpoint is unhealthy.
+
+use log::{info, warn};
+use prometheus_exporter::prometheus::{register_gauge, register_int_gauge, Gauge, IntGauge};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Number of most recent cycles the failure rate is computed over.
+const FAILURE_RATE_WINDOW: usize = 100;
+
+/// Upper bound of the open period, however many probes in a row fail.
+const MAX_OPEN_DURATION: Duration = Duration::from_secs(600);
+
+/// The state of a circuit breaker.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    /// Requests go through.
+    Closed,
+    /// Requests are held back until the given instant.
+    Open { until: Instant },
+    /// A single probe request is let through to check whether the endpoint has recovered.
+    HalfOpen,
+}
+
+/// The bookkeeping of a circuit breaker, independent of the exported metrics.
+#[derive(Debug)]
+struct Breaker {
+    /// Consecutive failures that open the circuit.
+    threshold: u32,
+    /// Initial open period, doubled on every failed probe.
+    cooldown: Duration,
+    /// Open period that applies the next time the circuit opens.
+    open_duration: Duration,
+    state: State,
+    consecutive_failures: u32,
+    /// Outcomes of the most recent cycles, `true` for failures.
+    recent: VecDeque<bool>,
+}
+
+impl Breaker {
+    fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            open_duration: cooldown,
+            state: State::Closed,
+            consecutive_failures: 0,
+            recent: VecDeque::with_capacity(FAILURE_RATE_WINDOW),
+        }
+    }
+
+    /// Returns whether a request may go through at `now`, half-opening the circuit once the open
+    /// period is over.
+    fn allow(&mut self, now: Instant) -> bool {
+        match self.state {
+            State::Closed | State::HalfOpen => true,
+            State::Open { until } if now >= until => {
+                self.state = State::HalfOpen;
+                true
+            }
+            State::Open { .. } => false,
+        }
+    }
+
+    /// Records the outcome of a request made at `now`.
+    fn record(&mut self, now: Instant, success: bool) {
+        if self.recent.len() == FAILURE_RATE_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(!success);
+
+        if success {
+            self.state = State::Closed;
+            self.consecutive_failures = 0;
+            self.open_duration = self.cooldown;
+            return;
+        }
+
+        self.consecutive_failures += 1;
+        match self.state {
+            State::HalfOpen => {
+                // The probe failed: stay open for longer.
+                self.open_duration = (self.open_duration * 2).min(MAX_OPEN_DURATION);
+                self.state = State::Open {
+                    until: now + self.open_duration,
+                };
+            }
+            State::Closed if self.consecutive_failures >= self.threshold => {
+                self.state = State::Open {
+                    until: now + self.open_duration,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        matches!(self.state, State::Open { .. })
+    }
+
+    /// Fraction of failed requests among the most recent ones.
+    fn failure_rate(&self) -> f64 {
+        if self.recent.is_empty() {
+            0.0
+        } else {
+            self.recent.iter().filter(|f| **f).count() as f64 / self.recent.len() as f64
+        }
+    }
+}
+
+/// A circuit breaker guarding the update cycle against an unhealthy RPC endpoint.
+///
+/// Once `threshold` consecutive cycles fail, the circuit opens and collection is skipped for a
+/// cooldown period. After that a single probe cycle is let through: if it succeeds the circuit
+/// closes, otherwise it opens again for twice as long, up to ten minutes.
+pub struct CircuitBreaker {
+    breaker: Breaker,
+    /// Prometheus gauge.
+    circuit_open: IntGauge,
+    /// Prometheus gauge.
+    failure_rate: Gauge,
+    /// Prometheus gauge.
+    consecutive_failures: IntGauge,
+}
+
+impl CircuitBreaker {
+    /// Makes a new closed circuit breaker and registers its gauges.
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            breaker: Breaker::new(threshold, cooldown),
+            circuit_open: register_int_gauge!(
+                "solana_exporter_rpc_circuit_open",
+                "Whether collection is suspended because the RPC endpoint is failing"
+            )
+            .unwrap(),
+            failure_rate: register_gauge!(
+                "solana_exporter_rpc_failure_rate",
+                "Fraction of failed update cycles among the last 100"
+            )
+            .unwrap(),
+            consecutive_failures: register_int_gauge!(
+                "solana_exporter_rpc_consecutive_failures",
+                "Number of consecutive failed update cycles"
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Returns whether the update cycle may query the RPC endpoint.
+    pub fn allow(&mut self) -> bool {
+        let was_open = self.breaker.is_open();
+        let allowed = self.breaker.allow(Instant::now());
+        if was_open && allowed {
+            info!("Probing whether the RPC endpoint has recovered");
+        }
+        allowed
+    }
+
+    /// Records a successful update cycle.
+    pub fn record_success(&mut self) {
+        if self.breaker.consecutive_failures >= self.breaker.threshold {
+            info!("RPC endpoint recovered, resuming collection");
+        }
+        self.breaker.record(Instant::now(), true);
+        self.update_gauges();
+    }
+
+    /// Records a failed update cycle.
+    pub fn record_failure(&mut self) {
+        let was_open = self.breaker.is_open();
+        self.breaker.record(Instant::now(), false);
+        if !was_open && self.breaker.is_open() {
+            warn!(
+                "RPC endpoint failed {} times in a row, suspending collection for {:?}",
+                self.breaker.consecutive_failures, self.breaker.open_duration
+            );
+        }
+        self.update_gauges();
+    }
+
+    fn update_gauges(&self) {
+        self.circuit_open.set(self.breaker.is_open() as i64);
+        self.failure_rate.set(self.breaker.failure_rate());
+        self.consecutive_failures
+            .set(self.breaker.consecutive_failures as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Breaker;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn opens_after_threshold_and_half_opens_after_cooldown() {
+        let start = Instant::now();
+        let mut breaker = Breaker::new(3, Duration::from_secs(10));
+
+        for _ in 0..2 {
+            assert!(breaker.allow(start));
+            breaker.record(start, false);
+        }
+        assert!(!breaker.is_open());
+        breaker.record(start, false);
+        assert!(breaker.is_open());
+        assert!(!breaker.allow(start + Duration::from_secs(9)));
+
+        // Probe fails: open again for twice as long.
+        let probe = start + Duration::from_secs(10);
+        assert!(breaker.allow(probe));
+        breaker.record(probe, false);
+        assert!(!breaker.allow(probe + Duration::from_secs(19)));
+
+        // Probe succeeds: closed again.
+        let probe = probe + Duration::from_secs(20);
+        assert!(breaker.allow(probe));
+        breaker.record(probe, true);
+        assert!(!breaker.is_open());
+        assert_eq!(breaker.consecutive_failures, 0);
+        assert_eq!(breaker.open_duration, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn failure_rate_over_recent_cycles() {
+        let now = Instant::now();
+        let mut breaker = Breaker::new(100, Duration::from_secs(10));
+        assert_eq!(breaker.failure_rate(), 0.0);
+        breaker.record(now, true);
+        breaker.record(now, false);
+        breaker.record(now, true);
+        breaker.record(now, false);
+        assert_eq!(breaker.failure_rate(), 0.5);
+    }
+}
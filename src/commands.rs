@@ -0,0 +1,380 @@
+//! Subcommands that work on the persistent database alone, without any RPC access.
+
+use anyhow::{anyhow, Context};
+use clap::ArgMatches;
+use serde::Serialize;
+use solana_clock::Epoch;
+use solana_exporter::persistent_database::PersistentDatabase;
+use solana_exporter::rewards::caching::RewardsCache;
+use solana_exporter::rewards::{cached_voter_apys, MAX_EPOCH_LOOKBACK};
+use solana_exporter::uptime::caching::{
+    PresenceHistory, ProductionHistory, PRESENCE_HISTORY_TREE_NAME, PRODUCTION_HISTORY_TREE_NAME,
+};
+use solana_pubkey::Pubkey;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use time::{Date, OffsetDateTime};
+
+/// `apy [--epochs N] [--voter PUBKEY]`: prints the current and average staking APY per voter as
+/// of the latest cached epoch.
+pub fn apy(database: &PersistentDatabase, args: &ArgMatches) -> anyhow::Result<()> {
+    let lookback = args
+        .value_of("epochs")
+        .map(str::parse::<u64>)
+        .transpose()
+        .context("invalid number of epochs")?
+        .unwrap_or(MAX_EPOCH_LOOKBACK);
+    if lookback == 0 {
+        return Err(anyhow!("the number of epochs must be positive"));
+    }
+    let voter = args
+        .value_of("voter")
+        .map(str::parse::<Pubkey>)
+        .transpose()
+        .context("invalid voter pubkey")?;
+
+    let cache = RewardsCache::open(database)?;
+    let epoch = cache
+        .latest_apy_epoch()?
+        .ok_or_else(|| anyhow!("the database holds no staking APY data"))?;
+    let mut apys: Vec<_> = cached_voter_apys(&cache, epoch, lookback)?
+        .into_iter()
+        .filter(|(v, _)| voter.is_none_or(|voter| voter == *v))
+        .map(|(v, apy)| (v.to_string(), apy))
+        .collect();
+    apys.sort_by(|(a, _), (b, _)| a.cmp(b));
+    if let (Some(voter), true) = (voter, apys.is_empty()) {
+        return Err(anyhow!("no staking APY data cached for voter {}", voter));
+    }
+
+    println!("Epoch {}, average over {} epochs", epoch, lookback);
+    println!(
+        "{:<44}  {:>11}  {:>11}",
+        "voter", "current APY", "average APY"
+    );
+    for (voter, apy) in apys {
+        println!(
+            "{:<44}  {:>10.4}%  {:>10.4}%",
+            voter, apy.current_apy, apy.average_apy
+        );
+    }
+    Ok(())
+}
+
+/// A validator's line of the uptime report.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct UptimeReportRow {
+    /// Vote account pubkey.
+    pub voter: String,
+    /// Seconds the exporter observed the validator.
+    pub observed_secs: u64,
+    /// Percentage of the observed time the validator was present in gossip.
+    pub uptime_percent: Option<f64>,
+    /// Seconds the vote account was delinquent or unknown.
+    pub delinquent_secs: u64,
+    /// Leader slots, whether skipped or not.
+    pub leader_slots: u64,
+    /// Skipped leader slots.
+    pub skipped_slots: u64,
+    /// Percentage of the leader slots that were skipped.
+    pub skip_rate_percent: Option<f64>,
+}
+
+/// `uptime-report --from DATE --to DATE [--voter PUBKEY] [--format text|json|csv] [--output
+/// FILE]`: reports the gossip uptime, delinquent time and skip rate per voter from the first
+/// until (excluding) the second date.
+pub fn uptime_report(database: &PersistentDatabase, args: &ArgMatches) -> anyhow::Result<()> {
+    let date = |name| {
+        let value = args
+            .value_of(name)
+            .ok_or_else(|| anyhow!("--{} is required", name))?;
+        Date::parse(value, "%F").with_context(|| format!("invalid --{} date {}", name, value))
+    };
+    let (from, to) = (date("from")?, date("to")?);
+    if from >= to {
+        return Err(anyhow!("--from must be before --to"));
+    }
+    let voter = args
+        .value_of("voter")
+        .map(str::parse::<Pubkey>)
+        .transpose()
+        .context("invalid voter pubkey")?;
+
+    let rows = uptime_report_rows(
+        &PresenceHistory::new(database.tree(PRESENCE_HISTORY_TREE_NAME)?),
+        &ProductionHistory::new(database.tree(PRODUCTION_HISTORY_TREE_NAME)?),
+        voter,
+        from.midnight().assume_utc().unix_timestamp(),
+        to.midnight().assume_utc().unix_timestamp(),
+    )?;
+    if let (Some(voter), true) = (voter, rows.is_empty()) {
+        return Err(anyhow!(
+            "no uptime history between the dates for voter {}",
+            voter
+        ));
+    }
+
+    let report = match args.value_of("format").unwrap_or("text") {
+        "json" => serde_json::to_string_pretty(&rows)? + "\n",
+        "csv" => uptime_report_csv(&rows),
+        _ => uptime_report_text(&rows, from, to),
+    };
+    match args.value_of("output") {
+        Some(path) => fs::write(path, report).with_context(|| format!("could not write {}", path)),
+        None => {
+            print!("{}", report);
+            Ok(())
+        }
+    }
+}
+
+/// Returns the uptime report of `voter`, or of every voter with any history in the range, between
+/// unix timestamps `start` and `end`.
+pub fn uptime_report_rows(
+    presence: &PresenceHistory,
+    production: &ProductionHistory,
+    voter: Option<Pubkey>,
+    start: i64,
+    end: i64,
+) -> anyhow::Result<Vec<UptimeReportRow>> {
+    let voters: BTreeSet<Pubkey> = match voter {
+        Some(voter) => std::iter::once(voter).collect(),
+        None => presence
+            .vote_pubkeys()?
+            .into_iter()
+            .chain(production.vote_pubkeys()?)
+            .collect(),
+    };
+    let mut rows = Vec::new();
+    for voter in voters {
+        let presence = presence.total(&voter, start, end)?;
+        let production = production.total(&voter, start, end)?;
+        if presence.observed_secs == 0 && production.leader_slots == 0 {
+            continue;
+        }
+        rows.push(UptimeReportRow {
+            voter: voter.to_string(),
+            observed_secs: presence.observed_secs,
+            uptime_percent: presence.uptime_percent(),
+            delinquent_secs: presence.delinquent_secs,
+            leader_slots: production.leader_slots,
+            skipped_slots: production.skipped_slots,
+            skip_rate_percent: production.skip_rate_percent(),
+        });
+    }
+    Ok(rows)
+}
+
+/// Formats the uptime report as a table.
+fn uptime_report_text(rows: &[UptimeReportRow], from: Date, to: Date) -> String {
+    let percent = |p: Option<f64>| p.map_or("-".to_string(), |p| format!("{:.4}%", p));
+    let hours = |secs: u64| format!("{:.2}", secs as f64 / 3600.0);
+    let mut report = format!(
+        "Uptime from {} until {}, UTC\n{:<44}  {:>10}  {:>10}  {:>12}  {:>12}  {:>10}  {:>10}\n",
+        from.format("%F"),
+        to.format("%F"),
+        "voter",
+        "observed h",
+        "uptime",
+        "delinquent h",
+        "leader slots",
+        "skipped",
+        "skip rate"
+    );
+    for row in rows {
+        // Writing to a string cannot fail.
+        let _ = writeln!(
+            report,
+            "{:<44}  {:>10}  {:>10}  {:>12}  {:>12}  {:>10}  {:>10}",
+            row.voter,
+            hours(row.observed_secs),
+            percent(row.uptime_percent),
+            hours(row.delinquent_secs),
+            row.leader_slots,
+            row.skipped_slots,
+            percent(row.skip_rate_percent)
+        );
+    }
+    report
+}
+
+/// Formats the uptime report as CSV with a header line, leaving undefined percentages empty.
+fn uptime_report_csv(rows: &[UptimeReportRow]) -> String {
+    let percent = |p: Option<f64>| p.map_or(String::new(), |p| p.to_string());
+    let mut report = "voter,observed_secs,uptime_percent,delinquent_secs,leader_slots,\
+                      skipped_slots,skip_rate_percent\n"
+        .to_string();
+    for row in rows {
+        let _ = writeln!(
+            report,
+            "{},{},{},{},{},{},{}",
+            row.voter,
+            row.observed_secs,
+            percent(row.uptime_percent),
+            row.delinquent_secs,
+            row.leader_slots,
+            row.skipped_slots,
+            percent(row.skip_rate_percent)
+        );
+    }
+    report
+}
+
+/// `db stats`: prints the size and files of the database, and the number of entries and cached
+/// epochs of each tree.
+pub fn db_stats(database: &PersistentDatabase) -> anyhow::Result<()> {
+    println!("Database: {}", database.path().display());
+    println!(
+        "Created with version: {}",
+        database.metadata().created_version()?
+    );
+    println!("Size on disk: {} bytes", database.size_on_disk()?);
+
+    println!();
+    println!("{:<32}  {:>12}  last modified", "file", "bytes");
+    let mut files = Vec::new();
+    list_files(database.path(), &mut files)?;
+    files.sort();
+    for path in files {
+        let metadata = fs::metadata(&path)?;
+        let modified = OffsetDateTime::from(metadata.modified()?).format("%F %T UTC");
+        let name = path.strip_prefix(database.path()).unwrap_or(&path);
+        println!(
+            "{:<32}  {:>12}  {}",
+            name.display(),
+            metadata.len(),
+            modified
+        );
+    }
+
+    let epoch_ranges: HashMap<_, _> = RewardsCache::open(database)?
+        .epoch_ranges()?
+        .into_iter()
+        .collect();
+    let mut tree_names = database.tree_names();
+    tree_names.sort();
+    println!();
+    println!("{:<32}  {:>12}  cached epochs", "tree", "entries");
+    for name in tree_names {
+        let epochs = match epoch_ranges.get(name.as_str()) {
+            Some(Some(range)) => format!("{}..={}", range.start(), range.end()),
+            Some(None) => "none".to_string(),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:<32}  {:>12}  {}",
+            name,
+            database.tree(&name)?.len(),
+            epochs
+        );
+    }
+    Ok(())
+}
+
+/// `db prune --before-epoch N`: removes cached epoch rewards, APYs and epoch lengths of epochs
+/// before `N`.
+pub fn db_prune(database: &PersistentDatabase, args: &ArgMatches) -> anyhow::Result<()> {
+    let epoch = args
+        .value_of("before-epoch")
+        .ok_or_else(|| anyhow!("--before-epoch is required"))?
+        .parse::<Epoch>()
+        .context("invalid epoch")?;
+
+    let removed = RewardsCache::open(database)?.prune_before(epoch)?;
+    database.flush()?;
+
+    println!("Removed entries of epochs before {}:", epoch);
+    for (name, count) in removed {
+        println!("{:<32}  {:>12}", name, count);
+    }
+    Ok(())
+}
+
+/// Appends the paths of all files under `dir` to `files`.
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("could not read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_exporter::uptime::caching::{PresenceBucket, ProductionBucket};
+
+    #[test]
+    fn reports_uptime_and_skip_rate_between_dates() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let presence = PresenceHistory::new(db.open_tree(PRESENCE_HISTORY_TREE_NAME).unwrap());
+        let production =
+            ProductionHistory::new(db.open_tree(PRODUCTION_HISTORY_TREE_NAME).unwrap());
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let day = 24 * 3600;
+        presence
+            .add(
+                &a,
+                0,
+                PresenceBucket {
+                    observed_secs: 3600,
+                    present_secs: 2700,
+                    delinquent_secs: 600,
+                },
+            )
+            .unwrap();
+        production
+            .add(
+                &a,
+                3600,
+                ProductionBucket {
+                    leader_slots: 8,
+                    skipped_slots: 2,
+                },
+            )
+            .unwrap();
+        // Outside the range.
+        presence
+            .add(
+                &b,
+                day,
+                PresenceBucket {
+                    observed_secs: 3600,
+                    present_secs: 3600,
+                    delinquent_secs: 0,
+                },
+            )
+            .unwrap();
+
+        let rows = uptime_report_rows(&presence, &production, None, 0, day).unwrap();
+        assert_eq!(
+            rows,
+            vec![UptimeReportRow {
+                voter: a.to_string(),
+                observed_secs: 3600,
+                uptime_percent: Some(75.0),
+                delinquent_secs: 600,
+                leader_slots: 8,
+                skipped_slots: 2,
+                skip_rate_percent: Some(25.0),
+            }]
+        );
+        assert_eq!(
+            uptime_report_csv(&rows),
+            format!(
+                "voter,observed_secs,uptime_percent,delinquent_secs,leader_slots,\
+                 skipped_slots,skip_rate_percent\n{},3600,75,600,8,2,25\n",
+                a
+            )
+        );
+        assert!(uptime_report_rows(&presence, &production, Some(b), 0, day)
+            .unwrap()
+            .is_empty());
+    }
+}
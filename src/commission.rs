@@ -0,0 +1,169 @@
+//! Detection of commission increases shortly before the end of an epoch.
+
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::persistent_database::counters::CounterBaselines;
+use async_trait::async_trait;
+use log::warn;
+use prometheus_exporter::prometheus::IntCounterVec;
+use solana_client::rpc_response::RpcVoteAccountStatus;
+use solana_clock::Slot;
+use solana_epoch_info::EpochInfo;
+use std::collections::HashMap;
+
+/// The monitor of "commission rugs": a validator raising its commission in the last slots of an
+/// epoch, so that the rewards of the epoch are paid at the higher rate before delegators can
+/// react, and usually lowering it again afterwards.
+///
+/// Commissions of all vote accounts, not just whitelisted ones, are compared between cycles, and
+/// an event is counted when one increases by at least `threshold` percentage points within the
+/// last `window` slots of the epoch. Only events are exported, one series per offending vote
+/// account. The commissions seen in the first cycle after a start are the baseline.
+pub struct CommissionRugMonitor<'a> {
+    /// Prometheus commission rug event counter.
+    commission_rugs: &'a IntCounterVec,
+    /// Persisted counter totals.
+    baselines: &'a CounterBaselines,
+    /// Number of slots before the end of an epoch that increases are counted in.
+    window: Slot,
+    /// Smallest counted increase in percentage points.
+    threshold: u8,
+    /// The last seen commission per vote account.
+    commissions: HashMap<String, u8>,
+}
+
+impl<'a> CommissionRugMonitor<'a> {
+    pub fn new(
+        commission_rugs: &'a IntCounterVec,
+        baselines: &'a CounterBaselines,
+        window: Slot,
+        threshold: u8,
+    ) -> Self {
+        Self {
+            commission_rugs,
+            baselines,
+            window,
+            threshold,
+            commissions: HashMap::new(),
+        }
+    }
+
+    /// Counts the commission increases since the previous cycle if `epoch_info` is within the
+    /// window before the end of the epoch.
+    pub fn export_commission_rugs(
+        &mut self,
+        epoch_info: &EpochInfo,
+        vote_accounts: &RpcVoteAccountStatus,
+    ) -> anyhow::Result<()> {
+        let remaining = epoch_info
+            .slots_in_epoch
+            .saturating_sub(epoch_info.slot_index);
+        let in_window = remaining <= self.window;
+        for v in vote_accounts
+            .current
+            .iter()
+            .chain(vote_accounts.delinquent.iter())
+        {
+            let previous = self.commissions.insert(v.vote_pubkey.clone(), v.commission);
+            let increase = previous.map_or(0, |previous| v.commission.saturating_sub(previous));
+            if in_window && increase > 0 && increase >= self.threshold {
+                warn!(
+                    "Vote account {} raised its commission from {}% to {}% {} slots before the end of epoch {}",
+                    v.vote_pubkey,
+                    v.commission - increase,
+                    v.commission,
+                    remaining,
+                    epoch_info.epoch
+                );
+                self.baselines
+                    .inc_by(self.commission_rugs, &[&v.vote_pubkey], 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Monitor for CommissionRugMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "commission rug"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![family_name(self.commission_rugs)]
+    }
+
+    /// Restores the event counter totals.
+    async fn setup(&mut self) -> anyhow::Result<()> {
+        self.baselines.restore(self.commission_rugs)
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.export_commission_rugs(&cycle.epoch_info, &cycle.vote_accounts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gauges::PUBKEY_LABEL;
+    use prometheus_exporter::prometheus::Opts;
+    use solana_client::rpc_response::RpcVoteAccountInfo;
+
+    #[test]
+    fn counts_increases_near_the_end_of_the_epoch() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let baselines = CounterBaselines::new(db.open_tree("counters").unwrap());
+        let rugs = IntCounterVec::new(Opts::new("rugs", "Rugs"), &[PUBKEY_LABEL]).unwrap();
+        let mut monitor = CommissionRugMonitor::new(&rugs, &baselines, 1000, 10);
+
+        let epoch_info = |slot_index| EpochInfo {
+            epoch: 500,
+            slot_index,
+            slots_in_epoch: 432_000,
+            absolute_slot: 500 * 432_000 + slot_index,
+            block_height: 0,
+            transaction_count: None,
+        };
+        let vote_accounts = |a_commission, b_commission| {
+            let account = |vote_pubkey: &str, commission| RpcVoteAccountInfo {
+                vote_pubkey: vote_pubkey.to_string(),
+                node_pubkey: String::new(),
+                activated_stake: 0,
+                commission,
+                epoch_vote_account: true,
+                epoch_credits: vec![],
+                last_vote: 0,
+                root_slot: 0,
+            };
+            RpcVoteAccountStatus {
+                current: vec![account("a", a_commission)],
+                delinquent: vec![account("b", b_commission)],
+            }
+        };
+        let count = |vote_pubkey| rugs.with_label_values(&[vote_pubkey]).get();
+
+        // Before the window, and the first observation, are not counted.
+        monitor
+            .export_commission_rugs(&epoch_info(0), &vote_accounts(0, 5))
+            .unwrap();
+        monitor
+            .export_commission_rugs(&epoch_info(100), &vote_accounts(100, 5))
+            .unwrap();
+        assert_eq!(count("a"), 0);
+
+        monitor
+            .export_commission_rugs(&epoch_info(431_500), &vote_accounts(0, 5))
+            .unwrap();
+        // Increases below the threshold are not counted.
+        monitor
+            .export_commission_rugs(&epoch_info(431_600), &vote_accounts(100, 10))
+            .unwrap();
+        assert_eq!(count("a"), 1);
+        assert_eq!(count("b"), 0);
+        monitor
+            .export_commission_rugs(&epoch_info(431_700), &vote_accounts(100, 100))
+            .unwrap();
+        assert_eq!(count("a"), 1);
+        assert_eq!(count("b"), 1);
+    }
+}
@@ -1,38 +1,498 @@
 use crate::geolocation::api::MaxMindAPIKey;
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use solana_pubkey::Pubkey;
+use std::collections::{BTreeMap, HashSet};
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use whitelist::WhitelistSource;
 
+pub mod profiles;
+pub mod solana_cli;
+pub mod whitelist;
+
+/// Whitelisted pubkeys. An empty whitelist, as configured, stands for all pubkeys, unless it was
+/// made with [`Whitelist::only`].
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
-pub struct Whitelist(pub HashSet<String>);
+#[serde(from = "HashSet<String>", into = "HashSet<String>")]
+pub struct Whitelist(pub HashSet<String>, bool);
 
 impl Whitelist {
+    /// Makes a whitelist of `pubkeys`, or of all pubkeys if they are empty.
+    pub fn new(pubkeys: HashSet<String>) -> Self {
+        Self(pubkeys, false)
+    }
+
+    /// Makes a whitelist of exactly `pubkeys`, which admits nothing if they are empty.
+    pub fn only(pubkeys: HashSet<String>) -> Self {
+        Self(pubkeys, true)
+    }
+
+    /// Whether the whitelist stands for all pubkeys.
+    pub fn is_all(&self) -> bool {
+        self.0.is_empty() && !self.1
+    }
+
     pub fn contains(&self, value: &str) -> bool {
-        self.0.is_empty() || self.0.contains(value)
+        self.is_all() || self.0.contains(value)
+    }
+}
+
+impl From<HashSet<String>> for Whitelist {
+    fn from(pubkeys: HashSet<String>) -> Self {
+        Self::new(pubkeys)
+    }
+}
+
+impl From<Whitelist> for HashSet<String> {
+    fn from(whitelist: Whitelist) -> Self {
+        whitelist.0
+    }
+}
+
+/// Addresses whose lamport balances are exported, keyed by a human-readable name (e.g.
+/// `treasury`, `fee_payer`, `withdraw_authority`). Invalid addresses fail the config.
+#[derive(Clone, Debug, Serialize, Default)]
+pub struct AddressWatchlist(pub BTreeMap<String, String>);
+
+impl<'de> Deserialize<'de> for AddressWatchlist {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let addresses = BTreeMap::<String, String>::deserialize(deserializer)?;
+        for (name, address) in &addresses {
+            if address.parse::<Pubkey>().is_err() {
+                return Err(serde::de::Error::custom(format!(
+                    "invalid watched address {}: {}",
+                    name, address
+                )));
+            }
+        }
+        Ok(Self(addresses))
+    }
+}
+
+/// Socket addresses the HTTP server listens on, written as a single address or a list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Targets(pub Vec<SocketAddr>);
+
+impl Serialize for Targets {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.as_slice() {
+            [target] => target.serialize(serializer),
+            targets => targets.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Targets {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum OneOrMany {
+            One(SocketAddr),
+            Many(Vec<SocketAddr>),
+        }
+
+        match OneOrMany::deserialize(deserializer)? {
+            OneOrMany::One(target) => Ok(Self(vec![target])),
+            OneOrMany::Many(targets) if targets.is_empty() => Err(serde::de::Error::custom(
+                "expected at least one socket address",
+            )),
+            OneOrMany::Many(targets) => Ok(Self(targets)),
+        }
+    }
+}
+
+/// An additional RPC endpoint that heavy calls are distributed to.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RpcEndpoint {
+    /// Solana RPC address.
+    pub url: String,
+    /// Share of heavy calls relative to the other endpoints. Defaults to 1.
+    pub weight: Option<u32>,
+}
+
+/// An InfluxDB v2 endpoint the metrics are written to after every update cycle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InfluxDbConfig {
+    /// Base URL of the InfluxDB server, e.g. `http://localhost:8086`.
+    pub url: String,
+    /// Organization the bucket belongs to.
+    pub org: String,
+    /// Bucket the metrics are written to.
+    pub bucket: String,
+    /// API token with write access to the bucket.
+    pub token: String,
+}
+
+/// Plaintext format of the lines pushed by a [`PlaintextSinkConfig`] sink.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaintextFormat {
+    /// StatsD gauges with DogStatsD tags, e.g. `name:1|g|#label:value`.
+    Statsd,
+    /// Graphite plaintext with tags, e.g. `name;label=value 1 1700000000`.
+    Graphite,
+}
+
+/// Transport protocol of a [`PlaintextSinkConfig`] sink.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    #[default]
+    Udp,
+    Tcp,
+}
+
+/// Unit the balance metrics are exported in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BalanceUnit {
+    /// Integer lamports, under the plain metric names.
+    #[default]
+    Lamports,
+    /// Fractional SOL, under the metric names suffixed with `_sol`.
+    Sol,
+    /// Both of the above.
+    Both,
+}
+
+impl BalanceUnit {
+    /// Whether balances are exported in lamports.
+    pub fn lamports(self) -> bool {
+        self != BalanceUnit::Sol
+    }
+
+    /// Whether balances are exported in SOL.
+    pub fn sol(self) -> bool {
+        self != BalanceUnit::Lamports
     }
 }
 
+/// What the exporter is set up to monitor.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// The validators of the vote account whitelist, or the whole cluster without one.
+    #[default]
+    Cluster,
+    /// A single validator given by its identity. Its vote account is looked up at startup and is
+    /// the only one per-validator metrics are exported for, and the metrics of its own blocks and
+    /// delegators are enabled by default.
+    #[serde(rename = "self")]
+    SelfValidator,
+}
+
+/// A StatsD or Graphite receiver the gauge values are pushed to after every update cycle.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlaintextSinkConfig {
+    /// Socket address of the receiver, e.g. `localhost:8125` or `graphite:2003`.
+    pub address: String,
+    /// Format of the lines sent.
+    pub format: PlaintextFormat,
+    /// Transport protocol. Defaults to `udp`.
+    pub transport: Option<Transport>,
+    /// Prefix prepended to every metric name, separated by a dot.
+    pub prefix: Option<String>,
+}
+
+/// A dead man's switch pinged after fully successful update cycles.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HeartbeatConfig {
+    /// URL requested with `GET` for every ping, e.g. `https://hc-ping.com/<uuid>`.
+    pub url: String,
+    /// Minimum seconds between two pings. Defaults to 60.
+    pub interval: Option<u64>,
+}
+
+/// Leader election between exporter replicas sharing a lease file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HighAvailabilityConfig {
+    /// Path of the lease file, on storage shared by all replicas.
+    pub lock_file: PathBuf,
+    /// Name of this replica in the lease file. Defaults to the host name and process id.
+    pub instance_id: Option<String>,
+    /// Seconds the lease of the leader lasts without being renewed, after which a standby takes
+    /// over. Defaults to 60.
+    pub lease: Option<u64>,
+}
+
+/// Log levels of the exporter, overridden by the `RUST_LOG` environment variable and the
+/// `--log-level` argument.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// Level of all modules not listed in `modules`, out of `off`, `error`, `warn`, `info`,
+    /// `debug` and `trace`. Defaults to `error`.
+    pub level: Option<String>,
+    /// Levels by module path, e.g. `solana_exporter::rewards = 'debug'`.
+    pub modules: Option<BTreeMap<String, String>>,
+}
+
+/// Timing of the update cycles, to spread the load of many exporters on a shared RPC endpoint.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SchedulingConfig {
+    /// Minimum seconds between the end of an update cycle and the start of the next. Defaults
+    /// to 1.
+    pub interval: Option<u64>,
+    /// Seconds of wall-clock time the update cycles start at multiples of, e.g. 15 for
+    /// :00/:15/:30/:45. Not aligned by default.
+    pub align: Option<u64>,
+    /// Largest random delay in seconds added to the start of every update cycle. Defaults to 0.
+    pub jitter: Option<f64>,
+}
+
+/// A third-party validator ranking service.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreProvider {
+    /// validators.app, which needs an API key.
+    ValidatorsApp,
+    /// Stakewiz, mainnet only.
+    Stakewiz,
+}
+
+/// A third-party service the scores and ranks of the validators are fetched from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ValidatorScoresConfig {
+    /// Service the scores are fetched from.
+    pub provider: ScoreProvider,
+    /// API key of the service, required by validators.app.
+    pub api_key: Option<String>,
+    /// Network the validators are ranked in by validators.app, `mainnet` or `testnet`. Defaults
+    /// to `mainnet`.
+    pub network: Option<String>,
+    /// Seconds the fetched scores are reused for before they are fetched again. Defaults to
+    /// 21600, six hours.
+    pub ttl: Option<u64>,
+}
+
+/// A source of the SOL/USD price.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    /// A Pyth price update account, read over RPC.
+    Pyth,
+    /// The Coingecko simple price API.
+    Coingecko,
+}
+
+/// The source of the SOL/USD price that USD values are derived with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PriceConfig {
+    /// Source the price is fetched from.
+    pub source: PriceSource,
+    /// Pyth price update account of SOL/USD. Defaults to the sponsored feed account on mainnet.
+    pub account: Option<String>,
+    /// Coingecko demo API key, without which the public rate limit applies.
+    pub api_key: Option<String>,
+    /// Seconds the fetched price is reused for before it is fetched again. Defaults to 60.
+    pub ttl: Option<u64>,
+    /// Seconds since its publication after which a price is stale and not exported. Defaults to
+    /// 300.
+    pub max_age: Option<u64>,
+}
+
 pub const CONFIG_FILE_NAME: &str = "config.toml";
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ExporterConfig {
-    /// Solana RPC address.
-    pub rpc: String,
-    /// Prometheus target socket address.
-    pub target: SocketAddr,
-    /// Whitelisted vote account pubkeys.
-    pub vote_account_whitelist: Option<Whitelist>,
-    /// Whitelisted staking account pubkeys for APY calculation
-    pub staking_account_whitelist: Option<Whitelist>,
-    /// Maxmind API username and password.
-    pub maxmind: Option<MaxMindAPIKey>,
+    /// Solana RPC address, or a moniker of the Solana CLI such as `mainnet-beta`. The `--url`
+    /// argument overrides it, and without either the `json_rpc_url` of the Solana CLI config is
+    /// used.
+    pub rpc: Option<String>,
+    /// Share of heavy calls (`getBlock`, `getMultipleAccounts`, `getProgramAccounts`) sent to
+    /// `rpc` relative to the `rpc_endpoints`. Defaults to 1; with 0, `rpc` only serves the cheap
+    /// per-cycle calls.
+    pub rpc_weight: Option<u32>,
+    /// Prometheus target socket address, or a list of addresses all serving the same metrics.
+    pub target: Targets,
+    /// HTTP path the Prometheus metrics are served at. Defaults to `/metrics`.
+    pub metrics_path: Option<String>,
+    /// Whitelisted vote account pubkeys, a file listing them that is reloaded when modified, or a
+    /// table of them by the display name their series are labelled with.
+    pub vote_account_whitelist: Option<WhitelistSource>,
+    /// Whitelisted staking account pubkeys for APY calculation, a file listing them that is
+    /// reloaded when modified, or a table of them by display name.
+    pub staking_account_whitelist: Option<WhitelistSource>,
+    /// `cluster` or `self`. Defaults to `cluster`.
+    pub mode: Option<Mode>,
+    /// Identity pubkey of the validator in self mode.
+    pub identity: Option<String>,
+    /// Path of the identity keypair of the validator in self mode, instead of `identity`. Only
+    /// the public key is read from it. A relative path is taken from the directory of the config
+    /// file.
+    pub identity_keypair: Option<PathBuf>,
     /// Whjether to process rewards data or not
     pub enable_rewards: Option<bool>,
     /// Whjether to process skipped slots data or not
     pub enable_skipped_slots: Option<bool>,
+    /// Number of uncached historical epochs whose rewards are fetched at once when filling the
+    /// rewards cache, e.g. on a cold start. Defaults to 2.
+    pub reward_backfill_concurrency: Option<usize>,
+    /// Seconds after which the rewards and APYs of the current epoch, which are otherwise only
+    /// computed once they have shown up in an epoch, are computed again, e.g. to pick up
+    /// whitelist changes. Defaults to 3600.
+    pub reward_recheck_interval: Option<u64>,
+    /// Largest plausible staking APY in percent of a stake account. Higher APYs are outliers,
+    /// excluded from the APY of their voter unless `clamp_staking_apy`. No limit by default.
+    pub max_staking_apy: Option<f64>,
+    /// Whether APYs above `max_staking_apy` are clamped to it rather than excluded. Defaults to
+    /// `false`.
+    pub clamp_staking_apy: Option<bool>,
+    /// Smallest balance in SOL before the reward of the stake accounts APYs are calculated from.
+    /// Smaller accounts are skipped before they are fetched, and counted as outliers. Defaults
+    /// to 0.
+    pub apy_min_stake_sol: Option<f64>,
+    /// Whether to export estimated vote fee expenditure of whitelisted node pubkeys. Defaults to
+    /// `true`, but has no effect without a vote account whitelist.
+    pub enable_vote_fees: Option<bool>,
+    /// Whether to export compute-unit utilization of blocks produced by whitelisted node pubkeys.
+    /// Every produced block is downloaded with full transaction details, so this defaults to
+    /// `false`, except in self mode. Has no effect without a vote account whitelist.
+    pub enable_block_compute_units: Option<bool>,
+    /// Whether to export the number of stake accounts delegated to each whitelisted vote account,
+    /// the lamports of stake accounts joining and leaving it between epochs, and its active,
+    /// activating and deactivating stake. Queried every 30 minutes; the first query of an epoch
+    /// scans the stake program with a `getProgramAccounts` call per vote account, which many RPC
+    /// providers restrict, and later ones only fetch the stake accounts found and those
+    /// delegated in the epoch, so this defaults to `false`, also in self mode. Has no effect
+    /// without a vote account whitelist.
+    pub enable_delegator_count: Option<bool>,
+    /// SPL stake pool addresses to export pool-level and per-validator metrics for.
+    pub stake_pools: Option<Vec<String>>,
     /// Whether to export cluster-wide gossip node info (`solana_gossip_node_info`),
     /// one series per cluster node. Unlike the other metrics this is NOT filtered
     /// by the vote-account whitelist, so it adds one series per network node
     /// (thousands). Defaults to `false`.
     pub enable_gossip_node_info: Option<bool>,
+    /// Whether to label the gossip node info with the reverse DNS names of the gossip IPs, looked
+    /// up from the system's nameserver and cached for a day. Defaults to `false`.
+    pub enable_reverse_dns: Option<bool>,
+    /// Whether to geolocate the TPU addresses of the whitelisted validators separately from their
+    /// gossip addresses, with MaxMind, and flag those whose two addresses are in different ASNs.
+    /// Defaults to `false`.
+    pub enable_tpu_geolocation: Option<bool>,
+    /// Number of slots before the end of an epoch in which commission increases of any validator
+    /// are counted as commission rugs. Defaults to 10000, about an hour.
+    pub commission_rug_window: Option<u64>,
+    /// Smallest commission increase in percentage points counted as a commission rug. Defaults
+    /// to 10.
+    pub commission_rug_threshold: Option<u8>,
+    /// Whether to export only cluster-level and whitelist rollup metrics, leaving out all series
+    /// per validator, node or IP address, for a few dozen series per exporter. Defaults to
+    /// `false`.
+    pub aggregates_only: Option<bool>,
+    /// Number of consecutive failed update cycles after which collection is suspended. Defaults
+    /// to 5.
+    pub circuit_breaker_threshold: Option<u32>,
+    /// Seconds collection stays suspended before a probe cycle checks whether the RPC endpoint
+    /// has recovered. Doubled on every failed probe, up to ten minutes. Defaults to 30.
+    pub circuit_breaker_cooldown: Option<u64>,
+    /// Seconds a `getClusterNodes` response is reused for before it is fetched again. Defaults
+    /// to 60; with 0, it is fetched every cycle. The last response is also reused while it cannot
+    /// be fetched.
+    pub cluster_nodes_ttl: Option<u64>,
+    /// Proxy for all outbound requests, to the RPC endpoints, MaxMind and score services, e.g.
+    /// `http://proxy:3128` or `socks5h://proxy:1080`. Defaults to the standard `HTTP_PROXY`,
+    /// `HTTPS_PROXY` and `ALL_PROXY` environment variables.
+    pub proxy: Option<String>,
+    /// Whether to export the share of the circulating supply held by the 20 largest accounts, and
+    /// how many of them hold more than each of `largest_account_thresholds`. Queried hourly with
+    /// `getLargestAccounts`, which many RPC providers restrict, so this defaults to `false`.
+    pub enable_largest_accounts: Option<bool>,
+    /// Balances in SOL the largest accounts are counted above. Defaults to 1 and 10 million.
+    pub largest_account_thresholds: Option<Vec<u64>>,
+    /// Unit of the node pubkey and watched address balances: `lamports`, `sol`, under the metric
+    /// names suffixed with `_sol`, or `both`. Defaults to `lamports`.
+    pub balance_unit: Option<BalanceUnit>,
+    /// Index of this instance among `shard_count` instances splitting the whitelisted vote and
+    /// stake accounts between them. Defaults to 0.
+    pub shard_index: Option<u32>,
+    /// Number of instances splitting the whitelisted accounts. Defaults to 1.
+    pub shard_count: Option<u32>,
+    // Table-valued fields must stay below plain values, otherwise `generate` fails to emit the
+    // TOML template.
+    /// Named addresses whose balances are exported every cycle, and whose transactions are counted
+    /// every minute. The config fails to load if any address is not a valid pubkey.
+    pub address_watchlist: Option<AddressWatchlist>,
+    /// Maxmind API username and password.
+    pub maxmind: Option<MaxMindAPIKey>,
+    /// Additional RPC endpoints that heavy calls are distributed across, round-robin by weight.
+    pub rpc_endpoints: Option<Vec<RpcEndpoint>>,
+    /// InfluxDB v2 endpoint the metrics are also written to, in line protocol after every update
+    /// cycle.
+    pub influxdb: Option<InfluxDbConfig>,
+    /// StatsD or Graphite receiver the metrics are also pushed to after every update cycle.
+    pub plaintext_sink: Option<PlaintextSinkConfig>,
+    /// Third-party service the validator scores and ranks are exported from.
+    pub validator_scores: Option<ValidatorScoresConfig>,
+    /// Source of the SOL/USD price, with which USD values of balances and rewards are exported.
+    pub price: Option<PriceConfig>,
+    /// Optional labels kept by metric family name, out of `name`, `version`, `gossip_ip`,
+    /// `tvu_ip`, `tpu_ip`, `isp_name`, `user_type` and `dc_identifier`. The other optional labels
+    /// of the listed families are left out, and their series that are then alike are summed up.
+    /// Families not listed keep all their labels.
+    pub metric_labels: Option<BTreeMap<String, Vec<String>>>,
+    /// Dead man's switch pinged after every fully successful update cycle.
+    pub heartbeat: Option<HeartbeatConfig>,
+    /// Leader election between replicas, of which only the leader collects the metrics.
+    pub high_availability: Option<HighAvailabilityConfig>,
+    /// Log levels, overall and by module.
+    pub logging: Option<LoggingConfig>,
+    /// Timing of the update cycles.
+    pub scheduling: Option<SchedulingConfig>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Config {
+        target: Targets,
+    }
+
+    #[test]
+    fn parses_one_or_many_targets() {
+        let one: Config = toml::from_str("target = '0.0.0.0:9179'").unwrap();
+        assert_eq!(one.target, Targets(vec!["0.0.0.0:9179".parse().unwrap()]));
+        assert_eq!(
+            toml::to_string(&one).unwrap(),
+            "target = \"0.0.0.0:9179\"\n"
+        );
+
+        let many: Config = toml::from_str("target = ['127.0.0.1:9179', '10.0.0.1:9179']").unwrap();
+        assert_eq!(many.target.0.len(), 2);
+
+        assert!(toml::from_str::<Config>("target = []").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_watched_addresses() {
+        let valid: AddressWatchlist =
+            toml::from_str("treasury = '13DmkMhdpmJJu7nU2ozAyPiKuopZbYShMHV3JAA7YVYC'").unwrap();
+        assert_eq!(valid.0.len(), 1);
+
+        let invalid = toml::from_str::<AddressWatchlist>(
+            "treasury = '13DmkMhdpmJJu7nU2ozAyPiKuopZbYShMHV3JAA7YVYC'\nfee_payer = 'replace_me'",
+        );
+        assert!(invalid.unwrap_err().to_string().contains("fee_payer"));
+    }
+
+    #[test]
+    fn parses_inline_or_file_whitelists() {
+        #[derive(Deserialize)]
+        struct Config {
+            whitelist: WhitelistSource,
+        }
+
+        let inline: Config = toml::from_str("whitelist = ['a', 'b']").unwrap();
+        assert!(matches!(inline.whitelist, WhitelistSource::Inline(w) if w.0.len() == 2));
+        let file: Config = toml::from_str("whitelist = { file = 'validators.txt' }").unwrap();
+        assert!(
+            matches!(file.whitelist, WhitelistSource::File { file } if file == Path::new("validators.txt"))
+        );
+        let named: Config = toml::from_str("whitelist = { Cogent = 'a' }").unwrap();
+        assert!(matches!(&named.whitelist, WhitelistSource::Named(n) if n["Cogent"] == "a"));
+        assert_eq!(named.whitelist.names()["a"], "Cogent");
+    }
 }
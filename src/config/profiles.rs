@@ -0,0 +1,132 @@
+//! Named profiles in one config file, selected with `--profile`.
+//!
+//! The top-level values of the config file are the defaults shared by all profiles, and each
+//! `[profiles.<name>]` table overrides them, tables being merged key by key. A profile named
+//! after a cluster moniker of the Solana CLI, such as `mainnet-beta` or `testnet`, is a preset
+//! for that cluster: unless it sets its own `rpc`, it uses the public RPC node of the cluster.
+
+use super::ExporterConfig;
+use anyhow::{anyhow, Context};
+use toml::value::{Table, Value};
+
+/// The table of profiles in the config file.
+const PROFILES_KEY: &str = "profiles";
+
+/// Cluster monikers a profile can be named after to preset its `rpc`.
+const CLUSTER_PRESETS: [&str; 4] = ["mainnet-beta", "testnet", "devnet", "localhost"];
+
+/// Checks that the profile `name` is made of ASCII letters, digits, `-` and `_` only, since it
+/// names the default database of the profile.
+pub fn check_name(name: &str) -> anyhow::Result<()> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if name.is_empty() || !name.chars().all(valid) {
+        return Err(anyhow!(
+            "invalid profile name `{}`, only letters, digits, `-` and `_` are allowed",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Parses the config file `contents` with the overrides of `profile` applied, or with none.
+pub fn parse_config(contents: &str, profile: Option<&str>) -> anyhow::Result<ExporterConfig> {
+    let mut config: Table = toml::from_str(contents)?;
+    let profiles = match config.remove(PROFILES_KEY) {
+        None => Table::new(),
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => return Err(anyhow!("`{}` must be a table of profiles", PROFILES_KEY)),
+    };
+    if let Some(name) = profile {
+        check_name(name)?;
+        let overrides = match profiles.get(name) {
+            Some(Value::Table(overrides)) => overrides.clone(),
+            Some(_) => return Err(anyhow!("profile `{}` must be a table", name)),
+            None if CLUSTER_PRESETS.contains(&name) => Table::new(),
+            None => {
+                return Err(anyhow!(
+                    "no profile `{}` in the config, only: {}",
+                    name,
+                    profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                ))
+            }
+        };
+        if CLUSTER_PRESETS.contains(&name) && !overrides.contains_key("rpc") {
+            config.insert("rpc".to_string(), Value::String(name.to_string()));
+        }
+        merge(&mut config, overrides);
+    }
+    Value::Table(config)
+        .try_into()
+        .with_context(|| match profile {
+            Some(name) => format!("invalid config for profile `{}`", name),
+            None => "invalid config".to_string(),
+        })
+}
+
+/// Merges `overrides` into `table`, recursing into the tables both have.
+fn merge(table: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        match (table.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(value)) => merge(existing, value),
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+rpc = 'http://localhost:8899'
+target = '0.0.0.0:9179'
+enable_rewards = true
+
+[heartbeat]
+url = 'https://status.example/ping'
+interval = 60
+
+[profiles.testnet]
+target = '0.0.0.0:9180'
+heartbeat = { interval = 30 }
+
+[profiles.staging]
+rpc = 'http://staging:8899'
+";
+
+    #[test]
+    fn applies_profile_overrides_and_presets() {
+        let base = parse_config(CONFIG, None).unwrap();
+        assert_eq!(base.rpc.as_deref(), Some("http://localhost:8899"));
+
+        let testnet = parse_config(CONFIG, Some("testnet")).unwrap();
+        assert_eq!(testnet.rpc.as_deref(), Some("testnet"));
+        assert_eq!(testnet.target.0[0].port(), 9180);
+        assert_eq!(testnet.enable_rewards, Some(true));
+        let heartbeat = testnet.heartbeat.unwrap();
+        assert_eq!(heartbeat.interval, Some(30));
+        assert_eq!(heartbeat.url, "https://status.example/ping");
+
+        let staging = parse_config(CONFIG, Some("staging")).unwrap();
+        assert_eq!(staging.rpc.as_deref(), Some("http://staging:8899"));
+        assert_eq!(staging.target.0[0].port(), 9179);
+
+        // Presets need no table.
+        let devnet = parse_config(CONFIG, Some("devnet")).unwrap();
+        assert_eq!(devnet.rpc.as_deref(), Some("devnet"));
+
+        assert!(parse_config(CONFIG, Some("nonsense")).is_err());
+    }
+
+    #[test]
+    fn rejects_profile_names_unfit_for_file_names() {
+        for name in ["mainnet-beta", "staging_2"] {
+            assert!(check_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", "../etc", "a/b", "a b", "prod.db"] {
+            assert!(check_name(name).is_err(), "{}", name);
+        }
+    }
+}
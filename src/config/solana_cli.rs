@@ -0,0 +1,104 @@
+//! The RPC URL fallback to the config of the Solana CLI, and the URL monikers it accepts.
+
+use anyhow::{anyhow, Context};
+use clap::YamlLoader;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Location of the Solana CLI config, relative to the home directory.
+const SOLANA_CLI_CONFIG: &str = ".config/solana/cli/config.yml";
+
+/// Resolves the RPC URL from the `--url` argument, the `rpc` of the exporter config, or else the
+/// `json_rpc_url` of the Solana CLI config, in that order. Monikers such as `mainnet-beta` or `d`
+/// are resolved the way the Solana CLI resolves them.
+pub fn rpc_url(url_arg: Option<&str>, config_rpc: Option<&str>) -> anyhow::Result<String> {
+    if let Some(url) = url_arg.or(config_rpc) {
+        return Ok(resolve_moniker(url));
+    }
+    let location = cli_config_location().ok_or_else(|| {
+        anyhow!("no `rpc` configured, and no home directory to find the Solana CLI config in")
+    })?;
+    let url = cli_config_url(&location)?.ok_or_else(|| {
+        anyhow!(
+            "no `rpc` configured, and {} does not set `json_rpc_url`",
+            location.display()
+        )
+    })?;
+    Ok(resolve_moniker(&url))
+}
+
+/// The location of the Solana CLI config.
+fn cli_config_location() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(SOLANA_CLI_CONFIG))
+}
+
+/// Reads the `json_rpc_url` of the Solana CLI config at `location`.
+fn cli_config_url(location: &Path) -> anyhow::Result<Option<String>> {
+    let contents = fs::read_to_string(location).with_context(|| {
+        format!(
+            "no `rpc` configured, and could not read the Solana CLI config at {}",
+            location.display()
+        )
+    })?;
+    parse_cli_config_url(&contents).with_context(|| {
+        format!(
+            "could not parse the Solana CLI config {}",
+            location.display()
+        )
+    })
+}
+
+/// Parses the `json_rpc_url` out of the `contents` of a Solana CLI config.
+fn parse_cli_config_url(contents: &str) -> anyhow::Result<Option<String>> {
+    let docs = YamlLoader::load_from_str(contents).map_err(|e| anyhow!("{:?}", e))?;
+    Ok(docs
+        .first()
+        .and_then(|doc| doc["json_rpc_url"].as_str())
+        .filter(|url| !url.is_empty())
+        .map(str::to_string))
+}
+
+/// Returns the URL of the cluster a moniker of the Solana CLI names, or `url` itself.
+fn resolve_moniker(url: &str) -> String {
+    match url {
+        "mainnet-beta" | "m" => "https://api.mainnet-beta.solana.com",
+        "testnet" | "t" => "https://api.testnet.solana.com",
+        "devnet" | "d" => "https://api.devnet.solana.com",
+        "localhost" | "l" => "http://localhost:8899",
+        url => url,
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_rpc_url_and_monikers() {
+        assert_eq!(
+            rpc_url(Some("devnet"), Some("http://a:8899")).unwrap(),
+            "https://api.devnet.solana.com"
+        );
+        assert_eq!(
+            rpc_url(None, Some("http://a:8899")).unwrap(),
+            "http://a:8899"
+        );
+        assert_eq!(
+            rpc_url(None, Some("m")).unwrap(),
+            "https://api.mainnet-beta.solana.com"
+        );
+
+        let contents = "---\njson_rpc_url: \"https://api.testnet.solana.com\"\n\
+                        websocket_url: \"\"\nkeypair_path: /home/sol/.config/solana/id.json\n\
+                        commitment: confirmed\n";
+        assert_eq!(
+            parse_cli_config_url(contents).unwrap().as_deref(),
+            Some("https://api.testnet.solana.com")
+        );
+        assert_eq!(
+            parse_cli_config_url("---\ncommitment: confirmed\n").unwrap(),
+            None
+        );
+    }
+}
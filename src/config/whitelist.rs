@@ -0,0 +1,184 @@
+//! Whitelists given inline in the config or read from files of their own, which are reloaded when
+//! they change.
+
+use super::Whitelist;
+use anyhow::Context;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Where a whitelist comes from: a list of pubkeys in the config, `{ file = "..." }`, or a table
+/// of pubkeys by display name.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum WhitelistSource {
+    Inline(Whitelist),
+    /// A file with one pubkey per line. Blank lines and everything after a `#` are ignored. A
+    /// relative path is taken from the directory of the config file.
+    File {
+        file: PathBuf,
+    },
+    /// Pubkeys by the display name they are labelled with, e.g. `{ Cogent = "Aw5w..." }`.
+    Named(BTreeMap<String, String>),
+}
+
+impl WhitelistSource {
+    /// Returns the display names of the whitelisted pubkeys by pubkey. Only named whitelists have
+    /// any.
+    pub fn names(&self) -> HashMap<String, String> {
+        match self {
+            WhitelistSource::Named(named) => named
+                .iter()
+                .map(|(name, pubkey)| (pubkey.clone(), name.clone()))
+                .collect(),
+            _ => HashMap::new(),
+        }
+    }
+}
+
+/// A whitelist that is reloaded from its file, if it has one, whenever the file is modified.
+pub struct ReloadableWhitelist {
+    /// Name of the whitelist, used in logs.
+    name: &'static str,
+    /// File the whitelist is read from, if not inline.
+    file: Option<PathBuf>,
+    /// The last loaded whitelist.
+    whitelist: Whitelist,
+    /// Modification time of `file` when last loaded.
+    modified: Option<SystemTime>,
+}
+
+impl ReloadableWhitelist {
+    /// Loads the whitelist `name` from `source`, resolving a relative file path against
+    /// `config_dir`. An absent source is an empty whitelist.
+    pub fn new(
+        name: &'static str,
+        source: Option<WhitelistSource>,
+        config_dir: &Path,
+    ) -> anyhow::Result<Self> {
+        let mut reloadable = Self {
+            name,
+            file: None,
+            whitelist: Whitelist::default(),
+            modified: None,
+        };
+        match source {
+            None => {}
+            Some(WhitelistSource::Inline(whitelist)) => reloadable.whitelist = whitelist,
+            Some(WhitelistSource::Named(named)) => {
+                reloadable.whitelist = Whitelist::new(named.into_values().collect())
+            }
+            Some(WhitelistSource::File { file }) => {
+                let file = config_dir.join(file);
+                let (whitelist, modified) = read(&file)
+                    .with_context(|| format!("could not read {} {}", name, file.display()))?;
+                reloadable.whitelist = whitelist;
+                reloadable.modified = modified;
+                reloadable.file = Some(file);
+            }
+        }
+        Ok(reloadable)
+    }
+
+    /// Reloads the whitelist if its file was modified since it was last loaded. If the file
+    /// cannot be read, the last loaded whitelist is kept.
+    pub fn reload(&mut self) {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return,
+        };
+        match fs::metadata(file).and_then(|m| m.modified()) {
+            Ok(modified) if Some(modified) == self.modified => return,
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Could not check {} {}: {}", self.name, file.display(), e);
+                return;
+            }
+        }
+        match read(file) {
+            Ok((whitelist, modified)) => {
+                info!(
+                    "Reloaded {} {} with {} pubkeys",
+                    self.name,
+                    file.display(),
+                    whitelist.0.len()
+                );
+                self.whitelist = whitelist;
+                self.modified = modified;
+            }
+            Err(e) => warn!(
+                "Could not reload {} {}, keeping the previous one: {:#}",
+                self.name,
+                file.display(),
+                e
+            ),
+        }
+    }
+
+    /// Returns the last loaded whitelist.
+    pub fn get(&self) -> &Whitelist {
+        &self.whitelist
+    }
+}
+
+/// Reads a whitelist file, returning it with its modification time if the platform has one.
+fn read(file: &Path) -> anyhow::Result<(Whitelist, Option<SystemTime>)> {
+    let modified = fs::metadata(file)?.modified().ok();
+    let contents = fs::read_to_string(file)?;
+    Ok((parse(&contents), modified))
+}
+
+/// Parses the pubkeys of a whitelist file.
+fn parse(contents: &str) -> Whitelist {
+    Whitelist::new(
+        contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn reloads_modified_file() {
+        let dir = std::env::temp_dir().join(format!("whitelist-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("validators.txt");
+        fs::write(&file, "# Our validators\nvote1\n\n  vote2  # backup\n").unwrap();
+
+        let source = WhitelistSource::File {
+            file: "validators.txt".into(),
+        };
+        let mut whitelist = ReloadableWhitelist::new("whitelist", Some(source), &dir).unwrap();
+        let mut expected: Vec<_> = whitelist.get().0.iter().cloned().collect();
+        expected.sort();
+        assert_eq!(expected, vec!["vote1", "vote2"]);
+
+        fs::write(&file, "vote3\n").unwrap();
+        // Make the modification visible on filesystems with coarse timestamps.
+        let later = SystemTime::now() + Duration::from_secs(10);
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        whitelist.reload();
+        assert!(whitelist.get().contains("vote3"));
+        assert!(!whitelist.get().contains("vote1"));
+
+        fs::remove_file(&file).unwrap();
+        whitelist.reload();
+        assert!(whitelist.get().contains("vote3"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
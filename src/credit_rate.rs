@@ -0,0 +1,131 @@
+//! The rate at which whitelisted validators earn vote credits.
+
+use crate::config::Whitelist;
+use crate::monitor::{family_name, Cycle, Monitor};
+use async_trait::async_trait;
+use prometheus_exporter::prometheus::GaugeVec;
+use solana_client::rpc_response::RpcVoteAccountStatus;
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds of observations the rate is measured over.
+const RATE_WINDOW: f64 = 300.0;
+
+/// Seconds of observations needed before a rate is exported.
+const MIN_SPAN: f64 = 60.0;
+
+/// The observed vote credit totals of a vote account, and their Unix times in seconds.
+type Observations = VecDeque<(f64, u64)>;
+
+/// The monitor of the vote credits earned per minute by the whitelisted validators.
+///
+/// The total credits of each vote account, which keep growing across epochs, are observed every
+/// cycle, and the rate is measured over the last five minutes of observations. A validator that
+/// votes late earns fewer credits per vote with timely vote credits, which this rate shows
+/// while the validator is not yet delinquent.
+pub struct CreditRateMonitor<'a> {
+    /// Prometheus gauge.
+    vote_credit_rate: &'a GaugeVec,
+    /// Observations by vote account pubkey.
+    observations: HashMap<String, Observations>,
+}
+
+impl<'a> CreditRateMonitor<'a> {
+    pub fn new(vote_credit_rate: &'a GaugeVec) -> Self {
+        Self {
+            vote_credit_rate,
+            observations: HashMap::new(),
+        }
+    }
+
+    /// Observes the credits of the vote accounts of `vote_accounts_whitelist` at `now`, and
+    /// exports their rates. Vote accounts that are no longer listed are forgotten, and their
+    /// series removed.
+    fn export_credit_rates(
+        &mut self,
+        vote_accounts: &RpcVoteAccountStatus,
+        vote_accounts_whitelist: &Whitelist,
+        now: f64,
+    ) -> anyhow::Result<()> {
+        let mut observations = HashMap::new();
+        for v in vote_accounts
+            .current
+            .iter()
+            .chain(&vote_accounts.delinquent)
+            .filter(|v| vote_accounts_whitelist.contains(&v.vote_pubkey))
+        {
+            let credits = match v.epoch_credits.last() {
+                Some((_, credits, _)) => *credits,
+                None => continue,
+            };
+            let mut observed = self.observations.remove(&v.vote_pubkey).unwrap_or_default();
+            if let Some(rate) = observe(&mut observed, now, credits) {
+                self.vote_credit_rate
+                    .get_metric_with_label_values(&[&v.vote_pubkey])
+                    .map(|m| m.set(rate))?;
+            }
+            observations.insert(v.vote_pubkey.clone(), observed);
+        }
+        for vote_pubkey in std::mem::replace(&mut self.observations, observations).keys() {
+            let _ = self.vote_credit_rate.remove_label_values(&[vote_pubkey]);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Monitor for CreditRateMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "vote credit rate"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![family_name(self.vote_credit_rate)]
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+        self.export_credit_rates(&cycle.vote_accounts, &cycle.vote_accounts_whitelist, now)
+    }
+}
+
+/// Records the `credits` total observed at `now`, drops the observations older than the window,
+/// and returns the credits earned per minute over the remaining ones once they span
+/// [`MIN_SPAN`]. A total lower than the previous one, e.g. from a lagging RPC node, restarts the
+/// observations.
+fn observe(observations: &mut Observations, now: f64, credits: u64) -> Option<f64> {
+    if observations.back().is_some_and(|(_, last)| credits < *last) {
+        observations.clear();
+    }
+    observations.push_back((now, credits));
+    while observations
+        .front()
+        .is_some_and(|(time, _)| now - time > RATE_WINDOW)
+    {
+        observations.pop_front();
+    }
+    let (first_time, first_credits) = *observations.front()?;
+    let span = now - first_time;
+    (span >= MIN_SPAN).then(|| (credits - first_credits) as f64 / span * 60.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_credits_per_minute_over_the_window() {
+        let mut observations = Observations::new();
+        assert_eq!(observe(&mut observations, 1000.0, 500), None);
+        assert_eq!(observe(&mut observations, 1030.0, 800), None);
+        assert_eq!(observe(&mut observations, 1060.0, 1_100), Some(600.0));
+
+        // Observations older than the window are dropped.
+        assert_eq!(observe(&mut observations, 1330.0, 1_100), Some(60.0));
+        assert_eq!(observations.len(), 3);
+
+        // A lower total restarts the observations.
+        assert_eq!(observe(&mut observations, 1340.0, 1_000), None);
+        assert_eq!(observations.len(), 1);
+    }
+}
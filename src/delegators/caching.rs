@@ -0,0 +1,102 @@
+use crate::persistent_database::envelope::{self, Encoding};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use solana_clock::Epoch;
+use std::collections::HashMap;
+
+/// Name of the tree holding the last delegations snapshot of every vote account.
+pub const DELEGATIONS_TREE_NAME: &str = "delegations";
+
+/// The active stake accounts delegated to a vote account, as last observed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DelegationsSnapshot {
+    /// The epoch the snapshot was taken in.
+    pub epoch: Epoch,
+    /// Delegated lamports per stake account.
+    pub stakes: HashMap<String, u64>,
+}
+
+/// A caching database for delegations, so that delegation churn is measured against the previous
+/// epoch across exporter restarts.
+pub struct DelegationsCache {
+    tree: sled::Tree,
+}
+
+impl DelegationsCache {
+    /// Creates a new cache using a tree.
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+
+    /// Returns the last persisted delegations snapshot of `vote_pubkey`.
+    pub fn get_snapshot(&self, vote_pubkey: &str) -> anyhow::Result<Option<DelegationsSnapshot>> {
+        self.tree
+            .get(vote_pubkey)
+            .context("could not fetch delegations snapshot from database")?
+            .map(|x| envelope::decode(&x))
+            .transpose()
+            .context("could not deserialize fetched delegations snapshot")
+    }
+
+    /// Persists a delegations snapshot of `vote_pubkey`.
+    pub fn set_snapshot(
+        &self,
+        vote_pubkey: &str,
+        snapshot: &DelegationsSnapshot,
+    ) -> anyhow::Result<()> {
+        self.tree
+            .insert(vote_pubkey, envelope::encode(Encoding::Json, snapshot)?)
+            .context("could not insert delegations snapshot into database")?;
+        Ok(())
+    }
+}
+
+/// Name of the tree holding the discovered stake accounts of every vote account.
+pub const DELEGATOR_SETS_TREE_NAME: &str = "delegator_sets";
+
+/// All stake accounts delegated to a vote account, including deactivating and deactivated ones,
+/// as last observed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DelegatorSet {
+    /// The epoch the stake accounts were last queried in.
+    pub epoch: Epoch,
+    /// Delegated lamports per stake account.
+    pub stakes: HashMap<String, u64>,
+    /// Balance per stake account. Empty in the sets persisted by earlier versions.
+    #[serde(default)]
+    pub lamports: HashMap<String, u64>,
+    /// Deactivation epoch per stake account whose stake was deactivated.
+    #[serde(default)]
+    pub deactivation_epochs: HashMap<String, Epoch>,
+}
+
+/// A caching database for the discovered stake accounts of every vote account, so that only the
+/// known ones and newly delegated ones are queried, even across epochs and exporter restarts.
+pub struct DelegatorSetsCache {
+    tree: sled::Tree,
+}
+
+impl DelegatorSetsCache {
+    /// Creates a new cache using a tree.
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+
+    /// Returns the last persisted delegator set of `vote_pubkey`.
+    pub fn get_delegator_set(&self, vote_pubkey: &str) -> anyhow::Result<Option<DelegatorSet>> {
+        self.tree
+            .get(vote_pubkey)
+            .context("could not fetch delegator set from database")?
+            .map(|x| envelope::decode(&x))
+            .transpose()
+            .context("could not deserialize fetched delegator set")
+    }
+
+    /// Persists the delegator set of `vote_pubkey`.
+    pub fn set_delegator_set(&self, vote_pubkey: &str, set: &DelegatorSet) -> anyhow::Result<()> {
+        self.tree
+            .insert(vote_pubkey, envelope::encode(Encoding::Json, set)?)
+            .context("could not insert delegator set into database")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,1060 @@
+//! Stake accounts delegated to whitelisted vote accounts.
+
+use crate::config::Whitelist;
+use crate::delegators::caching::{
+    DelegationsCache, DelegationsSnapshot, DelegatorSet, DelegatorSetsCache,
+};
+use crate::delegators::operations::{
+    deactivations, find_operation, withdrawals, StakeAccount, StakeEvent,
+};
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::persistent_database::counters::CounterBaselines;
+use crate::rpc_api::RpcApi;
+use crate::rpc_extra::MAX_MULTIPLE_ACCOUNTS;
+use agave_feature_set::reduce_stake_warmup_cooldown;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use log::{debug, warn};
+use prometheus_exporter::prometheus::{IntCounterVec, IntGaugeVec};
+use solana_account::Account;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcProgramAccountsConfig, UiAccountEncoding, UiDataSliceConfig,
+};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_clock::{Epoch, Slot};
+use solana_epoch_schedule::EpochSchedule;
+use solana_pubkey::Pubkey;
+use solana_stake_interface::stake_history::{StakeHistory, StakeHistoryEntry};
+use solana_stake_interface::state::{Delegation, StakeStateV2};
+use solana_stake_interface::sysvar::stake_history;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::time::{Duration, Instant};
+
+pub mod caching;
+pub mod operations;
+
+/// Offset of `Delegation::voter_pubkey` in the serialized `StakeStateV2::Stake` variant, after
+/// the enum tag and the `Meta`.
+const VOTER_PUBKEY_OFFSET: usize = 124;
+
+/// Offset of `Delegation::stake` in the fetched data.
+const STAKE_OFFSET: usize = 32;
+
+/// Offset of `Delegation::activation_epoch` in the fetched data.
+const ACTIVATION_EPOCH_OFFSET: usize = 40;
+
+/// Offset of `Delegation::deactivation_epoch` in the fetched data.
+const DEACTIVATION_EPOCH_OFFSET: usize = 48;
+
+/// Length of the data fetched per stake account: the voter pubkey, the stake and the activation
+/// and deactivation epochs of the delegation.
+const DELEGATION_LENGTH: usize = 56;
+
+/// Time between two queries of the stake accounts within an epoch.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Maximum number of epochs since the stake accounts of a vote account were last queried for
+/// which the stake accounts delegated in each are scanned for, rather than all of them at once.
+const MAX_SCANNED_EPOCHS: u64 = 5;
+
+/// Maximum number of stake accounts per vote account that appeared or disappeared whose
+/// transactions are searched for stake operations in a query. The others are searched in the
+/// next queries.
+const MAX_INSPECTED_ACCOUNTS: usize = 20;
+
+/// Maximum number of stake accounts per vote account left to be searched in the next queries.
+/// The earliest ones beyond it are not searched, and are counted instead.
+const MAX_UNINSPECTED_ACCOUNTS: usize = 1_000;
+
+/// The monitor of the stake accounts delegated to each whitelisted vote account: their number,
+/// the lamports of stake accounts that joined or left between epochs, and the split of the
+/// delegated stake into active, activating and deactivating lamports.
+///
+/// The stake program accounts are queried at the start of every epoch and then every
+/// `REFRESH_INTERVAL`, since `getProgramAccounts` is expensive, but stake that will be
+/// (de)activated at the next epoch boundary only shows up in queries made during the epoch.
+/// Churn is measured between the persisted first snapshots of consecutive epochs, and counted at
+/// most once per epoch.
+///
+/// Only the first query of a vote account scans the stake program for all stake accounts delegated
+/// to it. The stake accounts found are persisted, and later queries, in this epoch or later ones,
+/// fetch those, plus those delegated since the last query, which filtered scans find, one per
+/// epoch since. A stake account split off a known one keeps its activation epoch and is only found
+/// by a full scan, so a known stake account whose delegated stake shrank, which only a split does,
+/// triggers one, as does a last query more than `MAX_SCANNED_EPOCHS` epochs ago.
+///
+/// Stake operations are counted between consecutive queries, including the last query of an
+/// earlier run: stake accounts that started deactivating or whose balance dropped, and stake
+/// accounts that appeared or disappeared, whose latest transactions tell whether they were split
+/// off, merged or withdrawn from.
+pub struct DelegatorsMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Prometheus delegator count gauge.
+    delegators: &'a IntGaugeVec,
+    /// Prometheus cumulative newly delegated lamports counter.
+    delegated_lamports: &'a IntCounterVec,
+    /// Prometheus cumulative undelegated lamports counter.
+    undelegated_lamports: &'a IntCounterVec,
+    /// Prometheus delegated stake gauge by activation status.
+    delegated_stake: &'a IntGaugeVec,
+    /// Prometheus stake operation counter.
+    stake_operations: &'a IntCounterVec,
+    /// Prometheus stake operation lamports counter.
+    stake_operation_lamports: &'a IntCounterVec,
+    /// Prometheus counter of the changed stake accounts whose operations were not searched.
+    uninspected_stake_accounts: &'a IntCounterVec,
+    /// Persisted counter totals.
+    baselines: &'a CounterBaselines,
+    /// Caching database for delegations.
+    cache: &'a DelegationsCache,
+    /// Caching database for the discovered stake accounts.
+    delegator_sets: &'a DelegatorSetsCache,
+    /// The epoch the stake accounts were last queried in.
+    epoch: Option<Epoch>,
+    /// When the stake accounts were last queried.
+    last_query: Option<Instant>,
+    /// The epoch schedule, fetched when first needed.
+    epoch_schedule: Option<EpochSchedule>,
+    /// The stake accounts of the last query, by vote account.
+    last_delegations: HashMap<String, HashMap<Pubkey, StakeAccount>>,
+    /// The stake accounts that appeared or disappeared, with their last known delegated stake if
+    /// they disappeared, whose operations are left to be searched, by vote account.
+    uninspected: HashMap<String, Vec<(Pubkey, Option<u64>)>>,
+}
+
+impl<'a> DelegatorsMonitor<'a> {
+    /// Constructs a monitor given `rpc`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        rpc: &'a dyn RpcApi,
+        delegators: &'a IntGaugeVec,
+        delegated_lamports: &'a IntCounterVec,
+        undelegated_lamports: &'a IntCounterVec,
+        delegated_stake: &'a IntGaugeVec,
+        stake_operations: &'a IntCounterVec,
+        stake_operation_lamports: &'a IntCounterVec,
+        uninspected_stake_accounts: &'a IntCounterVec,
+        baselines: &'a CounterBaselines,
+        cache: &'a DelegationsCache,
+        delegator_sets: &'a DelegatorSetsCache,
+    ) -> Self {
+        Self {
+            rpc,
+            delegators,
+            delegated_lamports,
+            undelegated_lamports,
+            delegated_stake,
+            stake_operations,
+            stake_operation_lamports,
+            uninspected_stake_accounts,
+            baselines,
+            cache,
+            delegator_sets,
+            epoch: None,
+            last_query: None,
+            epoch_schedule: None,
+            last_delegations: HashMap::new(),
+            uninspected: HashMap::new(),
+        }
+    }
+
+    /// Exports the number of stake accounts delegated to each vote account of
+    /// `vote_accounts_whitelist`, the churn since the previous epoch and the activation status of
+    /// the delegated stake, unless they have been exported in `epoch` less than
+    /// `REFRESH_INTERVAL` ago. Stake accounts that are deactivated or being deactivated are not
+    /// counted as delegators. Only exported if a whitelist is set.
+    pub async fn export_delegators(
+        &mut self,
+        vote_accounts_whitelist: &Whitelist,
+        epoch: Epoch,
+    ) -> anyhow::Result<()> {
+        if vote_accounts_whitelist.0.is_empty()
+            || (self.epoch == Some(epoch)
+                && self
+                    .last_query
+                    .is_some_and(|t| t.elapsed() < REFRESH_INTERVAL))
+        {
+            return Ok(());
+        }
+
+        let (history, new_rate_activation_epoch) = self.stake_history().await?;
+        for vote_pubkey in &vote_accounts_whitelist.0 {
+            let voter = vote_pubkey.parse::<Pubkey>()?;
+            let previous = match self.last_delegations.remove(vote_pubkey) {
+                Some(previous) => Some(previous),
+                // After a restart, operations are counted from the last query of the earlier run.
+                None => self.persisted_delegations(vote_pubkey, &voter)?,
+            };
+            let delegations = self.delegations(vote_pubkey, &voter, epoch).await?;
+            if let Some(previous) = &previous {
+                let mut uninspected = self.uninspected.remove(vote_pubkey).unwrap_or_default();
+                self.export_operations(vote_pubkey, previous, &delegations, &mut uninspected)
+                    .await?;
+                self.uninspected.insert(vote_pubkey.clone(), uninspected);
+            }
+
+            let status = delegations
+                .values()
+                .map(|a| {
+                    a.delegation.stake_activating_and_deactivating(
+                        epoch,
+                        &history,
+                        new_rate_activation_epoch,
+                    )
+                })
+                .fold(StakeHistoryEntry::default(), |total, status| total + status);
+            // Effective stake includes the stake that is being deactivated.
+            for (label, lamports) in [
+                (
+                    "active",
+                    status.effective.saturating_sub(status.deactivating),
+                ),
+                ("activating", status.activating),
+                ("deactivating", status.deactivating),
+            ] {
+                self.delegated_stake
+                    .get_metric_with_label_values(&[vote_pubkey, label])
+                    .map(|c| c.set(lamports as i64))?;
+            }
+
+            let stakes: HashMap<_, _> = delegations
+                .iter()
+                .filter(|(_, a)| a.delegation.deactivation_epoch == Epoch::MAX)
+                .map(|(pubkey, a)| (pubkey.to_string(), a.delegation.stake))
+                .collect();
+            self.last_delegations
+                .insert(vote_pubkey.clone(), delegations);
+            self.delegators
+                .get_metric_with_label_values(&[vote_pubkey])
+                .map(|c| c.set(stakes.len() as i64))?;
+
+            // The first snapshot of a vote account is only the baseline of its churn.
+            let previous = self.cache.get_snapshot(vote_pubkey)?;
+            if previous.as_ref().is_some_and(|p| p.epoch >= epoch) {
+                continue;
+            }
+            if let Some(previous) = previous {
+                let (delegated, undelegated) = churn(&previous.stakes, &stakes);
+                self.baselines.inc_by_for_epoch(
+                    self.delegated_lamports,
+                    &[vote_pubkey],
+                    epoch,
+                    delegated,
+                )?;
+                self.baselines.inc_by_for_epoch(
+                    self.undelegated_lamports,
+                    &[vote_pubkey],
+                    epoch,
+                    undelegated,
+                )?;
+            }
+            self.cache
+                .set_snapshot(vote_pubkey, &DelegationsSnapshot { epoch, stakes })?;
+        }
+
+        self.epoch = Some(epoch);
+        self.last_query = Some(Instant::now());
+        debug!("Exported delegators for epoch {}", epoch);
+        Ok(())
+    }
+
+    /// Counts the stake operations on the stake accounts delegated to `vote_pubkey` between the
+    /// `previous` and `current` stake accounts. Those that appeared or disappeared are added to
+    /// `uninspected`, of which the transactions of the first `MAX_INSPECTED_ACCOUNTS` are searched,
+    /// skipping those that cannot be fetched.
+    async fn export_operations(
+        &self,
+        vote_pubkey: &str,
+        previous: &HashMap<Pubkey, StakeAccount>,
+        current: &HashMap<Pubkey, StakeAccount>,
+        uninspected: &mut Vec<(Pubkey, Option<u64>)>,
+    ) -> anyhow::Result<()> {
+        let mut events = deactivations(previous, current);
+        events.extend(withdrawals(previous, current));
+        let appeared = current
+            .keys()
+            .filter(|pubkey| !previous.contains_key(pubkey))
+            .map(|pubkey| (*pubkey, None));
+        let disappeared = previous
+            .iter()
+            .filter(|(pubkey, _)| !current.contains_key(pubkey))
+            .map(|(pubkey, a)| (*pubkey, Some(a.delegation.stake)));
+        uninspected.extend(appeared.chain(disappeared));
+        if uninspected.len() > MAX_UNINSPECTED_ACCOUNTS {
+            let skipped = uninspected.len() - MAX_UNINSPECTED_ACCOUNTS;
+            warn!(
+                "Not searching the stake operations of {} changed stake accounts of {}, too many \
+                 changed at once",
+                skipped, vote_pubkey
+            );
+            uninspected.drain(..skipped);
+            self.baselines.inc_by(
+                self.uninspected_stake_accounts,
+                &[vote_pubkey],
+                skipped as u64,
+            )?;
+        }
+        let inspected: Vec<_> = uninspected
+            .drain(..uninspected.len().min(MAX_INSPECTED_ACCOUNTS))
+            .collect();
+        if !uninspected.is_empty() {
+            debug!(
+                "Searching stake operations of {} changed stake accounts of {}, {} left for the \
+                 next queries",
+                inspected.len(),
+                vote_pubkey,
+                uninspected.len()
+            );
+        }
+        for (pubkey, stake) in inspected {
+            match find_operation(self.rpc, &pubkey, stake).await {
+                Ok(event) => events.extend(event),
+                Err(e) => warn!("Could not search stake operations of {}: {e:#}", pubkey),
+            }
+        }
+
+        for StakeEvent {
+            operation,
+            lamports,
+        } in events
+        {
+            let labels = [vote_pubkey, operation.name()];
+            self.baselines.inc_by(self.stake_operations, &labels, 1)?;
+            self.baselines
+                .inc_by(self.stake_operation_lamports, &labels, lamports)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the stake history and the epoch from which the reduced warmup and cooldown rate
+    /// applies, if it has been activated.
+    async fn stake_history(&mut self) -> anyhow::Result<(StakeHistory, Option<Epoch>)> {
+        let accounts = self
+            .rpc
+            .get_multiple_accounts(&[stake_history::ID, reduce_stake_warmup_cooldown::id()])
+            .await?;
+        let mut accounts = accounts.into_iter();
+        let history = match accounts.next().flatten() {
+            Some(account) => bincode::deserialize(&account.data)
+                .context("could not deserialize the stake history")?,
+            None => StakeHistory::default(),
+        };
+        // A feature account holds the slot the feature was activated in, once it is.
+        let activation_slot = accounts
+            .next()
+            .flatten()
+            .map(|account| bincode::deserialize::<Option<Slot>>(&account.data))
+            .transpose()
+            .context("could not deserialize the warmup and cooldown rate feature")?
+            .flatten();
+        let new_rate_activation_epoch = match activation_slot {
+            Some(slot) => {
+                if self.epoch_schedule.is_none() {
+                    self.epoch_schedule = Some(self.rpc.get_epoch_schedule().await?);
+                }
+                self.epoch_schedule.as_ref().map(|s| s.get_epoch(slot))
+            }
+            None => None,
+        };
+        Ok((history, new_rate_activation_epoch))
+    }
+
+    /// Returns the stake accounts of the persisted delegator set of `vote_pubkey`, delegated to
+    /// `voter`, or `None` if there is none or it has no balances, as persisted by earlier
+    /// versions.
+    fn persisted_delegations(
+        &self,
+        vote_pubkey: &str,
+        voter: &Pubkey,
+    ) -> anyhow::Result<Option<HashMap<Pubkey, StakeAccount>>> {
+        let set = match self.delegator_sets.get_delegator_set(vote_pubkey)? {
+            Some(set) => set,
+            None => return Ok(None),
+        };
+        let mut accounts = HashMap::new();
+        for (pubkey, stake) in &set.stakes {
+            let lamports = match set.lamports.get(pubkey) {
+                Some(lamports) => *lamports,
+                None => return Ok(None),
+            };
+            let delegation = Delegation {
+                voter_pubkey: *voter,
+                stake: *stake,
+                deactivation_epoch: set
+                    .deactivation_epochs
+                    .get(pubkey)
+                    .copied()
+                    .unwrap_or(Epoch::MAX),
+                ..Delegation::default()
+            };
+            accounts.insert(
+                pubkey.parse().context("invalid cached stake account")?,
+                StakeAccount {
+                    delegation,
+                    lamports,
+                },
+            );
+        }
+        Ok(Some(accounts))
+    }
+
+    /// Returns the stake accounts delegated to `voter`, `vote_pubkey`, in `epoch`, and persists
+    /// them.
+    async fn delegations(
+        &self,
+        vote_pubkey: &str,
+        voter: &Pubkey,
+        epoch: Epoch,
+    ) -> anyhow::Result<HashMap<Pubkey, StakeAccount>> {
+        let known = self
+            .delegator_sets
+            .get_delegator_set(vote_pubkey)?
+            .filter(|set| set.epoch <= epoch && epoch - set.epoch <= MAX_SCANNED_EPOCHS);
+        let refreshed = match known {
+            Some(known) => self.refresh_delegations(voter, &known, epoch).await?,
+            None => None,
+        };
+        let delegations = match refreshed {
+            Some(delegations) => delegations,
+            None => {
+                debug!("Scanning the stake accounts delegated to {}", vote_pubkey);
+                self.scan_delegations(voter, None).await?
+            }
+        };
+        let mut set = DelegatorSet {
+            epoch,
+            ..DelegatorSet::default()
+        };
+        for (pubkey, account) in &delegations {
+            let pubkey = pubkey.to_string();
+            set.stakes.insert(pubkey.clone(), account.delegation.stake);
+            set.lamports.insert(pubkey.clone(), account.lamports);
+            if account.delegation.deactivation_epoch != Epoch::MAX {
+                set.deactivation_epochs
+                    .insert(pubkey, account.delegation.deactivation_epoch);
+            }
+        }
+        self.delegator_sets.set_delegator_set(vote_pubkey, &set)?;
+        Ok(delegations)
+    }
+
+    /// Returns the `known` stake accounts that are still delegated to `voter`, and those delegated
+    /// to it from the epoch they were queried in to `epoch`, or `None` if a known stake account
+    /// was split and the stake program must be scanned in full.
+    ///
+    /// Compared with the full scan, the known stake accounts are fetched with the same 56-byte
+    /// slice of their data, in `getMultipleAccounts` calls of `MAX_MULTIPLE_ACCOUNTS`, so the
+    /// responses hold as much data plus the stake accounts delegated since. The filtered scans
+    /// still make a node without an index on these filters go through all stake accounts, so a
+    /// refresh in the epoch of the last query costs such a node about a full scan plus the
+    /// `getMultipleAccounts` calls, and another scan for each epoch since.
+    async fn refresh_delegations(
+        &self,
+        voter: &Pubkey,
+        known: &DelegatorSet,
+        epoch: Epoch,
+    ) -> anyhow::Result<Option<HashMap<Pubkey, StakeAccount>>> {
+        let pubkeys = known
+            .stakes
+            .keys()
+            .map(|pubkey| pubkey.parse::<Pubkey>())
+            .collect::<Result<Vec<_>, _>>()
+            .context("invalid cached stake account")?;
+        let mut delegations = HashMap::new();
+        for chunk in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let accounts = self
+                .rpc
+                .get_multiple_accounts_with_config(chunk, delegation_slice())
+                .await?;
+            for (pubkey, account) in chunk.iter().zip(accounts) {
+                let account = match account {
+                    Some(account) if account.owner == solana_stake_interface::program::ID => {
+                        account
+                    }
+                    _ => continue,
+                };
+                // A stake account that is no longer delegated has no voter at the offset.
+                let stake_account = match read_delegation(pubkey, &account)? {
+                    Some(stake_account) if stake_account.delegation.voter_pubkey == *voter => {
+                        stake_account
+                    }
+                    _ => continue,
+                };
+                if known
+                    .stakes
+                    .get(&pubkey.to_string())
+                    .is_some_and(|stake| stake_account.delegation.stake < *stake)
+                {
+                    return Ok(None);
+                }
+                delegations.insert(*pubkey, stake_account);
+            }
+        }
+        for activation_epoch in known.epoch..=epoch {
+            delegations.extend(self.scan_delegations(voter, Some(activation_epoch)).await?);
+        }
+        Ok(Some(delegations))
+    }
+
+    /// Returns the stake accounts delegated to `voter`, only those activated in
+    /// `activation_epoch` if set.
+    async fn scan_delegations(
+        &self,
+        voter: &Pubkey,
+        activation_epoch: Option<Epoch>,
+    ) -> anyhow::Result<HashMap<Pubkey, StakeAccount>> {
+        let mut filters = vec![
+            RpcFilterType::DataSize(StakeStateV2::size_of() as u64),
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                VOTER_PUBKEY_OFFSET,
+                voter.as_ref(),
+            )),
+        ];
+        if let Some(activation_epoch) = activation_epoch {
+            filters.push(RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                VOTER_PUBKEY_OFFSET + ACTIVATION_EPOCH_OFFSET,
+                &activation_epoch.to_le_bytes(),
+            )));
+        }
+        let config = RpcProgramAccountsConfig {
+            filters: Some(filters),
+            account_config: delegation_slice(),
+            ..RpcProgramAccountsConfig::default()
+        };
+        let accounts = self
+            .rpc
+            .get_program_accounts_with_config(&solana_stake_interface::program::ID, config)
+            .await?;
+
+        let mut delegations = HashMap::new();
+        for (pubkey, account) in accounts {
+            if let Some(stake_account) = read_delegation(&pubkey, &account)? {
+                delegations.insert(pubkey, stake_account);
+            }
+        }
+        Ok(delegations)
+    }
+}
+
+/// Returns the account config fetching only the `DELEGATION_LENGTH` bytes of the delegation of
+/// stake accounts.
+fn delegation_slice() -> RpcAccountInfoConfig {
+    RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        data_slice: Some(UiDataSliceConfig {
+            offset: VOTER_PUBKEY_OFFSET,
+            length: DELEGATION_LENGTH,
+        }),
+        ..RpcAccountInfoConfig::default()
+    }
+}
+
+/// Returns the stake account `pubkey` from its `account` fetched with [`delegation_slice`], or
+/// `None` if the slice has no voter, as in a stake account that is not delegated.
+fn read_delegation(pubkey: &Pubkey, account: &Account) -> anyhow::Result<Option<StakeAccount>> {
+    let read = |offset: usize, length: usize| {
+        account
+            .data
+            .get(offset..offset + length)
+            .ok_or_else(|| anyhow!("truncated delegation of stake account {}", pubkey))
+    };
+    let read_u64 = |offset: usize| -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(read(offset, 8)?.try_into()?))
+    };
+    let voter_pubkey = Pubkey::new_from_array(read(0, STAKE_OFFSET)?.try_into()?);
+    if voter_pubkey == Pubkey::default() {
+        return Ok(None);
+    }
+    let delegation = Delegation {
+        voter_pubkey,
+        stake: read_u64(STAKE_OFFSET)?,
+        activation_epoch: read_u64(ACTIVATION_EPOCH_OFFSET)?,
+        deactivation_epoch: read_u64(DEACTIVATION_EPOCH_OFFSET)?,
+        ..Delegation::default()
+    };
+    Ok(Some(StakeAccount {
+        delegation,
+        lamports: account.lamports,
+    }))
+}
+
+/// Returns the lamports of the stake accounts in `current` but not in `previous`, and of those in
+/// `previous` but not in `current`. Stake changes of accounts in both, such as staking rewards,
+/// are not churn.
+fn churn(previous: &HashMap<String, u64>, current: &HashMap<String, u64>) -> (u64, u64) {
+    let added = |from: &HashMap<String, u64>, to: &HashMap<String, u64>| {
+        to.iter()
+            .filter(|(pubkey, _)| !from.contains_key(*pubkey))
+            .map(|(_, stake)| stake)
+            .sum()
+    };
+    (added(previous, current), added(current, previous))
+}
+
+#[async_trait]
+impl Monitor for DelegatorsMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "delegators"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(self.delegators),
+            family_name(self.delegated_lamports),
+            family_name(self.undelegated_lamports),
+            family_name(self.delegated_stake),
+            family_name(self.stake_operations),
+            family_name(self.stake_operation_lamports),
+            family_name(self.uninspected_stake_accounts),
+        ]
+    }
+
+    /// Restores the churn and stake operation counter totals.
+    async fn setup(&mut self) -> anyhow::Result<()> {
+        self.baselines.restore(self.delegated_lamports)?;
+        self.baselines.restore(self.undelegated_lamports)?;
+        self.baselines.restore(self.stake_operations)?;
+        self.baselines.restore(self.stake_operation_lamports)?;
+        self.baselines.restore(self.uninspected_stake_accounts)
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.export_delegators(&cycle.vote_accounts_whitelist, cycle.epoch_info.epoch)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delegators::operations::tests::stake_transaction;
+    use crate::rpc_api::mock::MockRpc;
+    use prometheus_exporter::prometheus::Opts;
+    use serde_json::json;
+    use solana_account::Account;
+    use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+    use solana_stake_interface::stake_flags::StakeFlags;
+    use solana_stake_interface::state::{Delegation, Meta, Stake};
+
+    fn stake_account(
+        voter: &Pubkey,
+        stake: u64,
+        activation_epoch: Epoch,
+        deactivation_epoch: Epoch,
+    ) -> Account {
+        let mut delegation = Delegation::new(voter, stake, activation_epoch);
+        delegation.deactivation_epoch = deactivation_epoch;
+        let stake_state = StakeStateV2::Stake(
+            Meta::default(),
+            Stake {
+                delegation,
+                credits_observed: 0,
+            },
+            StakeFlags::empty(),
+        );
+        let mut data = bincode::serialize(&stake_state).unwrap();
+        data.resize(StakeStateV2::size_of(), 0);
+        Account {
+            lamports: stake,
+            data,
+            owner: solana_stake_interface::program::ID,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    struct Fixture {
+        delegators: IntGaugeVec,
+        delegated_lamports: IntCounterVec,
+        undelegated_lamports: IntCounterVec,
+        delegated_stake: IntGaugeVec,
+        stake_operations: IntCounterVec,
+        stake_operation_lamports: IntCounterVec,
+        uninspected_stake_accounts: IntCounterVec,
+        baselines: CounterBaselines,
+        cache: DelegationsCache,
+        delegator_sets: DelegatorSetsCache,
+        whitelist: Whitelist,
+    }
+
+    impl Fixture {
+        fn new(voter: &Pubkey) -> Self {
+            let db = sled::Config::default().temporary(true).open().unwrap();
+            let counter = |name| IntCounterVec::new(Opts::new(name, name), &["pubkey"]).unwrap();
+            let operation_counter =
+                |name| IntCounterVec::new(Opts::new(name, name), &["pubkey", "operation"]).unwrap();
+            Self {
+                delegators: IntGaugeVec::new(Opts::new("delegators", "Delegators"), &["pubkey"])
+                    .unwrap(),
+                delegated_lamports: counter("delegated"),
+                undelegated_lamports: counter("undelegated"),
+                delegated_stake: IntGaugeVec::new(
+                    Opts::new("delegated_stake", "Delegated stake"),
+                    &["pubkey", "status"],
+                )
+                .unwrap(),
+                stake_operations: operation_counter("operations"),
+                stake_operation_lamports: operation_counter("operation_lamports"),
+                uninspected_stake_accounts: counter("uninspected"),
+                baselines: CounterBaselines::new(db.open_tree("counters").unwrap()),
+                cache: DelegationsCache::new(db.open_tree(caching::DELEGATIONS_TREE_NAME).unwrap()),
+                delegator_sets: DelegatorSetsCache::new(
+                    db.open_tree(caching::DELEGATOR_SETS_TREE_NAME).unwrap(),
+                ),
+                whitelist: Whitelist::new(std::iter::once(voter.to_string()).collect()),
+            }
+        }
+
+        fn monitor<'a>(&'a self, rpc: &'a MockRpc) -> DelegatorsMonitor<'a> {
+            DelegatorsMonitor::new(
+                rpc,
+                &self.delegators,
+                &self.delegated_lamports,
+                &self.undelegated_lamports,
+                &self.delegated_stake,
+                &self.stake_operations,
+                &self.stake_operation_lamports,
+                &self.uninspected_stake_accounts,
+                &self.baselines,
+                &self.cache,
+                &self.delegator_sets,
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_active_delegations_once_per_epoch() {
+        let voter = Pubkey::new_unique();
+        let other_voter = Pubkey::new_unique();
+        let mut rpc = MockRpc::default();
+        for account in [
+            stake_account(&voter, 1_000, 0, Epoch::MAX),
+            stake_account(&voter, 1_000, 0, Epoch::MAX),
+            stake_account(&voter, 1_000, 0, 3),
+            stake_account(&other_voter, 1_000, 0, Epoch::MAX),
+        ] {
+            rpc.accounts.insert(Pubkey::new_unique(), account);
+        }
+        // Not owned by the stake program.
+        let mut foreign = stake_account(&voter, 1_000, 0, Epoch::MAX);
+        foreign.owner = Pubkey::new_unique();
+        rpc.accounts.insert(Pubkey::new_unique(), foreign);
+
+        let fixture = Fixture::new(&voter);
+        let delegators = fixture.delegators.with_label_values(&[&voter.to_string()]);
+        let mut monitor = fixture.monitor(&rpc);
+        monitor
+            .export_delegators(&fixture.whitelist, 5)
+            .await
+            .unwrap();
+        assert_eq!(delegators.get(), 2);
+
+        // Within the same epoch the stake program is not queried again.
+        delegators.set(0);
+        monitor
+            .export_delegators(&fixture.whitelist, 5)
+            .await
+            .unwrap();
+        assert_eq!(delegators.get(), 0);
+        monitor
+            .export_delegators(&fixture.whitelist, 6)
+            .await
+            .unwrap();
+        assert_eq!(delegators.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn counts_churn_between_epochs_across_restarts() {
+        let voter = Pubkey::new_unique();
+        let (departing, staying, joining) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mut before = MockRpc::default();
+        before
+            .accounts
+            .insert(departing, stake_account(&voter, 1_000, 0, Epoch::MAX));
+        before
+            .accounts
+            .insert(staying, stake_account(&voter, 2_000, 0, Epoch::MAX));
+        // Rewards grow the stake of accounts that stay, which is not churn.
+        let mut after = MockRpc::default();
+        after
+            .accounts
+            .insert(staying, stake_account(&voter, 2_500, 0, Epoch::MAX));
+        after
+            .accounts
+            .insert(joining, stake_account(&voter, 4_000, 6, Epoch::MAX));
+
+        let fixture = Fixture::new(&voter);
+        let labels = [voter.to_string()];
+        let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+        fixture
+            .monitor(&before)
+            .export_delegators(&fixture.whitelist, 5)
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture.delegated_lamports.with_label_values(&labels).get(),
+            0
+        );
+
+        // Each monitor stands for a restarted exporter.
+        for _ in 0..2 {
+            fixture
+                .monitor(&after)
+                .export_delegators(&fixture.whitelist, 6)
+                .await
+                .unwrap();
+            assert_eq!(
+                fixture.delegated_lamports.with_label_values(&labels).get(),
+                4_000
+            );
+            assert_eq!(
+                fixture
+                    .undelegated_lamports
+                    .with_label_values(&labels)
+                    .get(),
+                1_000
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn splits_delegated_stake_by_activation_status() {
+        let voter = Pubkey::new_unique();
+        let mut rpc = MockRpc::default();
+        for account in [
+            stake_account(&voter, 1_000, 0, Epoch::MAX),
+            stake_account(&voter, 2_000, 5, Epoch::MAX),
+            stake_account(&voter, 4_000, 0, 5),
+            // Deactivated in an earlier epoch.
+            stake_account(&voter, 8_000, 0, 3),
+        ] {
+            rpc.accounts.insert(Pubkey::new_unique(), account);
+        }
+
+        let fixture = Fixture::new(&voter);
+        fixture
+            .monitor(&rpc)
+            .export_delegators(&fixture.whitelist, 5)
+            .await
+            .unwrap();
+        let voter = voter.to_string();
+        let stake = |status| {
+            fixture
+                .delegated_stake
+                .with_label_values(&[&voter, status])
+                .get()
+        };
+        // Without a stake history, stake outside warmup and cooldown is fully (in)effective.
+        assert_eq!(stake("active"), 1_000);
+        assert_eq!(stake("activating"), 2_000);
+        assert_eq!(stake("deactivating"), 4_000);
+        assert_eq!(fixture.delegators.with_label_values(&[&voter]).get(), 2);
+    }
+
+    #[tokio::test]
+    async fn scans_in_full_once_unless_split() {
+        let voter = Pubkey::new_unique();
+        let (known, delegated, unknown) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mut rpc = MockRpc::default();
+        rpc.accounts
+            .insert(known, stake_account(&voter, 1_000, 0, Epoch::MAX));
+        let fixture = Fixture::new(&voter);
+        let delegators = fixture.delegators.with_label_values(&[&voter.to_string()]);
+        fixture
+            .monitor(&rpc)
+            .export_delegators(&fixture.whitelist, 5)
+            .await
+            .unwrap();
+        assert_eq!(delegators.get(), 1);
+
+        // After a restart in the same epoch, only the known stake accounts and those delegated in
+        // the epoch are found.
+        rpc.accounts
+            .insert(delegated, stake_account(&voter, 2_000, 5, Epoch::MAX));
+        rpc.accounts
+            .insert(unknown, stake_account(&voter, 4_000, 0, Epoch::MAX));
+        fixture
+            .monitor(&rpc)
+            .export_delegators(&fixture.whitelist, 5)
+            .await
+            .unwrap();
+        assert_eq!(delegators.get(), 2);
+
+        // In later epochs, only those delegated since the last query are found as well.
+        let redelegated = Pubkey::new_unique();
+        rpc.accounts
+            .insert(redelegated, stake_account(&voter, 8_000, 6, Epoch::MAX));
+        fixture
+            .monitor(&rpc)
+            .export_delegators(&fixture.whitelist, 7)
+            .await
+            .unwrap();
+        assert_eq!(delegators.get(), 3);
+
+        // A known stake account that is no longer delegated is left out.
+        let mut data = bincode::serialize(&StakeStateV2::Initialized(Meta::default())).unwrap();
+        data.resize(StakeStateV2::size_of(), 0);
+        rpc.accounts.get_mut(&delegated).unwrap().data = data;
+        fixture
+            .monitor(&rpc)
+            .export_delegators(&fixture.whitelist, 7)
+            .await
+            .unwrap();
+        assert_eq!(delegators.get(), 2);
+
+        // A known stake account whose stake shrank was split, and the split off one is found.
+        rpc.accounts
+            .insert(known, stake_account(&voter, 600, 0, Epoch::MAX));
+        fixture
+            .monitor(&rpc)
+            .export_delegators(&fixture.whitelist, 7)
+            .await
+            .unwrap();
+        assert_eq!(delegators.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn counts_stake_operations_across_restarts() {
+        let voter = Pubkey::new_unique();
+        let (deactivating, withdrawn, split, partial) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let with_lamports = |mut account: Account, lamports| {
+            account.lamports = lamports;
+            account
+        };
+        let mut before = MockRpc::default();
+        before
+            .accounts
+            .insert(deactivating, stake_account(&voter, 1_000, 0, Epoch::MAX));
+        before
+            .accounts
+            .insert(withdrawn, stake_account(&voter, 2_000, 0, 4));
+        before.accounts.insert(
+            partial,
+            with_lamports(stake_account(&voter, 4_000, 0, Epoch::MAX), 4_500),
+        );
+        let mut after = MockRpc::default();
+        // Split, then deactivated.
+        after
+            .accounts
+            .insert(deactivating, stake_account(&voter, 500, 0, 6));
+        after
+            .accounts
+            .insert(split, stake_account(&voter, 500, 0, Epoch::MAX));
+        // Undelegated lamports withdrawn from an account that stays open.
+        after.accounts.insert(
+            partial,
+            with_lamports(stake_account(&voter, 4_000, 0, Epoch::MAX), 4_200),
+        );
+        for (pubkey, parsed) in [
+            (
+                withdrawn,
+                json!({"type": "withdraw", "info": {
+                    "stakeAccount": withdrawn.to_string(), "lamports": 2_100,
+                }}),
+            ),
+            (
+                split,
+                json!({"type": "split", "info": {
+                    "stakeAccount": deactivating.to_string(),
+                    "newSplitAccount": split.to_string(),
+                    "lamports": 500,
+                }}),
+            ),
+        ] {
+            let signature = format!("signature-{}", pubkey);
+            after.signatures.insert(
+                pubkey,
+                vec![RpcConfirmedTransactionStatusWithSignature {
+                    signature: signature.clone(),
+                    slot: 1,
+                    err: None,
+                    memo: None,
+                    block_time: None,
+                    confirmation_status: None,
+                    transaction_index: None,
+                }],
+            );
+            after
+                .transactions
+                .insert(signature, stake_transaction(&[parsed]));
+        }
+
+        let fixture = Fixture::new(&voter);
+        fixture
+            .monitor(&before)
+            .export_delegators(&fixture.whitelist, 5)
+            .await
+            .unwrap();
+        // The restarted exporter counts from the persisted stake accounts.
+        fixture
+            .monitor(&after)
+            .export_delegators(&fixture.whitelist, 6)
+            .await
+            .unwrap();
+
+        let voter = voter.to_string();
+        let count = |operation| {
+            fixture
+                .stake_operations
+                .with_label_values(&[&voter, operation])
+                .get()
+        };
+        let lamports = |operation| {
+            fixture
+                .stake_operation_lamports
+                .with_label_values(&[&voter, operation])
+                .get()
+        };
+        assert_eq!((count("deactivate"), lamports("deactivate")), (1, 500));
+        assert_eq!((count("withdraw"), lamports("withdraw")), (2, 2_400));
+        assert_eq!((count("split"), lamports("split")), (1, 500));
+        assert_eq!(count("merge"), 0);
+    }
+
+    #[tokio::test]
+    async fn searches_many_changed_stake_accounts_over_several_queries() {
+        let voter = Pubkey::new_unique();
+        let mut rpc = MockRpc::default();
+        let fixture = Fixture::new(&voter);
+        let mut monitor = fixture.monitor(&rpc);
+        monitor
+            .export_delegators(&fixture.whitelist, 5)
+            .await
+            .unwrap();
+
+        for _ in 0..MAX_INSPECTED_ACCOUNTS + 5 {
+            rpc.accounts.insert(
+                Pubkey::new_unique(),
+                stake_account(&voter, 1_000, 6, Epoch::MAX),
+            );
+        }
+        let mut monitor = fixture.monitor(&rpc);
+        monitor
+            .export_delegators(&fixture.whitelist, 6)
+            .await
+            .unwrap();
+        assert_eq!(monitor.uninspected[&voter.to_string()].len(), 5);
+        assert_eq!(
+            fixture
+                .uninspected_stake_accounts
+                .with_label_values(&[&voter.to_string()])
+                .get(),
+            0
+        );
+    }
+}
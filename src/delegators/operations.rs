@@ -0,0 +1,285 @@
+//! Stake operations that changed the stake accounts delegated to a vote account between two
+//! queries.
+
+use crate::rpc_api::RpcApi;
+use serde_json::Value;
+use solana_client::rpc_config::{RpcSignaturesForAddressConfig, RpcTransactionConfig};
+use solana_clock::Epoch;
+use solana_pubkey::Pubkey;
+use solana_stake_interface::state::Delegation;
+use solana_transaction_status_client_types::option_serializer::OptionSerializer;
+use solana_transaction_status_client_types::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiInstruction, UiMessage,
+    UiParsedInstruction, UiTransactionEncoding,
+};
+use std::collections::HashMap;
+
+/// Number of the latest transactions of a stake account searched for the operation that created
+/// or closed it.
+const SIGNATURES_LIMIT: usize = 10;
+
+/// An operation on a stake account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StakeOperation {
+    /// Stake split off into a new stake account.
+    Split,
+    /// A stake account merged into another one.
+    Merge,
+    /// Stake that starts deactivating.
+    Deactivate,
+    /// Lamports withdrawn from a stake account, closing it or not.
+    Withdraw,
+}
+
+impl StakeOperation {
+    /// Returns the name of the operation, used as a label value.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StakeOperation::Split => "split",
+            StakeOperation::Merge => "merge",
+            StakeOperation::Deactivate => "deactivate",
+            StakeOperation::Withdraw => "withdraw",
+        }
+    }
+}
+
+/// A stake account delegated to a vote account.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StakeAccount {
+    pub delegation: Delegation,
+    /// Balance of the account, including undelegated lamports.
+    pub lamports: u64,
+}
+
+/// An operation on a stake account and the lamports it moved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StakeEvent {
+    pub operation: StakeOperation,
+    pub lamports: u64,
+}
+
+/// Returns the deactivations of the stake accounts in both `previous` and `current`, which is
+/// all that can be told from the delegations alone.
+pub fn deactivations(
+    previous: &HashMap<Pubkey, StakeAccount>,
+    current: &HashMap<Pubkey, StakeAccount>,
+) -> Vec<StakeEvent> {
+    current
+        .iter()
+        .filter(|(pubkey, account)| {
+            account.delegation.deactivation_epoch != Epoch::MAX
+                && previous
+                    .get(pubkey)
+                    .is_some_and(|p| p.delegation.deactivation_epoch == Epoch::MAX)
+        })
+        .map(|(_, account)| StakeEvent {
+            operation: StakeOperation::Deactivate,
+            lamports: account.delegation.stake,
+        })
+        .collect()
+}
+
+/// Returns the withdrawals from the stake accounts in both `previous` and `current` whose balance
+/// decreased while their delegated stake did not, which only a partial withdrawal does. Staking
+/// rewards credited in between offset the withdrawn lamports.
+pub fn withdrawals(
+    previous: &HashMap<Pubkey, StakeAccount>,
+    current: &HashMap<Pubkey, StakeAccount>,
+) -> Vec<StakeEvent> {
+    current
+        .iter()
+        .filter_map(|(pubkey, account)| {
+            let previous = previous.get(pubkey)?;
+            (account.delegation.stake >= previous.delegation.stake
+                && account.lamports < previous.lamports)
+                .then(|| StakeEvent {
+                    operation: StakeOperation::Withdraw,
+                    lamports: previous.lamports - account.lamports,
+                })
+        })
+        .collect()
+}
+
+/// Returns the operation that created the stake account `pubkey`, if it was split off another
+/// one, or closed it, given its last known delegated `stake`, if it was merged or withdrawn from.
+/// `None` stands for an account that appeared. Stake accounts that were delegated or redelegated
+/// are not operations on the delegated stake of a vote account, and return `None`.
+pub async fn find_operation(
+    rpc: &dyn RpcApi,
+    pubkey: &Pubkey,
+    stake: Option<u64>,
+) -> anyhow::Result<Option<StakeEvent>> {
+    let signatures = rpc
+        .get_signatures_for_address_with_config(
+            pubkey,
+            RpcSignaturesForAddressConfig {
+                limit: Some(SIGNATURES_LIMIT),
+                ..RpcSignaturesForAddressConfig::default()
+            },
+        )
+        .await?;
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::JsonParsed),
+        commitment: None,
+        max_supported_transaction_version: Some(0),
+    };
+    for signature in signatures.iter().filter(|s| s.err.is_none()) {
+        let transaction = rpc
+            .get_transaction_with_config(&signature.signature, config)
+            .await?;
+        let event = stake_instructions(&transaction)
+            .find_map(|parsed| classify(parsed, &pubkey.to_string(), stake));
+        if event.is_some() {
+            return Ok(event);
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the parsed stake program instructions of `transaction`, including those invoked by
+/// other programs.
+fn stake_instructions(
+    transaction: &EncodedConfirmedTransactionWithStatusMeta,
+) -> impl Iterator<Item = &Value> {
+    let outer = match &transaction.transaction.transaction {
+        EncodedTransaction::Json(ui) => match &ui.message {
+            UiMessage::Parsed(message) => message.instructions.as_slice(),
+            UiMessage::Raw(_) => &[],
+        },
+        _ => &[],
+    };
+    let inner = match transaction
+        .transaction
+        .meta
+        .as_ref()
+        .map(|meta| &meta.inner_instructions)
+    {
+        Some(OptionSerializer::Some(inner)) => inner.as_slice(),
+        _ => &[],
+    };
+    outer
+        .iter()
+        .chain(inner.iter().flat_map(|i| &i.instructions))
+        .filter_map(|instruction| match instruction {
+            UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed))
+                if parsed.program == "stake" =>
+            {
+                Some(&parsed.parsed)
+            }
+            _ => None,
+        })
+}
+
+/// Returns the operation the `parsed` stake instruction is on the stake account `pubkey`, given
+/// its last known delegated `stake`, or `None` if it appeared.
+fn classify(parsed: &Value, pubkey: &str, stake: Option<u64>) -> Option<StakeEvent> {
+    let info = &parsed["info"];
+    let lamports = info["lamports"].as_u64();
+    let (operation, lamports) = match (parsed["type"].as_str()?, stake) {
+        ("split", None) if info["newSplitAccount"] == pubkey => (StakeOperation::Split, lamports?),
+        ("merge", Some(stake)) if info["source"] == pubkey => (StakeOperation::Merge, stake),
+        ("withdraw", Some(_)) if info["stakeAccount"] == pubkey => {
+            (StakeOperation::Withdraw, lamports?)
+        }
+        _ => return None,
+    };
+    Some(StakeEvent {
+        operation,
+        lamports,
+    })
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Returns a transaction of the parsed stake `instructions`.
+    pub(crate) fn stake_transaction(
+        instructions: &[Value],
+    ) -> EncodedConfirmedTransactionWithStatusMeta {
+        let instructions: Vec<_> = instructions
+            .iter()
+            .map(|parsed| {
+                json!({
+                    "program": "stake",
+                    "programId": solana_stake_interface::program::ID.to_string(),
+                    "parsed": parsed,
+                })
+            })
+            .collect();
+        serde_json::from_value(json!({
+            "slot": 1,
+            "blockTime": null,
+            "transaction": {
+                "signatures": [],
+                "message": {
+                    "accountKeys": [],
+                    "recentBlockhash": "",
+                    "instructions": instructions,
+                },
+            },
+            "meta": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn classifies_stake_instructions_by_account() {
+        let transaction = stake_transaction(&[
+            json!({"type": "split", "info": {"stakeAccount": "a", "newSplitAccount": "b", "lamports": 5}}),
+            json!({"type": "merge", "info": {"destination": "a", "source": "c"}}),
+        ]);
+        let parsed: Vec<_> = stake_instructions(&transaction).collect();
+        let event = |operation, lamports| {
+            Some(StakeEvent {
+                operation,
+                lamports,
+            })
+        };
+        assert_eq!(
+            classify(parsed[0], "b", None),
+            event(StakeOperation::Split, 5)
+        );
+        // The split source did not appear, nor was it closed.
+        assert_eq!(classify(parsed[0], "a", Some(10)), None);
+        assert_eq!(
+            classify(parsed[1], "c", Some(7)),
+            event(StakeOperation::Merge, 7)
+        );
+        assert_eq!(classify(parsed[1], "a", Some(10)), None);
+    }
+
+    #[test]
+    fn tells_partial_withdrawals_from_splits_and_rewards() {
+        let account = |stake, lamports| StakeAccount {
+            delegation: Delegation {
+                stake,
+                ..Delegation::default()
+            },
+            lamports,
+        };
+        let (withdrawn, split, rewarded) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let previous = HashMap::from([
+            (withdrawn, account(1_000, 1_500)),
+            (split, account(1_000, 1_500)),
+            (rewarded, account(1_000, 1_500)),
+        ]);
+        let current = HashMap::from([
+            (withdrawn, account(1_000, 1_200)),
+            (split, account(600, 1_100)),
+            (rewarded, account(1_010, 1_510)),
+        ]);
+        assert_eq!(
+            withdrawals(&previous, &current),
+            vec![StakeEvent {
+                operation: StakeOperation::Withdraw,
+                lamports: 300,
+            }]
+        );
+    }
+}
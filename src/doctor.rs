@@ -0,0 +1,468 @@
+//! The `doctor` subcommand: checks the setup of the exporter for the common problems that keep it
+//! from starting or from exporting some of its metrics, and says how to fix them.
+
+use solana_exporter::config::whitelist::ReloadableWhitelist;
+use solana_exporter::config::{profiles, solana_cli, ExporterConfig, Targets};
+use solana_exporter::geolocation::api::{self, MaxMindAPIKey};
+use solana_exporter::http;
+use solana_exporter::rpc_pool::RpcPool;
+use solana_pubkey::Pubkey;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Genesis hashes of the public clusters, by moniker.
+const CLUSTER_GENESIS_HASHES: [(&str, &str); 3] = [
+    (
+        "mainnet-beta",
+        "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d",
+    ),
+    ("testnet", "4uhcVJyU9pJkvQyS88uRDiswHXSCkY3zQawwpjk2NsNY"),
+    ("devnet", "EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG"),
+];
+/// Address geolocated to check the MaxMind credentials.
+const MAXMIND_PROBE_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+/// Timeout of the RPC and MaxMind requests of the checks.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the setup that is checked comes from: the command line arguments and the locations they
+/// resolve to.
+pub struct Setup<'a> {
+    pub config: &'a Path,
+    pub database: &'a Path,
+    /// `--profile`.
+    pub profile: Option<&'a str>,
+    /// `--url`.
+    pub url: Option<&'a str>,
+}
+
+/// The outcomes of the checks, printed as they are made.
+#[derive(Default)]
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn pass(&mut self, check: &str, detail: impl Display) {
+        println!("[PASS] {}: {}", check, detail);
+    }
+
+    fn skip(&mut self, check: &str, reason: impl Display) {
+        println!("[SKIP] {}: {}", check, reason);
+    }
+
+    fn fail(&mut self, check: &str, problem: impl Display, hint: impl Display) {
+        println!("[FAIL] {}: {}", check, problem);
+        println!("       hint: {}", hint);
+        self.failures += 1;
+    }
+}
+
+/// `doctor`: checks that the config parses, that every RPC endpoint is reachable and serves the
+/// expected cluster, that the database directory is writable, that the MaxMind credentials are
+/// accepted, that the whitelisted pubkeys are valid and that the listen addresses are free, and
+/// prints a pass or fail line for each, with a hint on how to fix the failures. Checks that
+/// depend on a config that cannot be read are skipped. Fails if any check failed.
+pub async fn doctor(setup: &Setup<'_>) -> anyhow::Result<()> {
+    let mut report = Report::default();
+    check_database(&mut report, setup.database);
+
+    let config = fs::read_to_string(setup.config)
+        .map_err(anyhow::Error::from)
+        .and_then(|contents| profiles::parse_config(&contents, setup.profile));
+    let config = match config {
+        Ok(config) => {
+            report.pass("config", setup.config.display());
+            config
+        }
+        Err(e) => {
+            report.fail(
+                "config",
+                format!("could not read {}: {:#}", setup.config.display(), e),
+                "run `solana-exporter generate` to write a config, or pass its location with --config",
+            );
+            report.skip("rpc", "no config");
+            report.skip("whitelists", "no config");
+            report.skip("maxmind", "no config");
+            report.skip("ports", "no config");
+            return finish(report);
+        }
+    };
+    let config_dir = setup.config.parent().unwrap_or_else(|| Path::new(""));
+
+    check_rpc(&mut report, setup, &config).await;
+    check_whitelists(&mut report, &config, config_dir);
+    check_maxmind(
+        &mut report,
+        config.maxmind.as_ref(),
+        config.proxy.as_deref(),
+    )
+    .await;
+    check_ports(&mut report, &config.target);
+    finish(report)
+}
+
+/// Prints the summary of `report`, failing if any check failed.
+fn finish(report: Report) -> anyhow::Result<()> {
+    if report.failures > 0 {
+        return Err(anyhow::anyhow!("{} checks failed", report.failures));
+    }
+    println!("All checks passed");
+    Ok(())
+}
+
+/// Checks that the database, or the directory it would be created in, is writable, and reports
+/// its size.
+fn check_database(report: &mut Report, database: &Path) {
+    const CHECK: &str = "database";
+    let hint = "pass a writable location with --database, or fix the permissions of the directory";
+    if database.exists() && !database.is_dir() {
+        report.fail(
+            CHECK,
+            format!("{} is not a directory", database.display()),
+            hint,
+        );
+        return;
+    }
+    let existing = database.ancestors().find(|dir| dir.is_dir());
+    let probe = match existing {
+        Some(dir) => writable(dir),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no parent directory",
+        )),
+    };
+    let dir = existing.unwrap_or(database);
+    match probe {
+        Err(e) => report.fail(
+            CHECK,
+            format!("{} is not writable: {}", dir.display(), e),
+            hint,
+        ),
+        Ok(()) if dir != database => report.pass(
+            CHECK,
+            format!(
+                "{} does not exist yet and will be created",
+                database.display()
+            ),
+        ),
+        Ok(()) => match dir_size(database) {
+            Ok(bytes) => report.pass(
+                CHECK,
+                format!(
+                    "{}, {:.1} MiB",
+                    database.display(),
+                    bytes as f64 / (1024.0 * 1024.0)
+                ),
+            ),
+            Err(e) => report.fail(
+                CHECK,
+                format!("could not read {}: {}", database.display(), e),
+                hint,
+            ),
+        },
+    }
+}
+
+/// Checks that `dir` is writable, by creating and removing a file in it.
+fn writable(dir: &Path) -> io::Result<()> {
+    let probe: PathBuf = dir.join(format!(".solana-exporter-doctor-{}", std::process::id()));
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
+}
+
+/// Returns the total size of the files under `dir`.
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+/// Checks that every RPC endpoint answers with its version, and that all of them serve the
+/// expected cluster, if any.
+async fn check_rpc(report: &mut Report, setup: &Setup<'_>, config: &ExporterConfig) {
+    let rpc_url = match solana_cli::rpc_url(setup.url, config.rpc.as_deref()) {
+        Ok(url) => url,
+        Err(e) => {
+            report.fail(
+                "rpc",
+                format!("{:#}", e),
+                "set `rpc` in the config, or pass --url",
+            );
+            return;
+        }
+    };
+    let expected = expected_cluster(setup.profile, &rpc_url);
+    let urls = std::iter::once(rpc_url).chain(
+        config
+            .rpc_endpoints
+            .iter()
+            .flatten()
+            .map(|endpoint| endpoint.url.clone()),
+    );
+    for url in urls {
+        let check = format!("rpc {}", url);
+        let pool = match RpcPool::new(&[(url, 1)], TIMEOUT, config.proxy.as_deref()) {
+            Ok(pool) => pool,
+            Err(e) => {
+                report.fail(&check, format!("{:#}", e), "check `proxy` in the config");
+                continue;
+            }
+        };
+        let rpc = pool.primary();
+        let version = match rpc.get_version().await {
+            Ok(version) => version.solana_core,
+            Err(e) => {
+                report.fail(
+                    &check,
+                    format!("unreachable: {}", e),
+                    "check the URL, that the node runs with its RPC service enabled and that this \
+                     host may connect to it",
+                );
+                continue;
+            }
+        };
+        let genesis_hash = match rpc.get_genesis_hash().await {
+            Ok(hash) => hash.to_string(),
+            Err(e) => {
+                report.fail(
+                    &check,
+                    format!("version {}, but no genesis hash: {}", version, e),
+                    "check that the endpoint is a Solana RPC node",
+                );
+                continue;
+            }
+        };
+        let cluster = cluster_of(&genesis_hash);
+        match expected {
+            Some(expected) if cluster != Some(expected) => report.fail(
+                &check,
+                format!(
+                    "serves {}, not {}",
+                    cluster.unwrap_or("an unknown cluster"),
+                    expected
+                ),
+                "point `rpc` and `rpc_endpoints` at nodes of the cluster the profile is for",
+            ),
+            _ => report.pass(
+                &check,
+                format!(
+                    "version {}, {} (genesis hash {})",
+                    version,
+                    cluster.unwrap_or("unknown cluster"),
+                    genesis_hash
+                ),
+            ),
+        }
+    }
+}
+
+/// Returns the public cluster the exporter is meant to monitor: the one its profile is named
+/// after, or else the one named in its RPC URL, if any.
+fn expected_cluster(profile: Option<&str>, rpc_url: &str) -> Option<&'static str> {
+    let clusters = CLUSTER_GENESIS_HASHES.iter().map(|(cluster, _)| *cluster);
+    if let Some(cluster) = clusters.clone().find(|&c| profile == Some(c)) {
+        return Some(cluster);
+    }
+    let url = rpc_url.to_lowercase();
+    clusters
+        .into_iter()
+        .find(|cluster| url.contains(cluster.split('-').next().unwrap_or(cluster)))
+}
+
+/// Returns the public cluster with `genesis_hash`, if any.
+fn cluster_of(genesis_hash: &str) -> Option<&'static str> {
+    CLUSTER_GENESIS_HASHES
+        .iter()
+        .find(|(_, hash)| *hash == genesis_hash)
+        .map(|(cluster, _)| *cluster)
+}
+
+/// Checks that the whitelists can be read and only hold valid pubkeys.
+fn check_whitelists(report: &mut Report, config: &ExporterConfig, config_dir: &Path) {
+    let whitelists = [
+        (
+            "vote account whitelist",
+            config.vote_account_whitelist.clone(),
+        ),
+        (
+            "staking account whitelist",
+            config.staking_account_whitelist.clone(),
+        ),
+    ];
+    for (name, source) in whitelists {
+        match ReloadableWhitelist::new(name, source, config_dir) {
+            Ok(whitelist) => {
+                let pubkeys: Vec<&str> = whitelist.get().0.iter().map(String::as_str).collect();
+                check_pubkeys(report, name, &pubkeys);
+            }
+            Err(e) => report.fail(
+                name,
+                format!("{:#}", e),
+                "check the path of the whitelist file, relative to the config directory",
+            ),
+        }
+    }
+    if let Some(identity) = &config.identity {
+        check_pubkeys(report, "identity", &[identity]);
+    }
+}
+
+/// Checks that `pubkeys` of the list `name` are all valid.
+fn check_pubkeys(report: &mut Report, name: &str, pubkeys: &[&str]) {
+    let invalid = invalid_pubkeys(pubkeys);
+    if invalid.is_empty() {
+        report.pass(name, format!("{} valid pubkeys", pubkeys.len()));
+    } else {
+        report.fail(
+            name,
+            format!("invalid pubkeys: {}", invalid.join(", ")),
+            "pubkeys are base58, e.g. the output of `solana-keygen pubkey`",
+        );
+    }
+}
+
+/// Returns the entries of `pubkeys` that are not valid pubkeys, sorted.
+fn invalid_pubkeys<'a>(pubkeys: &[&'a str]) -> Vec<&'a str> {
+    let mut invalid: Vec<_> = pubkeys
+        .iter()
+        .copied()
+        .filter(|pubkey| Pubkey::from_str(pubkey).is_err())
+        .collect();
+    invalid.sort_unstable();
+    invalid
+}
+
+/// Checks that MaxMind accepts the credentials, with a single lookup.
+async fn check_maxmind(report: &mut Report, maxmind: Option<&MaxMindAPIKey>, proxy: Option<&str>) {
+    const CHECK: &str = "maxmind";
+    let maxmind = match maxmind {
+        Some(maxmind) => maxmind,
+        None => {
+            report.skip(CHECK, "not configured, IP addresses are not geolocated");
+            return;
+        }
+    };
+    let client = match http::client_builder(proxy).and_then(|b| Ok(b.timeout(TIMEOUT).build()?)) {
+        Ok(client) => client,
+        Err(e) => {
+            report.fail(CHECK, format!("{:#}", e), "check `proxy` in the config");
+            return;
+        }
+    };
+    match api::lookup(&client, maxmind, MAXMIND_PROBE_IP).await {
+        Ok(_) => report.pass(
+            CHECK,
+            format!("credentials of {} accepted", maxmind.username()),
+        ),
+        Err(e) => report.fail(
+            CHECK,
+            format!("lookup failed: {:#}", e),
+            "check the account ID and license key in `[maxmind]`, and that the account has \
+             queries left for its service",
+        ),
+    }
+}
+
+/// Checks that the listen addresses can be bound.
+fn check_ports(report: &mut Report, targets: &Targets) {
+    for addr in &targets.0 {
+        let check = format!("listen {}", addr);
+        match TcpListener::bind(addr) {
+            Ok(_) => report.pass(&check, "free"),
+            Err(e) => report.fail(
+                &check,
+                e,
+                "stop the process listening on it, e.g. another exporter, or change `target`; \
+                 ports below 1024 need privileges",
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infers_expected_cluster() {
+        assert_eq!(
+            expected_cluster(Some("testnet"), "http://10.0.0.1:8899"),
+            Some("testnet")
+        );
+        assert_eq!(
+            expected_cluster(None, "https://api.mainnet-beta.solana.com"),
+            Some("mainnet-beta")
+        );
+        assert_eq!(
+            expected_cluster(
+                Some("validator"),
+                "https://devnet.helius-rpc.com/?api-key=x"
+            ),
+            Some("devnet")
+        );
+        assert_eq!(expected_cluster(None, "http://localhost:8899"), None);
+        assert_eq!(
+            cluster_of("5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d"),
+            Some("mainnet-beta")
+        );
+    }
+
+    #[test]
+    fn finds_invalid_pubkeys() {
+        assert_eq!(
+            invalid_pubkeys(&[
+                "13DmkMhdpmJJu7nU2ozAyPiKuopZbYShMHV3JAA7YVYC",
+                "replace_me",
+                "0OIl",
+            ]),
+            vec!["0OIl", "replace_me"]
+        );
+    }
+
+    #[test]
+    fn checks_database_location() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut report = Report::default();
+
+        // Not created yet, in a writable directory.
+        check_database(&mut report, &dir.path().join("db"));
+        assert_eq!(report.failures, 0);
+
+        fs::create_dir(dir.path().join("db")).unwrap();
+        fs::write(dir.path().join("db").join("conf"), [0u8; 1024]).unwrap();
+        check_database(&mut report, &dir.path().join("db"));
+        assert_eq!(report.failures, 0);
+        assert_eq!(dir_size(&dir.path().join("db")).unwrap(), 1024);
+
+        fs::write(dir.path().join("file"), b"").unwrap();
+        check_database(&mut report, &dir.path().join("file"));
+        assert_eq!(report.failures, 1);
+    }
+
+    #[test]
+    fn checks_ports_are_free() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let taken = listener.local_addr().unwrap();
+        let mut report = Report::default();
+
+        check_ports(&mut report, &Targets(vec![taken]));
+        assert_eq!(report.failures, 1);
+
+        drop(listener);
+        check_ports(&mut report, &Targets(vec![taken]));
+        assert_eq!(report.failures, 1);
+    }
+}
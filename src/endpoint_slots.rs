@@ -0,0 +1,184 @@
+//! Comparison of the slot heights of the configured RPC endpoints.
+
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::rpc_api::RpcApi;
+use async_trait::async_trait;
+use log::warn;
+use prometheus_exporter::prometheus::IntGaugeVec;
+use solana_clock::Slot;
+use solana_commitment_config::CommitmentConfig;
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Time between two comparisons.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The monitor of the slot height of every RPC endpoint at `processed` and `finalized`
+/// commitment, and its lag behind the highest one.
+///
+/// An endpoint falling behind the others, or finalizing slots the others do not, is lagging or
+/// on a fork, and would skew the metrics of the calls it serves. Endpoints are labelled by host
+/// only, since the path or query of an RPC URL often holds an API token. The series of an
+/// endpoint that fails to answer are removed until it answers again.
+pub struct EndpointSlotsMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Prometheus gauge.
+    slot: &'a IntGaugeVec,
+    /// Prometheus gauge.
+    slot_lag: &'a IntGaugeVec,
+}
+
+impl<'a> EndpointSlotsMonitor<'a> {
+    /// Constructs a monitor of the endpoints of `rpc`.
+    pub fn new(rpc: &'a dyn RpcApi, slot: &'a IntGaugeVec, slot_lag: &'a IntGaugeVec) -> Self {
+        Self {
+            rpc,
+            slot,
+            slot_lag,
+        }
+    }
+
+    /// Exports the slots of the endpoints at `commitment`, labelled `name`.
+    async fn export_slots(&self, commitment: CommitmentConfig, name: &str) -> anyhow::Result<()> {
+        let slots = self.rpc.get_endpoint_slots(commitment).await;
+        let labels = endpoint_labels(slots.iter().map(|(url, _)| url.as_str()));
+        let max = slots
+            .iter()
+            .filter_map(|(_, slot)| slot.as_ref().ok())
+            .max()
+            .copied();
+        for ((_, slot), endpoint) in slots.iter().zip(&labels) {
+            match (slot, max) {
+                (Ok(slot), Some(max)) => {
+                    self.slot
+                        .get_metric_with_label_values(&[endpoint, name])
+                        .map(|c| c.set(*slot as i64))?;
+                    self.slot_lag
+                        .get_metric_with_label_values(&[endpoint, name])
+                        .map(|c| c.set(lag(*slot, max)))?;
+                }
+                (result, _) => {
+                    if let Err(e) = result {
+                        warn!("Could not get the {} slot of {}: {e:#}", name, endpoint);
+                    }
+                    let _ = self.slot.remove_label_values(&[endpoint, name]);
+                    let _ = self.slot_lag.remove_label_values(&[endpoint, name]);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Monitor for EndpointSlotsMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "RPC endpoint slots"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![family_name(self.slot), family_name(self.slot_lag)]
+    }
+
+    fn interval(&self) -> Duration {
+        REFRESH_INTERVAL
+    }
+
+    async fn collect(&mut self, _cycle: &Cycle) -> anyhow::Result<()> {
+        futures::try_join!(
+            self.export_slots(CommitmentConfig::processed(), "processed"),
+            self.export_slots(CommitmentConfig::finalized(), "finalized"),
+        )?;
+        Ok(())
+    }
+}
+
+/// Returns the number of slots `slot` is behind `max`.
+fn lag(slot: Slot, max: Slot) -> i64 {
+    max.saturating_sub(slot) as i64
+}
+
+/// Returns the host and port of every URL of `urls`, suffixed with its position if an earlier
+/// URL has the same host, e.g. `rpc.example.com#2`.
+pub fn endpoint_labels<'a>(urls: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    urls.into_iter()
+        .enumerate()
+        .map(|(i, url)| {
+            let host = reqwest::Url::parse(url)
+                .ok()
+                .and_then(|url| {
+                    let host = url.host_str()?.to_string();
+                    Some(match url.port() {
+                        Some(port) => format!("{}:{}", host, port),
+                        None => host,
+                    })
+                })
+                .unwrap_or_else(|| format!("endpoint{}", i));
+            if seen.insert(host.clone()) {
+                host
+            } else {
+                format!("{}#{}", host, i)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_api::mock::MockRpc;
+    use prometheus_exporter::prometheus::core::Collector;
+    use prometheus_exporter::prometheus::Opts;
+
+    #[tokio::test]
+    async fn exports_slot_lags_by_host() {
+        let rpc = MockRpc {
+            endpoint_slots: vec![
+                ("http://localhost:8899".to_string(), Some(1_000)),
+                (
+                    "https://rpc.example.com/token/secret".to_string(),
+                    Some(990),
+                ),
+                (
+                    "https://rpc.example.com/?api-key=secret".to_string(),
+                    Some(1_002),
+                ),
+                ("https://down.example.com".to_string(), None),
+            ],
+            ..MockRpc::default()
+        };
+        let labels = &["endpoint", "commitment"];
+        let slot = IntGaugeVec::new(Opts::new("slot", "Slot"), labels).unwrap();
+        let slot_lag = IntGaugeVec::new(Opts::new("slot_lag", "Slot lag"), labels).unwrap();
+        slot_lag
+            .with_label_values(&["down.example.com", "processed"])
+            .set(5);
+        let monitor = EndpointSlotsMonitor::new(&rpc, &slot, &slot_lag);
+        monitor
+            .export_slots(CommitmentConfig::processed(), "processed")
+            .await
+            .unwrap();
+
+        let lag_of = |endpoint| slot_lag.with_label_values(&[endpoint, "processed"]).get();
+        assert_eq!(lag_of("localhost:8899"), 2);
+        assert_eq!(lag_of("rpc.example.com"), 12);
+        assert_eq!(lag_of("rpc.example.com#2"), 0);
+        assert_eq!(
+            slot.with_label_values(&["rpc.example.com#2", "processed"])
+                .get(),
+            1_002
+        );
+        // The failed endpoint's stale series was removed, and no label leaks the tokens.
+        let series: Vec<_> = slot_lag
+            .collect()
+            .remove(0)
+            .take_metric()
+            .into_iter()
+            .map(|m| m.get_label()[1].get_value().to_string())
+            .collect();
+        assert_eq!(series.len(), 3);
+        assert!(series.iter().all(|endpoint| !endpoint.contains("secret")));
+    }
+}
@@ -0,0 +1,190 @@
+//! Detection of optimistically confirmed slots that fail to finalize.
+
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::persistent_database::counters::CounterBaselines;
+use crate::rpc_api::RpcApi;
+use async_trait::async_trait;
+use log::warn;
+use prometheus_exporter::prometheus::IntCounterVec;
+use solana_clock::Slot;
+use solana_commitment_config::CommitmentConfig;
+use solana_epoch_info::EpochInfo;
+use std::collections::BTreeSet;
+
+/// Maximum number of slots below the finalized slot that confirmed blocks are fetched from, e.g.
+/// after collection was suspended.
+const MAX_LOOKBACK: Slot = 10_000;
+
+/// The monitor of rolled-back slots and forks.
+///
+/// Every cycle, the blocks confirmed since the previous cycle are fetched at `confirmed`
+/// commitment, and kept until the finalized slot passes them. Those that are then missing from
+/// the finalized blocks were rolled back, and every run of rolled-back slots without a finalized
+/// block in between is counted as a fork. Both queries go to the same RPC node.
+pub struct ForksMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Prometheus rolled-back slot counter.
+    rolled_back_slots: &'a IntCounterVec,
+    /// Prometheus fork counter.
+    forks: &'a IntCounterVec,
+    /// Persisted counter totals.
+    baselines: &'a CounterBaselines,
+    /// Confirmed blocks above the finalized slot of the previous cycle.
+    pending: BTreeSet<Slot>,
+    /// The highest slot confirmed blocks have been fetched up to.
+    last_confirmed: Option<Slot>,
+}
+
+impl<'a> ForksMonitor<'a> {
+    /// Constructs a monitor given `rpc`.
+    pub fn new(
+        rpc: &'a dyn RpcApi,
+        rolled_back_slots: &'a IntCounterVec,
+        forks: &'a IntCounterVec,
+        baselines: &'a CounterBaselines,
+    ) -> Self {
+        Self {
+            rpc,
+            rolled_back_slots,
+            forks,
+            baselines,
+            pending: BTreeSet::new(),
+            last_confirmed: None,
+        }
+    }
+
+    /// Fetches the newly confirmed blocks, and counts the rolled-back slots and forks among the
+    /// pending blocks at or below the finalized slot of `epoch_info`.
+    pub async fn export_forks(&mut self, epoch_info: &EpochInfo) -> anyhow::Result<()> {
+        let finalized_slot = epoch_info.absolute_slot;
+        let start = self
+            .last_confirmed
+            .map_or(finalized_slot + 1, |slot| slot + 1)
+            .max(finalized_slot.saturating_sub(MAX_LOOKBACK));
+        let confirmed = self
+            .rpc
+            .get_blocks_with_commitment(start, None, CommitmentConfig::confirmed())
+            .await?;
+        if let Some(last) = confirmed.last() {
+            self.last_confirmed = Some(*last);
+        }
+        self.pending.extend(confirmed);
+
+        let first = match self.pending.first() {
+            Some(first) if *first <= finalized_slot => *first,
+            _ => return Ok(()),
+        };
+        let finalized: BTreeSet<Slot> = self
+            .rpc
+            .get_blocks(first, Some(finalized_slot))
+            .await?
+            .into_iter()
+            .collect();
+        let unsettled = self.pending.split_off(&(finalized_slot + 1));
+        let settled = std::mem::replace(&mut self.pending, unsettled);
+
+        let (rolled_back, forks) = rolled_back_slots(&settled, &finalized);
+        if !rolled_back.is_empty() {
+            warn!(
+                "Confirmed slots {:?} were rolled back in {} fork(s)",
+                rolled_back, forks
+            );
+        }
+        self.baselines
+            .inc_by(self.rolled_back_slots, &[], rolled_back.len() as u64)?;
+        self.baselines.inc_by(self.forks, &[], forks)
+    }
+}
+
+#[async_trait]
+impl Monitor for ForksMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "forks"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![family_name(self.rolled_back_slots), family_name(self.forks)]
+    }
+
+    /// Restores the counter totals.
+    async fn setup(&mut self) -> anyhow::Result<()> {
+        self.baselines.restore(self.rolled_back_slots)?;
+        self.baselines.restore(self.forks)
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.export_forks(&cycle.epoch_info).await
+    }
+}
+
+/// Returns the `confirmed` slots missing from `finalized`, and the number of runs of them that
+/// no finalized slot separates.
+fn rolled_back_slots(confirmed: &BTreeSet<Slot>, finalized: &BTreeSet<Slot>) -> (Vec<Slot>, u64) {
+    let mut rolled_back = Vec::new();
+    let mut forks = 0;
+    let mut in_fork = false;
+    for slot in confirmed {
+        if finalized.contains(slot) {
+            in_fork = false;
+        } else {
+            rolled_back.push(*slot);
+            forks += u64::from(!in_fork);
+            in_fork = true;
+        }
+    }
+    (rolled_back, forks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_api::mock::MockRpc;
+    use prometheus_exporter::prometheus::Opts;
+    use solana_transaction_status_client_types::Rewards;
+
+    #[tokio::test]
+    async fn counts_confirmed_slots_that_do_not_finalize() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let baselines = CounterBaselines::new(db.open_tree("counters").unwrap());
+        let rolled_back = IntCounterVec::new(Opts::new("rolled_back", "Rolled back"), &[]).unwrap();
+        let forks = IntCounterVec::new(Opts::new("forks", "Forks"), &[]).unwrap();
+        let epoch_info = |absolute_slot| EpochInfo {
+            epoch: 0,
+            slot_index: absolute_slot,
+            slots_in_epoch: 432_000,
+            absolute_slot,
+            block_height: 0,
+            transaction_count: None,
+        };
+
+        let mut rpc = MockRpc::default();
+        for slot in [100, 101, 103] {
+            rpc.add_block(slot, None, Rewards::new());
+        }
+        rpc.confirmed_blocks = BTreeSet::from([102, 104, 105, 107]);
+        let mut monitor = ForksMonitor::new(&rpc, &rolled_back, &forks, &baselines);
+        let counts = || {
+            (
+                rolled_back.with_label_values(&[]).get(),
+                forks.with_label_values(&[]).get(),
+            )
+        };
+
+        // Only blocks above the finalized slot are followed from the first cycle on.
+        monitor.export_forks(&epoch_info(106)).await.unwrap();
+        assert_eq!(monitor.pending, BTreeSet::from([107]));
+        assert_eq!(counts(), (0, 0));
+
+        // Fetch again from slot 100, as after the blocks from 100 to 107 were confirmed.
+        monitor.last_confirmed = Some(99);
+        monitor.export_forks(&epoch_info(105)).await.unwrap();
+        // 102 and 104-105 were rolled back, on either side of the finalized 103.
+        assert_eq!(counts(), (3, 2));
+        assert_eq!(monitor.pending, BTreeSet::from([107]));
+
+        monitor.export_forks(&epoch_info(107)).await.unwrap();
+        assert_eq!(counts(), (4, 3));
+        assert!(monitor.pending.is_empty());
+    }
+}
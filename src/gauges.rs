@@ -1,26 +1,33 @@
-use crate::config::Whitelist;
-use crate::geolocation::api::MaxMindAPIKey;
-use crate::geolocation::api::MAXMIND_CITY_URI;
-use crate::geolocation::caching::GeolocationCache;
-use crate::geolocation::get_rpc_contact_ip;
+use crate::config::{AddressWatchlist, BalanceUnit, Whitelist};
+use crate::geolocation::api::{self, MaxMindAPIKey};
+use crate::geolocation::caching::{GeoInfo, GeolocationCache};
 use crate::geolocation::identifier::DatacenterIdentifier;
-use crate::rpc_extra::first_block_in_epoch;
-use crate::rpc_extra::GossipNode;
-use anyhow::{anyhow, Context};
-use futures::TryFutureExt;
+use crate::geolocation::{
+    get_gossip_contact_ip, get_rpc_contact_ip, is_private_ip, PRIVATE_LOCATION,
+};
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::persistent_database::counters::CounterBaselines;
+use crate::reverse_dns::ReverseDns;
+use crate::rpc_api::RpcApi;
+use crate::rpc_extra::{average_slot_time_since, epoch_start_time};
+use crate::rpc_extra::{parse_gossip_nodes, GossipNode};
+use crate::rpc_extra::{LAMPORTS_PER_SOL, MAX_MULTIPLE_ACCOUNTS};
+use crate::{SOLANA_EXPORTER_GIT_COMMIT, SOLANA_EXPORTER_RUSTC_VERSION, SOLANA_EXPORTER_VERSION};
+use anyhow::Context;
+use async_trait::async_trait;
+use futures::{StreamExt, TryFutureExt};
 use geoip2_city::CityApiResponse;
 use log::{debug, error};
 use prometheus_exporter::prometheus::{
-    register_gauge, register_gauge_vec, register_int_counter_vec, register_int_gauge,
-    register_int_gauge_vec, Gauge, GaugeVec, IntCounterVec, IntGauge, IntGaugeVec,
+    register_gauge, register_gauge_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, register_int_gauge_vec, Gauge, GaugeVec, IntCounter, IntCounterVec,
+    IntGauge, IntGaugeVec,
 };
-use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_client::rpc_config::RpcBlockConfig;
 use solana_client::rpc_response::{RpcContactInfo, RpcVoteAccountInfo, RpcVoteAccountStatus};
 use solana_epoch_info::EpochInfo;
-use solana_transaction_status_client_types::{TransactionDetails, UiTransactionEncoding};
-use std::collections::HashMap;
-use time::{Duration, OffsetDateTime};
+use solana_pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 
 /// Label used for the status value
 pub const STATUS_LABEL: &str = "status";
@@ -30,6 +37,82 @@ pub const PUBKEY_LABEL: &str = "pubkey";
 pub const IDENTITY_LABEL: &str = "identity";
 /// Label used for peoch
 pub const EPOCH_LABEL: &str = "epoch";
+/// Label used for the percentile of a distribution
+pub const PERCENTILE_LABEL: &str = "percentile";
+/// Label used for a stake pool address
+pub const POOL_LABEL: &str = "pool";
+/// Label used for a user-given name
+pub const NAME_LABEL: &str = "name";
+/// Label used for the length of a rolling time window
+pub const WINDOW_LABEL: &str = "window";
+/// Label used for the kind of authority of a vote account
+pub const AUTHORITY_LABEL: &str = "authority";
+/// Label used for a third-party validator ranking service
+pub const PROVIDER_LABEL: &str = "provider";
+/// Label used for the vote account a stake is delegated to
+pub const VOTER_LABEL: &str = "voter";
+/// Label used for the vote account a stake was delegated to before a redelegation
+pub const PREVIOUS_VOTER_LABEL: &str = "previous_voter";
+/// Label used for the kind of operation on a stake account
+pub const OPERATION_LABEL: &str = "operation";
+/// Label used for the reason a value is an outlier
+pub const REASON_LABEL: &str = "reason";
+/// Label used for the custodian of a stake account lockup
+pub const CUSTODIAN_LABEL: &str = "custodian";
+/// Label used for the MaxMind user type of an IP address, empty unless geolocated with Insights
+pub const USER_TYPE_LABEL: &str = "user_type";
+/// Label used for the name of a metric family
+pub const METRIC_LABEL: &str = "metric";
+/// Label used for the name of a tree of the persistent database
+pub const TREE_LABEL: &str = "tree";
+/// Label used for the client implementation of a validator
+pub const CLIENT_LABEL: &str = "client";
+
+/// Maximum number of addresses geolocated at once for the TPU ASNs.
+const TPU_GEOLOCATION_CONCURRENCY: usize = 8;
+
+/// Labels of series that are per validator, node or IP address, which are not exported in
+/// aggregates-only mode.
+pub const PER_NODE_LABELS: &[&str] = &[
+    PUBKEY_LABEL,
+    IDENTITY_LABEL,
+    "vote_key",
+    "gossip_ip",
+    "tvu_ip",
+    "tpu_ip",
+];
+
+/// Labels that deployments can leave out of the series of a metric family with `metric_labels`,
+/// which add detail rather than identify the validator, node or status a series is about.
+pub const OPTIONAL_LABELS: &[&str] = &[
+    NAME_LABEL,
+    "version",
+    "gossip_ip",
+    "tvu_ip",
+    "tpu_ip",
+    "isp_name",
+    USER_TYPE_LABEL,
+    "dc_identifier",
+    "hostname",
+];
+
+/// Registers `solana_exporter_build_info`, a constant 1 labelled with the version, git commit and
+/// compiler of this build, so that mixed exporter versions across a fleet can be detected.
+pub fn register_build_info() -> anyhow::Result<()> {
+    let build_info = register_int_gauge_vec!(
+        "solana_exporter_build_info",
+        "Version, git commit and compiler version of the running solana-exporter",
+        &["version", "commit", "rustc"]
+    )?;
+    build_info
+        .with_label_values(&[
+            SOLANA_EXPORTER_VERSION,
+            SOLANA_EXPORTER_GIT_COMMIT,
+            SOLANA_EXPORTER_RUSTC_VERSION,
+        ])
+        .set(1);
+    Ok(())
+}
 
 /// Extracts the bare IP from an optional `ip:port` socket address string,
 /// returning an empty string when absent. Dashboards join on bare source IPs
@@ -51,42 +134,181 @@ fn ip_of(socket_addr: &Option<String>) -> String {
     }
 }
 
+/// Ranks the current and delinquent vote accounts by activated stake, 1 being the largest.
+/// Vote accounts with equal stake share a rank.
+fn stake_ranks(vote_accounts: &RpcVoteAccountStatus) -> HashMap<&str, usize> {
+    ranks(
+        vote_accounts
+            .current
+            .iter()
+            .chain(vote_accounts.delinquent.iter())
+            .map(|v| (v.vote_pubkey.as_str(), v.activated_stake))
+            .collect(),
+    )
+}
+
+/// Ranks the current and delinquent vote accounts by the credits earned in the latest epoch any
+/// of them earned credits in, 1 being the most. Vote accounts with equal credits share a rank.
+fn credit_ranks(vote_accounts: &RpcVoteAccountStatus) -> HashMap<&str, usize> {
+    let accounts = || {
+        vote_accounts
+            .current
+            .iter()
+            .chain(vote_accounts.delinquent.iter())
+    };
+    let epoch = accounts()
+        .filter_map(|v| v.epoch_credits.last().map(|(epoch, _, _)| *epoch))
+        .max();
+    ranks(
+        accounts()
+            .map(|v| {
+                let credits = v
+                    .epoch_credits
+                    .iter()
+                    .find(|(e, _, _)| Some(*e) == epoch)
+                    .map_or(0, |(_, credits, previous)| {
+                        credits.saturating_sub(*previous)
+                    });
+                (v.vote_pubkey.as_str(), credits)
+            })
+            .collect(),
+    )
+}
+
+/// Ranks keys by descending value, 1 being the largest. Keys with equal values share a rank.
+fn ranks(values: Vec<(&str, u64)>) -> HashMap<&str, usize> {
+    let mut sorted: Vec<u64> = values.iter().map(|(_, value)| *value).collect();
+    sorted.sort_unstable_by(|a, b| b.cmp(a));
+    values
+        .into_iter()
+        .map(|(key, value)| (key, sorted.partition_point(|v| *v > value) + 1))
+        .collect()
+}
+
 pub struct PrometheusGauges {
     pub active_validators: IntGaugeVec,
     pub is_delinquent: GaugeVec,
     pub activated_stake: IntGaugeVec,
+    pub whitelist_activated_stake: IntGauge,
+    pub stake_rank: IntGaugeVec,
+    pub credit_rank: IntGaugeVec,
+    pub validator_score: GaugeVec,
+    pub validator_score_rank: IntGaugeVec,
     pub last_vote: IntGaugeVec,
+    pub seconds_since_last_vote: GaugeVec,
+    pub vote_credit_rate: GaugeVec,
     pub root_slot: IntGaugeVec,
     pub epoch_vote_account: IntGaugeVec,
     pub vote_credits: IntGaugeVec,
     pub identity: GaugeVec,
+    pub identity_changes: IntCounterVec,
+    pub vote_authority: IntGaugeVec,
+    pub vote_authority_changes: IntCounterVec,
+    pub authorized_voters: IntGaugeVec,
+    pub voter_rotation_epoch: IntGaugeVec,
     pub transaction_count: IntGauge,
     pub slot_height: IntGauge,
+    pub block_height: IntGauge,
     pub current_epoch: IntGauge,
     pub current_epoch_first_slot: IntGauge,
     pub current_epoch_last_slot: IntGauge,
+    pub epoch_start_timestamp: IntGauge,
+    pub epoch_end_timestamp: IntGauge,
     pub isp_count: IntGaugeVec,
     pub isp_by_stake: IntGaugeVec,
     pub dc_by_stake: IntGaugeVec,
+    pub dc_delinquent_count: IntGaugeVec,
+    pub dc_delinquent_stake: IntGaugeVec,
+    pub validator_asn: IntGaugeVec,
+    pub tpu_asn_mismatch: IntGaugeVec,
+    pub private_ip_validators: IntGauge,
     pub leader_slots: IntCounterVec,
+    pub rolled_back_slots: IntCounterVec,
+    pub forks: IntCounterVec,
     pub skipped_slot_percent: GaugeVec,
+    pub skipped_slot_percentile: GaugeVec,
+    pub skipped_slot_percent_vs_cluster: GaugeVec,
+    pub gossip_uptime: GaugeVec,
     pub current_staking_apy: GaugeVec,
+    pub projected_staking_apy: GaugeVec,
     pub average_staking_apy: GaugeVec,
+    pub current_staking_apr: GaugeVec,
+    pub average_staking_apr: GaugeVec,
+    pub expected_annual_staking_rewards: GaugeVec,
+    pub stake_lockup_seconds_remaining: IntGaugeVec,
+    pub stake_lockup_epochs_remaining: IntGaugeVec,
+    pub stake_delegation: IntGaugeVec,
+    pub stake_redelegations: IntCounterVec,
+    pub stake_last_redelegation: IntGaugeVec,
     pub staking_commission: IntGaugeVec,
-    pub validator_rewards: IntGaugeVec,
+    pub commission_rugs: IntCounterVec,
+    pub delegators: IntGaugeVec,
+    pub delegated_lamports: IntCounterVec,
+    pub undelegated_lamports: IntCounterVec,
+    pub delegated_stake: IntGaugeVec,
+    pub stake_operations: IntCounterVec,
+    pub stake_operation_lamports: IntCounterVec,
+    pub uninspected_stake_accounts: IntCounterVec,
+    pub validator_rewards: IntCounterVec,
+    pub whitelist_validator_rewards: IntGauge,
+    pub whitelist_current_staking_apy: Gauge,
+    pub whitelist_average_staking_apy: Gauge,
+    pub cluster_staking_apy: GaugeVec,
+    pub cluster_staking_apy_voters: IntGaugeVec,
+    pub staking_apy_outliers: IntCounterVec,
+    pub stake_account_errors: IntCounterVec,
     pub node_pubkey_balances: IntGaugeVec,
+    pub node_pubkey_balances_sol: GaugeVec,
+    pub address_balances: IntGaugeVec,
+    pub address_balances_sol: GaugeVec,
+    pub sol_price: Gauge,
+    pub sol_price_timestamp: IntGauge,
+    pub node_pubkey_balances_usd: GaugeVec,
+    pub address_balances_usd: GaugeVec,
+    pub whitelist_validator_rewards_usd: Gauge,
+    pub expected_annual_staking_rewards_usd: GaugeVec,
+    pub address_transactions: IntCounterVec,
+    pub address_failed_transactions: IntCounterVec,
     pub node_versions: IntGaugeVec,
+    pub largest_accounts_supply_share: Gauge,
+    pub largest_accounts_above_threshold: IntGaugeVec,
+    pub rpc_endpoint_slot: IntGaugeVec,
+    pub rpc_endpoint_slot_lag: IntGaugeVec,
+    pub ingestion_slot: IntGaugeVec,
+    pub ingestion_gap: IntGaugeVec,
+    pub vote_fees: IntGaugeVec,
+    pub block_compute_units_average: GaugeVec,
+    pub block_compute_units_percentile: GaugeVec,
+    pub block_transactions_average: GaugeVec,
+    pub stake_pool_total_lamports: IntGaugeVec,
+    pub stake_pool_token_supply: IntGaugeVec,
+    pub stake_pool_exchange_rate: GaugeVec,
+    pub stake_pool_validator_count: IntGaugeVec,
+    pub stake_pool_validator_active_stake: IntGaugeVec,
+    pub stake_pool_validator_transient_stake: IntGaugeVec,
     pub nodes: IntGauge,
     pub average_slot_time: Gauge,
     pub gossip_node_info: IntGaugeVec,
+    pub client_nodes: IntGaugeVec,
+    pub client_stake: IntGaugeVec,
+    pub validator_client: IntGaugeVec,
+    pub snapshot_saved_at: IntGaugeVec,
+    pub database_size: IntGauge,
+    pub database_entries: IntGaugeVec,
+    pub database_flushed_bytes: IntCounter,
+    pub database_flush_failures: IntCounter,
+    pub database_flush_duration: Gauge,
+    pub database_last_flush: IntGauge,
     // Connection pool for querying
     client: reqwest::Client,
-    vote_accounts_whitelist: Whitelist,
+    /// Unit the balances are exported in.
+    balance_unit: BalanceUnit,
 }
 
 impl PrometheusGauges {
-    /// Makes new set of gauges.
-    pub fn new(vote_accounts_whitelist: Whitelist) -> Self {
+    /// Makes new set of gauges. `client` is used for MaxMind queries. Balances are exported in
+    /// `balance_unit`.
+    pub fn new(client: reqwest::Client, balance_unit: BalanceUnit) -> Self {
         Self {
             active_validators: register_int_gauge_vec!(
                 "solana_active_validators",
@@ -106,12 +328,55 @@ impl PrometheusGauges {
                 &[PUBKEY_LABEL]
             )
             .unwrap(),
+            whitelist_activated_stake: register_int_gauge!(
+                "solana_whitelist_activated_stake",
+                "Total activated stake of the whitelisted validators"
+            )
+            .unwrap(),
+            stake_rank: register_int_gauge_vec!(
+                "solana_validator_stake_rank",
+                "Rank of a validator by activated stake among all validators, 1 being the largest",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            credit_rank: register_int_gauge_vec!(
+                "solana_validator_credit_rank",
+                "Rank of a validator by vote credits earned in the current epoch so far among all \
+                 validators, 1 being the most",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            validator_score: register_gauge_vec!(
+                "solana_validator_score",
+                "Score of a validator at a third-party ranking service",
+                &[PUBKEY_LABEL, PROVIDER_LABEL]
+            )
+            .unwrap(),
+            validator_score_rank: register_int_gauge_vec!(
+                "solana_validator_score_rank",
+                "Rank of a validator by score at a third-party ranking service, 1 being the best",
+                &[PUBKEY_LABEL, PROVIDER_LABEL]
+            )
+            .unwrap(),
             last_vote: register_int_gauge_vec!(
                 "solana_validator_last_vote",
                 "Last voted slot of a validator",
                 &[PUBKEY_LABEL]
             )
             .unwrap(),
+            seconds_since_last_vote: register_gauge_vec!(
+                "solana_validator_seconds_since_last_vote",
+                "Seconds since the last voted slot of a validator, dated from the observed slot \
+                 times",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            vote_credit_rate: register_gauge_vec!(
+                "solana_validator_vote_credit_rate",
+                "Vote credits earned per minute by a validator over the last five minutes",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
             root_slot: register_int_gauge_vec!(
                 "solana_validator_root_slot",
                 "The root slot of a validator",
@@ -124,6 +389,39 @@ impl PrometheusGauges {
                 &[PUBKEY_LABEL, IDENTITY_LABEL]
             )
             .unwrap(),
+            identity_changes: register_int_counter_vec!(
+                "solana_validator_identity_changes",
+                "Number of times the identity behind a validator's vote account changed",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            vote_authority: register_int_gauge_vec!(
+                "solana_validator_vote_authority",
+                "The authorized voter and withdrawer of a validator's vote account",
+                &[PUBKEY_LABEL, AUTHORITY_LABEL, "authority_pubkey"]
+            )
+            .unwrap(),
+            vote_authority_changes: register_int_counter_vec!(
+                "solana_validator_vote_authority_changes",
+                "Number of times the authorized voter or withdrawer of a validator's vote account \
+                 changed",
+                &[PUBKEY_LABEL, AUTHORITY_LABEL]
+            )
+            .unwrap(),
+            authorized_voters: register_int_gauge_vec!(
+                "solana_validator_authorized_voters",
+                "Number of authorized voters of a validator's vote account, for the current and \
+                 later epochs",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            voter_rotation_epoch: register_int_gauge_vec!(
+                "solana_validator_voter_rotation_epoch",
+                "The next epoch a new authorized voter of a validator's vote account is scheduled \
+                 for",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
             vote_credits: register_int_gauge_vec!(
                 "solana_vote_credits",
                 "Vote credits per validator",
@@ -143,6 +441,11 @@ impl PrometheusGauges {
             .unwrap(),
             slot_height: register_int_gauge!("solana_slot_height", "Last confirmed slot height")
                 .unwrap(),
+            block_height: register_int_gauge!(
+                "solana_block_height",
+                "Number of blocks produced since genesis, up to the last confirmed slot"
+            )
+            .unwrap(),
             current_epoch: register_int_gauge!("solana_current_epoch", "Current epoch").unwrap(),
             current_epoch_first_slot: register_int_gauge!(
                 "solana_current_epoch_first_slot",
@@ -154,16 +457,27 @@ impl PrometheusGauges {
                 "Current epoch's last slot"
             )
             .unwrap(),
+            epoch_start_timestamp: register_int_gauge!(
+                "solana_epoch_start_timestamp_seconds",
+                "Unix time of the first block of the current epoch"
+            )
+            .unwrap(),
+            epoch_end_timestamp: register_int_gauge!(
+                "solana_epoch_end_timestamp_seconds",
+                "Projected Unix time of the end of the current epoch, at its average slot time so \
+                 far"
+            )
+            .unwrap(),
             isp_count: register_int_gauge_vec!(
                 "solana_active_validators_isp_count",
                 "ISP of active validators",
-                &["isp_name"]
+                &["isp_name", USER_TYPE_LABEL]
             )
             .unwrap(),
             isp_by_stake: register_int_gauge_vec!(
                 "solana_active_validators_isp_stake",
                 "ISP of active validators grouped by stake",
-                &["isp_name"]
+                &["isp_name", USER_TYPE_LABEL]
             )
             .unwrap(),
             dc_by_stake: register_int_gauge_vec!(
@@ -172,54 +486,425 @@ impl PrometheusGauges {
                 &["dc_identifier"]
             )
             .unwrap(),
+            dc_delinquent_count: register_int_gauge_vec!(
+                "solana_delinquent_validators_dc_count",
+                "Number of delinquent validators per datacenter",
+                &["dc_identifier"]
+            )
+            .unwrap(),
+            dc_delinquent_stake: register_int_gauge_vec!(
+                "solana_delinquent_validators_dc_stake",
+                "Datacenter of delinquent validators grouped by stake",
+                &["dc_identifier"]
+            )
+            .unwrap(),
+            validator_asn: register_int_gauge_vec!(
+                "solana_validator_asn",
+                "Autonomous system number of the gossip or TPU address of a validator",
+                &[PUBKEY_LABEL, "address"]
+            )
+            .unwrap(),
+            tpu_asn_mismatch: register_int_gauge_vec!(
+                "solana_validator_tpu_asn_mismatch",
+                "Whether the TPU and gossip addresses of a validator are in different ASNs",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            private_ip_validators: register_int_gauge!(
+                "solana_validators_private_ip_count",
+                "Number of validators at private or unroutable IP addresses, which are not \
+                 geolocated"
+            )
+            .unwrap(),
             leader_slots: register_int_counter_vec!(
                 "solana_leader_slots",
                 "Validated and skipped leader slots per validator",
                 &[PUBKEY_LABEL, STATUS_LABEL]
             )
             .unwrap(),
+            rolled_back_slots: register_int_counter_vec!(
+                "solana_rolled_back_slots",
+                "Number of confirmed slots that did not finalize",
+                &[]
+            )
+            .unwrap(),
+            forks: register_int_counter_vec!(
+                "solana_forks",
+                "Number of forks with confirmed slots that did not finalize",
+                &[]
+            )
+            .unwrap(),
             skipped_slot_percent: register_gauge_vec!(
                 "solana_skipped_slot_percent",
                 "Skipped slot percentage per validator",
                 &[PUBKEY_LABEL]
             )
             .unwrap(),
+            skipped_slot_percentile: register_gauge_vec!(
+                "solana_skipped_slot_percentile",
+                "Percentage of this epoch's leaders with a lower skip rate than a validator",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            skipped_slot_percent_vs_cluster: register_gauge_vec!(
+                "solana_skipped_slot_percent_vs_cluster",
+                "Skipped slot percentage of a validator minus the stake-weighted percentage of all \
+                 leaders this epoch",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            gossip_uptime: register_gauge_vec!(
+                "solana_validator_gossip_uptime_percent",
+                "Percentage of the time over a rolling window a validator was present in gossip",
+                &[PUBKEY_LABEL, WINDOW_LABEL]
+            )
+            .unwrap(),
             current_staking_apy: register_gauge_vec!(
                 "solana_current_staking_apy",
                 "Staking validator APY based on last epoch's performance, in percent",
                 &[PUBKEY_LABEL]
             )
             .unwrap(),
+            projected_staking_apy: register_gauge_vec!(
+                "solana_projected_staking_apy",
+                "Estimated staking validator APY of the current epoch, projected from the vote \
+                 credits earned so far before its rewards are paid, in percent",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
             average_staking_apy: register_gauge_vec!(
                 "solana_average_staking_apy",
                 "Staking validator APY averaged over a few past epochs, in percent",
                 &[PUBKEY_LABEL]
             )
             .unwrap(),
+            current_staking_apr: register_gauge_vec!(
+                "solana_current_staking_apr",
+                "Staking validator APR based on last epoch's performance, without compounding, in \
+                 percent",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            average_staking_apr: register_gauge_vec!(
+                "solana_average_staking_apr",
+                "Staking validator APR averaged over a few past epochs, without compounding, in \
+                 percent",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            expected_annual_staking_rewards: register_gauge_vec!(
+                "solana_expected_annual_staking_rewards_sol",
+                "Rewards a whitelisted stake account is expected to earn in a year at the current \
+                 staking APY of its voter, in SOL",
+                &[PUBKEY_LABEL, VOTER_LABEL]
+            )
+            .unwrap(),
+            stake_lockup_seconds_remaining: register_int_gauge_vec!(
+                "solana_stake_account_lockup_seconds_remaining",
+                "Seconds until the Unix timestamp of the lockup of a whitelisted stake account \
+                 passes",
+                &[PUBKEY_LABEL, CUSTODIAN_LABEL]
+            )
+            .unwrap(),
+            stake_lockup_epochs_remaining: register_int_gauge_vec!(
+                "solana_stake_account_lockup_epochs_remaining",
+                "Epochs until the epoch of the lockup of a whitelisted stake account passes",
+                &[PUBKEY_LABEL, CUSTODIAN_LABEL]
+            )
+            .unwrap(),
+            stake_delegation: register_int_gauge_vec!(
+                "solana_stake_account_delegation",
+                "Vote account a whitelisted stake account is delegated to, always 1",
+                &[PUBKEY_LABEL, VOTER_LABEL]
+            )
+            .unwrap(),
+            stake_redelegations: register_int_counter_vec!(
+                "solana_stake_account_redelegations",
+                "Changes of the vote account a whitelisted stake account is delegated to",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            stake_last_redelegation: register_int_gauge_vec!(
+                "solana_stake_account_last_redelegation",
+                "Unix time the last redelegation of a whitelisted stake account was observed at",
+                &[PUBKEY_LABEL, PREVIOUS_VOTER_LABEL, VOTER_LABEL]
+            )
+            .unwrap(),
             staking_commission: register_int_gauge_vec!(
                 "solana_staking_commission",
                 "Commission charged by staked validators",
                 &[PUBKEY_LABEL]
             )
             .unwrap(),
-            validator_rewards: register_int_gauge_vec!(
+            commission_rugs: register_int_counter_vec!(
+                "solana_validator_commission_rugs",
+                "Number of commission increases of a validator shortly before the end of an epoch",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            delegators: register_int_gauge_vec!(
+                "solana_validator_delegators",
+                "Number of active stake accounts delegated to a validator",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            delegated_lamports: register_int_counter_vec!(
+                "solana_validator_delegated_lamports",
+                "Cumulative lamports of stake accounts newly delegated to a validator between \
+                 epochs",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            undelegated_lamports: register_int_counter_vec!(
+                "solana_validator_undelegated_lamports",
+                "Cumulative lamports of stake accounts no longer delegated to a validator between \
+                 epochs",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            delegated_stake: register_int_gauge_vec!(
+                "solana_validator_delegated_stake",
+                "Lamports delegated to a validator that are active, activating or deactivating",
+                &[PUBKEY_LABEL, STATUS_LABEL]
+            )
+            .unwrap(),
+            stake_operations: register_int_counter_vec!(
+                "solana_validator_stake_operations",
+                "Stake operations on the stake accounts delegated to a whitelisted vote account",
+                &[PUBKEY_LABEL, OPERATION_LABEL]
+            )
+            .unwrap(),
+            stake_operation_lamports: register_int_counter_vec!(
+                "solana_validator_stake_operation_lamports",
+                "Lamports moved by stake operations on the stake accounts delegated to a \
+                 whitelisted vote account",
+                &[PUBKEY_LABEL, OPERATION_LABEL]
+            )
+            .unwrap(),
+            uninspected_stake_accounts: register_int_counter_vec!(
+                "solana_validator_uninspected_stake_accounts",
+                "Stake accounts delegated to a whitelisted vote account that appeared or \
+                 disappeared without being searched for stake operations",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            validator_rewards: register_int_counter_vec!(
                 "solana_validator_rewards",
                 "Cumulative validator rewards in lamports",
                 &[PUBKEY_LABEL]
             )
             .unwrap(),
+            whitelist_validator_rewards: register_int_gauge!(
+                "solana_whitelist_validator_rewards",
+                "Total rewards in lamports of the whitelisted validators for the last epoch"
+            )
+            .unwrap(),
+            whitelist_current_staking_apy: register_gauge!(
+                "solana_whitelist_current_staking_apy",
+                "Current staking APY of the whitelisted validators weighted by activated stake, \
+                 in percent"
+            )
+            .unwrap(),
+            whitelist_average_staking_apy: register_gauge!(
+                "solana_whitelist_average_staking_apy",
+                "Average staking APY of the whitelisted validators weighted by activated stake, \
+                 in percent"
+            )
+            .unwrap(),
+            cluster_staking_apy: register_gauge_vec!(
+                "solana_cluster_staking_apy",
+                "Mean and median current staking APY of all voters with a computed APY, in percent",
+                &["statistic"]
+            )
+            .unwrap(),
+            cluster_staking_apy_voters: register_int_gauge_vec!(
+                "solana_cluster_staking_apy_voters",
+                "Number of voters with a current staking APY of at most `le` percent",
+                &["le"]
+            )
+            .unwrap(),
+            staking_apy_outliers: register_int_counter_vec!(
+                "solana_staking_apy_outliers",
+                "Cumulative stake account APYs excluded or clamped as outliers, by reason",
+                &[REASON_LABEL]
+            )
+            .unwrap(),
+            stake_account_errors: register_int_counter_vec!(
+                "solana_stake_account_errors",
+                "Cumulative whitelisted stake accounts that were missing or could not be parsed \
+                 when calculating APYs, by reason",
+                &[REASON_LABEL]
+            )
+            .unwrap(),
             node_pubkey_balances: register_int_gauge_vec!(
                 "solana_node_pubkey_balances",
                 "Balance of node pubkeys",
                 &[PUBKEY_LABEL]
             )
             .unwrap(),
+            node_pubkey_balances_sol: register_gauge_vec!(
+                "solana_node_pubkey_balances_sol",
+                "Balance of node pubkeys in SOL",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            address_balances: register_int_gauge_vec!(
+                "solana_address_balance",
+                "Balance of watched addresses in lamports",
+                &[NAME_LABEL, PUBKEY_LABEL]
+            )
+            .unwrap(),
+            address_balances_sol: register_gauge_vec!(
+                "solana_address_balance_sol",
+                "Balance of watched addresses in SOL",
+                &[NAME_LABEL, PUBKEY_LABEL]
+            )
+            .unwrap(),
+            sol_price: register_gauge!("solana_sol_price_usd", "Price of SOL in USD").unwrap(),
+            sol_price_timestamp: register_int_gauge!(
+                "solana_sol_price_timestamp_seconds",
+                "Unix time the SOL price was published at by its source"
+            )
+            .unwrap(),
+            node_pubkey_balances_usd: register_gauge_vec!(
+                "solana_node_pubkey_balances_usd",
+                "Balance of node pubkeys in USD",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            address_balances_usd: register_gauge_vec!(
+                "solana_address_balance_usd",
+                "Balance of watched addresses in USD",
+                &[NAME_LABEL, PUBKEY_LABEL]
+            )
+            .unwrap(),
+            whitelist_validator_rewards_usd: register_gauge!(
+                "solana_whitelist_validator_rewards_usd",
+                "Total rewards of the whitelisted validators for the last epoch in USD at the \
+                 current price"
+            )
+            .unwrap(),
+            expected_annual_staking_rewards_usd: register_gauge_vec!(
+                "solana_expected_annual_staking_rewards_usd",
+                "Rewards a whitelisted stake account is expected to earn in a year at the current \
+                 staking APY of its voter, in USD at the current price",
+                &[PUBKEY_LABEL, VOTER_LABEL]
+            )
+            .unwrap(),
+            address_transactions: register_int_counter_vec!(
+                "solana_address_transactions",
+                "Transactions involving watched addresses",
+                &[NAME_LABEL, PUBKEY_LABEL]
+            )
+            .unwrap(),
+            address_failed_transactions: register_int_counter_vec!(
+                "solana_address_failed_transactions",
+                "Failed transactions involving watched addresses",
+                &[NAME_LABEL, PUBKEY_LABEL]
+            )
+            .unwrap(),
             node_versions: register_int_gauge_vec!(
                 "solana_node_versions",
                 "Count of node versions",
                 &["version"]
             )
             .unwrap(),
+            largest_accounts_supply_share: register_gauge!(
+                "solana_largest_accounts_supply_share",
+                "Percentage of the circulating supply held by the 20 largest accounts"
+            )
+            .unwrap(),
+            largest_accounts_above_threshold: register_int_gauge_vec!(
+                "solana_largest_accounts_above_threshold",
+                "Number of the 20 largest accounts holding more than `threshold` SOL",
+                &["threshold"]
+            )
+            .unwrap(),
+            rpc_endpoint_slot: register_int_gauge_vec!(
+                "solana_rpc_endpoint_slot",
+                "Slot height of a configured RPC endpoint at a commitment",
+                &["endpoint", "commitment"]
+            )
+            .unwrap(),
+            rpc_endpoint_slot_lag: register_int_gauge_vec!(
+                "solana_rpc_endpoint_slot_lag",
+                "Slots a configured RPC endpoint is behind the highest one at a commitment",
+                &["endpoint", "commitment"]
+            )
+            .unwrap(),
+            ingestion_slot: register_int_gauge_vec!(
+                "solana_node_ingestion_slot",
+                "Highest slot the RPC node retransmitted shreds of, inserted shreds of, or \
+                 processed",
+                &["stage"]
+            )
+            .unwrap(),
+            ingestion_gap: register_int_gauge_vec!(
+                "solana_node_ingestion_gap_slots",
+                "Slots a stage of ingestion of the RPC node is behind the stage before it",
+                &["stage"]
+            )
+            .unwrap(),
+            vote_fees: register_int_gauge_vec!(
+                "solana_vote_fees_lamports_per_epoch",
+                "Estimated lamports spent by node pubkeys on vote transaction fees this epoch",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            block_compute_units_average: register_gauge_vec!(
+                "solana_block_compute_units_average",
+                "Average compute units consumed by the blocks produced this epoch",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            block_compute_units_percentile: register_gauge_vec!(
+                "solana_block_compute_units_percentile",
+                "Percentiles of compute units consumed by the blocks produced this epoch",
+                &[PUBKEY_LABEL, PERCENTILE_LABEL]
+            )
+            .unwrap(),
+            block_transactions_average: register_gauge_vec!(
+                "solana_block_transactions_average",
+                "Average number of transactions in the blocks produced this epoch",
+                &[PUBKEY_LABEL]
+            )
+            .unwrap(),
+            stake_pool_total_lamports: register_int_gauge_vec!(
+                "solana_stake_pool_total_lamports",
+                "Total lamports under management of a stake pool",
+                &[POOL_LABEL]
+            )
+            .unwrap(),
+            stake_pool_token_supply: register_int_gauge_vec!(
+                "solana_stake_pool_token_supply",
+                "Pool token supply of a stake pool",
+                &[POOL_LABEL]
+            )
+            .unwrap(),
+            stake_pool_exchange_rate: register_gauge_vec!(
+                "solana_stake_pool_exchange_rate",
+                "Lamports per pool token of a stake pool",
+                &[POOL_LABEL]
+            )
+            .unwrap(),
+            stake_pool_validator_count: register_int_gauge_vec!(
+                "solana_stake_pool_validator_count",
+                "Number of validators in the validator list of a stake pool",
+                &[POOL_LABEL]
+            )
+            .unwrap(),
+            stake_pool_validator_active_stake: register_int_gauge_vec!(
+                "solana_stake_pool_validator_active_stake",
+                "Active stake lamports of a validator within a stake pool",
+                &[POOL_LABEL, PUBKEY_LABEL]
+            )
+            .unwrap(),
+            stake_pool_validator_transient_stake: register_int_gauge_vec!(
+                "solana_stake_pool_validator_transient_stake",
+                "Transient stake lamports of a validator within a stake pool",
+                &[POOL_LABEL, PUBKEY_LABEL]
+            )
+            .unwrap(),
             nodes: register_int_gauge!("solana_nodes", "Number of nodes").unwrap(),
             average_slot_time: register_gauge!("solana_average_slot_time", "Average slot time")
                 .unwrap(),
@@ -237,44 +922,103 @@ impl PrometheusGauges {
                     "tvu_ip",
                     "tpu_ip",
                     "version",
+                    // The reverse DNS name of the gossip IP, empty unless looked up.
+                    "hostname",
                 ]
             )
             .unwrap(),
-            client: reqwest::Client::new(),
-            vote_accounts_whitelist,
+            client_nodes: register_int_gauge_vec!(
+                "solana_client_nodes",
+                "Number of cluster nodes running a client implementation",
+                &[CLIENT_LABEL]
+            )
+            .unwrap(),
+            client_stake: register_int_gauge_vec!(
+                "solana_client_stake",
+                "Activated stake in lamports of the validators running a client implementation",
+                &[CLIENT_LABEL]
+            )
+            .unwrap(),
+            validator_client: register_int_gauge_vec!(
+                "solana_validator_client",
+                "Client implementation of a validator. Value is always 1.",
+                &[PUBKEY_LABEL, CLIENT_LABEL]
+            )
+            .unwrap(),
+            snapshot_saved_at: register_int_gauge_vec!(
+                "solana_exporter_snapshot_saved_timestamp_seconds",
+                "Unix time the values of a metric restored at startup were saved at on shutdown",
+                &[METRIC_LABEL]
+            )
+            .unwrap(),
+            database_size: register_int_gauge!(
+                "solana_exporter_database_size_bytes",
+                "Size of the persistent database on disk in bytes"
+            )
+            .unwrap(),
+            database_entries: register_int_gauge_vec!(
+                "solana_exporter_database_entries",
+                "Number of entries in a tree of the persistent database",
+                &[TREE_LABEL]
+            )
+            .unwrap(),
+            database_flushed_bytes: register_int_counter!(
+                "solana_exporter_database_flushed_bytes",
+                "Number of bytes written to disk by flushes of the persistent database"
+            )
+            .unwrap(),
+            database_flush_failures: register_int_counter!(
+                "solana_exporter_database_flush_failures",
+                "Number of failed flushes of the persistent database"
+            )
+            .unwrap(),
+            database_flush_duration: register_gauge!(
+                "solana_exporter_database_flush_duration_seconds",
+                "Duration of the last successful flush of the persistent database"
+            )
+            .unwrap(),
+            database_last_flush: register_int_gauge!(
+                "solana_exporter_database_last_flush_timestamp_seconds",
+                "Unix time of the last successful flush of the persistent database"
+            )
+            .unwrap(),
+            client,
+            balance_unit,
         }
     }
 
-    /// Exports gauges for vote accounts
-    pub fn export_vote_accounts(&self, vote_accounts: &RpcVoteAccountStatus) -> anyhow::Result<()> {
-        self.active_validators
-            .get_metric_with_label_values(&["current"])
-            .map(|m| {
-                m.set(
-                    vote_accounts
-                        .current
-                        .iter()
-                        .filter(|rpc| self.vote_accounts_whitelist.contains(&rpc.vote_pubkey))
-                        .count() as i64,
-                )
-            })?;
-
-        self.active_validators
-            .get_metric_with_label_values(&["delinquent"])
-            .map(|m| {
-                m.set(
-                    vote_accounts
-                        .delinquent
-                        .iter()
-                        .filter(|rpc| self.vote_accounts_whitelist.contains(&rpc.vote_pubkey))
-                        .count() as i64,
-                )
-            })?;
+    /// Sets the balance of `lamports` of the series with `labels` of `lamports_gauge` or
+    /// `sol_gauge`, or both, depending on the balance unit.
+    fn set_balance(
+        &self,
+        lamports_gauge: &IntGaugeVec,
+        sol_gauge: &GaugeVec,
+        labels: &[&str],
+        lamports: u64,
+    ) -> anyhow::Result<()> {
+        if self.balance_unit.lamports() {
+            lamports_gauge
+                .get_metric_with_label_values(labels)
+                .map(|c| c.set(lamports as i64))?;
+        }
+        if self.balance_unit.sol() {
+            sol_gauge
+                .get_metric_with_label_values(labels)
+                .map(|c| c.set(lamports as f64 / LAMPORTS_PER_SOL))?;
+        }
+        Ok(())
+    }
 
+    /// Exports gauges for vote accounts of `vote_accounts_whitelist`
+    pub fn export_vote_accounts(
+        &self,
+        vote_accounts: &RpcVoteAccountStatus,
+        vote_accounts_whitelist: &Whitelist,
+    ) -> anyhow::Result<()> {
         for v in vote_accounts
             .current
             .iter()
-            .filter(|rpc| self.vote_accounts_whitelist.contains(&rpc.vote_pubkey))
+            .filter(|rpc| vote_accounts_whitelist.contains(&rpc.vote_pubkey))
         {
             self.is_delinquent
                 .get_metric_with_label_values(&[&*v.vote_pubkey])
@@ -284,19 +1028,21 @@ impl PrometheusGauges {
         for v in vote_accounts
             .delinquent
             .iter()
-            .filter(|rpc| self.vote_accounts_whitelist.contains(&rpc.vote_pubkey))
+            .filter(|rpc| vote_accounts_whitelist.contains(&rpc.vote_pubkey))
         {
             self.is_delinquent
                 .get_metric_with_label_values(&[&*v.vote_pubkey])
                 .map(|m| m.set(1.))?;
         }
 
+        let mut whitelist_activated_stake = 0;
         for v in vote_accounts
             .current
             .iter()
             .chain(vote_accounts.delinquent.iter())
-            .filter(|rpc| self.vote_accounts_whitelist.contains(&rpc.vote_pubkey))
+            .filter(|rpc| vote_accounts_whitelist.contains(&rpc.vote_pubkey))
         {
+            whitelist_activated_stake += v.activated_stake;
             self.activated_stake
                 .get_metric_with_label_values(&[&*v.vote_pubkey])
                 .map(|m| m.set(v.activated_stake as i64))?;
@@ -322,6 +1068,63 @@ impl PrometheusGauges {
                     .map(|m| m.set(*current_epoch_credits as i64))?;
             }
         }
+        self.whitelist_activated_stake
+            .set(whitelist_activated_stake as i64);
+
+        Ok(())
+    }
+
+    /// Exports the cluster-level gauges of the vote accounts of `vote_accounts_whitelist`: the
+    /// number of active validators, and the stake and credit ranks, which are taken among all
+    /// vote accounts.
+    pub fn export_cluster_vote_accounts(
+        &self,
+        vote_accounts: &RpcVoteAccountStatus,
+        vote_accounts_whitelist: &Whitelist,
+    ) -> anyhow::Result<()> {
+        self.active_validators
+            .get_metric_with_label_values(&["current"])
+            .map(|m| {
+                m.set(
+                    vote_accounts
+                        .current
+                        .iter()
+                        .filter(|rpc| vote_accounts_whitelist.contains(&rpc.vote_pubkey))
+                        .count() as i64,
+                )
+            })?;
+
+        self.active_validators
+            .get_metric_with_label_values(&["delinquent"])
+            .map(|m| {
+                m.set(
+                    vote_accounts
+                        .delinquent
+                        .iter()
+                        .filter(|rpc| vote_accounts_whitelist.contains(&rpc.vote_pubkey))
+                        .count() as i64,
+                )
+            })?;
+
+        let stake_ranks = stake_ranks(vote_accounts);
+        let credit_ranks = credit_ranks(vote_accounts);
+        for v in vote_accounts
+            .current
+            .iter()
+            .chain(vote_accounts.delinquent.iter())
+            .filter(|rpc| vote_accounts_whitelist.contains(&rpc.vote_pubkey))
+        {
+            if let Some(rank) = stake_ranks.get(v.vote_pubkey.as_str()) {
+                self.stake_rank
+                    .get_metric_with_label_values(&[&*v.vote_pubkey])
+                    .map(|m| m.set(*rank as i64))?;
+            }
+            if let Some(rank) = credit_ranks.get(v.vote_pubkey.as_str()) {
+                self.credit_rank
+                    .get_metric_with_label_values(&[&*v.vote_pubkey])
+                    .map(|m| m.set(*rank as i64))?;
+            }
+        }
 
         Ok(())
     }
@@ -330,46 +1133,41 @@ impl PrometheusGauges {
     pub async fn export_epoch_info(
         &self,
         epoch_info: &EpochInfo,
-        client: &RpcClient,
+        client: &dyn RpcApi,
     ) -> anyhow::Result<()> {
         let first_slot = epoch_info.absolute_slot - epoch_info.slot_index;
         let last_slot = first_slot + epoch_info.slots_in_epoch;
 
-        self.transaction_count
-            .set(epoch_info.transaction_count.unwrap_or_default() as i64);
+        // Not all nodes report the transaction count; keep the last value rather than dropping
+        // to zero.
+        if let Some(transaction_count) = epoch_info.transaction_count {
+            self.transaction_count.set(transaction_count as i64);
+        }
         self.slot_height.set(epoch_info.absolute_slot as i64);
+        self.block_height.set(epoch_info.block_height as i64);
         self.current_epoch.set(epoch_info.epoch as i64);
         self.current_epoch_first_slot.set(first_slot as i64);
         self.current_epoch_last_slot.set(last_slot as i64);
 
-        if let Some(block) = first_block_in_epoch(client, epoch_info.epoch).await? {
-            let average_slot_time = (OffsetDateTime::now_utc().unix_timestamp()
-                - client
-                    .get_block_with_config(
-                        block,
-                        RpcBlockConfig {
-                            encoding: Some(UiTransactionEncoding::Base64),
-                            transaction_details: Some(TransactionDetails::None),
-                            rewards: Some(false),
-                            commitment: None,
-                            max_supported_transaction_version: Some(0),
-                        },
-                    )
-                    .await?
-                    .block_time
-                    .unwrap()) as f64
-                / (epoch_info.slot_index) as f64;
+        if let Some(start_time) = epoch_start_time(client, epoch_info.epoch).await? {
+            let average_slot_time = average_slot_time_since(start_time, epoch_info);
             self.average_slot_time.set(average_slot_time);
+            self.epoch_start_timestamp.set(start_time);
+            // Unknown in the first slot of the epoch.
+            let end_time = start_time as f64 + average_slot_time * epoch_info.slots_in_epoch as f64;
+            if end_time.is_finite() {
+                self.epoch_end_timestamp.set(end_time.round() as i64);
+            }
         }
 
         Ok(())
     }
 
-    /// Exports information about nodes
-    pub async fn export_nodes_info(
+    /// Exports the balances of the whitelisted nodes.
+    pub async fn export_node_balances(
         &self,
         nodes: &[RpcContactInfo],
-        client: &RpcClient,
+        client: &dyn RpcApi,
         node_whitelist: &Whitelist,
     ) -> anyhow::Result<()> {
         // Balance of node pubkeys. Only exported if a whitelist is set!
@@ -388,12 +1186,23 @@ impl PrometheusGauges {
             .await?;
 
             for (pubkey, balance) in balances {
-                self.node_pubkey_balances
-                    .get_metric_with_label_values(&[&pubkey])
-                    .map(|c| c.set(balance as i64))?;
+                self.set_balance(
+                    &self.node_pubkey_balances,
+                    &self.node_pubkey_balances_sol,
+                    &[&pubkey],
+                    balance,
+                )?;
             }
         }
+        Ok(())
+    }
 
+    /// Exports the number and versions of the whitelisted nodes.
+    pub fn export_nodes_info(
+        &self,
+        nodes: &[RpcContactInfo],
+        node_whitelist: &Whitelist,
+    ) -> anyhow::Result<()> {
         let nodes = nodes
             .iter()
             .filter(|rpc| node_whitelist.contains(&rpc.pubkey))
@@ -418,19 +1227,57 @@ impl PrometheusGauges {
         Ok(())
     }
 
+    /// Exports the balances of the addresses in `watchlist`. Addresses that do not exist on
+    /// chain are reported with a zero balance.
+    pub async fn export_address_balances(
+        &self,
+        watchlist: &AddressWatchlist,
+        client: &dyn RpcApi,
+    ) -> anyhow::Result<()> {
+        let addresses = watchlist
+            .0
+            .iter()
+            .map(|(name, address)| {
+                let pubkey = address
+                    .parse::<Pubkey>()
+                    .with_context(|| format!("invalid watched address {}: {}", name, address))?;
+                anyhow::Ok((name, address, pubkey))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        for chunk in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let pubkeys: Vec<_> = chunk.iter().map(|(_, _, pubkey)| *pubkey).collect();
+            let accounts = client.get_multiple_accounts(&pubkeys).await?;
+
+            for ((name, address, _), account) in chunk.iter().zip(accounts) {
+                let balance = account.map(|a| a.lamports).unwrap_or_default();
+                self.set_balance(
+                    &self.address_balances,
+                    &self.address_balances_sol,
+                    &[name, address],
+                    balance,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Exports cluster-wide gossip node info: one `solana_gossip_node_info`
     /// series per node in the cluster, mapping its identity and (where it has
     /// one) vote account to its bare gossip/TVU/TPU IP addresses.
     ///
-    /// Unlike [`export_nodes_info`], this is **not** filtered by the whitelist —
+    /// Unlike [`Self::export_nodes_info`], this is **not** filtered by the whitelist —
     /// it covers every node in `getClusterNodes` so dashboards can resolve any
     /// source IP on the network back to a node identity. The gauge is fully
     /// reset each cycle so series for nodes/IPs that left the cluster do not
-    /// accumulate (the cluster has thousands of nodes with IP churn).
+    /// accumulate (the cluster has thousands of nodes with IP churn). The
+    /// `hostname` label is the name of the gossip IP in `hostnames`, if any.
     pub fn export_gossip_node_info(
         &self,
         nodes: &[GossipNode],
         vote_accounts: &RpcVoteAccountStatus,
+        hostnames: &HashMap<IpAddr, String>,
     ) -> anyhow::Result<()> {
         // node identity pubkey -> vote account pubkey (non-voting nodes absent).
         let vote_by_node: HashMap<&str, &str> = vote_accounts
@@ -452,6 +1299,11 @@ impl PrometheusGauges {
             let tvu_ip = ip_of(&node.tvu);
             let tpu_ip = ip_of(&node.tpu);
             let version = node.version.as_deref().unwrap_or("unknown");
+            let hostname = gossip_ip
+                .parse::<IpAddr>()
+                .ok()
+                .and_then(|ip| hostnames.get(&ip))
+                .map_or("", String::as_str);
 
             self.gossip_node_info
                 .get_metric_with_label_values(&[
@@ -461,6 +1313,7 @@ impl PrometheusGauges {
                     &tvu_ip,
                     &tpu_ip,
                     version,
+                    hostname,
                 ])
                 .map(|m| m.set(1))?;
         }
@@ -468,7 +1321,8 @@ impl PrometheusGauges {
         Ok(())
     }
 
-    /// Exports gauges for geolocation of validators
+    /// Exports gauges for geolocation of validators. Delinquent validators are only counted in
+    /// the delinquency gauges, which are exported for every datacenter of a validator.
     pub async fn export_ip_addresses(
         &self,
         nodes: &[RpcContactInfo],
@@ -479,8 +1333,8 @@ impl PrometheusGauges {
     ) -> anyhow::Result<()> {
         // Define all types here
         type RpcInfo = (RpcContactInfo, RpcVoteAccountInfo);
-        type RpcInfoMaybeGeo = (RpcContactInfo, RpcVoteAccountInfo, Option<CityApiResponse>);
-        type RpcInfoGeo = (RpcContactInfo, RpcVoteAccountInfo, CityApiResponse);
+        type RpcInfoMaybeGeo = (RpcContactInfo, RpcVoteAccountInfo, Option<GeoInfo>);
+        type RpcInfoGeo = (RpcContactInfo, RpcVoteAccountInfo, GeoInfo);
 
         // All nodes that are validators
         let validator_nodes = {
@@ -489,6 +1343,7 @@ impl PrometheusGauges {
             let pubkeys = vote_accounts
                 .current
                 .iter()
+                .chain(&vote_accounts.delinquent)
                 .cloned()
                 .map(|vote| (vote.node_pubkey.to_string(), vote))
                 .collect::<HashMap<String, RpcVoteAccountInfo>>();
@@ -510,18 +1365,23 @@ impl PrometheusGauges {
             .filter(|(contact, _)| node_whitelist.contains(&contact.pubkey))
             .collect::<Vec<_>>();
 
+        // Private and unroutable addresses, e.g. on local test clusters, are not looked up.
+        let (private, validator_nodes): (Vec<RpcInfo>, Vec<RpcInfo>) =
+            validator_nodes.into_iter().partition(|(contact, _)| {
+                get_rpc_contact_ip(contact).is_some_and(|ip| is_private_ip(&ip))
+            });
+        self.private_ip_validators.set(private.len() as i64);
+
         // Separate cached data from uncached data
         let (cached, uncached): (Vec<RpcInfoMaybeGeo>, Vec<RpcInfoMaybeGeo>) = validator_nodes
             .into_iter()
             .map(|(contact, vote)| {
-                let cached = cache
-                    .fetch_ip_address_with_invalidation(
-                        &get_rpc_contact_ip(&contact).with_context(|| {
-                            format!("Validator node has no IP: {:?} {:?}", contact, vote)
-                        })?,
-                        |date| date + Duration::week() < OffsetDateTime::now_utc().date(),
-                    )?
-                    .map(|geo| geo.response);
+                let cached = cache.fetch_fresh_ip_address(
+                    &get_rpc_contact_ip(&contact).with_context(|| {
+                        format!("Validator node has no IP: {:?} {:?}", contact, vote)
+                    })?,
+                    maxmind.service(),
+                )?;
                 Ok((contact, vote, cached))
             })
             .collect::<anyhow::Result<Vec<RpcInfoMaybeGeo>>>()?
@@ -548,20 +1408,12 @@ impl PrometheusGauges {
                     get_rpc_contact_ip(&contact).unwrap()
                 );
 
-                self.client
-                    .get(format!(
-                        "{}/{}",
-                        MAXMIND_CITY_URI,
-                        get_rpc_contact_ip(&contact).unwrap()
-                    ))
-                    .basic_auth(maxmind.username(), Some(maxmind.password()))
-                    .send()
-                    .and_then(|resp| resp.json::<CityApiResponse>())
-                    .and_then(|json: CityApiResponse| async { Ok((contact, vote, json)) })
+                api::lookup(&self.client, maxmind, get_rpc_contact_ip(&contact).unwrap())
+                    .and_then(|geo| async { Ok((contact, vote, geo)) })
             }))
             .await
             .into_iter()
-            .collect::<Vec<reqwest::Result<RpcInfoGeo>>>()
+            .collect::<Vec<anyhow::Result<RpcInfoGeo>>>()
             .into_iter()
             .partition(Result::is_ok);
 
@@ -573,28 +1425,56 @@ impl PrometheusGauges {
         let uncached_err = uncached_err
             .into_iter()
             .map(Result::unwrap_err)
-            .collect::<Vec<reqwest::Error>>();
+            .collect::<Vec<anyhow::Error>>();
 
         for err in uncached_err {
-            error!("{:?}", anyhow!(err));
+            error!("{:?}", err);
         }
 
         // Add API requested data into database
-        for (contact, _, city) in &uncached {
-            cache.add_ip_address(&get_rpc_contact_ip(contact).unwrap(), &city.clone().into())?;
+        for (contact, _, geo) in &uncached {
+            cache.add_ip_address(&get_rpc_contact_ip(contact).unwrap(), geo)?;
             debug!("Caching into DB {:?}", get_rpc_contact_ip(contact).unwrap());
         }
 
         // Add API requested data into collection
         geolocations.append(&mut uncached);
 
-        // Gauges
-        let mut isp_staked: HashMap<String, u64> = HashMap::new();
-        let mut isp_count: HashMap<String, u64> = HashMap::new();
-        let mut dc_staked: HashMap<DatacenterIdentifier, u64> = HashMap::new();
+        // Gauges, by ISP and user type, which is only known with Insights.
+        let mut isp_staked: HashMap<(String, String), u64> = HashMap::new();
+        let mut isp_count: HashMap<(String, String), u64> = HashMap::new();
+        let mut dc_staked: HashMap<String, u64> = HashMap::new();
+        // Number and stake of delinquent validators per datacenter.
+        let mut dc_delinquent: HashMap<String, (u64, u64)> = HashMap::new();
+        let delinquent = vote_accounts
+            .delinquent
+            .iter()
+            .map(|v| v.vote_pubkey.as_str())
+            .collect::<HashSet<_>>();
 
-        for (_, validator, city) in &geolocations {
-            let isp = &city.traits.isp;
+        // Validators at private addresses count as both ISP and datacenter `private`.
+        let located = geolocations
+            .iter()
+            .map(|(_, validator, geo)| {
+                let isp = (
+                    geo.response.traits.isp.clone(),
+                    geo.user_type.clone().unwrap_or_default(),
+                );
+                let dc = DatacenterIdentifier::from(geo.response.clone()).to_string();
+                (validator, isp, dc)
+            })
+            .chain(private.iter().map(|(_, validator)| {
+                let isp = (PRIVATE_LOCATION.to_string(), String::new());
+                (validator, isp, PRIVATE_LOCATION.to_string())
+            }));
+
+        for (validator, isp, dc) in located {
+            let dc_delinquent = dc_delinquent.entry(dc.clone()).or_default();
+            if delinquent.contains(validator.vote_pubkey.as_str()) {
+                dc_delinquent.0 += 1;
+                dc_delinquent.1 += validator.activated_stake;
+                continue;
+            }
 
             // solana_active_validators_isp_stake
             let s = isp_staked.entry(isp.clone()).or_default();
@@ -605,42 +1485,481 @@ impl PrometheusGauges {
             *c += 1;
 
             // solana_active_validators_dc_stake
-            let dc = dc_staked.entry(city.clone().into()).or_default();
-            *dc += validator.activated_stake;
+            *dc_staked.entry(dc).or_default() += validator.activated_stake;
         }
 
         // Set gauges
-        for (isp, count) in &isp_count {
+        for ((isp, user_type), count) in &isp_count {
             self.isp_count
-                .get_metric_with_label_values(&[isp])
+                .get_metric_with_label_values(&[isp, user_type])
                 .map(|c| c.set(*count as i64))?;
         }
 
-        for (isp, staked) in &isp_staked {
+        for ((isp, user_type), staked) in &isp_staked {
             self.isp_by_stake
-                .get_metric_with_label_values(&[isp])
+                .get_metric_with_label_values(&[isp, user_type])
                 .map(|c| c.set(*staked as i64))?;
         }
 
         for (identifier, staked) in &dc_staked {
             self.dc_by_stake
-                .get_metric_with_label_values(&[&identifier.to_string()])
+                .get_metric_with_label_values(&[identifier])
+                .map(|c| c.set(*staked as i64))?;
+        }
+
+        // Reset, so that datacenters no longer hosting any validator are dropped.
+        self.dc_delinquent_count.reset();
+        self.dc_delinquent_stake.reset();
+        for (identifier, (count, staked)) in &dc_delinquent {
+            self.dc_delinquent_count
+                .get_metric_with_label_values(&[identifier])
+                .map(|c| c.set(*count as i64))?;
+            self.dc_delinquent_stake
+                .get_metric_with_label_values(&[identifier])
                 .map(|c| c.set(*staked as i64))?;
         }
 
         Ok(())
     }
+
+    /// Exports the ASNs of the gossip and TPU addresses of the whitelisted validators, and
+    /// whether they differ, e.g. behind a relayer or a TPU proxy. Addresses that cannot be
+    /// geolocated are logged and skipped.
+    pub async fn export_tpu_asns(
+        &self,
+        nodes: &[RpcContactInfo],
+        vote_accounts: &RpcVoteAccountStatus,
+        cache: &GeolocationCache,
+        maxmind: &MaxMindAPIKey,
+        node_whitelist: &Whitelist,
+    ) -> anyhow::Result<()> {
+        let asns = tpu_asns(
+            &self.client,
+            nodes,
+            vote_accounts,
+            cache,
+            maxmind,
+            node_whitelist,
+        )
+        .await;
+        for (vote_pubkey, gossip_asn, tpu_asn) in asns {
+            self.validator_asn
+                .get_metric_with_label_values(&[vote_pubkey, "gossip"])
+                .map(|c| c.set(gossip_asn as i64))?;
+            self.validator_asn
+                .get_metric_with_label_values(&[vote_pubkey, "tpu"])
+                .map(|c| c.set(tpu_asn as i64))?;
+            self.tpu_asn_mismatch
+                .get_metric_with_label_values(&[vote_pubkey])
+                .map(|c| c.set((gossip_asn != tpu_asn) as i64))?;
+        }
+        Ok(())
+    }
 }
 
 impl Default for PrometheusGauges {
     fn default() -> Self {
-        Self::new(Whitelist::default())
+        Self::new(reqwest::Client::new(), BalanceUnit::default())
+    }
+}
+
+/// Returns the vote pubkeys of the whitelisted validators with the ASNs of their gossip and TPU
+/// addresses. The distinct addresses are geolocated concurrently, at most
+/// `TPU_GEOLOCATION_CONCURRENCY` at a time. Addresses that cannot be geolocated are logged and
+/// skipped.
+async fn tpu_asns<'a>(
+    client: &reqwest::Client,
+    nodes: &[RpcContactInfo],
+    vote_accounts: &'a RpcVoteAccountStatus,
+    cache: &GeolocationCache,
+    maxmind: &MaxMindAPIKey,
+    node_whitelist: &Whitelist,
+) -> Vec<(&'a str, u32, u32)> {
+    let contacts = nodes
+        .iter()
+        .filter(|contact| node_whitelist.contains(&contact.pubkey))
+        .map(|contact| (contact.pubkey.as_str(), contact))
+        .collect::<HashMap<_, _>>();
+    let mut addresses = Vec::new();
+    for vote in vote_accounts
+        .current
+        .iter()
+        .chain(&vote_accounts.delinquent)
+    {
+        let contact = match contacts.get(vote.node_pubkey.as_str()) {
+            Some(contact) => contact,
+            None => continue,
+        };
+        let (gossip, tpu) = match (get_gossip_contact_ip(contact), contact.tpu) {
+            (Some(gossip), Some(tpu)) => (gossip, tpu.ip()),
+            _ => continue,
+        };
+        if is_private_ip(&gossip) || is_private_ip(&tpu) {
+            debug!("Not geolocating private addresses of {}", vote.node_pubkey);
+            continue;
+        }
+        addresses.push((vote, gossip, tpu));
+    }
+
+    let ips: HashSet<IpAddr> = addresses
+        .iter()
+        .flat_map(|(_, gossip, tpu)| [*gossip, *tpu])
+        .collect();
+    let asns: HashMap<IpAddr, anyhow::Result<u32>> = futures::stream::iter(ips)
+        .map(|ip| async move {
+            let asn = geolocate(client, ip, cache, maxmind)
+                .await
+                .map(|city| city.traits.autonomous_system_number);
+            (ip, asn)
+        })
+        .buffer_unordered(TPU_GEOLOCATION_CONCURRENCY)
+        .collect()
+        .await;
+
+    addresses
+        .into_iter()
+        .filter_map(|(vote, gossip, tpu)| match (&asns[&gossip], &asns[&tpu]) {
+            (Ok(gossip_asn), Ok(tpu_asn)) => {
+                Some((vote.vote_pubkey.as_str(), *gossip_asn, *tpu_asn))
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                error!("Could not geolocate node {}: {e:#}", vote.node_pubkey);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Geolocates `ip` from `cache`, or from MaxMind if it is not cached or cached over a week ago,
+/// caching the response.
+async fn geolocate(
+    client: &reqwest::Client,
+    ip: IpAddr,
+    cache: &GeolocationCache,
+    maxmind: &MaxMindAPIKey,
+) -> anyhow::Result<CityApiResponse> {
+    if let Some(geo) = cache.fetch_fresh_ip_address(&ip, maxmind.service())? {
+        return Ok(geo.response);
+    }
+    debug!("Contacting Maxmind for: {:?}", ip);
+    let geo = api::lookup(client, maxmind, ip).await?;
+    cache.add_ip_address(&ip, &geo)?;
+    Ok(geo.response)
+}
+
+/// The monitor of vote account gauges.
+///
+/// The identity of each whitelisted vote account is tracked across cycles, so that failovers and
+/// hot-spare switches are counted and the `solana_validator_identity` series of the previous
+/// identity is removed. The identities seen in the first cycle after a start are the baseline.
+pub struct VoteAccountsMonitor<'a> {
+    gauges: &'a PrometheusGauges,
+    /// Persisted counter totals.
+    baselines: &'a CounterBaselines,
+    /// Whether the cluster-level gauges are exported, besides the per-validator ones.
+    cluster_wide: bool,
+    /// The last seen identity per vote account.
+    identities: HashMap<String, String>,
+}
+
+impl<'a> VoteAccountsMonitor<'a> {
+    /// Constructs a monitor exporting into `gauges`, and the cluster-level gauges of the vote
+    /// accounts of all shards if `cluster_wide`.
+    pub fn new(
+        gauges: &'a PrometheusGauges,
+        baselines: &'a CounterBaselines,
+        cluster_wide: bool,
+    ) -> Self {
+        Self {
+            gauges,
+            baselines,
+            cluster_wide,
+            identities: HashMap::new(),
+        }
+    }
+
+    /// Counts the identity changes of the vote accounts of `whitelist` since the previous cycle.
+    fn export_identity_changes(
+        &mut self,
+        vote_accounts: &RpcVoteAccountStatus,
+        whitelist: &Whitelist,
+    ) -> anyhow::Result<()> {
+        for v in vote_accounts
+            .current
+            .iter()
+            .chain(vote_accounts.delinquent.iter())
+            .filter(|rpc| whitelist.contains(&rpc.vote_pubkey))
+        {
+            if let Some(previous) = identity_change(&mut self.identities, v) {
+                debug!(
+                    "Identity of vote account {} changed from {} to {}",
+                    v.vote_pubkey, previous, v.node_pubkey
+                );
+                self.baselines
+                    .inc_by(&self.gauges.identity_changes, &[&v.vote_pubkey], 1)?;
+                // The series may be gone already if the identity changed back and forth.
+                let _ = self
+                    .gauges
+                    .identity
+                    .remove_label_values(&[&v.vote_pubkey, &previous]);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Records the identity of `vote_account` in `identities`, returning the previous one if it
+/// changed.
+fn identity_change(
+    identities: &mut HashMap<String, String>,
+    vote_account: &RpcVoteAccountInfo,
+) -> Option<String> {
+    identities
+        .insert(
+            vote_account.vote_pubkey.clone(),
+            vote_account.node_pubkey.clone(),
+        )
+        .filter(|previous| *previous != vote_account.node_pubkey)
+}
+
+#[async_trait]
+impl Monitor for VoteAccountsMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "vote account"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(&self.gauges.active_validators),
+            family_name(&self.gauges.is_delinquent),
+            family_name(&self.gauges.activated_stake),
+            family_name(&self.gauges.stake_rank),
+            family_name(&self.gauges.credit_rank),
+            family_name(&self.gauges.last_vote),
+            family_name(&self.gauges.root_slot),
+            family_name(&self.gauges.staking_commission),
+            family_name(&self.gauges.identity),
+            family_name(&self.gauges.identity_changes),
+            family_name(&self.gauges.epoch_vote_account),
+            family_name(&self.gauges.vote_credits),
+            family_name(&self.gauges.whitelist_activated_stake),
+        ]
+    }
+
+    /// Restores the identity change counter totals.
+    async fn setup(&mut self) -> anyhow::Result<()> {
+        self.baselines.restore(&self.gauges.identity_changes)
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.gauges
+            .export_vote_accounts(&cycle.vote_accounts, &cycle.vote_accounts_whitelist)?;
+        if self.cluster_wide {
+            self.gauges.export_cluster_vote_accounts(
+                &cycle.vote_accounts,
+                &cycle.unsharded_vote_accounts_whitelist,
+            )?;
+        }
+        self.export_identity_changes(&cycle.vote_accounts, &cycle.vote_accounts_whitelist)
+    }
+}
+
+/// The monitor of epoch info gauges.
+pub struct EpochInfoMonitor<'a> {
+    gauges: &'a PrometheusGauges,
+    client: &'a dyn RpcApi,
+}
+
+impl<'a> EpochInfoMonitor<'a> {
+    /// Constructs a monitor exporting into `gauges`.
+    pub fn new(gauges: &'a PrometheusGauges, client: &'a dyn RpcApi) -> Self {
+        Self { gauges, client }
+    }
+}
+
+#[async_trait]
+impl Monitor for EpochInfoMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "epoch info"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(&self.gauges.transaction_count),
+            family_name(&self.gauges.slot_height),
+            family_name(&self.gauges.block_height),
+            family_name(&self.gauges.current_epoch),
+            family_name(&self.gauges.current_epoch_first_slot),
+            family_name(&self.gauges.current_epoch_last_slot),
+            family_name(&self.gauges.average_slot_time),
+            family_name(&self.gauges.epoch_start_timestamp),
+            family_name(&self.gauges.epoch_end_timestamp),
+        ]
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.gauges
+            .export_epoch_info(&cycle.epoch_info, self.client)
+            .await
+    }
+
+    fn epoch_metrics(&self) -> Vec<String> {
+        vec![
+            family_name(&self.gauges.current_epoch),
+            family_name(&self.gauges.current_epoch_first_slot),
+            family_name(&self.gauges.current_epoch_last_slot),
+            family_name(&self.gauges.epoch_start_timestamp),
+            family_name(&self.gauges.epoch_end_timestamp),
+            family_name(&self.gauges.average_slot_time),
+        ]
+    }
+}
+
+/// The monitor of node info gauges.
+pub struct NodesMonitor<'a> {
+    gauges: &'a PrometheusGauges,
+    client: &'a dyn RpcApi,
+    /// Whether the node counts and versions are exported, besides the node balances.
+    cluster_wide: bool,
+}
+
+impl<'a> NodesMonitor<'a> {
+    /// Constructs a monitor exporting into `gauges`, and the node counts and versions of all
+    /// shards if `cluster_wide`.
+    pub fn new(gauges: &'a PrometheusGauges, client: &'a dyn RpcApi, cluster_wide: bool) -> Self {
+        Self {
+            gauges,
+            client,
+            cluster_wide,
+        }
+    }
+}
+
+#[async_trait]
+impl Monitor for NodesMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "node info"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(&self.gauges.node_pubkey_balances),
+            family_name(&self.gauges.node_pubkey_balances_sol),
+            family_name(&self.gauges.nodes),
+            family_name(&self.gauges.node_versions),
+        ]
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.gauges
+            .export_node_balances(&cycle.nodes, self.client, &cycle.node_whitelist)
+            .await?;
+        if self.cluster_wide {
+            self.gauges
+                .export_nodes_info(&cycle.nodes, &cycle.unsharded_node_whitelist)?;
+        }
+        Ok(())
+    }
+}
+
+/// The monitor of watched address balances.
+pub struct AddressBalancesMonitor<'a> {
+    gauges: &'a PrometheusGauges,
+    rpc: &'a dyn RpcApi,
+    watchlist: AddressWatchlist,
+}
+
+impl<'a> AddressBalancesMonitor<'a> {
+    /// Constructs a monitor of `watchlist` exporting into `gauges`.
+    pub fn new(
+        gauges: &'a PrometheusGauges,
+        rpc: &'a dyn RpcApi,
+        watchlist: AddressWatchlist,
+    ) -> Self {
+        Self {
+            gauges,
+            rpc,
+            watchlist,
+        }
+    }
+}
+
+#[async_trait]
+impl Monitor for AddressBalancesMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "watched address balance"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(&self.gauges.address_balances),
+            family_name(&self.gauges.address_balances_sol),
+        ]
+    }
+
+    async fn collect(&mut self, _cycle: &Cycle) -> anyhow::Result<()> {
+        self.gauges
+            .export_address_balances(&self.watchlist, self.rpc)
+            .await
+    }
+}
+
+/// The monitor of cluster-wide gossip node info.
+pub struct GossipNodesMonitor<'a> {
+    gauges: &'a PrometheusGauges,
+    /// Resolver of the hostnames of gossip IPs, if enabled.
+    reverse_dns: Option<&'a ReverseDns>,
+}
+
+impl<'a> GossipNodesMonitor<'a> {
+    /// Constructs a monitor exporting into `gauges`, labelling nodes with the hostnames of
+    /// `reverse_dns`, if any.
+    pub fn new(gauges: &'a PrometheusGauges, reverse_dns: Option<&'a ReverseDns>) -> Self {
+        Self {
+            gauges,
+            reverse_dns,
+        }
+    }
+}
+
+#[async_trait]
+impl Monitor for GossipNodesMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "gossip node info"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![family_name(&self.gauges.gossip_node_info)]
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        let gossip_nodes = parse_gossip_nodes(&cycle.raw_nodes);
+        let hostnames = match self.reverse_dns {
+            Some(reverse_dns) => {
+                reverse_dns
+                    .hostnames(
+                        gossip_nodes
+                            .iter()
+                            .filter_map(|node| ip_of(&node.gossip).parse().ok()),
+                    )
+                    .await
+            }
+            None => HashMap::new(),
+        };
+        self.gauges
+            .export_gossip_node_info(&gossip_nodes, &cycle.vote_accounts, &hostnames)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::ip_of;
+    use super::{credit_ranks, identity_change, ip_of, stake_ranks, tpu_asns};
+    use crate::config::Whitelist;
+    use crate::geolocation::api::{parse_response, MaxMindAPIKey, MaxMindService};
+    use crate::geolocation::caching::{GeolocationCache, GEO_DB_CACHE_TREE_NAME};
+    use solana_client::rpc_response::{RpcContactInfo, RpcVoteAccountInfo, RpcVoteAccountStatus};
+    use std::collections::HashMap;
+    use std::net::IpAddr;
 
     #[test]
     fn ip_of_strips_port() {
@@ -663,4 +1982,168 @@ mod tests {
             "2001:db8::1"
         );
     }
+
+    #[test]
+    fn ranks_current_and_delinquent_validators_by_stake() {
+        let account = |vote_pubkey: &str, activated_stake| RpcVoteAccountInfo {
+            vote_pubkey: vote_pubkey.to_string(),
+            node_pubkey: String::new(),
+            activated_stake,
+            commission: 0,
+            epoch_vote_account: true,
+            epoch_credits: vec![],
+            last_vote: 0,
+            root_slot: 0,
+        };
+        let vote_accounts = RpcVoteAccountStatus {
+            current: vec![account("a", 10), account("b", 30), account("c", 10)],
+            delinquent: vec![account("d", 50)],
+        };
+
+        let ranks = stake_ranks(&vote_accounts);
+        assert_eq!(ranks["d"], 1);
+        assert_eq!(ranks["b"], 2);
+        assert_eq!(ranks["a"], 3);
+        assert_eq!(ranks["c"], 3);
+    }
+
+    #[tokio::test]
+    async fn exports_tpu_asns_of_cached_addresses() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let cache = GeolocationCache::new(db.open_tree(GEO_DB_CACHE_TREE_NAME).unwrap());
+        for (ip, asn) in [("3.0.0.1", 16509), ("3.0.0.2", 16509), ("8.8.8.8", 15169)] {
+            let json = serde_json::json!({
+                "registered_country": {
+                    "geoname_id": 6252001,
+                    "iso_code": "US",
+                    "names": { "en": "United States" }
+                },
+                "traits": {
+                    "autonomous_system_number": asn,
+                    "autonomous_system_organization": "AS",
+                    "ip_address": ip,
+                    "isp": "ISP",
+                    "network": format!("{}/32", ip),
+                    "organization": "ISP"
+                }
+            });
+            let geo = parse_response(json, MaxMindService::City).unwrap();
+            cache
+                .add_ip_address(&ip.parse::<IpAddr>().unwrap(), &geo)
+                .unwrap();
+        }
+        let contact = |pubkey: &str, gossip: &str, tpu: &str| RpcContactInfo {
+            pubkey: pubkey.to_string(),
+            gossip: Some(format!("{}:8001", gossip).parse().unwrap()),
+            tvu: None,
+            tpu: Some(format!("{}:8003", tpu).parse().unwrap()),
+            tpu_quic: None,
+            tpu_forwards: None,
+            tpu_forwards_quic: None,
+            tpu_vote: None,
+            serve_repair: None,
+            rpc: None,
+            pubsub: None,
+            version: None,
+            client_id: None,
+            feature_set: None,
+            shred_version: None,
+        };
+        let nodes = [
+            contact("same", "3.0.0.1", "3.0.0.2"),
+            contact("proxied", "3.0.0.1", "8.8.8.8"),
+            contact("unlisted", "3.0.0.1", "8.8.8.8"),
+            // Not cached, and MaxMind is not reachable.
+            contact("uncached", "1.1.1.1", "1.1.1.1"),
+        ];
+        let account = |node_pubkey: &str| RpcVoteAccountInfo {
+            vote_pubkey: format!("vote-{}", node_pubkey),
+            node_pubkey: node_pubkey.to_string(),
+            activated_stake: 0,
+            commission: 0,
+            epoch_vote_account: true,
+            epoch_credits: vec![],
+            last_vote: 0,
+            root_slot: 0,
+        };
+        let vote_accounts = RpcVoteAccountStatus {
+            current: vec![account("same"), account("unlisted"), account("uncached")],
+            delinquent: vec![account("proxied")],
+        };
+        let whitelist = Whitelist::new(
+            ["same", "proxied", "uncached"]
+                .iter()
+                .map(|pubkey| pubkey.to_string())
+                .collect(),
+        );
+        let client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::all("http://127.0.0.1:1").unwrap())
+            .build()
+            .unwrap();
+
+        let asns = tpu_asns(
+            &client,
+            &nodes,
+            &vote_accounts,
+            &cache,
+            &MaxMindAPIKey::new("user", "key"),
+            &whitelist,
+        )
+        .await;
+        assert_eq!(
+            asns,
+            vec![("vote-same", 16509, 16509), ("vote-proxied", 16509, 15169)]
+        );
+    }
+
+    #[test]
+    fn ranks_validators_by_credits_of_the_latest_epoch() {
+        let account = |vote_pubkey: &str, epoch_credits| RpcVoteAccountInfo {
+            vote_pubkey: vote_pubkey.to_string(),
+            node_pubkey: String::new(),
+            activated_stake: 0,
+            commission: 0,
+            epoch_vote_account: true,
+            epoch_credits,
+            last_vote: 0,
+            root_slot: 0,
+        };
+        let vote_accounts = RpcVoteAccountStatus {
+            current: vec![
+                account("a", vec![(9, 900, 0), (10, 1_000, 900)]),
+                account("b", vec![(10, 500, 200)]),
+                account("c", vec![(10, 400, 100)]),
+            ],
+            // Earned nothing yet in epoch 10.
+            delinquent: vec![account("d", vec![(9, 5_000, 0)])],
+        };
+
+        let ranks = credit_ranks(&vote_accounts);
+        assert_eq!(ranks["b"], 1);
+        assert_eq!(ranks["c"], 1);
+        assert_eq!(ranks["a"], 3);
+        assert_eq!(ranks["d"], 4);
+    }
+
+    #[test]
+    fn detects_identity_changes() {
+        let account = |node_pubkey: &str| RpcVoteAccountInfo {
+            vote_pubkey: "vote".to_string(),
+            node_pubkey: node_pubkey.to_string(),
+            activated_stake: 0,
+            commission: 0,
+            epoch_vote_account: true,
+            epoch_credits: vec![],
+            last_vote: 0,
+            root_slot: 0,
+        };
+        let mut identities = HashMap::new();
+        assert_eq!(identity_change(&mut identities, &account("primary")), None);
+        assert_eq!(identity_change(&mut identities, &account("primary")), None);
+        assert_eq!(
+            identity_change(&mut identities, &account("spare")),
+            Some("primary".to_string())
+        );
+        assert_eq!(identities["vote"], "spare");
+    }
 }
@@ -0,0 +1,284 @@
+//! `generate` arguments and `generate --interactive` prompts for the values of the template
+//! config that have no useful default.
+
+use anyhow::{anyhow, Context};
+use clap::ArgMatches;
+use solana_exporter::config::whitelist::WhitelistSource;
+use solana_exporter::config::{ExporterConfig, Targets, Whitelist};
+use solana_exporter::geolocation::api::MaxMindAPIKey;
+use solana_pubkey::Pubkey;
+use std::io::{BufRead, Write};
+use std::net::SocketAddr;
+
+/// Arguments of `generate` that prefill values of the config.
+const VALUE_ARGS: [&str; 4] = ["rpc", "target", "vote-account", "staking-account"];
+
+/// Sets the values of `config` given as `generate [--rpc URL] [--target ADDR]...
+/// [--vote-account PUBKEY]... [--staking-account PUBKEY]...` arguments. If any is given, the
+/// placeholder sections the arguments do not supply are left out.
+pub fn apply_args(config: &mut ExporterConfig, args: &ArgMatches) -> anyhow::Result<()> {
+    if VALUE_ARGS.iter().any(|arg| args.is_present(arg)) {
+        omit_placeholders(config);
+    }
+    if let Some(rpc) = args.value_of("rpc") {
+        config.rpc = Some(rpc.to_string());
+    }
+    if let Some(targets) = args.values_of("target") {
+        config.target = Targets(
+            targets
+                .map(|target| {
+                    target
+                        .parse()
+                        .with_context(|| format!("invalid address {}", target))
+                })
+                .collect::<anyhow::Result<_>>()?,
+        );
+    }
+    if let Some(pubkeys) = args.values_of("vote-account") {
+        config.vote_account_whitelist = Some(WhitelistSource::Inline(whitelist(
+            &pubkeys.collect::<Vec<_>>().join(" "),
+        )?));
+    }
+    if let Some(pubkeys) = args.values_of("staking-account") {
+        config.staking_account_whitelist = Some(WhitelistSource::Inline(whitelist(
+            &pubkeys.collect::<Vec<_>>().join(" "),
+        )?));
+    }
+    Ok(())
+}
+
+/// Prompts on `output` for the RPC URL, listen addresses, whitelists and MaxMind credentials of
+/// `config`, reading the answers from `input`. Empty answers keep the shown default. Invalid
+/// answers are asked again. The placeholder sections left unanswered are left out.
+pub fn prompt_config<R: BufRead, W: Write>(
+    config: &mut ExporterConfig,
+    input: &mut R,
+    output: &mut W,
+) -> anyhow::Result<()> {
+    omit_placeholders(config);
+    let rpc = config.rpc.clone().unwrap_or_default();
+    config.rpc = Some(prompt(
+        input,
+        output,
+        "RPC URL, or mainnet-beta, testnet, devnet or localhost",
+        &rpc,
+        |answer| Ok(answer.to_string()),
+    )?);
+    let targets = config
+        .target
+        .0
+        .iter()
+        .map(SocketAddr::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    config.target = prompt(input, output, "Listen addresses", &targets, |answer| {
+        let targets = split(answer)
+            .map(|target| {
+                target
+                    .parse()
+                    .with_context(|| format!("invalid address {}", target))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Targets(targets))
+    })?;
+    config.vote_account_whitelist = Some(prompt(
+        input,
+        output,
+        "Whitelisted vote account pubkeys (empty for all)",
+        &inline_pubkeys(&config.vote_account_whitelist),
+        whitelist,
+    )?)
+    .filter(|whitelist| !whitelist.is_all())
+    .map(WhitelistSource::Inline);
+    config.staking_account_whitelist = Some(prompt(
+        input,
+        output,
+        "Whitelisted staking account pubkeys (empty for all)",
+        &inline_pubkeys(&config.staking_account_whitelist),
+        whitelist,
+    )?)
+    .filter(|whitelist| !whitelist.is_all())
+    .map(WhitelistSource::Inline);
+    let username = prompt(
+        input,
+        output,
+        "MaxMind username (empty to skip geolocation)",
+        "",
+        |answer| Ok(answer.to_string()),
+    )?;
+    config.maxmind = if username.is_empty() {
+        None
+    } else {
+        let password = prompt(input, output, "MaxMind password", "", |answer| {
+            Ok(answer.to_string())
+        })?;
+        Some(MaxMindAPIKey::new(&username, &password))
+    };
+    Ok(())
+}
+
+/// Leaves out the sections of the template `config` whose values are placeholders: the MaxMind
+/// credentials, the additional RPC endpoints and the empty whitelists.
+fn omit_placeholders(config: &mut ExporterConfig) {
+    config.maxmind = None;
+    config.rpc_endpoints = None;
+    for whitelist in [
+        &mut config.vote_account_whitelist,
+        &mut config.staking_account_whitelist,
+    ] {
+        if matches!(whitelist, Some(WhitelistSource::Inline(w)) if w.is_all()) {
+            *whitelist = None;
+        }
+    }
+}
+
+/// Asks `question` until `parse` accepts the answer, or the empty answer stands for `default`.
+/// Fails at the end of `input`.
+fn prompt<R: BufRead, W: Write, T>(
+    input: &mut R,
+    output: &mut W,
+    question: &str,
+    default: &str,
+    parse: impl Fn(&str) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    loop {
+        if default.is_empty() {
+            write!(output, "{}: ", question)?;
+        } else {
+            write!(output, "{} [{}]: ", question, default)?;
+        }
+        output.flush()?;
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            return Err(anyhow!("no answer to \"{}\"", question));
+        }
+        let answer = match answer.trim() {
+            "" => default,
+            answer => answer,
+        };
+        match parse(answer) {
+            Ok(value) => return Ok(value),
+            Err(e) => writeln!(output, "{:#}", e)?,
+        }
+    }
+}
+
+/// Splits a list of values separated by commas or whitespace.
+fn split(answer: &str) -> impl Iterator<Item = &str> {
+    answer
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|value| !value.is_empty())
+}
+
+/// The sorted pubkeys of an inline whitelist, separated by commas.
+fn inline_pubkeys(whitelist: &Option<WhitelistSource>) -> String {
+    match whitelist {
+        Some(WhitelistSource::Inline(whitelist)) => {
+            let mut pubkeys: Vec<_> = whitelist.0.iter().map(String::as_str).collect();
+            pubkeys.sort_unstable();
+            pubkeys.join(", ")
+        }
+        _ => String::new(),
+    }
+}
+
+/// Parses a whitelist of pubkeys.
+fn whitelist(answer: &str) -> anyhow::Result<Whitelist> {
+    let pubkeys = split(answer)
+        .map(|pubkey| {
+            pubkey
+                .parse::<Pubkey>()
+                .map(|_| pubkey.to_string())
+                .map_err(|_| anyhow!("invalid pubkey {}", pubkey))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok(Whitelist::new(pubkeys))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompts_again_on_invalid_answers() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let answers = format!(
+            "devnet\n\n{a}, nonsense\n{a}, {b}\n\nuser\nsecret\n",
+            a = a,
+            b = b
+        );
+        let mut config: ExporterConfig = toml::from_str(
+            "rpc = 'http://localhost:8899'\ntarget = '0.0.0.0:9179'\nrpc_endpoints = [{ url = 'http://localhost:8900' }]",
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        prompt_config(&mut config, &mut answers.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(config.rpc.as_deref(), Some("devnet"));
+        assert_eq!(
+            config.target,
+            Targets(vec!["0.0.0.0:9179".parse().unwrap()])
+        );
+        match &config.vote_account_whitelist {
+            Some(WhitelistSource::Inline(w)) => assert_eq!(w.0.len(), 2),
+            _ => panic!("expected an inline whitelist"),
+        }
+        assert!(config.staking_account_whitelist.is_none());
+        assert!(config.rpc_endpoints.is_none());
+        assert_eq!(config.maxmind.as_ref().unwrap().username(), "user");
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("invalid pubkey nonsense"));
+
+        // Running out of answers fails rather than looping.
+        assert!(prompt_config(&mut config, &mut "devnet\n".as_bytes(), &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn applies_generate_arguments() {
+        let yaml = clap::load_yaml!("cli.yml");
+        let (a, b) = (
+            Pubkey::new_unique().to_string(),
+            Pubkey::new_unique().to_string(),
+        );
+        let matches = clap::App::from_yaml(yaml).get_matches_from(vec![
+            "solana-exporter",
+            "generate",
+            "--rpc",
+            "mainnet-beta",
+            "--target",
+            "0.0.0.0:9179",
+            "--target",
+            "[::]:9179",
+            "--vote-account",
+            &a,
+            "--vote-account",
+            &b,
+        ]);
+        let args = matches.subcommand_matches("generate").unwrap();
+        let mut config: ExporterConfig = toml::from_str(
+            "rpc = 'http://localhost:8899'\ntarget = '0.0.0.0:9179'\nstaking_account_whitelist = []\nmaxmind = { username = 'username', password = 'password' }",
+        )
+        .unwrap();
+        apply_args(&mut config, args).unwrap();
+
+        assert_eq!(config.rpc.as_deref(), Some("mainnet-beta"));
+        assert_eq!(config.target.0.len(), 2);
+        let mut expected = [a.as_str(), b.as_str()];
+        expected.sort_unstable();
+        assert_eq!(
+            inline_pubkeys(&config.vote_account_whitelist),
+            expected.join(", ")
+        );
+        assert!(config.staking_account_whitelist.is_none());
+        assert!(config.maxmind.is_none());
+
+        let invalid = clap::App::from_yaml(yaml).get_matches_from(vec![
+            "solana-exporter",
+            "generate",
+            "--vote-account",
+            "nonsense",
+        ]);
+        assert!(apply_args(&mut config, invalid.subcommand_matches("generate").unwrap()).is_err());
+    }
+}
@@ -1,12 +1,41 @@
+use crate::geolocation::caching::GeoInfo;
+use geoip2_city::CityApiResponse;
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use time::OffsetDateTime;
 
 pub const MAXMIND_CITY_URI: &str = "https://geoip.maxmind.com/geoip/v2.1/city";
+pub const MAXMIND_INSIGHTS_URI: &str = "https://geoip.maxmind.com/geoip/v2.1/insights";
+
+/// A MaxMind GeoIP2 web service IP addresses are geolocated with.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MaxMindService {
+    /// GeoIP2 City.
+    #[default]
+    City,
+    /// GeoIP2 Insights, which costs more per query than City but also returns the user type of
+    /// the IP address, e.g. `hosting` or `residential`.
+    Insights,
+}
+
+impl MaxMindService {
+    /// Gets the base URI of the service.
+    pub fn uri(self) -> &'static str {
+        match self {
+            MaxMindService::City => MAXMIND_CITY_URI,
+            MaxMindService::Insights => MAXMIND_INSIGHTS_URI,
+        }
+    }
+}
 
 /// An API key that can be used to access MaxMind services.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MaxMindAPIKey {
     username: String,
     password: String,
+    /// `city` or `insights`. Defaults to `city`.
+    service: Option<MaxMindService>,
 }
 
 impl MaxMindAPIKey {
@@ -15,6 +44,7 @@ impl MaxMindAPIKey {
         Self {
             username: username.to_owned(),
             password: password.to_owned(),
+            service: None,
         }
     }
 
@@ -27,4 +57,78 @@ impl MaxMindAPIKey {
     pub fn password(&self) -> &str {
         &self.password
     }
+
+    /// Gets the service queried with the API key.
+    pub fn service(&self) -> MaxMindService {
+        self.service.unwrap_or_default()
+    }
+}
+
+/// Geolocates `ip` with the service of `maxmind`.
+pub async fn lookup(
+    client: &reqwest::Client,
+    maxmind: &MaxMindAPIKey,
+    ip: IpAddr,
+) -> anyhow::Result<GeoInfo> {
+    let json = client
+        .get(format!("{}/{}", maxmind.service().uri(), ip))
+        .basic_auth(maxmind.username(), Some(maxmind.password()))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<serde_json::Value>()
+        .await?;
+    parse_response(json, maxmind.service())
+}
+
+/// Parses a response of `service`. The City fields are common to both services, and the user
+/// type is only returned by Insights.
+pub(crate) fn parse_response(
+    json: serde_json::Value,
+    service: MaxMindService,
+) -> anyhow::Result<GeoInfo> {
+    let user_type = json["traits"]["user_type"].as_str().map(str::to_string);
+    Ok(GeoInfo {
+        response: serde_json::from_value::<CityApiResponse>(json)?,
+        fetched_at: OffsetDateTime::now_utc().date(),
+        service,
+        user_type,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_user_type_of_insights() {
+        let json = serde_json::json!({
+            "registered_country": {
+                "geoname_id": 6252001,
+                "iso_code": "US",
+                "names": { "en": "United States" }
+            },
+            "traits": {
+                "autonomous_system_number": 16509,
+                "autonomous_system_organization": "AMAZON-02",
+                "ip_address": "3.0.0.1",
+                "isp": "Amazon.com",
+                "network": "3.0.0.0/15",
+                "organization": "Amazon.com",
+                "user_type": "hosting"
+            }
+        });
+        let insights = parse_response(json.clone(), MaxMindService::Insights).unwrap();
+        assert_eq!(insights.user_type.as_deref(), Some("hosting"));
+        assert_eq!(insights.response.traits.isp, "Amazon.com");
+
+        let mut city = json;
+        city["traits"].as_object_mut().unwrap().remove("user_type");
+        assert_eq!(
+            parse_response(city, MaxMindService::City)
+                .unwrap()
+                .user_type,
+            None
+        );
+    }
 }
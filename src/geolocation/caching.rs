@@ -1,8 +1,11 @@
+use crate::geolocation::api::MaxMindService;
+use crate::persistent_database::envelope::{self, Encoding};
 use anyhow::Context;
 use geoip2_city::CityApiResponse;
+use log::warn;
 use serde::{Deserialize, Serialize};
 use std::net::IpAddr;
-use time::{Date, OffsetDateTime};
+use time::{Date, Duration, OffsetDateTime};
 
 /// Name of the caching database.
 pub const GEO_DB_CACHE_TREE_NAME: &str = "geolocation_cache";
@@ -19,28 +22,36 @@ impl GeolocationCache {
     }
 
     /// Adds an IP address and its corresponding information to the database. Returns the previously
-    /// inserted value, if it exists.
+    /// inserted value, if it exists and can be decoded.
     pub fn add_ip_address(
         &self,
         ip_address: &IpAddr,
         info: &GeoInfo,
     ) -> anyhow::Result<Option<GeoInfo>> {
-        self.tree
-            .insert(bincode::serialize(ip_address)?, bincode::serialize(info)?)
+        Ok(self
+            .tree
+            .insert(
+                bincode::serialize(ip_address)?,
+                envelope::encode(Encoding::Json, info)?,
+            )
             .context("could not insert into database")?
-            .map(|x| bincode::deserialize(&x))
-            .transpose()
-            .context("could not deserialize the previously inserted GeoInfo")
+            .and_then(|x| decode(&x).ok()))
     }
 
-    /// Fetches the cached information about an IP address.
+    /// Fetches the cached information about an IP address. An entry that cannot be decoded is
+    /// treated as not cached, so that it is fetched and overwritten again.
     pub fn fetch_ip_address(&self, ip_address: &IpAddr) -> anyhow::Result<Option<GeoInfo>> {
-        self.tree
+        let bytes = self
+            .tree
             .get(bincode::serialize(ip_address)?)
-            .context("could not fetch from database")?
-            .map(|x| bincode::deserialize(&x))
-            .transpose()
-            .context("could not deserialize the fetched GeoInfo")
+            .context("could not fetch from database")?;
+        Ok(bytes.and_then(|x| match decode(&x) {
+            Ok(geo) => Some(geo),
+            Err(e) => {
+                warn!("Ignoring undecodable geolocation of {}: {e:#}", ip_address);
+                None
+            }
+        }))
     }
 
     /// Fetches the cached information about an IP address, after checking if will be invalidated.
@@ -68,12 +79,25 @@ impl GeolocationCache {
         }
     }
 
+    /// Fetches the cached information about an IP address, unless it was fetched over a week ago,
+    /// or from City while `service` is Insights, whose extra fields it lacks.
+    pub fn fetch_fresh_ip_address(
+        &self,
+        ip_address: &IpAddr,
+        service: MaxMindService,
+    ) -> anyhow::Result<Option<GeoInfo>> {
+        let geo = self.fetch_ip_address_with_invalidation(ip_address, |date| {
+            date + Duration::week() < OffsetDateTime::now_utc().date()
+        })?;
+        Ok(geo.filter(|geo| service == MaxMindService::City || geo.service == service))
+    }
+
     /// Removes cached information about an IP address.
     pub fn remove_ip_address(&self, ip_address: &IpAddr) -> anyhow::Result<Option<GeoInfo>> {
         self.tree
             .remove(bincode::serialize(ip_address)?)
             .context("could not remove IP address")?
-            .map(|x| bincode::deserialize(&x))
+            .map(|x| decode(&x))
             .transpose()
             .context("could not deserialize removed GeoInfo")
     }
@@ -85,6 +109,33 @@ impl GeolocationCache {
 pub struct GeoInfo {
     pub response: CityApiResponse,
     pub fetched_at: Date,
+    /// Service the response was fetched from, City for entries cached before it was recorded.
+    #[serde(default)]
+    pub service: MaxMindService,
+    /// User type of the IP address, only returned by Insights.
+    #[serde(default)]
+    pub user_type: Option<String>,
+}
+
+/// A [`GeoInfo`] as cached in bare bincode, before the service and user type were recorded.
+#[derive(Deserialize)]
+struct LegacyGeoInfo {
+    response: CityApiResponse,
+    fetched_at: Date,
+}
+
+/// Decodes a cached [`GeoInfo`], including the bare bincode entries written before the service
+/// and user type were recorded, which bincode cannot default.
+fn decode(bytes: &[u8]) -> anyhow::Result<GeoInfo> {
+    envelope::decode(bytes).or_else(|e| {
+        let legacy: LegacyGeoInfo = bincode::deserialize(bytes).map_err(|_| e)?;
+        Ok(GeoInfo {
+            response: legacy.response,
+            fetched_at: legacy.fetched_at,
+            service: MaxMindService::City,
+            user_type: None,
+        })
+    })
 }
 
 /// Converts a response from IP-API into something the database can store. We also store the date
@@ -94,6 +145,55 @@ impl From<CityApiResponse> for GeoInfo {
         Self {
             response: value,
             fetched_at: OffsetDateTime::now_utc().date(),
+            service: MaxMindService::City,
+            user_type: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geolocation::api::parse_response;
+
+    #[test]
+    fn decodes_legacy_entries() {
+        let json = serde_json::json!({
+            "registered_country": {
+                "geoname_id": 6252001,
+                "iso_code": "US",
+                "names": { "en": "United States" }
+            },
+            "traits": {
+                "autonomous_system_number": 16509,
+                "autonomous_system_organization": "AMAZON-02",
+                "ip_address": "3.0.0.1",
+                "isp": "Amazon.com",
+                "network": "3.0.0.0/15",
+                "organization": "Amazon.com"
+            }
+        });
+        let geo = parse_response(json, MaxMindService::City).unwrap();
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let cache = GeolocationCache::new(db.open_tree(GEO_DB_CACHE_TREE_NAME).unwrap());
+        let ip: IpAddr = "3.0.0.1".parse().unwrap();
+
+        let legacy = bincode::serialize(&(&geo.response, geo.fetched_at)).unwrap();
+        cache
+            .tree
+            .insert(bincode::serialize(&ip).unwrap(), legacy)
+            .unwrap();
+        let cached = cache.fetch_ip_address(&ip).unwrap().unwrap();
+        assert_eq!(cached.service, MaxMindService::City);
+        assert_eq!(cached.response.traits.isp, "Amazon.com");
+
+        // Entries that cannot be decoded at all are misses.
+        cache
+            .tree
+            .insert(bincode::serialize(&ip).unwrap(), vec![1, 2, 3])
+            .unwrap();
+        assert!(cache.fetch_ip_address(&ip).unwrap().is_none());
+        cache.add_ip_address(&ip, &geo).unwrap();
+        assert!(cache.fetch_ip_address(&ip).unwrap().is_some());
+    }
+}
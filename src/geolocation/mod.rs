@@ -1,5 +1,15 @@
-use solana_client::rpc_response::RpcContactInfo;
-use std::net::IpAddr;
+use crate::config::Whitelist;
+use crate::gauges::PrometheusGauges;
+use crate::geolocation::api::MaxMindAPIKey;
+use crate::geolocation::caching::GeolocationCache;
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::persistent_database::snapshot::GaugeSnapshot;
+use crate::warmup::CacheWarmer;
+use async_trait::async_trait;
+use log::debug;
+use solana_client::rpc_response::{RpcContactInfo, RpcVoteAccountStatus};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr};
 
 pub mod api;
 pub mod caching;
@@ -10,3 +20,245 @@ pub mod identifier;
 pub fn get_rpc_contact_ip(rpc: &RpcContactInfo) -> Option<IpAddr> {
     rpc.tpu.or(rpc.gossip).or(rpc.rpc).map(|s| s.ip())
 }
+
+/// Gets the gossip IP address of a node, or its RPC address if it has none.
+pub fn get_gossip_contact_ip(rpc: &RpcContactInfo) -> Option<IpAddr> {
+    rpc.gossip.or(rpc.rpc).map(|s| s.ip())
+}
+
+/// ISP and datacenter of the validators at private addresses.
+pub const PRIVATE_LOCATION: &str = "private";
+
+/// Whether `ip` is a private, loopback, link-local, unspecified or otherwise unroutable address,
+/// which MaxMind cannot geolocate.
+pub fn is_private_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_private_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_private_ipv4(&ip),
+            None => {
+                let first = ip.segments()[0];
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    // Unique local addresses, fc00::/7.
+                    || first & 0xfe00 == 0xfc00
+                    // Link-local addresses, fe80::/10.
+                    || first & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
+
+fn is_private_ipv4(ip: &Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        // Carrier-grade NAT, 100.64.0.0/10.
+        || (a == 100 && b & 0xc0 == 64)
+}
+
+/// The monitor of validator geolocation, querying MaxMind for any uncached IPs.
+///
+/// Delinquent validators often drop out of gossip, e.g. when their datacenter is down, so the
+/// last gossip contact info of every node is kept to locate them.
+pub struct GeolocationMonitor<'a> {
+    gauges: &'a PrometheusGauges,
+    cache: &'a GeolocationCache,
+    maxmind: MaxMindAPIKey,
+    /// Whether TPU addresses are geolocated separately from gossip addresses.
+    geolocate_tpu: bool,
+    /// Whether the aggregates over the validators of all shards are exported.
+    cluster_wide: bool,
+    /// Last gossip contact info by node pubkey.
+    last_contacts: HashMap<String, RpcContactInfo>,
+    /// Geolocator of the uncached addresses of the first collection in the background, which
+    /// collections wait for.
+    warmer: Option<&'a CacheWarmer>,
+    /// Whether the uncached addresses were handed to `warmer`.
+    warm_up_started: bool,
+    /// Last values of the aggregates saved on shutdown, restored until recomputed.
+    snapshot: Option<&'a GaugeSnapshot>,
+}
+
+impl<'a> GeolocationMonitor<'a> {
+    /// Constructs a monitor exporting into `gauges`, which also compares the ASNs of the TPU and
+    /// gossip addresses of every node if `geolocate_tpu`, and the aggregates if `cluster_wide`.
+    /// The addresses uncached at the first collection are geolocated by `warmer`, if any, rather
+    /// than within the collection. The aggregates are restored from `snapshot`, if any, at setup.
+    pub fn new(
+        gauges: &'a PrometheusGauges,
+        cache: &'a GeolocationCache,
+        maxmind: MaxMindAPIKey,
+        geolocate_tpu: bool,
+        cluster_wide: bool,
+        warmer: Option<&'a CacheWarmer>,
+        snapshot: Option<&'a GaugeSnapshot>,
+    ) -> Self {
+        Self {
+            gauges,
+            cache,
+            maxmind,
+            geolocate_tpu,
+            cluster_wide,
+            last_contacts: HashMap::new(),
+            warmer,
+            warm_up_started: false,
+            snapshot,
+        }
+    }
+
+    /// Returns the addresses of the whitelisted validators among `contacts` that are not cached,
+    /// including their TPU addresses if `geolocate_tpu`.
+    fn uncached_ips(
+        &self,
+        contacts: &[RpcContactInfo],
+        vote_accounts: &RpcVoteAccountStatus,
+        node_whitelist: &Whitelist,
+    ) -> anyhow::Result<Vec<IpAddr>> {
+        let validators: HashSet<_> = vote_accounts
+            .current
+            .iter()
+            .chain(&vote_accounts.delinquent)
+            .map(|vote| vote.node_pubkey.as_str())
+            .collect();
+        let mut ips = BTreeSet::new();
+        for contact in contacts.iter().filter(|contact| {
+            validators.contains(contact.pubkey.as_str()) && node_whitelist.contains(&contact.pubkey)
+        }) {
+            ips.extend(get_rpc_contact_ip(contact));
+            if self.geolocate_tpu {
+                ips.extend(get_gossip_contact_ip(contact));
+            }
+        }
+        let mut uncached = Vec::new();
+        for ip in ips.into_iter().filter(|ip| !is_private_ip(ip)) {
+            if self
+                .cache
+                .fetch_fresh_ip_address(&ip, self.maxmind.service())?
+                .is_none()
+            {
+                uncached.push(ip);
+            }
+        }
+        Ok(uncached)
+    }
+}
+
+#[async_trait]
+impl Monitor for GeolocationMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "IP address info"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(&self.gauges.isp_count),
+            family_name(&self.gauges.isp_by_stake),
+            family_name(&self.gauges.dc_by_stake),
+            family_name(&self.gauges.dc_delinquent_count),
+            family_name(&self.gauges.dc_delinquent_stake),
+            family_name(&self.gauges.validator_asn),
+            family_name(&self.gauges.tpu_asn_mismatch),
+            family_name(&self.gauges.private_ip_validators),
+        ]
+    }
+
+    async fn setup(&mut self) -> anyhow::Result<()> {
+        if let Some(snapshot) = self.snapshot.filter(|_| self.cluster_wide) {
+            snapshot.restore(&self.gauges.isp_count)?;
+            snapshot.restore(&self.gauges.isp_by_stake)?;
+            snapshot.restore(&self.gauges.dc_by_stake)?;
+            snapshot.restore(&self.gauges.dc_delinquent_count)?;
+            snapshot.restore(&self.gauges.dc_delinquent_stake)?;
+        }
+        Ok(())
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.last_contacts.extend(
+            cycle
+                .nodes
+                .iter()
+                .map(|node| (node.pubkey.clone(), node.clone())),
+        );
+        let contacts = self.last_contacts.values().cloned().collect::<Vec<_>>();
+        // The aggregates cover the validators of all shards.
+        let node_whitelist = if self.cluster_wide {
+            &cycle.unsharded_node_whitelist
+        } else {
+            &cycle.node_whitelist
+        };
+        if let Some(warmer) = self.warmer {
+            if !self.warm_up_started {
+                let ips = self.uncached_ips(&contacts, &cycle.vote_accounts, node_whitelist)?;
+                if !ips.is_empty() {
+                    warmer.warm_geolocation(self.maxmind.clone(), ips);
+                }
+                self.warm_up_started = true;
+            }
+            if warmer.geolocation_warming() {
+                debug!("Waiting for the validators to be geolocated");
+                return Ok(());
+            }
+        }
+        if self.cluster_wide {
+            self.gauges
+                .export_ip_addresses(
+                    &contacts,
+                    &cycle.vote_accounts,
+                    self.cache,
+                    &self.maxmind,
+                    node_whitelist,
+                )
+                .await?;
+        }
+        if self.geolocate_tpu {
+            self.gauges
+                .export_tpu_asns(
+                    &contacts,
+                    &cycle.vote_accounts,
+                    self.cache,
+                    &self.maxmind,
+                    &cycle.node_whitelist,
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_private_ip;
+
+    #[test]
+    fn detects_private_ips() {
+        for ip in [
+            "10.0.0.1",
+            "172.16.5.4",
+            "192.168.1.1",
+            "127.0.0.1",
+            "0.0.0.0",
+            "169.254.1.1",
+            "100.64.0.1",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:192.168.1.1",
+        ] {
+            assert!(is_private_ip(&ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "8.8.8.8",
+            "100.128.0.1",
+            "2001:4860::8888",
+            "::ffff:1.1.1.1",
+        ] {
+            assert!(!is_private_ip(&ip.parse().unwrap()), "{}", ip);
+        }
+    }
+}
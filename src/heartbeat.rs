@@ -0,0 +1,56 @@
+//! Heartbeat pings to a dead man's switch, e.g. healthchecks.io.
+
+use crate::config::HeartbeatConfig;
+use anyhow::Context;
+use log::{debug, warn};
+use std::time::{Duration, Instant};
+
+/// Pings the URL of a [`HeartbeatConfig`] after fully successful update cycles, at most once per
+/// interval. The receiver alerts when the pings stop, which also covers the exporter being down
+/// altogether or unable to reach its RPC endpoint, and the monitoring stack failing with it.
+pub struct Heartbeat {
+    client: reqwest::Client,
+    url: String,
+    /// Minimum time between two pings.
+    interval: Duration,
+    /// When the last ping was sent.
+    last_ping: Option<Instant>,
+}
+
+impl Heartbeat {
+    /// Makes a heartbeat pinging the URL of `config` using `client`.
+    pub fn new(client: reqwest::Client, config: HeartbeatConfig) -> Self {
+        Self {
+            client,
+            url: config.url,
+            interval: Duration::from_secs(config.interval.unwrap_or(60)),
+            last_ping: None,
+        }
+    }
+
+    /// Pings the URL if the interval has elapsed since the last ping. To be called after every
+    /// fully successful update cycle; a failed ping is logged and retried after the next one.
+    pub async fn beat(&mut self) {
+        if self.last_ping.is_some_and(|t| t.elapsed() < self.interval) {
+            return;
+        }
+        match self.ping().await {
+            Ok(()) => {
+                debug!("Sent heartbeat");
+                self.last_ping = Some(Instant::now());
+            }
+            Err(e) => warn!("Failed to send heartbeat: {e:#}"),
+        }
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        self.client
+            .get(&self.url)
+            .send()
+            .await
+            .with_context(|| format!("could not reach {}", self.url))?
+            .error_for_status()
+            .with_context(|| format!("{} rejected the heartbeat", self.url))?;
+        Ok(())
+    }
+}
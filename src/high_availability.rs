@@ -0,0 +1,231 @@
+//! Leader election between exporter replicas through a lease file on shared storage.
+
+use crate::config::HighAvailabilityConfig;
+use anyhow::{anyhow, Context};
+use log::{info, warn};
+use prometheus_exporter::prometheus::{register_int_gauge, IntGauge};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use time::OffsetDateTime;
+
+/// The holder of a lease and when it expires, in Unix seconds.
+#[derive(Debug, PartialEq)]
+struct Lease {
+    holder: String,
+    expires: i64,
+}
+
+impl Lease {
+    fn parse(contents: &str) -> Option<Self> {
+        let (holder, expires) = contents.trim().rsplit_once(' ')?;
+        Some(Self {
+            holder: holder.to_string(),
+            expires: expires.parse().ok()?,
+        })
+    }
+
+    fn format(&self) -> String {
+        format!("{} {}\n", self.holder, self.expires)
+    }
+}
+
+/// A lease on the leadership of a set of exporter replicas sharing a lease file.
+///
+/// Only the leader collects metrics; the standbys skip their update cycles, so they make no RPC
+/// calls, and serve none of the collected metrics. The leader renews the lease after every
+/// successful base RPC fetch, so a replica whose RPC path fails gives up the leadership along
+/// with one that dies. Once the lease has expired, the first standby to poll it takes over by
+/// replacing the file. Two standbys racing for an expired lease can both briefly lead, until the
+/// loser sees the winner's name in the file on its next poll. A leader that shuts down releases
+/// the lease when dropped, so a standby takes over on its next poll.
+pub struct LeaderLease {
+    /// Path of the shared lease file.
+    path: PathBuf,
+    /// Name of this replica in the lease file.
+    instance_id: String,
+    /// Seconds a lease lasts without being renewed.
+    duration: i64,
+    /// When this replica's lease expires, if it is the leader.
+    expires: Option<i64>,
+    /// Prometheus gauge of whether this replica is the leader.
+    leader: IntGauge,
+}
+
+impl LeaderLease {
+    /// Makes a lease of the file of `config`, which no replica holds yet, and registers its
+    /// metric.
+    pub fn new(config: HighAvailabilityConfig) -> anyhow::Result<Self> {
+        let duration = config.lease.unwrap_or(60);
+        if duration < 2 {
+            return Err(anyhow!("the leader lease must last at least 2 seconds"));
+        }
+        let instance_id = config.instance_id.unwrap_or_else(default_instance_id);
+        if instance_id.is_empty()
+            || instance_id.contains(|c: char| c.is_whitespace() || std::path::is_separator(c))
+        {
+            return Err(anyhow!("invalid instance id {:?}", instance_id));
+        }
+        Ok(Self {
+            path: config.lock_file,
+            instance_id,
+            duration: duration as i64,
+            expires: None,
+            leader: register_int_gauge!(
+                "solana_exporter_leader",
+                "Whether this exporter replica is the leader collecting the metrics"
+            )?,
+        })
+    }
+
+    /// Whether this replica leads, polling the lease file. Takes over an expired lease. If the
+    /// file cannot be read or written, the replica keeps its role.
+    pub fn poll(&mut self) -> bool {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        match self.poll_at(now) {
+            Ok(leader) => self.set_leader(leader),
+            Err(e) => warn!("Failed to poll the leader lease: {e:#}"),
+        }
+        self.expires.is_some()
+    }
+
+    /// Renews the lease if this replica leads and half of it has passed.
+    pub fn renew(&mut self) {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        if self
+            .expires
+            .is_some_and(|expires| expires - now < self.duration / 2)
+        {
+            if let Err(e) = self.write(now) {
+                warn!("Failed to renew the leader lease: {e:#}");
+            }
+        }
+    }
+
+    fn poll_at(&mut self, now: i64) -> anyhow::Result<bool> {
+        let current = match fs::read_to_string(&self.path) {
+            Ok(contents) => Lease::parse(&contents),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(e).with_context(|| format!("could not read {}", self.path.display()))
+            }
+        };
+        match current {
+            Some(lease) if lease.holder == self.instance_id => {
+                self.expires = Some(lease.expires);
+                Ok(true)
+            }
+            Some(lease) if lease.expires > now => Ok(false),
+            // Free, expired or unreadable.
+            _ => {
+                self.write(now)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Takes or renews the lease from `now` on, replacing the file atomically.
+    fn write(&mut self, now: i64) -> anyhow::Result<()> {
+        let lease = Lease {
+            holder: self.instance_id.clone(),
+            expires: now + self.duration,
+        };
+        // Named after the instance, since replicas on different hosts may share a process id.
+        let tmp = self
+            .path
+            .with_extension(format!("{}.tmp", self.instance_id));
+        let mut file = fs::File::create(&tmp)
+            .with_context(|| format!("could not create {}", tmp.display()))?;
+        file.write_all(lease.format().as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("could not replace {}", self.path.display()))?;
+        self.expires = Some(lease.expires);
+        Ok(())
+    }
+
+    fn set_leader(&mut self, leader: bool) {
+        if leader != (self.leader.get() == 1) {
+            if leader {
+                info!("Leading as {}", self.instance_id);
+            } else {
+                info!("Standing by as {}, another replica leads", self.instance_id);
+            }
+        }
+        if !leader {
+            self.expires = None;
+        }
+        self.leader.set(leader as i64);
+    }
+}
+
+impl Drop for LeaderLease {
+    /// Removes the lease file if this replica still holds the lease.
+    fn drop(&mut self) {
+        if self.expires.is_none() {
+            return;
+        }
+        let held = fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|contents| Lease::parse(&contents))
+            .is_some_and(|lease| lease.holder == self.instance_id);
+        if held {
+            match fs::remove_file(&self.path) {
+                Ok(()) => info!("Released the leader lease"),
+                Err(e) => warn!("Failed to release the leader lease: {e:#}"),
+            }
+        }
+    }
+}
+
+/// Returns the host name and process id, e.g. `exporter-1-4242`.
+fn default_instance_id() -> String {
+    let hostname = std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "solana-exporter".to_string());
+    format!("{}-{}", hostname, std::process::id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_exporter::prometheus::IntGauge;
+
+    #[test]
+    fn hands_over_expired_leases() {
+        let dir = std::env::temp_dir().join(format!("lease-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("leader.lock");
+        let lease = |instance_id: &str| LeaderLease {
+            path: path.clone(),
+            instance_id: instance_id.to_string(),
+            duration: 60,
+            expires: None,
+            leader: IntGauge::new("leader", "Leader").unwrap(),
+        };
+        let (mut a, mut b) = (lease("a"), lease("b"));
+
+        assert!(a.poll_at(1_000).unwrap());
+        assert!(!b.poll_at(1_030).unwrap());
+        assert!(a.poll_at(1_030).unwrap());
+        // `a` stopped renewing, so `b` takes over once the lease has expired.
+        assert!(b.poll_at(1_061).unwrap());
+        assert!(!a.poll_at(1_062).unwrap());
+        assert_eq!(
+            Lease::parse(&fs::read_to_string(&path).unwrap()),
+            Some(Lease {
+                holder: "b".to_string(),
+                expires: 1_121,
+            })
+        );
+        // Only the holder releases the lease.
+        drop(a);
+        assert!(path.exists());
+        drop(b);
+        assert!(!path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
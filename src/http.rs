@@ -0,0 +1,20 @@
+//! Outbound HTTP clients.
+
+use anyhow::Context;
+use reqwest::{ClientBuilder, NoProxy, Proxy};
+
+/// Returns a builder of HTTP clients that send all requests through `proxy`, an `http://`,
+/// `https://`, `socks5://` or `socks5h://` URL, except for hosts listed in `NO_PROXY`. Without
+/// an explicit proxy, the standard `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY`
+/// environment variables are honoured.
+pub fn client_builder(proxy: Option<&str>) -> anyhow::Result<ClientBuilder> {
+    let builder = reqwest::Client::builder();
+    Ok(match proxy {
+        Some(url) => builder.proxy(
+            Proxy::all(url)
+                .with_context(|| format!("invalid proxy URL: {}", url))?
+                .no_proxy(NoProxy::from_env()),
+        ),
+        None => builder,
+    })
+}
@@ -0,0 +1,120 @@
+//! Progress of the RPC node through the stages of ingesting a slot.
+
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::rpc_api::RpcApi;
+use async_trait::async_trait;
+use prometheus_exporter::prometheus::IntGaugeVec;
+use solana_clock::Slot;
+use solana_commitment_config::CommitmentConfig;
+use std::time::Duration;
+
+/// Time between two queries.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The monitor of the highest slot the RPC node retransmitted shreds of, inserted shreds of, and
+/// processed, and the gap between each stage and the one before it.
+///
+/// A node whose processed slot lags the cluster may not be receiving shreds through turbine at
+/// all, or receive them but fall behind inserting them into its blockstore, or insert them but
+/// fall behind replaying them; the gaps tell these apart.
+pub struct IngestionMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Prometheus gauge.
+    slot: &'a IntGaugeVec,
+    /// Prometheus gauge.
+    gap: &'a IntGaugeVec,
+}
+
+impl<'a> IngestionMonitor<'a> {
+    /// Constructs a monitor of the primary endpoint of `rpc`.
+    pub fn new(rpc: &'a dyn RpcApi, slot: &'a IntGaugeVec, gap: &'a IntGaugeVec) -> Self {
+        Self { rpc, slot, gap }
+    }
+
+    /// Exports the slot of every stage and the gaps between them.
+    async fn export_slots(&self) -> anyhow::Result<()> {
+        let (retransmit, shred_insert, processed) = futures::try_join!(
+            self.rpc.get_max_retransmit_slot(),
+            self.rpc.get_max_shred_insert_slot(),
+            self.rpc
+                .get_slot_with_commitment(CommitmentConfig::processed()),
+        )?;
+        for (stage, slot) in [
+            ("retransmit", retransmit),
+            ("shred_insert", shred_insert),
+            ("processed", processed),
+        ] {
+            self.slot
+                .get_metric_with_label_values(&[stage])
+                .map(|c| c.set(slot as i64))?;
+        }
+        for (stage, gap) in gaps(retransmit, shred_insert, processed) {
+            self.gap
+                .get_metric_with_label_values(&[stage])
+                .map(|c| c.set(gap))?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Monitor for IngestionMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "slot ingestion"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![family_name(self.slot), family_name(self.gap)]
+    }
+
+    fn interval(&self) -> Duration {
+        REFRESH_INTERVAL
+    }
+
+    async fn collect(&mut self, _cycle: &Cycle) -> anyhow::Result<()> {
+        self.export_slots().await
+    }
+}
+
+/// Returns the number of slots the shred insertion is behind the retransmission, and the
+/// processing behind the shred insertion, by the lagging stage. Shreds are retransmitted before
+/// they are inserted, but the slots are not queried at once, so a stage ahead counts as no gap.
+fn gaps(retransmit: Slot, shred_insert: Slot, processed: Slot) -> [(&'static str, i64); 2] {
+    [
+        (
+            "shred_insert",
+            retransmit.saturating_sub(shred_insert) as i64,
+        ),
+        ("processed", shred_insert.saturating_sub(processed) as i64),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_api::mock::MockRpc;
+    use prometheus_exporter::prometheus::Opts;
+
+    #[tokio::test]
+    async fn exports_gaps_between_stages() {
+        let rpc = MockRpc {
+            max_retransmit_slot: Some(1_010),
+            max_shred_insert_slot: Some(1_004),
+            slot: Some(1_000),
+            ..MockRpc::default()
+        };
+        let slot = IntGaugeVec::new(Opts::new("slot", "Slot"), &["stage"]).unwrap();
+        let gap = IntGaugeVec::new(Opts::new("gap", "Gap"), &["stage"]).unwrap();
+        let monitor = IngestionMonitor::new(&rpc, &slot, &gap);
+        monitor.export_slots().await.unwrap();
+
+        assert_eq!(slot.with_label_values(&["retransmit"]).get(), 1_010);
+        assert_eq!(gap.with_label_values(&["shred_insert"]).get(), 6);
+        assert_eq!(gap.with_label_values(&["processed"]).get(), 4);
+        assert_eq!(
+            gaps(1_000, 1_001, 1_002),
+            [("shred_insert", 0), ("processed", 0)]
+        );
+    }
+}
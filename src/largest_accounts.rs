@@ -0,0 +1,123 @@
+//! Concentration of the circulating supply in the largest accounts.
+
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::rpc_api::RpcApi;
+use crate::rpc_extra::LAMPORTS_PER_SOL;
+use async_trait::async_trait;
+use prometheus_exporter::prometheus::{Gauge, IntGaugeVec};
+use solana_client::rpc_response::RpcAccountBalance;
+use std::time::Duration;
+
+/// Time between two queries. `getLargestAccounts` scans every account, so RPC nodes cache it for
+/// hours.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// The monitor of the share of the circulating supply held by the largest accounts.
+///
+/// `getLargestAccounts` only returns the 20 largest circulating accounts, so the counts above the
+/// thresholds cannot exceed 20.
+pub struct LargestAccountsMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Prometheus gauge.
+    supply_share: &'a Gauge,
+    /// Prometheus gauge.
+    above_threshold: &'a IntGaugeVec,
+    /// Balances in SOL the largest accounts are counted above.
+    thresholds: Vec<u64>,
+}
+
+impl<'a> LargestAccountsMonitor<'a> {
+    /// Constructs a monitor given `rpc`, counting the largest accounts above each of `thresholds`
+    /// in SOL.
+    pub fn new(
+        rpc: &'a dyn RpcApi,
+        supply_share: &'a Gauge,
+        above_threshold: &'a IntGaugeVec,
+        thresholds: Vec<u64>,
+    ) -> Self {
+        Self {
+            rpc,
+            supply_share,
+            above_threshold,
+            thresholds,
+        }
+    }
+
+    /// Exports the share of the circulating supply held by the largest accounts, and their
+    /// numbers above the thresholds.
+    async fn export_concentration(&self) -> anyhow::Result<()> {
+        let (accounts, supply) =
+            futures::try_join!(self.rpc.get_largest_accounts(), self.rpc.get_supply())?;
+        if let Some(share) = supply_share(&accounts, supply.circulating) {
+            self.supply_share.set(share);
+        }
+        for threshold in &self.thresholds {
+            self.above_threshold
+                .get_metric_with_label_values(&[&threshold.to_string()])
+                .map(|c| c.set(count_above(&accounts, *threshold) as i64))?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Monitor for LargestAccountsMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "largest accounts"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(self.supply_share),
+            family_name(self.above_threshold),
+        ]
+    }
+
+    fn interval(&self) -> Duration {
+        REFRESH_INTERVAL
+    }
+
+    async fn collect(&mut self, _cycle: &Cycle) -> anyhow::Result<()> {
+        self.export_concentration().await
+    }
+}
+
+/// Returns the percentage of `circulating` lamports held by `accounts`, or `None` without a
+/// circulating supply.
+fn supply_share(accounts: &[RpcAccountBalance], circulating: u64) -> Option<f64> {
+    if circulating == 0 {
+        return None;
+    }
+    let held: u64 = accounts.iter().map(|a| a.lamports).sum();
+    Some(held as f64 / circulating as f64 * 100.0)
+}
+
+/// Returns the number of `accounts` holding more than `threshold` SOL.
+fn count_above(accounts: &[RpcAccountBalance], threshold: u64) -> usize {
+    accounts
+        .iter()
+        .filter(|a| a.lamports as f64 / LAMPORTS_PER_SOL > threshold as f64)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_concentration_of_largest_accounts() {
+        let accounts: Vec<_> = [30_000_000, 12_000_000, 8_000_000]
+            .iter()
+            .map(|sol| RpcAccountBalance {
+                address: String::new(),
+                lamports: sol * LAMPORTS_PER_SOL as u64,
+            })
+            .collect();
+        let circulating = 500_000_000 * LAMPORTS_PER_SOL as u64;
+        assert_eq!(supply_share(&accounts, circulating), Some(10.0));
+        assert_eq!(supply_share(&accounts, 0), None);
+        assert_eq!(count_above(&accounts, 10_000_000), 2);
+        assert_eq!(count_above(&accounts, 30_000_000), 0);
+    }
+}
@@ -0,0 +1,148 @@
+//! Wall-clock time since the last vote of whitelisted validators.
+
+use crate::config::Whitelist;
+use crate::monitor::{family_name, Cycle, Monitor};
+use async_trait::async_trait;
+use prometheus_exporter::prometheus::GaugeVec;
+use solana_client::rpc_response::RpcVoteAccountStatus;
+use solana_clock::{Slot, DEFAULT_MS_PER_SLOT};
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of cycles the observed slot times are kept for, about ten minutes of cycles.
+const MAX_OBSERVATIONS: usize = 600;
+
+/// The times at which slots were observed as the current slot, used to date older slots.
+#[derive(Debug, Default)]
+struct SlotClock {
+    /// Observed slots and their Unix times in seconds, by ascending slot.
+    observations: VecDeque<(Slot, f64)>,
+}
+
+impl SlotClock {
+    /// Records that `slot` was the current slot at `time`.
+    fn observe(&mut self, slot: Slot, time: f64) {
+        if self
+            .observations
+            .back()
+            .is_some_and(|(last, _)| slot <= *last)
+        {
+            return;
+        }
+        if self.observations.len() == MAX_OBSERVATIONS {
+            self.observations.pop_front();
+        }
+        self.observations.push_back((slot, time));
+    }
+
+    /// Estimates the time of `slot`, interpolating between the observed slots around it. Slots
+    /// before the first observation are dated at the average slot time of the observations, or
+    /// [`DEFAULT_MS_PER_SLOT`] with fewer than two.
+    fn time_of(&self, slot: Slot) -> Option<f64> {
+        let (first, last) = (self.observations.front()?, self.observations.back()?);
+        if slot >= last.0 {
+            return Some(last.1);
+        }
+        if slot < first.0 {
+            let slot_time = if last.0 > first.0 {
+                (last.1 - first.1) / (last.0 - first.0) as f64
+            } else {
+                DEFAULT_MS_PER_SLOT as f64 / 1000.0
+            };
+            return Some(first.1 - (first.0 - slot) as f64 * slot_time);
+        }
+        let after = self.observations.partition_point(|(s, _)| *s <= slot);
+        let (before, after) = (self.observations[after - 1], self.observations[after]);
+        let fraction = (slot - before.0) as f64 / (after.0 - before.0) as f64;
+        Some(before.1 + fraction * (after.1 - before.1))
+    }
+}
+
+/// The monitor of the time since the last vote of whitelisted validators.
+///
+/// The current slot is observed every cycle, and the last voted slot of each vote account is
+/// dated from these observations. This is measured in seconds rather than slots, which vary in
+/// duration.
+pub struct LastVoteMonitor<'a> {
+    /// Prometheus gauge.
+    seconds_since_last_vote: &'a GaugeVec,
+    /// Observed times of recent slots.
+    clock: SlotClock,
+}
+
+impl<'a> LastVoteMonitor<'a> {
+    pub fn new(seconds_since_last_vote: &'a GaugeVec) -> Self {
+        Self {
+            seconds_since_last_vote,
+            clock: SlotClock::default(),
+        }
+    }
+
+    /// Exports the seconds between the last vote of each vote account of
+    /// `vote_accounts_whitelist` and `now`, at which `slot` is the current slot.
+    fn export_seconds_since_last_vote(
+        &mut self,
+        vote_accounts: &RpcVoteAccountStatus,
+        vote_accounts_whitelist: &Whitelist,
+        slot: Slot,
+        now: f64,
+    ) -> anyhow::Result<()> {
+        self.clock.observe(slot, now);
+        for v in vote_accounts
+            .current
+            .iter()
+            .chain(&vote_accounts.delinquent)
+            .filter(|v| vote_accounts_whitelist.contains(&v.vote_pubkey))
+        {
+            if let Some(time) = self.clock.time_of(v.last_vote) {
+                self.seconds_since_last_vote
+                    .get_metric_with_label_values(&[&v.vote_pubkey])
+                    .map(|m| m.set((now - time).max(0.0)))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Monitor for LastVoteMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "last vote"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![family_name(self.seconds_since_last_vote)]
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+        self.export_seconds_since_last_vote(
+            &cycle.vote_accounts,
+            &cycle.vote_accounts_whitelist,
+            cycle.epoch_info.absolute_slot,
+            now,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_slots_from_observations() {
+        let mut clock = SlotClock::default();
+        assert_eq!(clock.time_of(100), None);
+
+        clock.observe(100, 1000.0);
+        assert_eq!(clock.time_of(100), Some(1000.0));
+        assert_eq!(clock.time_of(90), Some(996.0));
+
+        clock.observe(110, 1005.0);
+        // A slot observed again, e.g. while the RPC node is stuck, keeps its first time.
+        clock.observe(110, 1009.0);
+        assert_eq!(clock.time_of(104), Some(1002.0));
+        assert_eq!(clock.time_of(120), Some(1005.0));
+        assert_eq!(clock.time_of(80), Some(990.0));
+    }
+}
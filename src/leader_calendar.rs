@@ -0,0 +1,163 @@
+//! The `leader-calendar` subcommand: the leader windows of the whitelisted identities in the
+//! current epoch, with estimated times, as JSON or iCalendar.
+
+use crate::config::Whitelist;
+use crate::rpc_api::RpcApi;
+use crate::rpc_extra::{average_slot_time, leader_slots, node_pubkeys};
+use crate::slots::caching::LeaderScheduleCache;
+use anyhow::{anyhow, Context};
+use clap::ArgMatches;
+use serde::Serialize;
+use solana_clock::{Slot, DEFAULT_MS_PER_SLOT};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use time::{Format, OffsetDateTime};
+
+/// Consecutive leader slots of an identity.
+#[derive(Debug, PartialEq, Serialize)]
+struct LeaderWindow {
+    identity: String,
+    first_slot: Slot,
+    last_slot: Slot,
+    /// Estimated start of the first slot and end of the last slot, in RFC 3339.
+    start: String,
+    end: String,
+    #[serde(skip)]
+    start_time: OffsetDateTime,
+    #[serde(skip)]
+    end_time: OffsetDateTime,
+}
+
+/// `leader-calendar [--format json|ics] [--output FILE]`: writes the leader windows of the
+/// identities of the whitelisted vote accounts from the current slot to the end of the epoch.
+/// Their times are estimated from the average slot time of the epoch so far.
+pub async fn leader_calendar(
+    rpc: &dyn RpcApi,
+    vote_accounts_whitelist: &Whitelist,
+    cache: &LeaderScheduleCache,
+    args: &ArgMatches<'_>,
+) -> anyhow::Result<()> {
+    if vote_accounts_whitelist.0.is_empty() {
+        return Err(anyhow!(
+            "the leader calendar needs a vote account whitelist"
+        ));
+    }
+    let epoch_info = rpc.get_epoch_info().await?;
+    let node_whitelist = node_pubkeys(vote_accounts_whitelist, &rpc.get_vote_accounts().await?);
+    let schedule = leader_slots(rpc, cache, &epoch_info, &node_whitelist).await?;
+    let slot_time = average_slot_time(rpc, &epoch_info)
+        .await?
+        .unwrap_or(DEFAULT_MS_PER_SLOT as f64 / 1000.0);
+
+    let windows = windows(
+        &schedule,
+        epoch_info.absolute_slot,
+        OffsetDateTime::now_utc(),
+        slot_time,
+    );
+    let calendar = match args.value_of("format").unwrap_or("json") {
+        "ics" => ics(&windows, OffsetDateTime::now_utc()),
+        _ => serde_json::to_string_pretty(&windows)? + "\n",
+    };
+    match args.value_of("output") {
+        Some(path) => {
+            fs::write(path, calendar).with_context(|| format!("could not write {}", path))
+        }
+        None => {
+            print!("{}", calendar);
+            Ok(())
+        }
+    }
+}
+
+/// Groups the slots of `schedule` from `current_slot` on into windows of consecutive slots of
+/// the same identity, dated from `now` at `slot_time` seconds per slot.
+fn windows(
+    schedule: &BTreeMap<Slot, String>,
+    current_slot: Slot,
+    now: OffsetDateTime,
+    slot_time: f64,
+) -> Vec<LeaderWindow> {
+    let time = |slot: Slot| {
+        let offset = (slot as f64 - current_slot as f64) * slot_time;
+        now + time::Duration::milliseconds((offset * 1000.0) as i64)
+    };
+    let mut windows: Vec<LeaderWindow> = Vec::new();
+    for (&slot, identity) in schedule.range(current_slot..) {
+        if let Some(window) = windows.last_mut() {
+            if window.identity == *identity && window.last_slot + 1 == slot {
+                window.last_slot = slot;
+                window.end_time = time(slot + 1);
+                window.end = window.end_time.format(Format::Rfc3339);
+                continue;
+            }
+        }
+        let (start_time, end_time) = (time(slot), time(slot + 1));
+        windows.push(LeaderWindow {
+            identity: identity.clone(),
+            first_slot: slot,
+            last_slot: slot,
+            start: start_time.format(Format::Rfc3339),
+            end: end_time.format(Format::Rfc3339),
+            start_time,
+            end_time,
+        });
+    }
+    windows
+}
+
+/// Renders `windows` as an iCalendar file with an event per window, stamped `now`.
+fn ics(windows: &[LeaderWindow], now: OffsetDateTime) -> String {
+    const DATE_TIME: &str = "%Y%m%dT%H%M%SZ";
+    let mut calendar = String::from(
+        "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//rpcpool//solana-exporter//EN\r\n",
+    );
+    for window in windows {
+        // Writing to a string cannot fail.
+        let _ = write!(
+            calendar,
+            "BEGIN:VEVENT\r\nUID:{}-{}@solana-exporter\r\nDTSTAMP:{}\r\nDTSTART:{}\r\nDTEND:{}\r\n\
+             SUMMARY:Leader slots {}-{} of {}\r\nEND:VEVENT\r\n",
+            window.first_slot,
+            window.identity,
+            now.format(DATE_TIME),
+            window.start_time.format(DATE_TIME),
+            window.end_time.format(DATE_TIME),
+            window.first_slot,
+            window.last_slot,
+            window.identity,
+        );
+    }
+    calendar.push_str("END:VCALENDAR\r\n");
+    calendar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_consecutive_leader_slots() {
+        let schedule = [(96, "a"), (100, "a"), (101, "a"), (102, "b"), (104, "a")]
+            .iter()
+            .map(|(slot, identity)| (*slot, identity.to_string()))
+            .collect();
+        let now = OffsetDateTime::from_unix_timestamp(1_700_000_000);
+        let windows = windows(&schedule, 100, now, 0.5);
+        let slots: Vec<_> = windows
+            .iter()
+            .map(|w| (w.identity.as_str(), w.first_slot, w.last_slot))
+            .collect();
+        assert_eq!(
+            slots,
+            vec![("a", 100, 101), ("b", 102, 102), ("a", 104, 104)]
+        );
+        assert_eq!(windows[0].start, "2023-11-14T22:13:20+00:00");
+        assert_eq!(windows[2].end, "2023-11-14T22:13:22+00:00");
+
+        let calendar = ics(&windows[..1], now);
+        assert!(calendar.contains("DTSTART:20231114T221320Z\r\nDTEND:20231114T221321Z\r\n"));
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+    }
+}
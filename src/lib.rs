@@ -0,0 +1,141 @@
+// Copyright 2021 Vladimir Komendantskiy
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Prometheus exporter of Solana validator metrics, as a library the `solana-exporter` binary is
+//! a command line wrapper of.
+//!
+//! The metrics are collected by [`monitor::Monitor`]s, such as [`rewards::RewardsMonitor`] for
+//! the staking rewards and APYs or [`slots::SkippedSlotsMonitor`] for the skip rates, into the
+//! Prometheus gauges of [`gauges::PrometheusGauges`], which are registered in the default
+//! Prometheus registry. Monitors keep their history in caches backed by a
+//! [`persistent_database::PersistentDatabase`], e.g. [`rewards::caching::RewardsCache`] and
+//! [`slots::caching::SlotsCache`].
+//!
+//! A service embedding the exporter registers the monitors it wants in a
+//! [`monitor::MonitorRegistry`], runs [`monitor::MonitorRegistry::setup`] once, and then on every
+//! update cycle fetches a [`monitor::Cycle`] of cluster data with [`monitor::Cycle::fetch`] and
+//! runs [`monitor::MonitorRegistry::collect`] with it. The metrics can then be gathered with
+//! `prometheus::gather()`, or served with [`server::Exporter`].
+//!
+//! The `solana-exporter` binary runs the same way, from an [`config::ExporterConfig`]:
+//! [`service::Stores`] opens the caches, [`service::register_monitors`] registers the monitors
+//! the config enables, and [`service::UpdateLoop`] runs the update cycles, fetched by a
+//! [`monitor::CycleFetcher`] that caches the cluster nodes and splits the whitelists into shards.
+//!
+//! ```no_run
+//! use solana_exporter::config::{BalanceUnit, Whitelist};
+//! use solana_exporter::gauges::PrometheusGauges;
+//! use solana_exporter::monitor::{Cycle, MonitorRegistry};
+//! use solana_exporter::persistent_database::counters::{CounterBaselines, COUNTERS_TREE_NAME};
+//! use solana_exporter::persistent_database::PersistentDatabase;
+//! use solana_exporter::rpc_pool::RpcPool;
+//! use solana_exporter::slots::caching::{SlotsCache, BLOCK_PRODUCTION_TREE_NAME};
+//! use solana_exporter::slots::SkippedSlotsMonitor;
+//! use solana_exporter::uptime::caching::{ProductionHistory, PRODUCTION_HISTORY_TREE_NAME};
+//! use std::path::Path;
+//! use std::time::Duration;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let rpc = RpcPool::new(
+//!     &[("http://localhost:8899".to_string(), 1)],
+//!     Duration::from_secs(60),
+//!     None,
+//! )?;
+//! let database = PersistentDatabase::new(Path::new("exporter-db"))?;
+//! let baselines = CounterBaselines::new(database.tree(COUNTERS_TREE_NAME)?);
+//! let slots_cache = SlotsCache::new(database.tree(BLOCK_PRODUCTION_TREE_NAME)?);
+//! let history = ProductionHistory::new(database.tree(PRODUCTION_HISTORY_TREE_NAME)?);
+//! let gauges = PrometheusGauges::new(reqwest::Client::new(), BalanceUnit::Lamports);
+//!
+//! let mut monitors = MonitorRegistry::new();
+//! monitors.register(SkippedSlotsMonitor::new(
+//!     &rpc,
+//!     &gauges.leader_slots,
+//!     &gauges.skipped_slot_percent,
+//!     &gauges.skipped_slot_percentile,
+//!     &gauges.skipped_slot_percent_vs_cluster,
+//!     &baselines,
+//!     &slots_cache,
+//!     &history,
+//! ));
+//! monitors.setup().await?;
+//! let vote_accounts: Whitelist = Whitelist::new(
+//!     ["13DmkMhdpmJJu7nU2ozAyPiKuopZbYShMHV3JAA7YVYC".to_string()].into(),
+//! );
+//! loop {
+//!     let cycle = Cycle::fetch(&rpc, vote_accounts.clone(), Whitelist::default()).await?;
+//!     monitors.collect(&cycle).await;
+//!     tokio::time::sleep(Duration::from_secs(10)).await;
+//! }
+//! # }
+//! ```
+
+pub mod address_activity;
+pub mod blocks;
+pub mod circuit_breaker;
+pub mod commission;
+pub mod config;
+pub mod credit_rate;
+pub mod delegators;
+pub mod endpoint_slots;
+pub mod forks;
+pub mod gauges;
+pub mod geolocation;
+pub mod heartbeat;
+pub mod high_availability;
+pub mod http;
+pub mod ingestion;
+pub mod largest_accounts;
+pub mod last_vote;
+pub mod leader_calendar;
+pub mod lockups;
+pub mod monitor;
+pub mod persistent_database;
+pub mod price;
+pub mod redelegations;
+pub mod reverse_dns;
+pub mod rewards;
+pub mod rpc_api;
+pub mod rpc_extra;
+pub mod rpc_pool;
+pub mod schedule;
+pub mod self_mode;
+pub mod server;
+pub mod service;
+pub mod sharding;
+pub mod sinks;
+pub mod slots;
+pub mod stake_pool;
+pub mod uptime;
+pub mod validator_client;
+pub mod validator_scores;
+pub mod verify_cache;
+pub mod vote_authorities;
+pub mod vote_fees;
+pub mod warmup;
+
+/// Name of directory where solana-exporter will store information
+pub const EXPORTER_DATA_DIR: &str = ".solana-exporter";
+/// Current version of `solana-exporter`
+pub const SOLANA_EXPORTER_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Git commit `solana-exporter` was built from, set by `build.rs`
+pub const SOLANA_EXPORTER_GIT_COMMIT: &str = match option_env!("SOLANA_EXPORTER_GIT_COMMIT") {
+    Some(commit) => commit,
+    None => "unknown",
+};
+/// Version of the compiler `solana-exporter` was built with, set by `build.rs`
+pub const SOLANA_EXPORTER_RUSTC_VERSION: &str = match option_env!("SOLANA_EXPORTER_RUSTC_VERSION") {
+    Some(version) => version,
+    None => "unknown",
+};
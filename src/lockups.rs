@@ -0,0 +1,168 @@
+//! Lockups of whitelisted stake accounts.
+
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::rpc_api::RpcApi;
+use crate::rpc_extra::MAX_MULTIPLE_ACCOUNTS;
+use anyhow::Context;
+use async_trait::async_trait;
+use log::debug;
+use prometheus_exporter::prometheus::IntGaugeVec;
+use solana_clock::Epoch;
+use solana_pubkey::Pubkey;
+use solana_stake_interface::state::{Lockup, StakeStateV2};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+/// Time between two queries of the stake accounts. Lockups rarely change, while the time left
+/// until they expire is updated every cycle.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// The monitor of the time left until the lockups of the whitelisted stake accounts expire.
+///
+/// A lockup keeps the stake account from being withdrawn from, or its authorities from being
+/// changed, without the signature of its custodian until both its Unix timestamp and its epoch
+/// have passed. Stake accounts without a lockup are exported with nothing left.
+pub struct LockupsMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Prometheus gauge.
+    seconds_remaining: &'a IntGaugeVec,
+    /// Prometheus gauge.
+    epochs_remaining: &'a IntGaugeVec,
+    /// The lockups of the last queried stake accounts, by pubkey.
+    lockups: BTreeMap<String, Lockup>,
+    /// The whitelist the stake accounts were last queried for.
+    whitelist: Vec<String>,
+    /// When the stake accounts were last queried.
+    last_query: Option<Instant>,
+}
+
+impl<'a> LockupsMonitor<'a> {
+    /// Constructs a monitor given `rpc`.
+    pub fn new(
+        rpc: &'a dyn RpcApi,
+        seconds_remaining: &'a IntGaugeVec,
+        epochs_remaining: &'a IntGaugeVec,
+    ) -> Self {
+        Self {
+            rpc,
+            seconds_remaining,
+            epochs_remaining,
+            lockups: BTreeMap::new(),
+            whitelist: Vec::new(),
+            last_query: None,
+        }
+    }
+
+    /// Queries the lockups of the stake accounts of `whitelist`. Accounts that do not exist or are
+    /// not initialized stake accounts are left out.
+    async fn query_lockups(&mut self, whitelist: Vec<String>) -> anyhow::Result<()> {
+        let pubkeys = whitelist
+            .iter()
+            .map(|pubkey| {
+                pubkey
+                    .parse::<Pubkey>()
+                    .with_context(|| format!("invalid stake account {}", pubkey))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut lockups = BTreeMap::new();
+        for chunk in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let accounts = self.rpc.get_multiple_accounts(chunk).await?;
+            for (pubkey, account) in chunk.iter().zip(accounts) {
+                let meta = account
+                    .and_then(|a| bincode::deserialize::<StakeStateV2>(&a.data).ok())
+                    .and_then(|state| state.meta());
+                match meta {
+                    Some(meta) => {
+                        lockups.insert(pubkey.to_string(), meta.lockup);
+                    }
+                    None => debug!("{} is not an initialized stake account", pubkey),
+                }
+            }
+        }
+        self.lockups = lockups;
+        self.whitelist = whitelist;
+        self.last_query = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Exports the time left until the last queried lockups expire, as of `now` in `epoch`.
+    fn export_lockups(&self, now: i64, epoch: Epoch) -> anyhow::Result<()> {
+        // Reset, so that stake accounts no longer whitelisted are dropped.
+        self.seconds_remaining.reset();
+        self.epochs_remaining.reset();
+        for (pubkey, lockup) in &self.lockups {
+            let (seconds, epochs) = remaining(lockup, now, epoch);
+            let custodian = if *lockup == Lockup::default() {
+                String::new()
+            } else {
+                lockup.custodian.to_string()
+            };
+            self.seconds_remaining
+                .get_metric_with_label_values(&[pubkey, &custodian])
+                .map(|m| m.set(seconds))?;
+            self.epochs_remaining
+                .get_metric_with_label_values(&[pubkey, &custodian])
+                .map(|m| m.set(epochs as i64))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the seconds and epochs left of `lockup` as of `now` in `epoch`, zero once passed.
+fn remaining(lockup: &Lockup, now: i64, epoch: Epoch) -> (i64, Epoch) {
+    (
+        (lockup.unix_timestamp - now).max(0),
+        lockup.epoch.saturating_sub(epoch),
+    )
+}
+
+#[async_trait]
+impl Monitor for LockupsMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "stake account lockup"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(self.seconds_remaining),
+            family_name(self.epochs_remaining),
+        ]
+    }
+
+    /// Without a stake account whitelist, which then stands for all stake accounts, nothing is
+    /// exported.
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        let mut whitelist: Vec<_> = cycle.staking_account_whitelist.0.iter().cloned().collect();
+        whitelist.sort_unstable();
+        let due = self
+            .last_query
+            .is_none_or(|t| t.elapsed() >= REFRESH_INTERVAL);
+        if due || whitelist != self.whitelist {
+            self.query_lockups(whitelist).await?;
+        }
+        self.export_lockups(
+            OffsetDateTime::now_utc().unix_timestamp(),
+            cycle.epoch_info.epoch,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_down_to_the_later_of_timestamp_and_epoch() {
+        let lockup = Lockup {
+            unix_timestamp: 1_700_000_000,
+            epoch: 500,
+            custodian: Pubkey::new_unique(),
+        };
+        assert_eq!(remaining(&lockup, 1_699_999_000, 498), (1_000, 2));
+        assert_eq!(remaining(&lockup, 1_700_000_500, 499), (0, 1));
+        assert_eq!(remaining(&lockup, 1_700_000_500, 501), (0, 0));
+        assert_eq!(remaining(&Lockup::default(), 1_700_000_000, 500), (0, 0));
+    }
+}
@@ -0,0 +1,150 @@
+//! Log levels from the `[logging]` section of the config, `RUST_LOG` and `--log-level`.
+
+use anyhow::{anyhow, Context};
+use log::{LevelFilter, Log, Metadata, Record};
+use solana_exporter::config::LoggingConfig;
+use std::env;
+use std::sync::RwLock;
+
+/// The logger, which is set up from the command line first and reconfigured once the config is
+/// read, so that nothing logged before is lost.
+static LOGGER: Reconfigurable = Reconfigurable(RwLock::new(None));
+
+/// A logger whose levels can be changed after it is installed, unlike `env_logger`'s.
+struct Reconfigurable(RwLock<Option<env_logger::Logger>>);
+
+impl Log for Reconfigurable {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let logger = self.0.read().expect("poisoned lock, should never happen");
+        logger
+            .as_ref()
+            .is_some_and(|logger| logger.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        let logger = self.0.read().expect("poisoned lock, should never happen");
+        if let Some(logger) = logger.as_ref() {
+            logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        let logger = self.0.read().expect("poisoned lock, should never happen");
+        if let Some(logger) = logger.as_ref() {
+            logger.flush();
+        }
+    }
+}
+
+/// Initialises the logger with the levels of the `RUST_LOG` environment variable, then
+/// `log_level`, each overriding the levels set before it.
+pub fn init(log_level: Option<&str>) -> anyhow::Result<()> {
+    configure(None, log_level)?;
+    log::set_logger(&LOGGER).map_err(|_| anyhow!("could not initialise the logger"))
+}
+
+/// Sets the levels of the logger to those of `config`, then those of `RUST_LOG`, then
+/// `log_level`, each overriding the levels set before it.
+pub fn configure(config: Option<&LoggingConfig>, log_level: Option<&str>) -> anyhow::Result<()> {
+    let directives = directives(config, env::var("RUST_LOG").ok().as_deref(), log_level)?;
+    let mut builder = env_logger::Builder::new();
+    builder.parse_filters(&directives);
+    if let Ok(style) = env::var("RUST_LOG_STYLE") {
+        builder.parse_write_style(&style);
+    }
+    let logger = builder.build();
+    log::set_max_level(logger.filter());
+    *LOGGER
+        .0
+        .write()
+        .expect("poisoned lock, should never happen") = Some(logger);
+    Ok(())
+}
+
+/// Returns the `RUST_LOG` directives of `config`, then `rust_log` and `log_level`. Later
+/// directives for a module override earlier ones.
+fn directives(
+    config: Option<&LoggingConfig>,
+    rust_log: Option<&str>,
+    log_level: Option<&str>,
+) -> anyhow::Result<String> {
+    // The default of `env_logger` without `RUST_LOG`.
+    let mut directives = vec![LevelFilter::Error.to_string()];
+    if let Some(config) = config {
+        if let Some(level) = &config.level {
+            directives.push(parse_level(level).context("invalid [logging] level")?);
+        }
+        for (module, level) in config.modules.iter().flatten() {
+            let level = parse_level(level)
+                .with_context(|| format!("invalid [logging] level of {}", module))?;
+            directives.push(format!("{}={}", module, level));
+        }
+    }
+    directives.extend(rust_log.map(str::to_string));
+    if let Some(level) = log_level {
+        directives.push(parse_level(level).context("invalid --log-level")?);
+    }
+    Ok(directives.join(","))
+}
+
+/// Parses a log level, e.g. `debug`, failing on anything else.
+fn parse_level(level: &str) -> anyhow::Result<String> {
+    let level: LevelFilter = level.parse().map_err(|_| {
+        anyhow!(
+            "unknown level {:?}, expected off, error, warn, info, debug or trace",
+            level
+        )
+    })?;
+    Ok(level.to_string().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn overrides_config_levels() {
+        let config = LoggingConfig {
+            level: Some("warn".to_string()),
+            modules: Some(
+                std::iter::once(("solana_exporter::rewards".to_string(), "debug".to_string()))
+                    .collect(),
+            ),
+        };
+        let enabled = |directives: &str, target: &str, level: Level| {
+            env_logger::filter::Builder::new()
+                .parse(directives)
+                .build()
+                .enabled(&Metadata::builder().target(target).level(level).build())
+        };
+
+        let config_only = directives(Some(&config), None, None).unwrap();
+        assert!(enabled(
+            &config_only,
+            "solana_exporter::rewards::caching",
+            Level::Debug
+        ));
+        assert!(!enabled(
+            &config_only,
+            "solana_exporter::slots",
+            Level::Info
+        ));
+        assert!(enabled(&config_only, "solana_exporter::slots", Level::Warn));
+
+        let overridden = directives(Some(&config), Some("info"), Some("error")).unwrap();
+        assert!(!enabled(&overridden, "solana_exporter::slots", Level::Warn));
+        assert!(enabled(
+            &overridden,
+            "solana_exporter::rewards",
+            Level::Debug
+        ));
+
+        assert!(!enabled(
+            &directives(None, None, None).unwrap(),
+            "sled",
+            Level::Warn
+        ));
+        assert!(directives(None, None, Some("verbose")).is_err());
+    }
+}
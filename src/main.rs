@@ -12,64 +12,116 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::config::{ExporterConfig, Whitelist, CONFIG_FILE_NAME};
-use crate::gauges::PrometheusGauges;
-use crate::geolocation::api::MaxMindAPIKey;
-use crate::geolocation::caching::{GeolocationCache, GEO_DB_CACHE_TREE_NAME};
-use crate::persistent_database::{PersistentDatabase, DATABASE_FILE_NAME};
-use crate::rewards::caching::{
-    RewardsCache, APY_TREE_NAME, EPOCH_LENGTH_TREE_NAME, EPOCH_REWARDS_TREE_NAME,
-    EPOCH_VOTER_APY_TREE_NAME,
-};
-use crate::rewards::RewardsMonitor;
-use crate::slots::SkippedSlotsMonitor;
+mod commands;
+mod doctor;
+mod generate;
+mod logging;
+mod simulate;
+mod top;
+
 use anyhow::Context;
 use clap::{load_yaml, App};
-use log::{debug, warn};
-use solana_client::nonblocking::rpc_client::RpcClient;
+use log::{info, warn};
+use solana_exporter::config::whitelist::{ReloadableWhitelist, WhitelistSource};
+use solana_exporter::config::{profiles, solana_cli};
+use solana_exporter::config::{
+    AddressWatchlist, BalanceUnit, ExporterConfig, Mode, Targets, Whitelist, CONFIG_FILE_NAME,
+};
+use solana_exporter::endpoint_slots::endpoint_labels;
+use solana_exporter::gauges::PrometheusGauges;
+use solana_exporter::geolocation::api::MaxMindAPIKey;
+use solana_exporter::monitor::MonitorRegistry;
+use solana_exporter::persistent_database::snapshot::{GaugeSnapshot, GAUGE_SNAPSHOT_TREE_NAME};
+use solana_exporter::persistent_database::{PersistentDatabase, DATABASE_FILE_NAME};
+use solana_exporter::rewards::caching::RewardsCache;
+use solana_exporter::rewards::ApyOutlierRules;
+use solana_exporter::rpc_api::instrumented::InstrumentedRpc;
+use solana_exporter::rpc_api::RpcApi;
+use solana_exporter::rpc_pool::RpcPool;
+use solana_exporter::server::Exporter;
+use solana_exporter::service::{self, Stores, UpdateLoop};
+use solana_exporter::slots::caching::{LeaderScheduleCache, LEADER_SCHEDULE_TREE_NAME};
+use solana_exporter::uptime::caching::{ProductionHistory, PRODUCTION_HISTORY_TREE_NAME};
+use solana_exporter::warmup::CacheWarmer;
+use solana_exporter::{
+    gauges, http, leader_calendar, self_mode, server, verify_cache, EXPORTER_DATA_DIR,
+};
+use std::collections::HashMap;
 use std::fs::{create_dir_all, File};
 use std::io::Write;
 use std::net::SocketAddr;
 use std::path::Path;
+use std::sync::Arc;
 use std::{fs, time::Duration};
 
-pub mod config;
-pub mod gauges;
-pub mod geolocation;
-pub mod persistent_database;
-pub mod rewards;
-pub mod rpc_extra;
-pub mod slots;
-
-/// Name of directory where solana-exporter will store information
-pub const EXPORTER_DATA_DIR: &str = ".solana-exporter";
-/// Current version of `solana-exporter`
-pub const SOLANA_EXPORTER_VERSION: &str = env!("CARGO_PKG_VERSION");
-
-// The metric-update guard returned by `exporter.wait_duration` is intentionally
-// held across the async MaxMind queries in the update loop, so a concurrent
-// `/metrics` scrape blocks until a full, consistent update is published rather
-// than observing a half-updated set. See the loop body for details.
-#[allow(clippy::await_holding_lock)]
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    env_logger::init();
     // Read from CLI arguments
     let yaml = load_yaml!("cli.yml");
     let cli_configs = App::from_yaml(yaml).get_matches();
+    let log_level = cli_configs.value_of("log-level");
+    // Logs at the levels of `RUST_LOG` and `--log-level` until the config is read.
+    logging::init(log_level)?;
 
     // Subcommands
     if let ("generate", Some(sc)) = cli_configs.subcommand() {
-        let template_config = ExporterConfig {
-            rpc: "http://localhost:8899".to_string(),
-            target: SocketAddr::new("0.0.0.0".parse()?, 9179),
+        let mut template_config = ExporterConfig {
+            rpc: Some("http://localhost:8899".to_string()),
+            target: Targets(vec![SocketAddr::new("0.0.0.0".parse()?, 9179)]),
+            metrics_path: Some(server::DEFAULT_METRICS_PATH.to_string()),
             maxmind: Some(MaxMindAPIKey::new("username", "password")),
-            vote_account_whitelist: Some(Whitelist::default()),
-            staking_account_whitelist: Some(Whitelist::default()),
+            vote_account_whitelist: Some(WhitelistSource::Inline(Whitelist::default())),
+            staking_account_whitelist: Some(WhitelistSource::Inline(Whitelist::default())),
+            mode: Some(Mode::Cluster),
+            identity: None,
+            identity_keypair: None,
+            address_watchlist: Some(AddressWatchlist::default()),
             enable_rewards: Some(true),
+            reward_backfill_concurrency: Some(2),
+            reward_recheck_interval: Some(3600),
+            max_staking_apy: None,
+            clamp_staking_apy: Some(false),
+            apy_min_stake_sol: Some(0.0),
             enable_skipped_slots: Some(true),
+            enable_vote_fees: Some(true),
+            enable_block_compute_units: Some(false),
+            enable_delegator_count: Some(false),
+            stake_pools: Some(vec![]),
             enable_gossip_node_info: Some(false),
+            enable_reverse_dns: Some(false),
+            enable_tpu_geolocation: Some(false),
+            commission_rug_window: Some(10_000),
+            commission_rug_threshold: Some(10),
+            aggregates_only: Some(false),
+            circuit_breaker_threshold: Some(5),
+            circuit_breaker_cooldown: Some(30),
+            cluster_nodes_ttl: Some(60),
+            rpc_weight: Some(1),
+            proxy: None,
+            enable_largest_accounts: Some(false),
+            largest_account_thresholds: Some(vec![1_000_000, 10_000_000]),
+            balance_unit: Some(BalanceUnit::Lamports),
+            shard_index: Some(0),
+            shard_count: Some(1),
+            rpc_endpoints: None,
+            influxdb: None,
+            plaintext_sink: None,
+            validator_scores: None,
+            price: None,
+            metric_labels: None,
+            heartbeat: None,
+            high_availability: None,
+            logging: None,
+            scheduling: None,
         };
+        generate::apply_args(&mut template_config, sc)?;
+        if sc.is_present("interactive") {
+            generate::prompt_config(
+                &mut template_config,
+                &mut std::io::stdin().lock(),
+                &mut std::io::stdout(),
+            )?;
+        }
 
         let location = sc
             .value_of("output")
@@ -92,180 +144,332 @@ async fn main() -> anyhow::Result<()> {
         std::process::exit(0);
     }
 
-    let persistent_database = {
-        // Use override from CLI or default.
-        let location = cli_configs
-            .value_of("database")
-            .map(|s| Path::new(s).to_path_buf())
-            .unwrap_or_else(|| {
-                dirs::home_dir()
-                    .unwrap()
-                    .join(EXPORTER_DATA_DIR)
-                    .join(DATABASE_FILE_NAME)
-            });
+    if let Some(profile) = cli_configs.value_of("profile") {
+        profiles::check_name(profile)?;
+    }
+
+    // Use overrides from CLI or defaults.
+    let database_location = cli_configs
+        .value_of("database")
+        .map(|s| Path::new(s).to_path_buf())
+        .unwrap_or_else(|| {
+            let data_dir = dirs::home_dir().unwrap().join(EXPORTER_DATA_DIR);
+            // Profiles are usually different clusters, whose caches must not mix.
+            match cli_configs.value_of("profile") {
+                Some(profile) => data_dir.join(format!("{}-{}", profile, DATABASE_FILE_NAME)),
+                None => data_dir.join(DATABASE_FILE_NAME),
+            }
+        });
+    let config_location = cli_configs
+        .value_of("config")
+        .map(|s| Path::new(s).to_path_buf())
+        .unwrap_or_else(|| {
+            dirs::home_dir()
+                .unwrap()
+                .join(EXPORTER_DATA_DIR)
+                .join(CONFIG_FILE_NAME)
+        });
+
+    // Checks the setup without opening the database, which a running exporter holds locked.
+    if let ("doctor", Some(_)) = cli_configs.subcommand() {
+        return doctor::doctor(&doctor::Setup {
+            config: &config_location,
+            database: &database_location,
+            profile: cli_configs.value_of("profile"),
+            url: cli_configs.value_of("url"),
+        })
+        .await;
+    }
 
-        // Show warning if database not found, since sled will make a new file?
-        if !location.exists() {
-            warn!("Database could not found at specified location. A new one will be generated!")
+    // Checked before the database is opened, since sled makes a new file.
+    let new_database = !database_location.exists();
+    let warn_new_database = || {
+        if new_database {
+            warn!("Database could not found at specified location. A new one was generated!")
         }
+    };
 
-        PersistentDatabase::new(&location)
-    }?;
+    // These commands do not read the config, and log at the levels of `RUST_LOG` and
+    // `--log-level` only. The uptime report only reads the history, from a copy of the database,
+    // which a running exporter holds locked.
+    if let ("apy" | "db" | "uptime-report", Some(sc)) = cli_configs.subcommand() {
+        let persistent_database = if cli_configs.subcommand_name() == Some("uptime-report") {
+            PersistentDatabase::open_read_only(&database_location)?
+        } else {
+            let persistent_database = PersistentDatabase::new(&database_location)?;
+            warn_new_database();
+            persistent_database
+        };
+        return match (cli_configs.subcommand_name(), sc.subcommand()) {
+            (Some("apy"), _) => commands::apy(&persistent_database, sc),
+            (Some("db"), ("stats", Some(_))) => commands::db_stats(&persistent_database),
+            (Some("db"), ("prune", Some(sc))) => commands::db_prune(&persistent_database, sc),
+            (Some("uptime-report"), _) => commands::uptime_report(&persistent_database, sc),
+            _ => Ok(()),
+        };
+    }
 
     let config = {
-        // Use override from CLI or default.
-        let location = cli_configs
-            .value_of("config")
-            .map(|s| Path::new(s).to_path_buf())
-            .unwrap_or_else(|| {
-                dirs::home_dir()
-                    .unwrap()
-                    .join(EXPORTER_DATA_DIR)
-                    .join(CONFIG_FILE_NAME)
-            });
-
-        let file_contents = fs::read_to_string(location).context(
+        let file_contents = fs::read_to_string(&config_location).context(
             "Could not find config file in specified location. \
 If running for the first time, run `solana-exporter generate` to initialise the config file \
 and then put real values there.",
         )?;
 
-        toml::from_str::<ExporterConfig>(&file_contents)
+        profiles::parse_config(&file_contents, cli_configs.value_of("profile"))
     }?;
+    logging::configure(config.logging.as_ref(), log_level)?;
+    // Whitelist files are looked up next to the config file.
+    let config_dir = config_location.parent().unwrap_or_else(|| Path::new(""));
 
-    let exporter = prometheus_exporter::start(config.target)?;
-    let duration = Duration::from_secs(1);
-    // Interim generous ceiling: a single timeout aborts the whole update cycle
-    // and exits the process, so until per-export error isolation lands, give
-    // slow calls (e.g. epoch-boundary `getBlock` on the rewards path) room to
-    // complete rather than triggering a cold restart.
-    let client = RpcClient::new_with_timeout(config.rpc.clone(), Duration::from_secs(120));
-
-    let geolocation_cache =
-        GeolocationCache::new(persistent_database.tree(GEO_DB_CACHE_TREE_NAME)?);
-    let rewards_cache = RewardsCache::new(
-        persistent_database.tree(EPOCH_REWARDS_TREE_NAME)?,
-        persistent_database.tree(APY_TREE_NAME)?,
-        persistent_database.tree(EPOCH_LENGTH_TREE_NAME)?,
-        persistent_database.tree(EPOCH_VOTER_APY_TREE_NAME)?,
+    let rpc_url = solana_cli::rpc_url(cli_configs.value_of("url"), config.rpc.as_deref())?;
+    let mut endpoints = vec![(rpc_url, config.rpc_weight.unwrap_or(1))];
+    endpoints.extend(
+        config
+            .rpc_endpoints
+            .iter()
+            .flatten()
+            .map(|e| (e.url.clone(), e.weight.unwrap_or(1))),
     );
+    // Only the hosts are logged, since URLs often carry API keys.
+    info!(
+        "Using RPC endpoints {}",
+        endpoint_labels(endpoints.iter().map(|(url, _)| url.as_str())).join(", ")
+    );
+    let proxy = config.proxy.as_deref();
+    let rpc_pool = Arc::new(InstrumentedRpc::new(RpcPool::new(
+        &endpoints,
+        // Room for slow calls, e.g. `getBlock` at an epoch boundary on the rewards path.
+        Duration::from_secs(120),
+        proxy,
+    )?));
+    let client: &dyn RpcApi = &*rpc_pool;
 
-    let vote_accounts_whitelist = config.vote_account_whitelist.unwrap_or_default();
-    let staking_account_whitelist = config.staking_account_whitelist.unwrap_or_default();
-    let enable_rewards = config.enable_rewards.unwrap_or(true);
-    let enable_skipped_slots = config.enable_skipped_slots.unwrap_or(true);
-    let enable_gossip_node_info = config.enable_gossip_node_info.unwrap_or(false);
-
-    let gauges = PrometheusGauges::new(vote_accounts_whitelist.clone());
-    let mut skipped_slots_monitor = if enable_skipped_slots {
-        Some(SkippedSlotsMonitor::new(
-            &client,
-            &gauges.leader_slots,
-            &gauges.skipped_slot_percent,
-        ))
-    } else {
-        None
-    };
+    let self_mode = config.mode.unwrap_or_default() == Mode::SelfValidator;
 
-    let rewards_monitor = if enable_rewards {
-        Some(RewardsMonitor::new(
-            &client,
-            &gauges.current_staking_apy,
-            &gauges.average_staking_apy,
-            &gauges.validator_rewards,
+    // Replays the cache without RPC calls, on a copy of the database, which a running exporter
+    // holds locked. In self mode, the vote account of the identity is not looked up: the vote
+    // accounts with a production history, which only whitelisted validators have, stand in for it.
+    if let ("simulate", Some(sc)) = cli_configs.subcommand() {
+        let interval = sc
+            .value_of("interval")
+            .map(str::parse::<u64>)
+            .transpose()
+            .context("invalid replay interval")?
+            .unwrap_or(10);
+        let persistent_database = PersistentDatabase::open_read_only(&database_location)?;
+        let rewards_cache = RewardsCache::open(&persistent_database)?;
+        let production_history =
+            ProductionHistory::new(persistent_database.tree(PRODUCTION_HISTORY_TREE_NAME)?);
+        let vote_accounts_whitelist = if self_mode {
+            Whitelist::new(
+                production_history
+                    .vote_pubkeys()?
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+            )
+        } else {
+            ReloadableWhitelist::new(
+                "vote account whitelist",
+                config.vote_account_whitelist.clone(),
+                config_dir,
+            )?
+            .get()
+            .clone()
+        };
+        let gauges = PrometheusGauges::new(
+            http::client_builder(proxy)?.build()?,
+            config.balance_unit.unwrap_or_default(),
+        );
+        let exporter = start_exporter(&config, HashMap::new())?;
+        return simulate::simulate(
+            client,
+            &gauges,
             &rewards_cache,
-            &staking_account_whitelist,
+            &production_history,
             &vote_accounts_whitelist,
-        ))
-    } else {
-        None
-    };
+            &exporter,
+            Duration::from_secs(interval),
+        )
+        .await;
+    }
 
-    loop {
-        // Held for the entire update cycle (including the async MaxMind queries)
-        // so a concurrent `/metrics` scrape waits for a complete, consistent
-        // update. See the `#[allow(clippy::await_holding_lock)]` on `main`.
-        let _guard = exporter.wait_duration(duration);
-        debug!("Updating metrics");
+    let vote_accounts_whitelist = ReloadableWhitelist::new(
+        "vote account whitelist",
+        self_mode::vote_account_whitelist(&config, config_dir, client).await?,
+        config_dir,
+    )?;
+    let staking_account_whitelist = ReloadableWhitelist::new(
+        "staking account whitelist",
+        config.staking_account_whitelist.clone(),
+        config_dir,
+    )?;
 
-        // Base data every export below depends on. If any of these fail there
-        // is nothing meaningful to publish this cycle, so log and wait for the
-        // next tick instead of propagating out of `main` — a `?` here exits the
-        // process and drops every metric until the orchestrator restarts us.
-        let base = async {
-            let epoch_info = client.get_epoch_info().await?;
-            // Fetch getClusterNodes once and derive both the typed view (used by
-            // the whitelisted exporters) and, when enabled, the raw gossip view
-            // (which preserves the `tvu` field the typed struct drops).
-            let raw_nodes = rpc_extra::get_cluster_nodes_raw(&client).await?;
-            let nodes: Vec<_> = serde_json::from_value(raw_nodes.clone())
-                .context("failed to deserialize getClusterNodes response")?;
-            let vote_accounts = client.get_vote_accounts().await?;
-            anyhow::Ok((epoch_info, raw_nodes, nodes, vote_accounts))
-        }
+    // Shows live values without opening the database, which a running exporter holds locked.
+    if let ("top", Some(sc)) = cli_configs.subcommand() {
+        let interval = sc
+            .value_of("interval")
+            .map(str::parse::<u64>)
+            .transpose()
+            .context("invalid refresh interval")?
+            .unwrap_or(5);
+        return top::top(
+            client,
+            vote_accounts_whitelist.get(),
+            Duration::from_secs(interval.max(1)),
+        )
         .await;
-        let (epoch_info, raw_nodes, nodes, vote_accounts) = match base {
-            Ok(v) => v,
-            Err(e) => {
-                warn!("Skipping update cycle, base RPC fetch failed: {e:#}");
-                continue;
-            }
-        };
-        let node_whitelist = rpc_extra::node_pubkeys(&vote_accounts_whitelist, &vote_accounts);
+    }
 
-        // Each export is isolated: a transient error on one (e.g. testnet
-        // `getBlockProduction` racing the node's slot history) is logged and the
-        // remaining exports still publish, rather than one failure aborting the
-        // whole cycle or exiting the process.
-        if let Err(e) = gauges.export_vote_accounts(&vote_accounts) {
-            warn!("Failed to export vote account metrics: {e:#}");
-        }
-        if let Err(e) = gauges.export_epoch_info(&epoch_info, &client).await {
-            warn!("Failed to export epoch info metrics: {e:#}");
-        }
-        if let Err(e) = gauges
-            .export_nodes_info(&nodes, &client, &node_whitelist)
-            .await
-        {
-            warn!("Failed to export node info metrics: {e:#}");
-        }
-        if enable_gossip_node_info {
-            let gossip_nodes = rpc_extra::parse_gossip_nodes(&raw_nodes);
-            if let Err(e) = gauges.export_gossip_node_info(&gossip_nodes, &vote_accounts) {
-                warn!("Failed to export gossip node info metrics: {e:#}");
-            }
-        }
-        if let Some(maxmind) = config.maxmind.clone() {
-            // If the MaxMind API is configured, submit queries for any uncached IPs.
-            if let Err(e) = gauges
-                .export_ip_addresses(
-                    &nodes,
-                    &vote_accounts,
-                    &geolocation_cache,
-                    &maxmind,
-                    &node_whitelist,
-                )
-                .await
-            {
-                warn!("Failed to export IP address info metrics: {e:#}");
-            }
-        }
+    // Reads the cached leader slots from a copy of the database, which a running exporter holds
+    // locked.
+    if let ("leader-calendar", Some(sc)) = cli_configs.subcommand() {
+        let persistent_database = PersistentDatabase::open_read_only(&database_location)?;
+        let leader_schedule_cache =
+            LeaderScheduleCache::new(persistent_database.tree(LEADER_SCHEDULE_TREE_NAME)?);
+        return leader_calendar::leader_calendar(
+            client,
+            vote_accounts_whitelist.get(),
+            &leader_schedule_cache,
+            sc,
+        )
+        .await;
+    }
 
-        if enable_skipped_slots {
-            if let Err(e) = skipped_slots_monitor
-                .as_mut()
-                .unwrap()
-                .export_skipped_slots(&node_whitelist)
-                .await
-            {
-                warn!("Failed to export skipped slots: {e:#}");
+    // Leaves the cache as it is, by checking a copy of the database, which a running exporter
+    // holds locked.
+    if let ("verify-cache", Some(sc)) = cli_configs.subcommand() {
+        let persistent_database = PersistentDatabase::open_read_only(&database_location)?;
+        let rewards_cache = RewardsCache::open(&persistent_database)?;
+        return verify_cache::verify_cache(
+            client,
+            &rewards_cache,
+            ApyOutlierRules::new(&config),
+            sc,
+        )
+        .await;
+    }
+
+    let persistent_database = Arc::new(PersistentDatabase::new(&database_location)?);
+    warn_new_database();
+    let stores = Stores::open(Arc::clone(&persistent_database), &config)?;
+
+    let mut routes: HashMap<_, server::Handler> = HashMap::new();
+    routes.insert(
+        "/debug/cache",
+        server::debug_cache_handler(
+            Arc::clone(&persistent_database),
+            Arc::clone(&stores.rewards),
+        ),
+    );
+    routes.insert(
+        "/api/v1/rewards",
+        server::rewards_api_handler(Arc::clone(&stores.rewards)),
+    );
+    let exporter = start_exporter(&config, routes)?;
+    gauges::register_build_info()?;
+
+    let gauges = PrometheusGauges::new(
+        http::client_builder(proxy)?.build()?,
+        config.balance_unit.unwrap_or_default(),
+    );
+
+    // The caches a cold start fills are warmed in the background, while the other metrics are
+    // already served. The rewards are backfilled once a cycle gets through the leader lease and
+    // the circuit breaker.
+    let warmer = CacheWarmer::new(
+        rpc_pool.clone(),
+        Arc::clone(&stores.rewards),
+        Arc::clone(&stores.geolocation),
+        http::client_builder(proxy)?.build()?,
+    );
+    // Slow-moving gauges carry on from their values at the last shutdown until recomputed.
+    let snapshot = GaugeSnapshot::new(
+        persistent_database.tree(GAUGE_SNAPSHOT_TREE_NAME)?,
+        gauges.snapshot_saved_at.clone(),
+    );
+
+    let mut monitors = MonitorRegistry::new();
+    service::register_monitors(
+        &mut monitors,
+        &config,
+        client,
+        &gauges,
+        &stores,
+        &warmer,
+        &snapshot,
+    )?;
+    // On SIGINT or SIGTERM, the loop is left between update cycles, and the slow-moving gauges are
+    // saved before returning, which releases the leader lease and the database.
+    UpdateLoop::new(
+        &config,
+        client,
+        monitors,
+        &exporter,
+        &warmer,
+        vote_accounts_whitelist,
+        staking_account_whitelist,
+    )?
+    .run(shutdown_signal())
+    .await?;
+
+    info!("Shutting down");
+    let saved = snapshot
+        .save(&prometheus_exporter::prometheus::gather())
+        .and_then(|()| persistent_database.flush().map(drop));
+    if let Err(e) = saved {
+        warn!("Failed to save the metrics snapshot: {e:#}");
+    }
+    Ok(())
+}
+
+/// Starts serving the metrics at the target and path of `config`, with the extra `routes`.
+fn start_exporter(
+    config: &ExporterConfig,
+    routes: HashMap<&'static str, server::Handler>,
+) -> anyhow::Result<Exporter> {
+    let names = config
+        .vote_account_whitelist
+        .iter()
+        .chain(&config.staking_account_whitelist)
+        .flat_map(WhitelistSource::names)
+        .collect();
+    let filter = server::MetricFilter::new(
+        config.aggregates_only.unwrap_or(false),
+        config.metric_labels.clone().unwrap_or_default(),
+        names,
+    )?;
+    let metrics_path = config
+        .metrics_path
+        .clone()
+        .unwrap_or_else(|| server::DEFAULT_METRICS_PATH.to_string());
+    Exporter::start(&config.target.0, metrics_path, routes, filter)
+}
+
+/// Waits for SIGINT or SIGTERM. Never returns if neither can be listened for.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Could not listen for SIGTERM: {e:#}");
+                futures::future::pending::<()>().await;
             }
         }
-
-        if let Some(x) = &rewards_monitor {
-            if let Err(e) = x.export_rewards(&epoch_info).await {
-                warn!("Failed to export rewards: {e:#}");
+    };
+    #[cfg(not(unix))]
+    let terminate = futures::future::pending::<()>();
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            if let Err(e) = result {
+                warn!("Could not listen for SIGINT: {e:#}");
+                futures::future::pending::<()>().await;
             }
         }
+        _ = terminate => {}
     }
 }
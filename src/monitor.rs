@@ -0,0 +1,444 @@
+//! Pluggable metric collectors driven by the scrape loop.
+
+use crate::config::Whitelist;
+use crate::rpc_api::RpcApi;
+use crate::rpc_extra::{self, ClusterNodesCache};
+use crate::sharding::{Shard, StakeVoters};
+use anyhow::Context;
+use async_trait::async_trait;
+use log::{debug, warn};
+use prometheus_exporter::prometheus::core::Collector;
+use prometheus_exporter::prometheus::proto::MetricType;
+use prometheus_exporter::prometheus::{
+    register_int_counter_vec, register_int_gauge_vec, IntCounterVec, IntGaugeVec,
+};
+use serde_json::Value;
+use solana_client::rpc_response::{RpcContactInfo, RpcVoteAccountStatus};
+use solana_epoch_info::EpochInfo;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+/// Label of the monitor metrics naming the monitor.
+const MONITOR_LABEL: &str = "monitor";
+
+/// Cluster data fetched once per update cycle and shared by all monitors.
+pub struct Cycle {
+    /// Current epoch info.
+    pub epoch_info: EpochInfo,
+    /// Raw `getClusterNodes` response, see [`crate::rpc_extra::get_cluster_nodes_raw`].
+    pub raw_nodes: Value,
+    /// Typed view of `raw_nodes`.
+    pub nodes: Vec<RpcContactInfo>,
+    /// Current and delinquent vote accounts.
+    pub vote_accounts: RpcVoteAccountStatus,
+    /// Whitelisted vote account pubkeys of this shard, as last loaded.
+    pub vote_accounts_whitelist: Whitelist,
+    /// Whitelisted stake account pubkeys of this shard, as last loaded.
+    pub staking_account_whitelist: Whitelist,
+    /// Node pubkeys of the whitelisted vote accounts of this shard.
+    pub node_whitelist: Whitelist,
+    /// Whitelisted vote account pubkeys of all shards, which the cluster-wide metrics cover.
+    pub unsharded_vote_accounts_whitelist: Whitelist,
+    /// Node pubkeys of the whitelisted vote accounts of all shards.
+    pub unsharded_node_whitelist: Whitelist,
+}
+
+impl Cycle {
+    /// Fetches the cluster data of a cycle from `rpc`, for the validators of
+    /// `vote_accounts_whitelist` and the stakes of `staking_account_whitelist`. Unlike the
+    /// exporter's own [`CycleFetcher`], the cluster nodes are not cached and the whitelists are
+    /// not sharded.
+    pub async fn fetch(
+        rpc: &dyn RpcApi,
+        vote_accounts_whitelist: Whitelist,
+        staking_account_whitelist: Whitelist,
+    ) -> anyhow::Result<Self> {
+        CycleFetcher::new(Duration::ZERO, Shard::new(None, None)?)
+            .fetch(rpc, &vote_accounts_whitelist, &staking_account_whitelist)
+            .await
+    }
+}
+
+/// Fetches the cycles of the update loop, with the whitelisted accounts of a shard.
+///
+/// The `getClusterNodes` response is cached for a TTL, and refreshed alongside the other calls
+/// once expired.
+pub struct CycleFetcher {
+    cluster_nodes: ClusterNodesCache,
+    shard: Shard,
+    /// Vote accounts of the whitelisted stake accounts, if the accounts are split across shards.
+    stake_voters: StakeVoters,
+}
+
+impl CycleFetcher {
+    /// Constructs a fetcher of the cycles of `shard`, keeping the cluster nodes for
+    /// `cluster_nodes_ttl`.
+    pub fn new(cluster_nodes_ttl: Duration, shard: Shard) -> Self {
+        Self {
+            cluster_nodes: ClusterNodesCache::new(cluster_nodes_ttl),
+            shard,
+            stake_voters: StakeVoters::default(),
+        }
+    }
+
+    /// Fetches the cluster data of a cycle from `rpc`, for the accounts of
+    /// `vote_accounts_whitelist` and `staking_account_whitelist` in the shard. Fails if the epoch
+    /// info, the cluster nodes or the vote accounts cannot be fetched, which every monitor
+    /// depends on.
+    pub async fn fetch(
+        &mut self,
+        rpc: &dyn RpcApi,
+        vote_accounts_whitelist: &Whitelist,
+        staking_account_whitelist: &Whitelist,
+    ) -> anyhow::Result<Cycle> {
+        // Fetch getClusterNodes once and derive both the typed view (used by the whitelisted
+        // exporters) and the raw gossip view (which preserves the `tvu` field the typed struct
+        // drops).
+        let (epoch_info, raw_nodes, vote_accounts) = futures::try_join!(
+            rpc.get_epoch_info(),
+            self.cluster_nodes.get(rpc),
+            rpc.get_vote_accounts(),
+        )?;
+        let nodes = serde_json::from_value(raw_nodes.clone())
+            .context("failed to deserialize getClusterNodes response")?;
+
+        let shard_vote_accounts_whitelist = self.shard.whitelist(
+            vote_accounts_whitelist,
+            vote_accounts
+                .current
+                .iter()
+                .chain(&vote_accounts.delinquent)
+                .map(|v| &v.vote_pubkey),
+        );
+        // Stake accounts go to the shard of the vote account they are delegated to. Without a
+        // stake account whitelist, the APYs cover all stakes of the shard's voters.
+        if self.shard.is_split() {
+            if let Err(e) = self
+                .stake_voters
+                .refresh(rpc, staking_account_whitelist, epoch_info.epoch)
+                .await
+            {
+                warn!("Failed to look up the vote accounts of the stake accounts: {e:#}");
+            }
+        }
+        let shard_staking_account_whitelist = self
+            .shard
+            .stake_whitelist(staking_account_whitelist, &self.stake_voters);
+        let node_whitelist =
+            rpc_extra::node_pubkeys(&shard_vote_accounts_whitelist, &vote_accounts);
+        let unsharded_node_whitelist =
+            rpc_extra::node_pubkeys(vote_accounts_whitelist, &vote_accounts);
+        Ok(Cycle {
+            epoch_info,
+            raw_nodes,
+            nodes,
+            vote_accounts,
+            vote_accounts_whitelist: shard_vote_accounts_whitelist,
+            staking_account_whitelist: shard_staking_account_whitelist,
+            node_whitelist,
+            unsharded_vote_accounts_whitelist: vote_accounts_whitelist.clone(),
+            unsharded_node_whitelist,
+        })
+    }
+}
+
+/// A collector of a family of metrics.
+///
+/// New metric families are added by implementing this trait and registering the monitor in the
+/// [`MonitorRegistry`]; the scrape loop takes care of scheduling and error isolation.
+#[async_trait]
+pub trait Monitor: Send {
+    /// Short name of the monitor, used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Initialises the monitor before its first collection, e.g. by restoring persisted state.
+    async fn setup(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Minimum time between two collections. Defaults to collecting on every cycle.
+    fn interval(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    /// Collects the metrics of the monitor for the current cycle.
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()>;
+
+    /// Names of all the metric families the monitor exports, which scrapes can select by the
+    /// [`collector_name`] of the monitor. Defaults to none.
+    fn metrics(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Names of the metric families of the monitor that describe the current epoch. Defaults to
+    /// none.
+    fn epoch_metrics(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Whether the data the monitor last collected is complete for the epoch of `cycle`, rather
+    /// than lagging behind it, e.g. because it is only read at `finalized` commitment. Defaults
+    /// to `true`.
+    fn epoch_complete(&self, _cycle: &Cycle) -> bool {
+        true
+    }
+
+    /// Names of the metric families of the monitor that would still be computed from partial
+    /// history, e.g. because not all the earlier epochs they are averaged over are cached yet.
+    /// They are withheld until then. Defaults to none.
+    fn warming_up_metrics(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// A monitor collected at most every `interval`, e.g. to make fewer RPC calls than it would on
+/// every cycle.
+pub struct Throttled<M> {
+    monitor: M,
+    interval: Duration,
+}
+
+impl<M: Monitor> Throttled<M> {
+    /// Throttles `monitor` to one collection every `interval`, unless its own interval is
+    /// longer.
+    pub fn new(monitor: M, interval: Duration) -> Self {
+        Self { monitor, interval }
+    }
+}
+
+#[async_trait]
+impl<M: Monitor> Monitor for Throttled<M> {
+    fn name(&self) -> &'static str {
+        self.monitor.name()
+    }
+
+    async fn setup(&mut self) -> anyhow::Result<()> {
+        self.monitor.setup().await
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval.max(self.monitor.interval())
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.monitor.collect(cycle).await
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        self.monitor.metrics()
+    }
+
+    fn epoch_metrics(&self) -> Vec<String> {
+        self.monitor.epoch_metrics()
+    }
+
+    fn epoch_complete(&self, cycle: &Cycle) -> bool {
+        self.monitor.epoch_complete(cycle)
+    }
+
+    fn warming_up_metrics(&self) -> Vec<String> {
+        self.monitor.warming_up_metrics()
+    }
+}
+
+/// Returns the metric family name of `collector`, for [`Monitor::metrics`] and
+/// [`Monitor::epoch_metrics`].
+pub fn family_name(collector: &dyn Collector) -> String {
+    collector.desc()[0].fq_name.clone()
+}
+
+/// Returns the label values, in the order of the variable labels, and the value of every series
+/// of the gauge or counter `collector`.
+pub fn series(collector: &dyn Collector) -> Vec<(Vec<String>, f64)> {
+    let names = match collector.desc().first() {
+        Some(desc) => desc.variable_labels.clone(),
+        None => return Vec::new(),
+    };
+    let mut series = Vec::new();
+    for family in collector.collect() {
+        for metric in family.get_metric() {
+            let labels: HashMap<&str, &str> = metric
+                .get_label()
+                .iter()
+                .map(|l| (l.get_name(), l.get_value()))
+                .collect();
+            let value = match family.get_field_type() {
+                MetricType::COUNTER => metric.get_counter().get_value(),
+                _ => metric.get_gauge().get_value(),
+            };
+            let labels = names
+                .iter()
+                .map(|name| labels.get(name.as_str()).unwrap_or(&"").to_string())
+                .collect();
+            series.push((labels, value));
+        }
+    }
+    series
+}
+
+/// Returns the name a monitor named `name` is selected by in `collect[]` query parameters of the
+/// metrics endpoint, e.g. `skipped_slots`.
+pub fn collector_name(name: &str) -> String {
+    name.to_lowercase().replace(' ', "_")
+}
+
+/// A registered monitor and its scheduling state.
+struct Entry<'a> {
+    monitor: Box<dyn Monitor + 'a>,
+    last_run: Option<Instant>,
+    /// Whether the last collection succeeded.
+    up: bool,
+}
+
+/// The set of monitors driven by the scrape loop, run in registration order.
+pub struct MonitorRegistry<'a> {
+    entries: Vec<Entry<'a>>,
+    /// Prometheus counter.
+    failures: IntCounterVec,
+    /// Prometheus gauge.
+    up: IntGaugeVec,
+    /// Prometheus gauge of the Unix time of the last successful collection.
+    last_success: IntGaugeVec,
+    /// Prometheus gauge.
+    warming_up: IntGaugeVec,
+}
+
+impl<'a> MonitorRegistry<'a> {
+    /// Makes an empty registry and registers its metrics. Not `Default`, since the metrics can
+    /// only be registered once.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            failures: register_int_counter_vec!(
+                "solana_exporter_monitor_failures",
+                "Number of failed collections of a monitor",
+                &[MONITOR_LABEL]
+            )
+            .unwrap(),
+            up: register_int_gauge_vec!(
+                "solana_exporter_monitor_up",
+                "Whether the last collection of a monitor succeeded; its metrics are stale if not",
+                &[MONITOR_LABEL]
+            )
+            .unwrap(),
+            last_success: register_int_gauge_vec!(
+                "solana_exporter_monitor_last_success_timestamp_seconds",
+                "Unix time of the last successful collection of a monitor",
+                &[MONITOR_LABEL]
+            )
+            .unwrap(),
+            warming_up: register_int_gauge_vec!(
+                "solana_exporter_monitor_warming_up",
+                "Whether a monitor withholds metrics until the history they are computed from is \
+                 complete",
+                &[MONITOR_LABEL]
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Registers `monitor` to be run after the already registered ones.
+    pub fn register(&mut self, monitor: impl Monitor + 'a) {
+        // Export a zero failure count before the first failure, so that rates can be taken.
+        self.failures.with_label_values(&[monitor.name()]);
+        self.entries.push(Entry {
+            monitor: Box::new(monitor),
+            last_run: None,
+            up: false,
+        });
+    }
+
+    /// Sets up all registered monitors.
+    pub async fn setup(&mut self) -> anyhow::Result<()> {
+        for entry in &mut self.entries {
+            debug!("Setting up {} monitor", entry.monitor.name());
+            entry.monitor.setup().await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the epoch metrics of all monitors while the data of any monitor is not complete
+    /// for the epoch of `cycle`, so that they move to a new epoch together. Otherwise returns
+    /// none. Monitors whose last collection failed are not waited for.
+    pub fn pending_metrics(&self, cycle: &Cycle) -> HashSet<String> {
+        let complete = self
+            .entries
+            .iter()
+            .all(|e| !e.up || e.monitor.epoch_complete(cycle));
+        if complete {
+            return HashSet::new();
+        }
+        debug!(
+            "Holding back epoch metrics until all monitors reached epoch {}",
+            cycle.epoch_info.epoch
+        );
+        self.entries
+            .iter()
+            .flat_map(|e| e.monitor.epoch_metrics())
+            .collect()
+    }
+
+    /// Returns the metrics of all monitors that are still warming up, which are withheld.
+    pub fn warming_up_metrics(&self) -> HashSet<String> {
+        self.entries
+            .iter()
+            .flat_map(|e| e.monitor.warming_up_metrics())
+            .collect()
+    }
+
+    /// Returns the metric families of all monitors by collector name.
+    pub fn collectors(&self) -> HashMap<String, HashSet<String>> {
+        let mut collectors: HashMap<String, HashSet<String>> = HashMap::new();
+        for entry in &self.entries {
+            collectors
+                .entry(collector_name(entry.monitor.name()))
+                .or_default()
+                .extend(entry.monitor.metrics());
+        }
+        collectors
+    }
+
+    /// Whether the last collection of every monitor succeeded.
+    pub fn all_up(&self) -> bool {
+        self.entries.iter().all(|e| e.up)
+    }
+
+    /// Runs every monitor whose interval has elapsed. Each monitor is isolated: a failure is
+    /// logged and counted, and the monitor marked down until its next successful collection, while
+    /// the remaining monitors still run, rather than one failure aborting the whole cycle or
+    /// exiting the process.
+    pub async fn collect(&mut self, cycle: &Cycle) {
+        for entry in &mut self.entries {
+            let due = entry
+                .last_run
+                .is_none_or(|t| t.elapsed() >= entry.monitor.interval());
+            if !due {
+                continue;
+            }
+            entry.last_run = Some(Instant::now());
+
+            let name = entry.monitor.name();
+            let result = entry.monitor.collect(cycle).await;
+            entry.up = result.is_ok();
+            let warming_up = !entry.monitor.warming_up_metrics().is_empty();
+            self.warming_up
+                .with_label_values(&[name])
+                .set(warming_up as i64);
+            match result {
+                Ok(()) => {
+                    self.up.with_label_values(&[name]).set(1);
+                    self.last_success
+                        .with_label_values(&[name])
+                        .set(OffsetDateTime::now_utc().unix_timestamp());
+                }
+                Err(e) => {
+                    warn!("Failed to export {} metrics: {e:#}", name);
+                    self.failures.with_label_values(&[name]).inc();
+                    self.up.with_label_values(&[name]).set(0);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::{anyhow, Context};
+use prometheus_exporter::prometheus::core::Collector;
+use prometheus_exporter::prometheus::IntCounterVec;
+use serde::{Deserialize, Serialize};
+use solana_clock::Epoch;
+
+/// Name of the tree holding counter baselines.
+pub const COUNTERS_TREE_NAME: &str = "counters";
+
+/// The key of a single counter series: the metric name followed by its label values.
+#[derive(Serialize, Deserialize)]
+struct CounterKey(String, Vec<String>);
+
+/// The persisted state of a single counter series.
+#[derive(Serialize, Deserialize, Default)]
+struct CounterBaseline {
+    /// Total the counter has been incremented by since it was first created.
+    total: u64,
+    /// The last epoch that was counted, for counters incremented once per epoch.
+    last_epoch: Option<Epoch>,
+}
+
+/// Persisted totals of Prometheus counters, so that counters carry on from their previous value
+/// across exporter restarts instead of resetting to zero. Every increment is written through to
+/// the database, atomically with the read of the previous total.
+pub struct CounterBaselines {
+    tree: sled::Tree,
+}
+
+impl CounterBaselines {
+    /// Creates a new store using a tree.
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+
+    /// Restores all persisted series of `counter` into the registry. Must be called once, before
+    /// the counter is incremented.
+    pub fn restore(&self, counter: &IntCounterVec) -> anyhow::Result<()> {
+        let name = counter_name(counter)?;
+        for kv in self.tree.scan_prefix(bincode::serialize(&name)?) {
+            let (k, v) = kv.context("could not read counter baseline from database")?;
+            let CounterKey(key_name, labels) = bincode::deserialize(&k)?;
+            // A prefix scan may also match names that extend `name`.
+            if key_name != name {
+                continue;
+            }
+            let baseline: CounterBaseline = bincode::deserialize(&v)
+                .context("could not deserialize fetched counter baseline")?;
+            let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+            counter
+                .get_metric_with_label_values(&labels)?
+                .inc_by(baseline.total);
+        }
+        Ok(())
+    }
+
+    /// Increments the series of `counter` with `labels` by `v` and persists the new total.
+    pub fn inc_by(&self, counter: &IntCounterVec, labels: &[&str], v: u64) -> anyhow::Result<()> {
+        if v == 0 {
+            return Ok(());
+        }
+        let key = series_key(counter, labels)?;
+        self.update(&key, |baseline| {
+            baseline.total += v;
+            true
+        })?;
+        counter.get_metric_with_label_values(labels)?.inc_by(v);
+        Ok(())
+    }
+
+    /// Increments the series of `counter` with `labels` by `v` unless it has already been
+    /// incremented for `epoch` or a later epoch. Returns whether the counter was incremented.
+    pub fn inc_by_for_epoch(
+        &self,
+        counter: &IntCounterVec,
+        labels: &[&str],
+        epoch: Epoch,
+        v: u64,
+    ) -> anyhow::Result<bool> {
+        let key = series_key(counter, labels)?;
+        let incremented = self.update(&key, |baseline| {
+            if baseline.last_epoch.is_some_and(|last| last >= epoch) {
+                return false;
+            }
+            baseline.total += v;
+            baseline.last_epoch = Some(epoch);
+            true
+        })?;
+        if incremented {
+            counter.get_metric_with_label_values(labels)?.inc_by(v);
+        }
+        Ok(incremented)
+    }
+
+    /// Applies `f` to the persisted baseline of `key`, or to a zero one, and persists it if `f`
+    /// returns that it changed it. The baseline is read and written atomically, so concurrent
+    /// increments are not lost. Returns what `f` returned.
+    fn update(
+        &self,
+        key: &[u8],
+        mut f: impl FnMut(&mut CounterBaseline) -> bool,
+    ) -> anyhow::Result<bool> {
+        let mut result = Ok(false);
+        self.tree
+            .update_and_fetch(key, |old| {
+                let baseline = old
+                    .map(bincode::deserialize::<CounterBaseline>)
+                    .transpose()
+                    .context("could not deserialize fetched counter baseline");
+                let mut baseline = match baseline {
+                    Ok(baseline) => baseline.unwrap_or_default(),
+                    Err(e) => {
+                        result = Err(e);
+                        return old.map(<[u8]>::to_vec);
+                    }
+                };
+                if !f(&mut baseline) {
+                    result = Ok(false);
+                    return old.map(<[u8]>::to_vec);
+                }
+                match bincode::serialize(&baseline) {
+                    Ok(bytes) => {
+                        result = Ok(true);
+                        Some(bytes)
+                    }
+                    Err(e) => {
+                        result = Err(e.into());
+                        old.map(<[u8]>::to_vec)
+                    }
+                }
+            })
+            .context("could not update counter baseline in database")?;
+        result
+    }
+}
+
+/// Returns the fully-qualified name of `counter`.
+fn counter_name(counter: &IntCounterVec) -> anyhow::Result<String> {
+    counter
+        .desc()
+        .first()
+        .map(|d| d.fq_name.clone())
+        .ok_or_else(|| anyhow!("counter has no descriptor"))
+}
+
+/// Returns the database key of the series of `counter` with `labels`.
+fn series_key(counter: &IntCounterVec, labels: &[&str]) -> anyhow::Result<Vec<u8>> {
+    Ok(bincode::serialize(&CounterKey(
+        counter_name(counter)?,
+        labels.iter().map(|l| l.to_string()).collect(),
+    ))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CounterBaselines;
+    use prometheus_exporter::prometheus::{IntCounterVec, Opts};
+
+    fn new_counter() -> IntCounterVec {
+        IntCounterVec::new(Opts::new("test_counter", "Test counter"), &["pubkey"]).unwrap()
+    }
+
+    #[test]
+    fn restores_persisted_totals() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let baselines = CounterBaselines::new(db.open_tree("counters").unwrap());
+
+        let counter = new_counter();
+        baselines.inc_by(&counter, &["a"], 3).unwrap();
+        baselines.inc_by(&counter, &["a"], 4).unwrap();
+        baselines.inc_by(&counter, &["b"], 1).unwrap();
+
+        let restored = new_counter();
+        baselines.restore(&restored).unwrap();
+        assert_eq!(restored.with_label_values(&["a"]).get(), 7);
+        assert_eq!(restored.with_label_values(&["b"]).get(), 1);
+    }
+
+    #[test]
+    fn counts_each_epoch_once() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let baselines = CounterBaselines::new(db.open_tree("counters").unwrap());
+
+        let counter = new_counter();
+        assert!(baselines.inc_by_for_epoch(&counter, &["a"], 10, 5).unwrap());
+        assert!(!baselines.inc_by_for_epoch(&counter, &["a"], 10, 5).unwrap());
+        assert!(!baselines.inc_by_for_epoch(&counter, &["a"], 9, 5).unwrap());
+        assert!(baselines.inc_by_for_epoch(&counter, &["a"], 11, 2).unwrap());
+        assert_eq!(counter.with_label_values(&["a"]).get(), 7);
+    }
+
+    #[test]
+    fn keeps_concurrent_increments() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let baselines = CounterBaselines::new(db.open_tree("counters").unwrap());
+
+        let counter = new_counter();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        baselines.inc_by(&counter, &["a"], 1).unwrap();
+                    }
+                });
+            }
+        });
+        let restored = new_counter();
+        baselines.restore(&restored).unwrap();
+        assert_eq!(restored.with_label_values(&["a"]).get(), 400);
+    }
+}
@@ -0,0 +1,154 @@
+//! The versioned envelope of cached values.
+//!
+//! Values are stored behind a header of two magic bytes, the envelope version and a tag of the
+//! encoding of the payload, so that the encoding of a tree can change without misreading the
+//! entries written before. JSON is self-describing and keeps decoding when fields are reordered or
+//! added with `#[serde(default)]`, so it is used for records, in particular those holding types of
+//! other crates, and for maps keyed by pubkeys, written with string keys since JSON cannot key by
+//! pubkeys. Bincode is kept for bulk values, like the leader slot indices.
+//!
+//! Values without a header, or whose header does not decode, were written by earlier versions as
+//! bare bincode, and are decoded as such.
+
+use anyhow::{anyhow, Context};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Marks the start of an envelope. Bare bincode values may start with it too, and are decoded as
+/// bare bincode if they do not decode as an envelope.
+const MAGIC: [u8; 2] = [0xe5, 0x7c];
+
+/// The envelope version written.
+pub const VERSION: u8 = 1;
+
+/// The encoding of the payload of an envelope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Bincode = 1,
+    Json = 2,
+}
+
+/// Encodes `value` in an envelope, with `encoding`.
+pub fn encode<T: Serialize + ?Sized>(encoding: Encoding, value: &T) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = vec![MAGIC[0], MAGIC[1], VERSION, encoding as u8];
+    match encoding {
+        Encoding::Bincode => bincode::serialize_into(&mut bytes, value)?,
+        Encoding::Json => serde_json::to_writer(&mut bytes, value)?,
+    }
+    Ok(bytes)
+}
+
+/// Returns the encoding of the payload of an envelope of a known version, or `None` if `bytes`
+/// are not one, e.g. bare bincode.
+pub fn encoding(bytes: &[u8]) -> Option<Encoding> {
+    match bytes {
+        [m0, m1, version, encoding, ..] if [*m0, *m1] == MAGIC && *version <= VERSION => {
+            match *encoding {
+                e if e == Encoding::Bincode as u8 => Some(Encoding::Bincode),
+                e if e == Encoding::Json as u8 => Some(Encoding::Json),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Decodes a value from an envelope, or from bare bincode if there is no header or the envelope
+/// does not decode. Fails on envelopes of a newer version, whose payload may not be what it
+/// seems, unless they decode as bare bincode.
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> anyhow::Result<T> {
+    match bytes {
+        [m0, m1, version, encoding, payload @ ..] if [*m0, *m1] == MAGIC => {
+            decode_envelope(*version, *encoding, payload).or_else(|e| {
+                // A bare bincode value that happens to start with the magic bytes.
+                bincode::deserialize(bytes).map_err(|_| e)
+            })
+        }
+        _ => bincode::deserialize(bytes).context("could not decode bare bincode value"),
+    }
+}
+
+/// Decodes the payload of an envelope of `version`, with the encoding tagged `encoding`.
+fn decode_envelope<T: DeserializeOwned>(
+    version: u8,
+    encoding: u8,
+    payload: &[u8],
+) -> anyhow::Result<T> {
+    if version > VERSION {
+        return Err(anyhow!(
+            "value was written in envelope version {}, newer than {}",
+            version,
+            VERSION
+        ));
+    }
+    match encoding {
+        e if e == Encoding::Bincode as u8 => {
+            bincode::deserialize(payload).context("could not decode bincode payload")
+        }
+        e if e == Encoding::Json as u8 => {
+            serde_json::from_slice(payload).context("could not decode JSON payload")
+        }
+        e => Err(anyhow!("unknown value encoding {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        name: String,
+        count: u64,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct ExtendedRecord {
+        count: u64,
+        name: String,
+        #[serde(default)]
+        flag: bool,
+    }
+
+    #[test]
+    fn decodes_envelopes_and_bare_bincode() {
+        let record = Record {
+            name: "a".to_string(),
+            count: 3,
+        };
+        for encoding in [Encoding::Bincode, Encoding::Json].iter() {
+            let bytes = encode(*encoding, &record).unwrap();
+            assert_eq!(decode::<Record>(&bytes).unwrap(), record);
+        }
+        let bare = bincode::serialize(&record).unwrap();
+        assert_eq!(decode::<Record>(&bare).unwrap(), record);
+
+        // JSON survives reordered and added fields.
+        let json = encode(Encoding::Json, &record).unwrap();
+        assert_eq!(
+            decode::<ExtendedRecord>(&json).unwrap(),
+            ExtendedRecord {
+                count: 3,
+                name: "a".to_string(),
+                flag: false
+            }
+        );
+
+        assert_eq!(encoding(&json), Some(Encoding::Json));
+        assert_eq!(encoding(&bare), None);
+        let mut newer = json;
+        newer[2] = VERSION + 1;
+        assert!(decode::<Record>(&newer).is_err());
+        assert_eq!(encoding(&newer), None);
+
+        // Bare bincode starting with the magic bytes, here the length of the name.
+        let legacy = Record {
+            name: "x".repeat(usize::from(u16::from_le_bytes(MAGIC))),
+            count: 3,
+        };
+        let bare = bincode::serialize(&legacy).unwrap();
+        assert_eq!(bare[..2], MAGIC);
+        assert_eq!(decode::<Record>(&bare).unwrap(), legacy);
+    }
+}
@@ -2,10 +2,16 @@ use crate::persistent_database::metadata::Metadata;
 use crate::SOLANA_EXPORTER_VERSION;
 use anyhow::Context;
 use log::warn;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use time::OffsetDateTime;
 
+pub mod counters;
+pub mod envelope;
 pub mod metadata;
+pub mod snapshot;
+pub mod stats;
 
 /// Name of database name
 pub const DATABASE_FILE_NAME: &str = "persistent.db";
@@ -16,6 +22,7 @@ pub const DATABASE_FILE_NAME: &str = "persistent.db";
 pub struct PersistentDatabase {
     database: sled::Db,
     metadata: Metadata,
+    path: PathBuf,
 }
 
 impl PersistentDatabase {
@@ -26,6 +33,27 @@ impl PersistentDatabase {
         // forbids two packages linking the same native lib). The cache is small,
         // so storing it uncompressed is fine.
         let database = sled::Config::default().path(dir).open()?;
+        Self::with_database(database, dir)
+    }
+
+    /// Opens a private copy of the database in the path provided, which is deleted when dropped,
+    /// or an empty temporary database if there is none. sled has no read-only mode, and a running
+    /// exporter holds its database locked, so the commands only reading it work on a copy.
+    pub fn open_read_only(dir: &Path) -> anyhow::Result<Self> {
+        let copy = std::env::temp_dir().join(format!(
+            "solana-exporter-{}-{}",
+            std::process::id(),
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+        if dir.exists() {
+            copy_dir(dir, &copy)
+                .with_context(|| format!("could not copy the database {}", dir.display()))?;
+        }
+        let database = sled::Config::default().path(&copy).temporary(true).open()?;
+        Self::with_database(database, dir)
+    }
+
+    fn with_database(database: sled::Db, dir: &Path) -> anyhow::Result<Self> {
         let metadata = Metadata::new(database.open_tree("metadata")?)
             .context("could not read metadata from database")?;
 
@@ -40,7 +68,11 @@ impl PersistentDatabase {
             );
         }
 
-        Ok(Self { database, metadata })
+        Ok(Self {
+            database,
+            metadata,
+            path: dir.to_path_buf(),
+        })
     }
 
     /// Opens a tree in the database with the given name.
@@ -48,8 +80,74 @@ impl PersistentDatabase {
         self.database.open_tree(name)
     }
 
+    /// Returns the names of all trees in the database, except sled's unused default tree.
+    pub fn tree_names(&self) -> Vec<String> {
+        self.database
+            .tree_names()
+            .into_iter()
+            .map(|name| String::from_utf8_lossy(&name).into_owned())
+            .filter(|name| !name.starts_with("__sled__"))
+            .collect()
+    }
+
+    /// Returns the size of the database on disk in bytes.
+    pub fn size_on_disk(&self) -> anyhow::Result<u64> {
+        Ok(self.database.size_on_disk()?)
+    }
+
+    /// Writes all pending changes to disk, and returns the number of bytes written.
+    pub fn flush(&self) -> anyhow::Result<usize> {
+        Ok(self.database.flush()?)
+    }
+
+    /// Returns the directory the database is stored in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     /// Returns metadata for the database.
     pub fn metadata(&self) -> &Metadata {
         &self.metadata
     }
 }
+
+/// Copies the directory `from` with its contents to `to`.
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_a_copy_of_a_locked_database() {
+        let dir = std::env::temp_dir().join(format!("database-test-{}", std::process::id()));
+        let database = PersistentDatabase::new(&dir).unwrap();
+        database.tree("t").unwrap().insert("k", "v").unwrap();
+        database.flush().unwrap();
+
+        let copy = PersistentDatabase::open_read_only(&dir).unwrap();
+        let tree = copy.tree("t").unwrap();
+        assert_eq!(tree.get("k").unwrap().as_deref(), Some(&b"v"[..]));
+        tree.insert("k", "w").unwrap();
+        drop(copy);
+        assert_eq!(
+            database.tree("t").unwrap().get("k").unwrap().as_deref(),
+            Some(&b"v"[..])
+        );
+
+        drop(database);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
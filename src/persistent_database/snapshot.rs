@@ -0,0 +1,196 @@
+use crate::persistent_database::envelope::{self, Encoding};
+use anyhow::{anyhow, Context};
+use prometheus_exporter::prometheus::core::Collector;
+use prometheus_exporter::prometheus::proto::{MetricFamily, MetricType};
+use prometheus_exporter::prometheus::{Gauge, GaugeVec, IntGauge, IntGaugeVec};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use time::OffsetDateTime;
+
+/// Name of the tree holding the last values of slow-moving gauges.
+pub const GAUGE_SNAPSHOT_TREE_NAME: &str = "gauge_snapshot";
+
+/// The series of a gauge family, as saved on shutdown.
+#[derive(Serialize, Deserialize)]
+struct SavedFamily {
+    /// Unix time the family was saved at.
+    saved_at: i64,
+    series: Vec<SavedSeries>,
+}
+
+/// A single series of a saved gauge family.
+#[derive(Serialize, Deserialize)]
+struct SavedSeries {
+    /// Label values by label name.
+    labels: BTreeMap<String, String>,
+    value: f64,
+}
+
+/// A gauge whose series can be restored from a [`GaugeSnapshot`].
+pub trait SnapshotGauge: Collector {
+    /// Sets the series with the values of the variable labels `labels` to `value`.
+    fn set_series(&self, labels: &[&str], value: f64) -> anyhow::Result<()>;
+}
+
+impl SnapshotGauge for GaugeVec {
+    fn set_series(&self, labels: &[&str], value: f64) -> anyhow::Result<()> {
+        self.get_metric_with_label_values(labels)?.set(value);
+        Ok(())
+    }
+}
+
+impl SnapshotGauge for IntGaugeVec {
+    fn set_series(&self, labels: &[&str], value: f64) -> anyhow::Result<()> {
+        self.get_metric_with_label_values(labels)?.set(value as i64);
+        Ok(())
+    }
+}
+
+impl SnapshotGauge for Gauge {
+    fn set_series(&self, _labels: &[&str], value: f64) -> anyhow::Result<()> {
+        self.set(value);
+        Ok(())
+    }
+}
+
+impl SnapshotGauge for IntGauge {
+    fn set_series(&self, _labels: &[&str], value: f64) -> anyhow::Result<()> {
+        self.set(value as i64);
+        Ok(())
+    }
+}
+
+/// The last values of slow-moving gauges, such as APYs and geolocation aggregates, saved on
+/// shutdown and restored on startup, so that their series carry on across exporter restarts
+/// instead of missing until the monitor exporting them first completes.
+pub struct GaugeSnapshot {
+    tree: sled::Tree,
+    /// Names of the restored families, which are the ones saved.
+    families: Mutex<BTreeSet<String>>,
+    /// Prometheus gauge of the Unix time the restored families were saved at.
+    saved_at: IntGaugeVec,
+}
+
+impl GaugeSnapshot {
+    /// Creates a new snapshot using a tree, exporting the times the restored families were saved
+    /// at into `saved_at`, by family name.
+    pub fn new(tree: sled::Tree, saved_at: IntGaugeVec) -> Self {
+        Self {
+            tree,
+            families: Mutex::new(BTreeSet::new()),
+            saved_at,
+        }
+    }
+
+    /// Restores the saved series of `gauge` into the registry, and has it saved on shutdown. Must
+    /// be called before the gauge is first set.
+    pub fn restore(&self, gauge: &dyn SnapshotGauge) -> anyhow::Result<()> {
+        let desc = gauge
+            .desc()
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("gauge has no descriptor"))?;
+        self.families
+            .lock()
+            .expect("poisoned mutex, should never happen")
+            .insert(desc.fq_name.clone());
+        let saved: SavedFamily = match self
+            .tree
+            .get(&desc.fq_name)
+            .context("could not fetch gauge snapshot from database")?
+        {
+            Some(bytes) => {
+                envelope::decode(&bytes).context("could not deserialize fetched gauge snapshot")?
+            }
+            None => return Ok(()),
+        };
+        for series in &saved.series {
+            // Series of earlier versions with other labels are left out.
+            let labels: Option<Vec<&str>> = desc
+                .variable_labels
+                .iter()
+                .map(|name| series.labels.get(name).map(String::as_str))
+                .collect();
+            if let Some(labels) = labels.filter(|l| l.len() == series.labels.len()) {
+                gauge.set_series(&labels, series.value)?;
+            }
+        }
+        self.saved_at
+            .get_metric_with_label_values(&[&desc.fq_name])?
+            .set(saved.saved_at);
+        Ok(())
+    }
+
+    /// Saves the series of the restored families among `families`. Families without series are
+    /// left as last saved.
+    pub fn save(&self, families: &[MetricFamily]) -> anyhow::Result<()> {
+        let names = self
+            .families
+            .lock()
+            .expect("poisoned mutex, should never happen")
+            .clone();
+        let saved_at = OffsetDateTime::now_utc().unix_timestamp();
+        for family in families {
+            if !names.contains(family.get_name())
+                || family.get_field_type() != MetricType::GAUGE
+                || family.get_metric().is_empty()
+            {
+                continue;
+            }
+            let series = family
+                .get_metric()
+                .iter()
+                .map(|metric| SavedSeries {
+                    labels: metric
+                        .get_label()
+                        .iter()
+                        .map(|l| (l.get_name().to_string(), l.get_value().to_string()))
+                        .collect(),
+                    value: metric.get_gauge().get_value(),
+                })
+                .collect();
+            let saved = SavedFamily { saved_at, series };
+            self.tree
+                .insert(family.get_name(), envelope::encode(Encoding::Json, &saved)?)
+                .context("could not insert gauge snapshot into database")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GaugeSnapshot;
+    use prometheus_exporter::prometheus::{Gauge, GaugeVec, IntGaugeVec, Opts, Registry};
+
+    #[test]
+    fn restores_saved_gauges() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let saved_at = || IntGaugeVec::new(Opts::new("saved_at", "Saved at"), &["metric"]).unwrap();
+        let apy = || GaugeVec::new(Opts::new("apy", "APY"), &["pubkey"]).unwrap();
+        let average = || Gauge::new("average", "Average").unwrap();
+
+        let snapshot = GaugeSnapshot::new(db.open_tree("snapshot").unwrap(), saved_at());
+        let (saved_apy, saved_average) = (apy(), average());
+        snapshot.restore(&saved_apy).unwrap();
+        snapshot.restore(&saved_average).unwrap();
+        saved_apy.with_label_values(&["a"]).set(6.5);
+        saved_apy.with_label_values(&["b"]).set(7.25);
+        saved_average.set(6.875);
+        let registry = Registry::new();
+        registry.register(Box::new(saved_apy)).unwrap();
+        registry.register(Box::new(saved_average)).unwrap();
+        snapshot.save(&registry.gather()).unwrap();
+
+        let restored_at = saved_at();
+        let snapshot = GaugeSnapshot::new(db.open_tree("snapshot").unwrap(), restored_at.clone());
+        let (restored_apy, restored_average) = (apy(), average());
+        snapshot.restore(&restored_apy).unwrap();
+        snapshot.restore(&restored_average).unwrap();
+        assert_eq!(restored_apy.with_label_values(&["a"]).get(), 6.5);
+        assert_eq!(restored_apy.with_label_values(&["b"]).get(), 7.25);
+        assert_eq!(restored_average.get(), 6.875);
+        assert!(restored_at.with_label_values(&["apy"]).get() > 0);
+    }
+}
@@ -0,0 +1,167 @@
+//! Size and flushes of the persistent database.
+
+use crate::gauges::PrometheusGauges;
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::persistent_database::PersistentDatabase;
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+/// Time between two collections.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Time between two counts of the entries of every tree, which reads all of them.
+const ENTRIES_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The monitor of the persistent database, exporting its size on disk and the number of entries
+/// of every tree, and flushing it to disk, so that a cache growing without bounds or failing to
+/// be written is noticed before the disk fills.
+///
+/// sled flushes in the background as well, but does not tell about it, so only the flushes of
+/// this monitor are counted. Flushing and counting entries block on disk reads and writes, so
+/// they run on the blocking thread pool. Counting entries iterates over every tree, so it is only
+/// done every `ENTRIES_INTERVAL`, while the size on disk, read from the file metadata, follows
+/// every collection.
+pub struct DatabaseMonitor<'a> {
+    gauges: &'a PrometheusGauges,
+    database: Arc<PersistentDatabase>,
+    /// When the entries were last counted.
+    entries_counted: Option<Instant>,
+}
+
+/// What a collection read from the database.
+struct DatabaseStats {
+    /// The bytes flushed, or the failure.
+    flushed: anyhow::Result<usize>,
+    /// Time the flush took.
+    flush_duration: Duration,
+    size: anyhow::Result<u64>,
+    /// The number of entries of every tree by name, if counted.
+    entries: Option<anyhow::Result<Vec<(String, usize)>>>,
+}
+
+impl<'a> DatabaseMonitor<'a> {
+    /// Constructs a monitor of `database` exporting into `gauges`.
+    pub fn new(gauges: &'a PrometheusGauges, database: Arc<PersistentDatabase>) -> Self {
+        Self {
+            gauges,
+            database,
+            entries_counted: None,
+        }
+    }
+
+    /// Exports the `stats` read from the database, counting the bytes flushed or the failure.
+    fn export(&self, stats: DatabaseStats) -> anyhow::Result<()> {
+        match stats.flushed {
+            Ok(bytes) => {
+                self.gauges.database_flushed_bytes.inc_by(bytes as u64);
+                self.gauges
+                    .database_flush_duration
+                    .set(stats.flush_duration.as_secs_f64());
+                self.gauges
+                    .database_last_flush
+                    .set(OffsetDateTime::now_utc().unix_timestamp());
+            }
+            Err(e) => {
+                self.gauges.database_flush_failures.inc();
+                return Err(e.context("could not flush the persistent database"));
+            }
+        }
+        self.gauges.database_size.set(stats.size? as i64);
+        if let Some(entries) = stats.entries {
+            for (name, entries) in entries? {
+                self.gauges
+                    .database_entries
+                    .get_metric_with_label_values(&[&name])
+                    .map(|c| c.set(entries as i64))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Flushes `database` and reads its size, and the number of entries of every tree if
+/// `count_entries`.
+fn read_stats(database: &PersistentDatabase, count_entries: bool) -> DatabaseStats {
+    let start = Instant::now();
+    let flushed = database.flush();
+    let flush_duration = start.elapsed();
+    let entries = count_entries.then(|| {
+        database
+            .tree_names()
+            .into_iter()
+            .map(|name| {
+                let entries = database.tree(&name)?.len();
+                Ok((name, entries))
+            })
+            .collect()
+    });
+    DatabaseStats {
+        flushed,
+        flush_duration,
+        size: database.size_on_disk(),
+        entries,
+    }
+}
+
+#[async_trait]
+impl Monitor for DatabaseMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "database"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(&self.gauges.database_size),
+            family_name(&self.gauges.database_entries),
+            family_name(&self.gauges.database_flushed_bytes),
+            family_name(&self.gauges.database_flush_failures),
+            family_name(&self.gauges.database_flush_duration),
+            family_name(&self.gauges.database_last_flush),
+        ]
+    }
+
+    fn interval(&self) -> Duration {
+        REFRESH_INTERVAL
+    }
+
+    async fn collect(&mut self, _cycle: &Cycle) -> anyhow::Result<()> {
+        let count_entries = self
+            .entries_counted
+            .is_none_or(|t| t.elapsed() >= ENTRIES_INTERVAL);
+        let database = Arc::clone(&self.database);
+        let stats =
+            tokio::task::spawn_blocking(move || read_stats(&database, count_entries)).await?;
+        if count_entries && stats.entries.as_ref().is_some_and(Result::is_ok) {
+            self.entries_counted = Some(Instant::now());
+        }
+        self.export(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn flushes_and_counts_entries() {
+        let dir = std::env::temp_dir().join(format!("database-stats-test-{}", std::process::id()));
+        let database = PersistentDatabase::new(&dir).unwrap();
+        let tree = database.tree("t").unwrap();
+        for i in 0u32..3 {
+            tree.insert(i.to_be_bytes(), "v").unwrap();
+        }
+
+        let stats = read_stats(&database, true);
+        assert!(stats.flushed.unwrap() > 0);
+        assert!(stats.size.unwrap() > 0);
+        let entries = stats.entries.unwrap().unwrap();
+        assert!(entries.contains(&("t".to_string(), 3)), "{:?}", entries);
+        assert!(read_stats(&database, false).entries.is_none());
+
+        drop((tree, database));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,318 @@
+//! The SOL/USD price, and the USD values of balances and rewards derived with it.
+
+use crate::config::{PriceConfig, PriceSource};
+use crate::gauges::PrometheusGauges;
+use crate::monitor::{family_name, series, Cycle, Monitor};
+use crate::rpc_api::RpcApi;
+use crate::rpc_extra::LAMPORTS_PER_SOL;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use log::debug;
+use prometheus_exporter::prometheus::core::Collector;
+use prometheus_exporter::prometheus::GaugeVec;
+use serde::Deserialize;
+use solana_pubkey::Pubkey;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+/// URL of the Coingecko simple price API.
+const COINGECKO_URI: &str = "https://api.coingecko.com/api/v3/simple/price";
+/// Pyth sponsored price update account of SOL/USD on mainnet.
+const PYTH_SOL_USD_ACCOUNT: &str = "7UVimffxr9ow1uXYxsr4LHAcV58mLzhmwaeKvJ1pjLiE";
+/// Default seconds since its publication after which a price is stale.
+const DEFAULT_MAX_AGE: u64 = 300;
+
+/// A price of SOL.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Price {
+    usd: f64,
+    /// Unix time the price was published at.
+    published_at: i64,
+}
+
+/// A price in the Coingecko simple price response.
+#[derive(Deserialize)]
+struct CoingeckoPrice {
+    usd: f64,
+    last_updated_at: i64,
+}
+
+/// The monitor of the SOL/USD price, exporting it and the USD values of the node and watched
+/// address balances and of the whitelist rewards, at the current price.
+///
+/// The price is fetched every `ttl`, and the USD values derived from it on every cycle, after the
+/// monitors exporting the balances and rewards. If a fetch fails, the previous price is kept
+/// until the next cycle retries it. A price published over `max_age` ago, fetched or kept, is
+/// stale: it fails the cycle rather than being exported.
+pub struct PriceMonitor<'a> {
+    /// HTTP client for Coingecko.
+    client: reqwest::Client,
+    /// Shared Solana RPC client, for Pyth.
+    rpc: &'a dyn RpcApi,
+    gauges: &'a PrometheusGauges,
+    /// Source and its settings.
+    config: PriceConfig,
+    /// Pyth price update account.
+    account: Pubkey,
+    /// Time the fetched price is reused for.
+    ttl: Duration,
+    /// Time since its publication after which a price is stale.
+    max_age: Duration,
+    /// Last fetched price, and when.
+    price: Option<(Price, Instant)>,
+}
+
+impl<'a> PriceMonitor<'a> {
+    /// Constructs a monitor of the price source of `config`, queried with `client` or `rpc`.
+    pub fn new(
+        client: reqwest::Client,
+        rpc: &'a dyn RpcApi,
+        gauges: &'a PrometheusGauges,
+        config: PriceConfig,
+    ) -> anyhow::Result<Self> {
+        let account = config
+            .account
+            .as_deref()
+            .unwrap_or(PYTH_SOL_USD_ACCOUNT)
+            .parse()
+            .context("invalid Pyth price account")?;
+        Ok(Self {
+            client,
+            rpc,
+            gauges,
+            ttl: Duration::from_secs(config.ttl.unwrap_or(60)),
+            max_age: Duration::from_secs(config.max_age.unwrap_or(DEFAULT_MAX_AGE)),
+            config,
+            account,
+            price: None,
+        })
+    }
+
+    /// Fetches the price from the source.
+    async fn fetch(&self) -> anyhow::Result<Price> {
+        match self.config.source {
+            PriceSource::Pyth => {
+                let account = self
+                    .rpc
+                    .get_multiple_accounts(&[self.account])
+                    .await?
+                    .pop()
+                    .flatten()
+                    .ok_or_else(|| anyhow!("Pyth price account {} not found", self.account))?;
+                parse_pyth_price_update(&account.data)
+            }
+            PriceSource::Coingecko => {
+                let mut request = self.client.get(COINGECKO_URI).query(&[
+                    ("ids", "solana"),
+                    ("vs_currencies", "usd"),
+                    ("include_last_updated_at", "true"),
+                ]);
+                if let Some(api_key) = &self.config.api_key {
+                    request = request.header("x-cg-demo-api-key", api_key);
+                }
+                let prices = request
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<HashMap<String, CoingeckoPrice>>()
+                    .await?;
+                let price = prices
+                    .get("solana")
+                    .ok_or_else(|| anyhow!("Coingecko returned no SOL price"))?;
+                Ok(Price {
+                    usd: price.usd,
+                    published_at: price.last_updated_at,
+                })
+            }
+        }
+    }
+
+    /// Exports the price, fetching it first if it is older than `ttl`, and the USD values.
+    async fn export_price(&mut self) -> anyhow::Result<()> {
+        if self.price.is_none_or(|(_, t)| t.elapsed() >= self.ttl) {
+            let price = self.fetch().await?;
+            debug!("Fetched the SOL price: {} USD", price.usd);
+            self.price = Some((price, Instant::now()));
+        }
+        let price = match self.price {
+            Some((price, _)) => price,
+            None => return Ok(()),
+        };
+        check_fresh(
+            price,
+            OffsetDateTime::now_utc().unix_timestamp(),
+            self.max_age,
+        )?;
+        let gauges = self.gauges;
+        gauges.sol_price.set(price.usd);
+        gauges.sol_price_timestamp.set(price.published_at);
+
+        export_balances_usd(
+            &gauges.node_pubkey_balances,
+            &gauges.node_pubkey_balances_sol,
+            &gauges.node_pubkey_balances_usd,
+            price.usd,
+        )?;
+        export_balances_usd(
+            &gauges.address_balances,
+            &gauges.address_balances_sol,
+            &gauges.address_balances_usd,
+            price.usd,
+        )?;
+        export_usd(
+            &gauges.expected_annual_staking_rewards,
+            &gauges.expected_annual_staking_rewards_usd,
+            price.usd,
+        )?;
+        gauges
+            .whitelist_validator_rewards_usd
+            .set(gauges.whitelist_validator_rewards.get() as f64 / LAMPORTS_PER_SOL * price.usd);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Monitor for PriceMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "price"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(&self.gauges.sol_price),
+            family_name(&self.gauges.sol_price_timestamp),
+            family_name(&self.gauges.node_pubkey_balances_usd),
+            family_name(&self.gauges.address_balances_usd),
+            family_name(&self.gauges.whitelist_validator_rewards_usd),
+            family_name(&self.gauges.expected_annual_staking_rewards_usd),
+        ]
+    }
+
+    async fn collect(&mut self, _cycle: &Cycle) -> anyhow::Result<()> {
+        self.export_price().await
+    }
+}
+
+/// Checks that `price` was published no more than `max_age` before the unix time `now`.
+fn check_fresh(price: Price, now: i64, max_age: Duration) -> anyhow::Result<()> {
+    let age = now.saturating_sub(price.published_at);
+    if age > max_age.as_secs() as i64 {
+        return Err(anyhow!(
+            "the SOL price of {} USD is stale, published {} seconds ago",
+            price.usd,
+            age
+        ));
+    }
+    Ok(())
+}
+
+/// Parses the price of a Pyth `PriceUpdateV2` account: an 8-byte discriminator, the write
+/// authority, the verification level, and the price feed message.
+fn parse_pyth_price_update(data: &[u8]) -> anyhow::Result<Price> {
+    let read = |offset: usize, len: usize| {
+        data.get(offset..offset + len)
+            .ok_or_else(|| anyhow!("Pyth price update account is too short"))
+    };
+    // A partial verification level is followed by its number of signatures.
+    let verification_level = read(40, 1)?[0];
+    let message = match verification_level {
+        0 => 42,
+        1 => 41,
+        level => return Err(anyhow!("unknown Pyth verification level {}", level)),
+    };
+    // The feed id comes first in the message.
+    let price = i64::from_le_bytes(read(message + 32, 8)?.try_into()?);
+    let exponent = i32::from_le_bytes(read(message + 48, 4)?.try_into()?);
+    let published_at = i64::from_le_bytes(read(message + 52, 8)?.try_into()?);
+    Ok(Price {
+        usd: price as f64 * 10f64.powi(exponent),
+        published_at,
+    })
+}
+
+/// Exports the balances of `lamports`, or of `sol` if balances are only exported in SOL, into
+/// `usd` at `price`.
+fn export_balances_usd(
+    lamports: &dyn Collector,
+    sol: &dyn Collector,
+    usd: &GaugeVec,
+    price: f64,
+) -> anyhow::Result<()> {
+    let lamport_balances = series(lamports);
+    if lamport_balances.is_empty() {
+        return export_usd(sol, usd, price);
+    }
+    usd.reset();
+    for (labels, value) in lamport_balances {
+        let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+        usd.get_metric_with_label_values(&labels)
+            .map(|c| c.set(value / LAMPORTS_PER_SOL * price))?;
+    }
+    Ok(())
+}
+
+/// Exports the SOL amounts of `sol` into `usd` at `price`, with the same labels.
+fn export_usd(sol: &dyn Collector, usd: &GaugeVec, price: f64) -> anyhow::Result<()> {
+    usd.reset();
+    for (labels, value) in series(sol) {
+        let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+        usd.get_metric_with_label_values(&labels)
+            .map(|c| c.set(value * price))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_exporter::prometheus::{IntGaugeVec, Opts};
+
+    #[test]
+    fn parses_pyth_price_updates() {
+        let mut data = vec![0u8; 8 + 32];
+        // Fully verified.
+        data.push(1);
+        data.extend([7u8; 32]);
+        data.extend(14_512_345_678i64.to_le_bytes());
+        data.extend(5_000_000u64.to_le_bytes());
+        data.extend((-8i32).to_le_bytes());
+        data.extend(1_760_431_122i64.to_le_bytes());
+        data.extend([0u8; 8 * 4]);
+        let price = parse_pyth_price_update(&data).unwrap();
+        assert!((price.usd - 145.12345678).abs() < 1e-9, "{}", price.usd);
+        assert_eq!(price.published_at, 1_760_431_122);
+        assert!(parse_pyth_price_update(&data[..60]).is_err());
+    }
+
+    #[test]
+    fn rejects_stale_prices() {
+        let price = Price {
+            usd: 150.0,
+            published_at: 1_760_431_122,
+        };
+        let max_age = Duration::from_secs(300);
+        assert!(check_fresh(price, 1_760_431_122, max_age).is_ok());
+        assert!(check_fresh(price, 1_760_431_122 + 300, max_age).is_ok());
+        assert!(check_fresh(price, 1_760_431_122 + 301, max_age).is_err());
+    }
+
+    #[test]
+    fn derives_usd_balances() {
+        let lamports =
+            IntGaugeVec::new(Opts::new("balance", "Balance"), &["name", "pubkey"]).unwrap();
+        let sol = GaugeVec::new(Opts::new("balance_sol", "Balance"), &["name", "pubkey"]).unwrap();
+        let usd = GaugeVec::new(Opts::new("balance_usd", "Balance"), &["name", "pubkey"]).unwrap();
+
+        sol.with_label_values(&["treasury", "a"]).set(2.0);
+        export_balances_usd(&lamports, &sol, &usd, 150.0).unwrap();
+        assert_eq!(usd.with_label_values(&["treasury", "a"]).get(), 300.0);
+
+        lamports
+            .with_label_values(&["treasury", "a"])
+            .set(3 * LAMPORTS_PER_SOL as i64);
+        export_balances_usd(&lamports, &sol, &usd, 150.0).unwrap();
+        assert_eq!(usd.with_label_values(&["treasury", "a"]).get(), 450.0);
+    }
+}
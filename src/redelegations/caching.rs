@@ -0,0 +1,60 @@
+use crate::persistent_database::envelope::{self, Encoding};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use solana_clock::Epoch;
+
+/// Name of the tree holding the last observed delegation target of every stake account.
+pub const DELEGATION_TARGETS_TREE_NAME: &str = "delegation_targets";
+
+/// The vote account a stake account was last observed delegated to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DelegationTarget {
+    /// Vote account pubkey.
+    pub voter: String,
+    /// The epoch the delegation was last observed in.
+    pub epoch: Epoch,
+    /// The last redelegation away from another vote account, if any was observed.
+    pub last_redelegation: Option<Redelegation>,
+}
+
+/// A change of the vote account a stake account is delegated to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Redelegation {
+    /// The vote account the stake account was delegated to before.
+    pub previous_voter: String,
+    /// The epoch the change was observed in.
+    pub epoch: Epoch,
+    /// Unix time the change was observed at.
+    pub observed_at: i64,
+}
+
+/// A caching database for delegation targets, so that redelegations are detected across exporter
+/// restarts.
+pub struct DelegationTargetsCache {
+    tree: sled::Tree,
+}
+
+impl DelegationTargetsCache {
+    /// Creates a new cache using a tree.
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+
+    /// Returns the last persisted delegation target of `stake_pubkey`.
+    pub fn get_target(&self, stake_pubkey: &str) -> anyhow::Result<Option<DelegationTarget>> {
+        self.tree
+            .get(stake_pubkey)
+            .context("could not fetch delegation target from database")?
+            .map(|x| envelope::decode(&x))
+            .transpose()
+            .context("could not deserialize fetched delegation target")
+    }
+
+    /// Persists the delegation target of `stake_pubkey`.
+    pub fn set_target(&self, stake_pubkey: &str, target: &DelegationTarget) -> anyhow::Result<()> {
+        self.tree
+            .insert(stake_pubkey, envelope::encode(Encoding::Json, target)?)
+            .context("could not insert delegation target into database")?;
+        Ok(())
+    }
+}
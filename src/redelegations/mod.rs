@@ -0,0 +1,302 @@
+//! Changes of the vote accounts whitelisted stake accounts are delegated to.
+
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::persistent_database::counters::CounterBaselines;
+use crate::redelegations::caching::{DelegationTarget, DelegationTargetsCache, Redelegation};
+use crate::rpc_api::RpcApi;
+use crate::rpc_extra::MAX_MULTIPLE_ACCOUNTS;
+use anyhow::Context;
+use async_trait::async_trait;
+use log::info;
+use prometheus_exporter::prometheus::{IntCounterVec, IntGaugeVec};
+use solana_clock::Epoch;
+use solana_pubkey::Pubkey;
+use solana_stake_interface::state::StakeStateV2;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+pub mod caching;
+
+/// Time between two queries of the stake accounts.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// The monitor of the vote accounts the whitelisted stake accounts are delegated to.
+///
+/// The vote account of every stake account is persisted, so that a stake account found delegated
+/// to a different one, even after an exporter restart, is counted as a redelegation. A stake
+/// account keeps its vote account while it deactivates, and until it is delegated again.
+pub struct RedelegationsMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Prometheus gauge of the vote account of each stake account.
+    delegation: &'a IntGaugeVec,
+    /// Prometheus counter.
+    redelegations: &'a IntCounterVec,
+    /// Prometheus gauge of the Unix time of the last redelegation of each stake account.
+    last_redelegation: &'a IntGaugeVec,
+    /// Persisted counter totals.
+    baselines: &'a CounterBaselines,
+    /// Caching database for delegation targets.
+    cache: &'a DelegationTargetsCache,
+}
+
+impl<'a> RedelegationsMonitor<'a> {
+    /// Constructs a monitor given `rpc`.
+    pub fn new(
+        rpc: &'a dyn RpcApi,
+        delegation: &'a IntGaugeVec,
+        redelegations: &'a IntCounterVec,
+        last_redelegation: &'a IntGaugeVec,
+        baselines: &'a CounterBaselines,
+        cache: &'a DelegationTargetsCache,
+    ) -> Self {
+        Self {
+            rpc,
+            delegation,
+            redelegations,
+            last_redelegation,
+            baselines,
+            cache,
+        }
+    }
+
+    /// Queries the delegations of `stake_accounts` in `epoch`, counts and persists their
+    /// redelegations, and exports their vote accounts. Stake accounts that are not delegated are
+    /// left out. The series are only replaced once all stake accounts are queried, so a failed
+    /// query keeps the previous ones.
+    async fn export_delegations(
+        &self,
+        stake_accounts: &[Pubkey],
+        epoch: Epoch,
+    ) -> anyhow::Result<()> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let mut voters = Vec::new();
+        for chunk in stake_accounts.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let accounts = self.rpc.get_multiple_accounts(chunk).await?;
+            for (pubkey, account) in chunk.iter().zip(accounts) {
+                let delegation = account
+                    .and_then(|a| bincode::deserialize::<StakeStateV2>(&a.data).ok())
+                    .and_then(|state| state.delegation());
+                if let Some(delegation) = delegation {
+                    voters.push((pubkey.to_string(), delegation.voter_pubkey.to_string()));
+                }
+            }
+        }
+
+        let mut targets = Vec::with_capacity(voters.len());
+        for (stake_pubkey, voter) in voters {
+            let previous = self.cache.get_target(&stake_pubkey)?;
+            if let Some(previous) = previous.as_ref().filter(|p| p.voter != voter) {
+                info!(
+                    "Stake account {} redelegated from {} to {}",
+                    stake_pubkey, previous.voter, voter
+                );
+                self.baselines
+                    .inc_by(self.redelegations, &[&stake_pubkey], 1)?;
+            }
+            let target = observe(previous, &voter, epoch, now);
+            self.cache.set_target(&stake_pubkey, &target)?;
+            targets.push((stake_pubkey, target));
+        }
+
+        // Reset, so that stake accounts no longer whitelisted are dropped.
+        self.delegation.reset();
+        self.last_redelegation.reset();
+        for (stake_pubkey, target) in targets {
+            self.delegation
+                .get_metric_with_label_values(&[&stake_pubkey, &target.voter])
+                .map(|m| m.set(1))?;
+            if let Some(redelegation) = &target.last_redelegation {
+                self.last_redelegation
+                    .get_metric_with_label_values(&[
+                        &stake_pubkey,
+                        &redelegation.previous_voter,
+                        &target.voter,
+                    ])
+                    .map(|m| m.set(redelegation.observed_at))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the delegation target of a stake account observed delegated to `voter` in `epoch` at
+/// `now`, given its `previous` one. A different vote account than before is a redelegation
+/// observed at `now`.
+fn observe(
+    previous: Option<DelegationTarget>,
+    voter: &str,
+    epoch: Epoch,
+    now: i64,
+) -> DelegationTarget {
+    let last_redelegation = match previous {
+        Some(previous) if previous.voter != voter => Some(Redelegation {
+            previous_voter: previous.voter,
+            epoch,
+            observed_at: now,
+        }),
+        Some(previous) => previous.last_redelegation,
+        None => None,
+    };
+    DelegationTarget {
+        voter: voter.to_string(),
+        epoch,
+        last_redelegation,
+    }
+}
+
+#[async_trait]
+impl Monitor for RedelegationsMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "redelegation"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(self.delegation),
+            family_name(self.redelegations),
+            family_name(self.last_redelegation),
+        ]
+    }
+
+    /// Restores the redelegation counter totals.
+    async fn setup(&mut self) -> anyhow::Result<()> {
+        self.baselines.restore(self.redelegations)
+    }
+
+    fn interval(&self) -> Duration {
+        REFRESH_INTERVAL
+    }
+
+    /// Without a stake account whitelist, which then stands for all stake accounts, nothing is
+    /// exported.
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        let mut stake_accounts = cycle
+            .staking_account_whitelist
+            .0
+            .iter()
+            .map(|pubkey| {
+                pubkey
+                    .parse::<Pubkey>()
+                    .with_context(|| format!("invalid stake account {}", pubkey))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        stake_accounts.sort_unstable();
+        self.export_delegations(&stake_accounts, cycle.epoch_info.epoch)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_api::mock::MockRpc;
+    use prometheus_exporter::prometheus::core::Collector;
+    use prometheus_exporter::prometheus::Opts;
+    use solana_account::Account;
+    use solana_stake_interface::stake_flags::StakeFlags;
+    use solana_stake_interface::state::{Delegation, Meta, Stake};
+
+    #[test]
+    fn keeps_the_last_redelegation() {
+        let first = observe(None, "a", 10, 1_000);
+        assert_eq!(first.last_redelegation, None);
+
+        let same = observe(Some(first), "a", 11, 2_000);
+        assert_eq!(same.epoch, 11);
+        assert_eq!(same.last_redelegation, None);
+
+        let moved = observe(Some(same), "b", 12, 3_000);
+        let redelegation = Redelegation {
+            previous_voter: "a".to_string(),
+            epoch: 12,
+            observed_at: 3_000,
+        };
+        assert_eq!(moved.last_redelegation, Some(redelegation.clone()));
+        assert_eq!(
+            observe(Some(moved), "b", 13, 4_000).last_redelegation,
+            Some(redelegation)
+        );
+    }
+
+    #[tokio::test]
+    async fn counts_persisted_redelegations() {
+        let stake_account = |voter: &Pubkey| {
+            let stake_state = StakeStateV2::Stake(
+                Meta::default(),
+                Stake {
+                    delegation: Delegation::new(voter, 1_000_000, 0),
+                    credits_observed: 0,
+                },
+                StakeFlags::empty(),
+            );
+            Account {
+                lamports: 1_000_000,
+                data: bincode::serialize(&stake_state).unwrap(),
+                owner: Pubkey::default(),
+                executable: false,
+                rent_epoch: 0,
+            }
+        };
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let baselines = CounterBaselines::new(db.open_tree("counters").unwrap());
+        let cache = DelegationTargetsCache::new(db.open_tree("targets").unwrap());
+        let delegation =
+            IntGaugeVec::new(Opts::new("delegation", "Delegation"), &["pubkey", "voter"]).unwrap();
+        let redelegations =
+            IntCounterVec::new(Opts::new("redelegations", "Redelegations"), &["pubkey"]).unwrap();
+        let last_redelegation = IntGaugeVec::new(
+            Opts::new("last_redelegation", "Last redelegation"),
+            &["pubkey", "previous_voter", "voter"],
+        )
+        .unwrap();
+        let (stake, first_voter, second_voter) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        let mut rpc = MockRpc::default();
+        rpc.accounts.insert(stake, stake_account(&first_voter));
+        let monitor = RedelegationsMonitor::new(
+            &rpc,
+            &delegation,
+            &redelegations,
+            &last_redelegation,
+            &baselines,
+            &cache,
+        );
+        monitor.export_delegations(&[stake], 10).await.unwrap();
+        let labels = [stake.to_string(), first_voter.to_string()];
+        assert_eq!(
+            delegation
+                .with_label_values(&[&labels[0], &labels[1]])
+                .get(),
+            1
+        );
+        assert_eq!(redelegations.with_label_values(&[&labels[0]]).get(), 0);
+
+        rpc.accounts.insert(stake, stake_account(&second_voter));
+        let monitor = RedelegationsMonitor::new(
+            &rpc,
+            &delegation,
+            &redelegations,
+            &last_redelegation,
+            &baselines,
+            &cache,
+        );
+        monitor.export_delegations(&[stake], 11).await.unwrap();
+        assert_eq!(redelegations.with_label_values(&[&labels[0]]).get(), 1);
+        assert_eq!(
+            cache.get_target(&labels[0]).unwrap().unwrap().voter,
+            second_voter.to_string()
+        );
+        assert_eq!(delegation.collect()[0].get_metric().len(), 1);
+        assert!(
+            last_redelegation
+                .with_label_values(&[&labels[0], &labels[1], &second_voter.to_string()])
+                .get()
+                > 0
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Reverse DNS lookups of node IP addresses, cached in the persistent database.
+
+use crate::persistent_database::envelope::{self, Encoding};
+use anyhow::anyhow;
+use dns_lookup::LookupErrorKind;
+use futures::StreamExt;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use time::OffsetDateTime;
+
+/// Name of the caching database.
+pub const REVERSE_DNS_TREE_NAME: &str = "reverse_dns";
+
+/// Seconds a looked up hostname, or the lack of one, is reused for.
+const TTL: i64 = 24 * 3600;
+/// Number of lookups in flight at once.
+const LOOKUP_CONCURRENCY: usize = 16;
+/// Maximum number of lookups per update cycle; the cluster has thousands of nodes, which are
+/// looked up over the first cycles rather than in one.
+const MAX_LOOKUPS_PER_CYCLE: usize = 256;
+
+/// The cached result of a lookup.
+#[derive(Serialize, Deserialize)]
+struct CachedHostname {
+    /// The hostname, or `None` if the address has no PTR record.
+    hostname: Option<String>,
+    /// When it was looked up, in Unix seconds.
+    fetched_at: i64,
+}
+
+/// A reverse DNS resolver looking up hostnames with the system resolver, `getnameinfo`, which
+/// follows the system's configuration of hosts files, nameservers and timeouts.
+pub struct ReverseDns {
+    tree: sled::Tree,
+}
+
+impl ReverseDns {
+    /// Constructs a resolver caching into `tree`.
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+
+    /// Returns the hostnames of those of `ips` that have one, looking up the uncached or stale
+    /// ones. Failed lookups are retried on the next call.
+    pub async fn hostnames(
+        &self,
+        ips: impl IntoIterator<Item = IpAddr>,
+    ) -> HashMap<IpAddr, String> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let mut hostnames = HashMap::new();
+        let mut uncached = Vec::new();
+        for ip in ips {
+            match self.cached(&ip) {
+                Some(cached) if now - cached.fetched_at < TTL => {
+                    if let Some(hostname) = cached.hostname {
+                        hostnames.insert(ip, hostname);
+                    }
+                }
+                _ => uncached.push(ip),
+            }
+        }
+        if !uncached.is_empty() {
+            debug!("Looking up the hostnames of {} addresses", uncached.len());
+        }
+
+        let looked_up: Vec<_> =
+            futures::stream::iter(uncached.into_iter().take(MAX_LOOKUPS_PER_CYCLE))
+                .map(|ip| async move { (ip, lookup(ip).await) })
+                .buffer_unordered(LOOKUP_CONCURRENCY)
+                .collect()
+                .await;
+        for (ip, hostname) in looked_up {
+            let hostname = match hostname {
+                Ok(hostname) => hostname,
+                Err(e) => {
+                    debug!("Could not look up the hostname of {}: {e:#}", ip);
+                    continue;
+                }
+            };
+            let cached = CachedHostname {
+                hostname,
+                fetched_at: now,
+            };
+            if let Err(e) = self.cache(&ip, &cached) {
+                debug!("Could not cache the hostname of {}: {e:#}", ip);
+            }
+            if let Some(hostname) = cached.hostname {
+                hostnames.insert(ip, hostname);
+            }
+        }
+        hostnames
+    }
+
+    fn cached(&self, ip: &IpAddr) -> Option<CachedHostname> {
+        let bytes = self.tree.get(bincode::serialize(ip).ok()?).ok()??;
+        envelope::decode(&bytes).ok()
+    }
+
+    fn cache(&self, ip: &IpAddr, cached: &CachedHostname) -> anyhow::Result<()> {
+        self.tree.insert(
+            bincode::serialize(ip)?,
+            envelope::encode(Encoding::Json, cached)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Looks up the hostname of `ip` on a blocking thread, `None` if it has none.
+async fn lookup(ip: IpAddr) -> anyhow::Result<Option<String>> {
+    match tokio::task::spawn_blocking(move || dns_lookup::lookup_addr(&ip)).await? {
+        Ok(hostname) => Ok(Some(hostname)),
+        Err(e) if matches!(e.kind(), LookupErrorKind::NoName | LookupErrorKind::NoData) => Ok(None),
+        Err(e) => Err(anyhow!("{}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn serves_fresh_cached_hostnames() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let reverse_dns = ReverseDns::new(db.open_tree(REVERSE_DNS_TREE_NAME).unwrap());
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let (named, unnamed): (IpAddr, IpAddr) =
+            ("192.0.2.1".parse().unwrap(), "192.0.2.2".parse().unwrap());
+        let cached = |hostname: Option<&str>| CachedHostname {
+            hostname: hostname.map(str::to_string),
+            fetched_at: now,
+        };
+        reverse_dns
+            .cache(&named, &cached(Some("node.example.com")))
+            .unwrap();
+        reverse_dns.cache(&unnamed, &cached(None)).unwrap();
+
+        let hostnames = reverse_dns.hostnames([named, unnamed]).await;
+        assert_eq!(
+            hostnames,
+            HashMap::from([(named, "node.example.com".to_string())])
+        );
+    }
+}
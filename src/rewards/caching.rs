@@ -1,12 +1,21 @@
-use crate::rewards::VoterApy;
+use crate::persistent_database::envelope::{self, Encoding};
+use crate::persistent_database::PersistentDatabase;
+use crate::rewards::{apr_of_apy, VoterApy, DEFAULT_EPOCH_LENGTH};
 use anyhow::Context;
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
 use solana_clock::Epoch;
 use solana_pubkey::Pubkey;
+use solana_reward_info::RewardType;
 use solana_transaction_status_client_types::{Reward, Rewards};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryInto;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 pub type PubkeyVoterApyMapping = HashMap<Pubkey, (Pubkey, f64)>;
+/// The first and last epoch cached in a tree.
+pub type EpochRange = RangeInclusive<Epoch>;
 
 pub const EPOCH_REWARDS_TREE_NAME: &str = "epoch_rewards";
 pub const APY_TREE_NAME: &str = "apy";
@@ -16,15 +25,139 @@ pub const EPOCH_VOTER_APY_TREE_NAME: &str = "epoch_voter_apy";
 #[derive(Copy, Clone, Serialize, Deserialize)]
 struct ApyTreeKey(Epoch, Pubkey);
 
+/// The APY of a stake account and its voter, as written in bincode by earlier versions.
 #[derive(Copy, Clone, Serialize, Deserialize)]
 struct ApyTreeValue(Pubkey, f64);
 
+/// The APY of a stake account and its voter, as stored in the database.
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedApy {
+    voter: String,
+    apy: f64,
+}
+
+/// A [`VoterApy`] as stored in the database before APRs were added.
+#[derive(Clone, Serialize, Deserialize)]
+struct LegacyVoterApy {
+    current_apy: f64,
+    average_apy: f64,
+}
+
+/// A [`Reward`] as stored in the database. `Reward` skips serializing unset optional fields, which
+/// the bincode entries of earlier versions could not deserialize, so it is not stored as is.
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedReward {
+    pubkey: String,
+    lamports: i64,
+    post_balance: u64,
+    reward_type: Option<RewardType>,
+    commission: Option<u8>,
+    commission_bps: Option<u16>,
+}
+
+impl From<&Reward> for CachedReward {
+    fn from(r: &Reward) -> Self {
+        Self {
+            pubkey: r.pubkey.clone(),
+            lamports: r.lamports,
+            post_balance: r.post_balance,
+            reward_type: r.reward_type,
+            commission: r.commission,
+            commission_bps: r.commission_bps,
+        }
+    }
+}
+
+impl From<CachedReward> for Reward {
+    fn from(r: CachedReward) -> Self {
+        Self {
+            pubkey: r.pubkey,
+            lamports: r.lamports,
+            post_balance: r.post_balance,
+            reward_type: r.reward_type,
+            commission: r.commission,
+            commission_bps: r.commission_bps,
+        }
+    }
+}
+
+/// A [`Reward`] as written in bare bincode by versions before [`CachedReward`], without the
+/// commission in basis points.
+#[derive(Clone, Serialize, Deserialize)]
+struct LegacyReward {
+    pubkey: String,
+    lamports: i64,
+    post_balance: u64,
+    reward_type: Option<RewardType>,
+    commission: Option<u8>,
+}
+
+impl From<LegacyReward> for CachedReward {
+    fn from(r: LegacyReward) -> Self {
+        Self {
+            pubkey: r.pubkey,
+            lamports: r.lamports,
+            post_balance: r.post_balance,
+            reward_type: r.reward_type,
+            commission: r.commission,
+            commission_bps: None,
+        }
+    }
+}
+
+/// The cached epochs of a tree and its entries that cannot be deserialized.
+#[derive(Debug)]
+pub struct TreeEntries {
+    pub name: &'static str,
+    pub epochs: BTreeSet<Epoch>,
+    /// Descriptions of the unreadable entries.
+    pub unreadable: Vec<String>,
+}
+
+/// Numbers of lookups in a tree since start that found an entry or not.
+#[derive(Debug, Default)]
+pub struct LookupCounts {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl LookupCounts {
+    /// Counts a successful lookup as a hit if it found an entry.
+    fn record<T>(&self, result: &anyhow::Result<Option<T>>) {
+        match result {
+            Ok(Some(_)) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(None) => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {}
+        }
+    }
+
+    /// Returns the number of lookups that found an entry.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of lookups that found no entry.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
 /// A caching database for vote accounts' credit growth
 pub struct RewardsCache {
     epoch_rewards_tree: sled::Tree,
     apy_tree: sled::Tree,
     epoch_length_tree: sled::Tree,
     epoch_voter_apy_tree: sled::Tree,
+    epoch_rewards_lookups: LookupCounts,
+    apy_lookups: LookupCounts,
+    epoch_length_lookups: LookupCounts,
+    epoch_voter_apy_lookups: LookupCounts,
+    /// Whether unreadable cached rewards were warned about already.
+    unreadable_rewards_warned: AtomicBool,
 }
 
 impl RewardsCache {
@@ -40,13 +173,43 @@ impl RewardsCache {
             apy_tree,
             epoch_length_tree,
             epoch_voter_apy_tree,
+            epoch_rewards_lookups: LookupCounts::default(),
+            apy_lookups: LookupCounts::default(),
+            epoch_length_lookups: LookupCounts::default(),
+            epoch_voter_apy_lookups: LookupCounts::default(),
+            unreadable_rewards_warned: AtomicBool::new(false),
         }
     }
 
+    /// Makes a cache in a temporary database, for tests.
+    #[cfg(test)]
+    pub(crate) fn temporary() -> Self {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        Self::new(
+            db.open_tree(EPOCH_REWARDS_TREE_NAME).unwrap(),
+            db.open_tree(APY_TREE_NAME).unwrap(),
+            db.open_tree(EPOCH_LENGTH_TREE_NAME).unwrap(),
+            db.open_tree(EPOCH_VOTER_APY_TREE_NAME).unwrap(),
+        )
+    }
+
+    /// Opens the cache in the trees of `database`.
+    pub fn open(database: &PersistentDatabase) -> anyhow::Result<Self> {
+        Ok(Self::new(
+            database.tree(EPOCH_REWARDS_TREE_NAME)?,
+            database.tree(APY_TREE_NAME)?,
+            database.tree(EPOCH_LENGTH_TREE_NAME)?,
+            database.tree(EPOCH_VOTER_APY_TREE_NAME)?,
+        ))
+    }
+
     /// Adds the length of an epoch.
     pub fn add_epoch_length(&self, epoch: Epoch, length: f64) -> anyhow::Result<()> {
         self.epoch_length_tree
-            .insert(epoch.to_be_bytes(), bincode::serialize(&length)?)
+            .insert(
+                epoch.to_be_bytes(),
+                envelope::encode(Encoding::Json, &length)?,
+            )
             .context("could not insert epoch length into database")?;
 
         Ok(())
@@ -54,32 +217,79 @@ impl RewardsCache {
 
     /// Returns the length of an epoch
     pub fn get_epoch_length(&self, epoch: Epoch) -> anyhow::Result<Option<f64>> {
-        self.epoch_length_tree
+        let length = self
+            .epoch_length_tree
             .get(epoch.to_be_bytes())
             .context("could not fetch epoch length from database")?
-            .map(|x| bincode::deserialize(&x))
+            .map(|x| envelope::decode(&x))
             .transpose()
-            .context("could not deserialize fetched epoch length")
+            .context("could not deserialize fetched epoch length");
+        self.epoch_length_lookups.record(&length);
+        length
     }
 
     /// Adds a set of rewards of an epoch.
     pub fn add_epoch_rewards(&self, epoch: Epoch, rewards: &[Reward]) -> anyhow::Result<()> {
         // Insert into database
         self.epoch_rewards_tree
-            .insert(epoch.to_be_bytes(), bincode::serialize(&rewards.to_vec())?)
+            .insert(
+                epoch.to_be_bytes(),
+                envelope::encode(
+                    Encoding::Json,
+                    &rewards.iter().map(CachedReward::from).collect::<Vec<_>>(),
+                )?,
+            )
             .context("could not insert epoch rewards into database")?;
 
         Ok(())
     }
 
-    /// Returns the set of rewards of an epoch.
+    /// Returns the set of rewards of an epoch. Entries written by older versions as plain
+    /// `Reward`s are migrated to the current format. Entries that cannot be deserialized at all
+    /// are treated as missing so that they are fetched and cached again, with a warning the first
+    /// time.
     pub fn get_epoch_rewards(&self, epoch: Epoch) -> anyhow::Result<Option<Rewards>> {
-        self.epoch_rewards_tree
+        let cached = self
+            .epoch_rewards_tree
             .get(epoch.to_be_bytes())
-            .context("could not fetch epoch rewards from database")?
-            .map(|x| bincode::deserialize(&x))
-            .transpose()
-            .context("could not deserialize fetched epoch rewards")
+            .context("could not fetch epoch rewards from database")?;
+        let rewards = match cached {
+            Some(bytes) => self.decode_epoch_rewards(epoch, &bytes),
+            None => Ok(None),
+        };
+        self.epoch_rewards_lookups.record(&rewards);
+        rewards
+    }
+
+    /// Decodes the cached rewards of `epoch`, migrating legacy entries. Unreadable entries are
+    /// `None`.
+    fn decode_epoch_rewards(&self, epoch: Epoch, bytes: &[u8]) -> anyhow::Result<Option<Rewards>> {
+        let error = match envelope::decode::<Vec<CachedReward>>(bytes) {
+            Ok(rewards) => return Ok(Some(rewards.into_iter().map(Reward::from).collect())),
+            Err(e) => e,
+        };
+        if let Ok(legacy) = bincode::deserialize::<Vec<LegacyReward>>(bytes) {
+            let rewards: Vec<Reward> = legacy
+                .into_iter()
+                .map(|r| CachedReward::from(r).into())
+                .collect();
+            self.add_epoch_rewards(epoch, &rewards)?;
+            info!("Migrated the cached rewards of epoch {}", epoch);
+            return Ok(Some(rewards));
+        }
+        if self.unreadable_rewards_warned.swap(true, Ordering::Relaxed) {
+            debug!(
+                "Discarding unreadable cached rewards of epoch {}: {}",
+                epoch, error
+            );
+        } else {
+            warn!(
+                "Discarding unreadable cached rewards of epoch {}, and of any other epochs, which \
+                 are fetched again: {}",
+                epoch, error
+            );
+        }
+        Ok(None)
     }
 
     /// Adds a set of staking APY data of an epoch.
@@ -87,26 +297,121 @@ impl RewardsCache {
         for (pubkey, (voter, apy)) in apys {
             let key = bincode::serialize(&ApyTreeKey(epoch, pubkey))?;
             self.apy_tree
-                .insert(key, bincode::serialize(&ApyTreeValue(voter, apy))?)
+                .insert(
+                    key,
+                    envelope::encode(
+                        Encoding::Json,
+                        &CachedApy {
+                            voter: voter.to_string(),
+                            apy,
+                        },
+                    )?,
+                )
                 .context("could not insert APY data into database")?;
         }
         Ok(())
     }
 
+    /// Whether staking APY data of an epoch is cached, without counting as a lookup. An epoch
+    /// whose voter APYs are cached counts as covered, even if none of its stake accounts had an
+    /// APY.
+    pub fn has_epoch_apy(&self, epoch: Epoch) -> anyhow::Result<bool> {
+        if self
+            .epoch_voter_apy_tree
+            .contains_key(epoch.to_be_bytes())?
+        {
+            return Ok(true);
+        }
+        Ok(self
+            .apy_tree
+            .scan_prefix(bincode::serialize(&epoch)?)
+            .next()
+            .transpose()?
+            .is_some())
+    }
+
     /// Returns a set of staking APY data of an epoch
     pub fn get_epoch_apy(&self, epoch: Epoch) -> anyhow::Result<Option<PubkeyVoterApyMapping>> {
         let mut mapping = PubkeyVoterApyMapping::new();
         for kv in self.apy_tree.scan_prefix(bincode::serialize(&epoch)?) {
             let (k, v) = kv?;
             let k: ApyTreeKey = bincode::deserialize(&k)?;
-            let v: ApyTreeValue = bincode::deserialize(&v)?;
-            mapping.insert(k.1, (v.0, v.1));
+            mapping.insert(k.1, decode_apy(&v)?);
         }
-        if mapping.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(mapping))
+        let apy = Ok((!mapping.is_empty()).then_some(mapping));
+        self.apy_lookups.record(&apy);
+        apy
+    }
+
+    /// Returns the latest epoch that staking APY data is cached for.
+    pub fn latest_apy_epoch(&self) -> anyhow::Result<Option<Epoch>> {
+        Ok(self.apy_epoch_range()?.map(|r| *r.end()))
+    }
+
+    /// Returns the first and last epoch that staking APY data is cached for.
+    fn apy_epoch_range(&self) -> anyhow::Result<Option<EpochRange>> {
+        // Keys are not ordered by epoch, since bincode encodes integers little-endian.
+        let mut range: Option<EpochRange> = None;
+        for k in self.apy_tree.iter().keys() {
+            let ApyTreeKey(epoch, _) = bincode::deserialize(&k?)?;
+            range = Some(match range {
+                Some(r) => *r.start().min(&epoch)..=*r.end().max(&epoch),
+                None => epoch..=epoch,
+            });
         }
+        Ok(range)
+    }
+
+    /// Removes all data of epochs before `epoch`. Returns the number of removed entries of each
+    /// tree, by tree name.
+    pub fn prune_before(&self, epoch: Epoch) -> anyhow::Result<Vec<(&'static str, usize)>> {
+        let mut apy_keys = Vec::new();
+        for k in self.apy_tree.iter().keys() {
+            let k = k?;
+            let ApyTreeKey(key_epoch, _) = bincode::deserialize(&k)?;
+            if key_epoch < epoch {
+                apy_keys.push(k);
+            }
+        }
+        let apy_removed = apy_keys.len();
+        for k in apy_keys {
+            self.apy_tree.remove(k)?;
+        }
+
+        Ok(vec![
+            (
+                EPOCH_REWARDS_TREE_NAME,
+                be_prune_before(&self.epoch_rewards_tree, epoch)?,
+            ),
+            (APY_TREE_NAME, apy_removed),
+            (
+                EPOCH_LENGTH_TREE_NAME,
+                be_prune_before(&self.epoch_length_tree, epoch)?,
+            ),
+            (
+                EPOCH_VOTER_APY_TREE_NAME,
+                be_prune_before(&self.epoch_voter_apy_tree, epoch)?,
+            ),
+        ])
+    }
+
+    /// Returns the range of cached epochs of each tree, by tree name.
+    pub fn epoch_ranges(&self) -> anyhow::Result<Vec<(&'static str, Option<EpochRange>)>> {
+        Ok(vec![
+            (
+                EPOCH_REWARDS_TREE_NAME,
+                be_epoch_range(&self.epoch_rewards_tree)?,
+            ),
+            (APY_TREE_NAME, self.apy_epoch_range()?),
+            (
+                EPOCH_LENGTH_TREE_NAME,
+                be_epoch_range(&self.epoch_length_tree)?,
+            ),
+            (
+                EPOCH_VOTER_APY_TREE_NAME,
+                be_epoch_range(&self.epoch_voter_apy_tree)?,
+            ),
+        ])
     }
 
     /// Adds an epoch's hashmap of voter APY mapping.
@@ -116,7 +421,16 @@ impl RewardsCache {
         voter_apys: &HashMap<Pubkey, VoterApy>,
     ) -> anyhow::Result<()> {
         self.epoch_voter_apy_tree
-            .insert(epoch.to_be_bytes(), bincode::serialize(voter_apys)?)
+            .insert(
+                epoch.to_be_bytes(),
+                envelope::encode(
+                    Encoding::Json,
+                    &voter_apys
+                        .iter()
+                        .map(|(voter, apy)| (voter.to_string(), apy))
+                        .collect::<HashMap<_, _>>(),
+                )?,
+            )
             .context("could not insert voter apy into database")?;
         Ok(())
     }
@@ -126,11 +440,283 @@ impl RewardsCache {
         &self,
         epoch: Epoch,
     ) -> anyhow::Result<Option<HashMap<Pubkey, VoterApy>>> {
-        self.epoch_voter_apy_tree
+        let voter_apy = self
+            .epoch_voter_apy_tree
             .get(epoch.to_be_bytes())
             .context("could not fetch epoch voter apy from database")?
-            .map(|x| bincode::deserialize(&x))
+            .map(|x| self.deserialize_voter_apy(epoch, &x))
             .transpose()
-            .context("could not deserialize fetched epoch voter apy")
+            .context("could not deserialize fetched epoch voter apy");
+        self.epoch_voter_apy_lookups.record(&voter_apy);
+        voter_apy
+    }
+
+    /// Deserializes the voter APYs of `epoch`, keyed by pubkey strings in JSON, or by pubkeys in
+    /// the bincode of earlier versions. Entries cached before APRs were added are shorter, so they
+    /// never deserialize as the current format; their APRs are derived from the APYs at the cached
+    /// length of the epoch the rewards were earned in, which is exact for the current APR and
+    /// close for the average one.
+    fn deserialize_voter_apy(
+        &self,
+        epoch: Epoch,
+        bytes: &[u8],
+    ) -> anyhow::Result<HashMap<Pubkey, VoterApy>> {
+        if envelope::encoding(bytes) == Some(Encoding::Json) {
+            let voter_apy: HashMap<String, VoterApy> = envelope::decode(bytes)?;
+            return voter_apy
+                .into_iter()
+                .map(|(voter, apy)| Ok((voter.parse()?, apy)))
+                .collect();
+        }
+        if let Ok(voter_apy) = envelope::decode(bytes) {
+            return Ok(voter_apy);
+        }
+        let legacy: HashMap<Pubkey, LegacyVoterApy> = envelope::decode(bytes)?;
+        let duration = match epoch.checked_sub(1) {
+            Some(prev) => self.get_epoch_length(prev)?,
+            None => None,
+        }
+        .unwrap_or(DEFAULT_EPOCH_LENGTH);
+        Ok(legacy
+            .into_iter()
+            .map(|(voter, apy)| {
+                let voter_apy = VoterApy {
+                    current_apy: apy.current_apy,
+                    average_apy: apy.average_apy,
+                    current_apr: apr_of_apy(apy.current_apy, duration),
+                    average_apr: apr_of_apy(apy.average_apy, duration),
+                };
+                (voter, voter_apy)
+            })
+            .collect())
+    }
+
+    /// Returns the lookup counts of each tree since start, by tree name.
+    pub fn lookup_counts(&self) -> Vec<(&'static str, &LookupCounts)> {
+        vec![
+            (EPOCH_REWARDS_TREE_NAME, &self.epoch_rewards_lookups),
+            (APY_TREE_NAME, &self.apy_lookups),
+            (EPOCH_LENGTH_TREE_NAME, &self.epoch_length_lookups),
+            (EPOCH_VOTER_APY_TREE_NAME, &self.epoch_voter_apy_lookups),
+        ]
+    }
+
+    /// Reads every entry of every tree, and returns the cached epochs and unreadable entries of
+    /// each, by tree name.
+    pub fn tree_entries(&self) -> anyhow::Result<Vec<TreeEntries>> {
+        let mut apy = TreeEntries {
+            name: APY_TREE_NAME,
+            epochs: BTreeSet::new(),
+            unreadable: Vec::new(),
+        };
+        for kv in self.apy_tree.iter() {
+            let (k, v) = kv?;
+            match bincode::deserialize::<ApyTreeKey>(&k) {
+                Ok(ApyTreeKey(epoch, stake)) => {
+                    apy.epochs.insert(epoch);
+                    if decode_apy(&v).is_err() {
+                        apy.unreadable
+                            .push(format!("epoch {} stake {}", epoch, stake));
+                    }
+                }
+                Err(_) => apy.unreadable.push(format!("key {:02x?}", k.as_ref())),
+            }
+        }
+        Ok(vec![
+            be_tree_entries(EPOCH_REWARDS_TREE_NAME, &self.epoch_rewards_tree, |_, v| {
+                envelope::decode::<Vec<CachedReward>>(v).is_ok()
+                    || bincode::deserialize::<Vec<LegacyReward>>(v).is_ok()
+            })?,
+            apy,
+            be_tree_entries(EPOCH_LENGTH_TREE_NAME, &self.epoch_length_tree, |_, v| {
+                envelope::decode::<f64>(v).is_ok()
+            })?,
+            be_tree_entries(
+                EPOCH_VOTER_APY_TREE_NAME,
+                &self.epoch_voter_apy_tree,
+                |epoch, v| self.deserialize_voter_apy(epoch, v).is_ok(),
+            )?,
+        ])
+    }
+}
+
+/// Decodes the voter and APY of a stake account, from JSON, or from the bincode of earlier
+/// versions.
+fn decode_apy(bytes: &[u8]) -> anyhow::Result<(Pubkey, f64)> {
+    if envelope::encoding(bytes) == Some(Encoding::Json) {
+        let CachedApy { voter, apy } = envelope::decode(bytes)?;
+        return Ok((voter.parse()?, apy));
+    }
+    let ApyTreeValue(voter, apy) = envelope::decode(bytes)?;
+    Ok((voter, apy))
+}
+
+/// Returns the first and last epoch of a tree keyed by big-endian epochs.
+fn be_epoch_range(tree: &sled::Tree) -> anyhow::Result<Option<EpochRange>> {
+    let epoch = |k: sled::IVec| {
+        k.as_ref()
+            .try_into()
+            .map(Epoch::from_be_bytes)
+            .context("epoch key has an unexpected length")
+    };
+    match (tree.first()?, tree.last()?) {
+        (Some((first, _)), Some((last, _))) => Ok(Some(epoch(first)?..=epoch(last)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Reads the entries of a tree keyed by big-endian epochs, checking their values with `readable`.
+fn be_tree_entries(
+    name: &'static str,
+    tree: &sled::Tree,
+    readable: impl Fn(Epoch, &[u8]) -> bool,
+) -> anyhow::Result<TreeEntries> {
+    let mut entries = TreeEntries {
+        name,
+        epochs: BTreeSet::new(),
+        unreadable: Vec::new(),
+    };
+    for kv in tree.iter() {
+        let (k, v) = kv?;
+        match k.as_ref().try_into().map(Epoch::from_be_bytes) {
+            Ok(epoch) => {
+                entries.epochs.insert(epoch);
+                if !readable(epoch, &v) {
+                    entries.unreadable.push(format!("epoch {}", epoch));
+                }
+            }
+            Err(_) => entries.unreadable.push(format!("key {:02x?}", k.as_ref())),
+        }
+    }
+    Ok(entries)
+}
+
+/// Removes the entries of epochs before `epoch` from a tree keyed by big-endian epochs. Returns
+/// the number of removed entries.
+fn be_prune_before(tree: &sled::Tree, epoch: Epoch) -> anyhow::Result<usize> {
+    let keys = tree
+        .range(..epoch.to_be_bytes())
+        .keys()
+        .collect::<sled::Result<Vec<_>>>()?;
+    for k in &keys {
+        tree.remove(k)?;
+    }
+    Ok(keys.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prunes_epochs_before() {
+        let cache = RewardsCache::temporary();
+        // Epoch 256 sorts before epoch 2 in the little-endian APY keys.
+        for epoch in [2, 3, 256] {
+            cache.add_epoch_length(epoch, 2.0).unwrap();
+            let mapping =
+                std::iter::once((Pubkey::new_unique(), (Pubkey::new_unique(), 1.0))).collect();
+            cache.add_epoch_data(epoch, mapping).unwrap();
+        }
+
+        let removed = cache.prune_before(3).unwrap();
+        assert!(removed.contains(&(APY_TREE_NAME, 1)));
+        assert!(removed.contains(&(EPOCH_LENGTH_TREE_NAME, 1)));
+        assert_eq!(cache.get_epoch_length(2).unwrap(), None);
+        assert_eq!(cache.get_epoch_length(3).unwrap(), Some(2.0));
+        let lookups = &cache.lookup_counts()[2];
+        assert_eq!(lookups.0, EPOCH_LENGTH_TREE_NAME);
+        assert_eq!((lookups.1.hits(), lookups.1.misses()), (1, 1));
+        assert!(cache.get_epoch_apy(2).unwrap().is_none());
+        assert!(cache.get_epoch_apy(256).unwrap().is_some());
+        assert_eq!(
+            cache.epoch_ranges().unwrap()[1],
+            (APY_TREE_NAME, Some(3..=256))
+        );
+    }
+
+    #[test]
+    fn migrates_legacy_rewards() {
+        let cache = RewardsCache::temporary();
+        let legacy = |pubkey: &str| LegacyReward {
+            pubkey: pubkey.to_string(),
+            lamports: 10,
+            post_balance: 1_010,
+            reward_type: Some(RewardType::Staking),
+            commission: Some(5),
+        };
+        let bytes = bincode::serialize(&vec![legacy("a"), legacy("b")]).unwrap();
+        cache
+            .epoch_rewards_tree
+            .insert(5u64.to_be_bytes(), bytes)
+            .unwrap();
+        cache
+            .epoch_rewards_tree
+            .insert(6u64.to_be_bytes(), vec![1, 2, 3])
+            .unwrap();
+
+        let rewards = cache.get_epoch_rewards(5).unwrap().unwrap();
+        assert_eq!(rewards.len(), 2);
+        assert_eq!(
+            (rewards[1].pubkey.as_str(), rewards[1].commission),
+            ("b", Some(5))
+        );
+        assert_eq!(rewards[1].commission_bps, None);
+        let migrated = cache.epoch_rewards_tree.get(5u64.to_be_bytes()).unwrap();
+        assert_eq!(envelope::encoding(&migrated.unwrap()), Some(Encoding::Json));
+
+        assert!(cache.get_epoch_rewards(6).unwrap().is_none());
+        assert!(cache.unreadable_rewards_warned.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn derives_aprs_of_legacy_voter_apys() {
+        let cache = RewardsCache::temporary();
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let legacy: HashMap<_, _> = [(a, 7.0), (b, 6.0)]
+            .iter()
+            .map(|(voter, apy)| {
+                let apy = LegacyVoterApy {
+                    current_apy: *apy,
+                    average_apy: *apy,
+                };
+                (*voter, apy)
+            })
+            .collect();
+        cache
+            .epoch_voter_apy_tree
+            .insert(5u64.to_be_bytes(), bincode::serialize(&legacy).unwrap())
+            .unwrap();
+        cache.add_epoch_length(4, 2.0).unwrap();
+
+        let apys = cache.get_epoch_voter_apy(5).unwrap().unwrap();
+        assert_eq!(apys[&a].current_apy, 7.0);
+        assert_eq!(apys[&a].current_apr, apr_of_apy(7.0, 2.0));
+        assert_eq!(apys[&b].average_apr, apr_of_apy(6.0, 2.0));
+
+        cache.add_epoch_voter_apy(6, &apys).unwrap();
+        assert_eq!(cache.get_epoch_voter_apy(6).unwrap().unwrap(), apys);
+
+        // Stake account APYs written in bincode by earlier versions.
+        let (legacy_stake, stake) = (Pubkey::new_unique(), Pubkey::new_unique());
+        cache
+            .apy_tree
+            .insert(
+                bincode::serialize(&ApyTreeKey(8, legacy_stake)).unwrap(),
+                envelope::encode(Encoding::Bincode, &ApyTreeValue(a, 7.0)).unwrap(),
+            )
+            .unwrap();
+        cache
+            .add_epoch_data(8, HashMap::from([(stake, (b, 6.0))]))
+            .unwrap();
+        assert_eq!(
+            cache.get_epoch_apy(8).unwrap().unwrap(),
+            HashMap::from([(legacy_stake, (a, 7.0)), (stake, (b, 6.0))])
+        );
+
+        // An epoch without any stake account APYs is covered once its voter APYs are cached.
+        assert!(!cache.has_epoch_apy(7).unwrap());
+        cache.add_epoch_voter_apy(7, &HashMap::new()).unwrap();
+        assert!(cache.has_epoch_apy(7).unwrap());
     }
 }
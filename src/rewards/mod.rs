@@ -1,14 +1,22 @@
-use crate::config::Whitelist;
+use crate::config::{ExporterConfig, Whitelist};
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::persistent_database::counters::CounterBaselines;
+use crate::persistent_database::snapshot::GaugeSnapshot;
 use crate::rewards::caching::{PubkeyVoterApyMapping, RewardsCache};
+use crate::rpc_api::RpcApi;
 use crate::rpc_extra::first_block_in_epoch;
+use crate::rpc_extra::LAMPORTS_PER_SOL;
+use crate::warmup::CacheWarmer;
 use anyhow::anyhow;
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
 use log::debug;
-use prometheus_exporter::prometheus::{GaugeVec, IntGaugeVec};
+use prometheus_exporter::prometheus::{Gauge, GaugeVec, IntCounterVec, IntGauge, IntGaugeVec};
 use serde::{Deserialize, Serialize};
 use solana_account::Account;
-use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_client::rpc_config::RpcBlockConfig;
-use solana_clock::Epoch;
+use solana_client::rpc_response::RpcVoteAccountStatus;
+use solana_clock::{Epoch, Slot};
 use solana_epoch_info::EpochInfo;
 use solana_pubkey::Pubkey;
 use solana_reward_info::RewardType;
@@ -17,21 +25,31 @@ use solana_transaction_status_client_types::{
     Reward, Rewards, TransactionDetails, UiTransactionEncoding,
 };
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::time::{Duration, Instant};
 use time::OffsetDateTime;
 
 pub mod caching;
+pub mod projection;
 
 /// How many seconds there are in a day
-const SECONDS_IN_DAY: u64 = 86400;
+pub const SECONDS_IN_DAY: u64 = 86400;
 
 /// How many days there are in a year
 const DAYS_IN_YEAR: u64 = 365;
 
 /// A default epoch length to use in case it cannot be found.
-const DEFAULT_EPOCH_LENGTH: f64 = 3.0;
+pub const DEFAULT_EPOCH_LENGTH: f64 = 3.0;
+
+/// Upper bounds in percent of the buckets of the cluster APY distribution, below `+Inf`.
+pub const APY_BUCKETS: [f64; 13] = [
+    1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 12.0, 15.0, 20.0,
+];
+
+/// Number of reward partition blocks fetched at once.
+const PARTITION_BLOCK_CONCURRENCY: usize = 8;
 
 /// Maximum number of epochs to look back, INCLUSIVE of the current epoch.
-const MAX_EPOCH_LOOKBACK: u64 = 5;
+pub const MAX_EPOCH_LOOKBACK: u64 = 5;
 
 pub(crate) type VoterEpoch = (Pubkey, Epoch);
 type VoterEpochRewardMap = HashMap<VoterEpoch, Reward>;
@@ -44,6 +62,47 @@ struct StakingApy {
     percent: f64,
 }
 
+/// Rules excluding or clamping the APYs of stake accounts that would skew the APYs of their
+/// voters, such as those of tiny accounts.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ApyOutlierRules {
+    /// Largest plausible APY in percent, above which an APY is an outlier.
+    pub max_apy: Option<f64>,
+    /// Whether APYs above `max_apy` are clamped to it rather than excluded.
+    pub clamp: bool,
+    /// Smallest balance in lamports before the reward of the stake accounts APYs are calculated
+    /// from. Smaller accounts are skipped before they are fetched.
+    pub min_stake_lamports: u64,
+}
+
+impl ApyOutlierRules {
+    /// Returns the rules set in `config`.
+    pub fn new(config: &ExporterConfig) -> Self {
+        Self {
+            max_apy: config.max_staking_apy,
+            clamp: config.clamp_staking_apy.unwrap_or(false),
+            min_stake_lamports: (config.apy_min_stake_sol.unwrap_or(0.0) * LAMPORTS_PER_SOL) as u64,
+        }
+    }
+
+    /// Returns whether `reward` was paid to a stake account below the minimum stake.
+    fn is_dust(&self, reward: &StakingReward) -> bool {
+        let prev_balance = reward
+            .post_balance
+            .saturating_sub(reward.lamports.max(0) as u64);
+        prev_balance < self.min_stake_lamports
+    }
+
+    /// Returns the APY to use for a stake account that earned `apy`, `None` if it is excluded,
+    /// and the reason it is an outlier, if it is one.
+    pub(crate) fn apply(&self, apy: f64) -> (Option<f64>, Option<&'static str>) {
+        match self.max_apy {
+            Some(max_apy) if apy > max_apy => (self.clamp.then_some(max_apy), Some("max_apy")),
+            _ => (Some(apy), None),
+        }
+    }
+}
+
 /// Amount of staking rewards of a particular voter pubkey in an epoch.
 #[derive(Clone, Default, Debug, PartialOrd, PartialEq)]
 pub struct StakingReward {
@@ -63,102 +122,276 @@ struct ValidatorReward {
 #[derive(Clone, Default, Debug, PartialOrd, PartialEq, Serialize, Deserialize)]
 pub struct VoterApy {
     /// APY for the current epoch
-    current_apy: f64,
+    pub current_apy: f64,
     /// APY over the last `MAX_EPOCH_LOOKBACK` epochs.
-    average_apy: f64,
+    pub average_apy: f64,
+    /// APR for the current epoch, the simple annualized rate the APY compounds.
+    pub current_apr: f64,
+    /// APR over the last `MAX_EPOCH_LOOKBACK` epochs.
+    pub average_apr: f64,
+}
+
+/// Distribution of the current APYs of a set of voters.
+#[derive(Clone, Debug, PartialEq)]
+struct ApyDistribution {
+    mean: f64,
+    median: f64,
+    /// Number of voters with an APY of at most each bound of `APY_BUCKETS`, then of all voters.
+    buckets: Vec<usize>,
 }
 
 /// The monitor of rewards paid to validators and delegators.
 pub struct RewardsMonitor<'a> {
     /// Shared Solana RPC client.
-    client: &'a RpcClient,
+    rpc: &'a dyn RpcApi,
     /// Prometheus current staking APY gauge.
     current_staking_apy: &'a GaugeVec,
     /// Prometheus average staking APY gauge.
     average_staking_apy: &'a GaugeVec,
-    /// Prometheus cumulative validator rewards gauge.
-    validator_rewards: &'a IntGaugeVec,
+    /// Prometheus current staking APR gauge.
+    current_staking_apr: &'a GaugeVec,
+    /// Prometheus average staking APR gauge.
+    average_staking_apr: &'a GaugeVec,
+    /// Prometheus gauge of the expected annual rewards of whitelisted stake accounts.
+    expected_annual_staking_rewards: &'a GaugeVec,
+    /// Prometheus cumulative validator rewards counter.
+    validator_rewards: &'a IntCounterVec,
+    /// Prometheus gauge of the last epoch's total rewards of whitelisted validators.
+    whitelist_validator_rewards: &'a IntGauge,
+    /// Prometheus stake-weighted current staking APY gauge of whitelisted validators.
+    whitelist_current_staking_apy: &'a Gauge,
+    /// Prometheus stake-weighted average staking APY gauge of whitelisted validators.
+    whitelist_average_staking_apy: &'a Gauge,
+    /// Prometheus gauge of the mean and median current staking APY of all computed voters.
+    cluster_staking_apy: &'a GaugeVec,
+    /// Prometheus gauge of the cumulative distribution of the current staking APY.
+    cluster_staking_apy_voters: &'a IntGaugeVec,
+    /// Prometheus counter of the stake account APYs excluded or clamped as outliers.
+    staking_apy_outliers: &'a IntCounterVec,
+    /// Prometheus counter of the stake accounts that are missing or cannot be parsed.
+    stake_account_errors: &'a IntCounterVec,
+    /// Persisted counter totals.
+    baselines: &'a CounterBaselines,
     /// Caching database for rewards
     cache: &'a RewardsCache,
-    /// The whitelist of staking account pubkeys constraining APY calculations.
-    staking_account_whitelist: &'a Whitelist,
-    /// The whitelist of vote account pubkeys
-    vote_accounts_whitelist: &'a Whitelist,
+    /// Number of uncached historical epochs whose rewards are fetched at once.
+    backfill_concurrency: usize,
+    /// Time after which the rewards of an epoch already exported are exported again, e.g. to
+    /// pick up whitelist changes.
+    recheck_interval: Duration,
+    /// The epoch whose rewards were last exported, and when.
+    exported: Option<(Epoch, Instant)>,
+    /// Rules for the APYs of stake accounts that are outliers.
+    outlier_rules: ApyOutlierRules,
+    /// Whether the APYs of the earlier epochs of the lookback are not all cached yet.
+    warming_up: bool,
+    /// Whether the averages are still the ones restored from `snapshot`, which are served rather
+    /// than withheld while warming up.
+    restored: bool,
+    /// Backfiller of the rewards of past epochs at startup, which collections wait for.
+    warmer: Option<&'a CacheWarmer>,
+    /// Last values of the APY gauges saved on shutdown, restored until recomputed.
+    snapshot: Option<&'a GaugeSnapshot>,
 }
 
 impl<'a> RewardsMonitor<'a> {
     /// Initialises a new rewards monitor.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        client: &'a RpcClient,
+        rpc: &'a dyn RpcApi,
         current_staking_apy: &'a GaugeVec,
         average_staking_apy: &'a GaugeVec,
-        validator_rewards: &'a IntGaugeVec,
+        current_staking_apr: &'a GaugeVec,
+        average_staking_apr: &'a GaugeVec,
+        expected_annual_staking_rewards: &'a GaugeVec,
+        validator_rewards: &'a IntCounterVec,
+        whitelist_validator_rewards: &'a IntGauge,
+        whitelist_current_staking_apy: &'a Gauge,
+        whitelist_average_staking_apy: &'a Gauge,
+        cluster_staking_apy: &'a GaugeVec,
+        cluster_staking_apy_voters: &'a IntGaugeVec,
+        staking_apy_outliers: &'a IntCounterVec,
+        stake_account_errors: &'a IntCounterVec,
+        baselines: &'a CounterBaselines,
         rewards_cache: &'a RewardsCache,
-        staking_account_whitelist: &'a Whitelist,
-        vote_accounts_whitelist: &'a Whitelist,
+        backfill_concurrency: usize,
+        recheck_interval: Duration,
+        outlier_rules: ApyOutlierRules,
+        warmer: Option<&'a CacheWarmer>,
+        snapshot: Option<&'a GaugeSnapshot>,
     ) -> Self {
         Self {
-            client,
+            rpc,
             current_staking_apy,
             average_staking_apy,
+            current_staking_apr,
+            average_staking_apr,
+            expected_annual_staking_rewards,
             validator_rewards,
+            whitelist_validator_rewards,
+            whitelist_current_staking_apy,
+            whitelist_average_staking_apy,
+            cluster_staking_apy,
+            cluster_staking_apy_voters,
+            staking_apy_outliers,
+            stake_account_errors,
+            baselines,
             cache: rewards_cache,
-            staking_account_whitelist,
-            vote_accounts_whitelist,
+            backfill_concurrency: backfill_concurrency.max(1),
+            recheck_interval,
+            exported: None,
+            outlier_rules,
+            warming_up: true,
+            restored: false,
+            warmer,
+            snapshot,
         }
     }
 
     /// Exports reward metrics. APY values will not be re-calculated more than once an epoch.
-    pub async fn export_rewards(&self, epoch_info: &EpochInfo) -> anyhow::Result<()> {
+    /// The whitelist-wide APYs are weighted by the activated stake in `vote_accounts`. APYs are
+    /// calculated from the stake accounts of `staking_account_whitelist`, whose expected annual
+    /// rewards are exported if the whitelist is not empty. Returns whether the rewards of the epoch
+    /// have shown up.
+    pub async fn export_rewards(
+        &self,
+        epoch_info: &EpochInfo,
+        vote_accounts: &RpcVoteAccountStatus,
+        vote_accounts_whitelist: &Whitelist,
+        staking_account_whitelist: &Whitelist,
+    ) -> anyhow::Result<bool> {
         let epoch = epoch_info.epoch;
 
         // Possible that rewards haven't shown up yet for this epoch
-        if self.get_rewards_for_epoch(epoch).await?.is_some() {
-            let staking_apys = self.calculate_staking_rewards(epoch_info).await?;
+        if let Some(rewards) = self.get_rewards_for_epoch(epoch).await? {
+            let staking_apys = self
+                .calculate_staking_rewards(
+                    epoch_info,
+                    vote_accounts_whitelist,
+                    staking_account_whitelist,
+                )
+                .await?;
 
-            for (
-                voter,
-                VoterApy {
-                    current_apy,
-                    average_apy,
-                },
-            ) in staking_apys
-            {
-                self.current_staking_apy
-                    .get_metric_with_label_values(&[&format!("{}", voter)])
-                    .map(|c| c.set(current_apy))?;
-                self.average_staking_apy
-                    .get_metric_with_label_values(&[&format!("{}", voter)])
-                    .map(|c| c.set(average_apy))?;
+            let whitelist_stakes: HashMap<_, _> = vote_accounts
+                .current
+                .iter()
+                .chain(vote_accounts.delinquent.iter())
+                .filter(|v| vote_accounts_whitelist.contains(&v.vote_pubkey))
+                .filter_map(|v| Some((v.vote_pubkey.parse().ok()?, v.activated_stake)))
+                .collect();
+            self.export_epoch_rewards(
+                epoch,
+                &staking_apys,
+                &whitelist_stakes,
+                vote_accounts_whitelist,
+            )?;
+
+            if !staking_account_whitelist.0.is_empty() {
+                let stake_voters = self.cache.get_epoch_apy(epoch)?.unwrap_or_default();
+                for (stake_account, voter, sol) in expected_annual_rewards(
+                    &rewards,
+                    &stake_voters,
+                    &staking_apys,
+                    staking_account_whitelist,
+                ) {
+                    self.expected_annual_staking_rewards
+                        .get_metric_with_label_values(&[&stake_account, &voter])
+                        .map(|c| c.set(sol))?;
+                }
             }
+            return Ok(true);
+        }
+        Ok(false)
+    }
 
-            let validator_rewards = self
-                .calculate_validator_rewards(epoch)?
-                .ok_or_else(|| anyhow!("current epoch has no rewards"))?;
-            for v in validator_rewards {
-                self.validator_rewards
-                    .get_metric_with_label_values(&[&v.voter])
-                    .map(|c| c.set(v.lamports as i64))?;
+    /// Exports the `staking_apys` and the cached validator rewards of `epoch`, without RPC
+    /// calls. The whitelist-wide APYs are weighted by `whitelist_stakes`.
+    pub fn export_epoch_rewards(
+        &self,
+        epoch: Epoch,
+        staking_apys: &HashMap<Pubkey, VoterApy>,
+        whitelist_stakes: &HashMap<Pubkey, u64>,
+        vote_accounts_whitelist: &Whitelist,
+    ) -> anyhow::Result<()> {
+        for (voter, apy) in staking_apys {
+            let voter = voter.to_string();
+            self.current_staking_apy
+                .get_metric_with_label_values(&[&voter])
+                .map(|c| c.set(apy.current_apy))?;
+            self.average_staking_apy
+                .get_metric_with_label_values(&[&voter])
+                .map(|c| c.set(apy.average_apy))?;
+            self.current_staking_apr
+                .get_metric_with_label_values(&[&voter])
+                .map(|c| c.set(apy.current_apr))?;
+            self.average_staking_apr
+                .get_metric_with_label_values(&[&voter])
+                .map(|c| c.set(apy.average_apr))?;
+        }
+
+        if let Some(apy) = stake_weighted_apy(staking_apys, whitelist_stakes) {
+            self.whitelist_current_staking_apy.set(apy.current_apy);
+            self.whitelist_average_staking_apy.set(apy.average_apy);
+        }
+
+        // Voters with an APY only in earlier epochs of the lookback are left out.
+        let current_apys = staking_apys
+            .values()
+            .map(|apy| apy.current_apy)
+            .filter(|apy| *apy > 0.0)
+            .collect();
+        if let Some(distribution) = apy_distribution(current_apys) {
+            self.cluster_staking_apy
+                .get_metric_with_label_values(&["mean"])
+                .map(|c| c.set(distribution.mean))?;
+            self.cluster_staking_apy
+                .get_metric_with_label_values(&["median"])
+                .map(|c| c.set(distribution.median))?;
+            let bounds = APY_BUCKETS
+                .iter()
+                .map(|bound| bound.to_string())
+                .chain(std::iter::once("+Inf".to_string()));
+            for (bound, count) in bounds.zip(distribution.buckets) {
+                self.cluster_staking_apy_voters
+                    .get_metric_with_label_values(&[&bound])
+                    .map(|c| c.set(count as i64))?;
             }
         }
+
+        let validator_rewards = self
+            .calculate_validator_rewards(epoch, vote_accounts_whitelist)?
+            .ok_or_else(|| anyhow!("current epoch has no rewards"))?;
+        self.whitelist_validator_rewards
+            .set(validator_rewards.iter().map(|v| v.lamports).sum::<u64>() as i64);
+        // The counter is incremented once per epoch and voter, however many times the
+        // rewards are exported during the epoch or the exporter is restarted.
+        for v in validator_rewards {
+            self.baselines.inc_by_for_epoch(
+                self.validator_rewards,
+                &[&v.voter],
+                epoch,
+                v.lamports,
+            )?;
+        }
         Ok(())
     }
 
-    /// Calculates the validator rewards for an epoch.
+    /// Calculates the rewards of the validators of `vote_accounts_whitelist` for an epoch.
     fn calculate_validator_rewards(
         &self,
         epoch: Epoch,
+        vote_accounts_whitelist: &Whitelist,
     ) -> anyhow::Result<Option<HashSet<ValidatorReward>>> {
         Ok(self.cache.get_epoch_rewards(epoch)?.map(|rewards| {
             rewards
                 .into_iter()
                 .filter(|r| {
                     r.reward_type == Some(RewardType::Voting)
-                        && self.vote_accounts_whitelist.contains(&r.pubkey)
+                        && vote_accounts_whitelist.contains(&r.pubkey)
                 })
                 .map(|r| ValidatorReward {
                     voter: r.pubkey,
-                    lamports: r.post_balance,
+                    lamports: r.lamports.max(0) as u64,
                 })
                 .collect::<HashSet<_>>()
         }))
@@ -168,6 +401,8 @@ impl<'a> RewardsMonitor<'a> {
     async fn calculate_staking_rewards(
         &self,
         current_epoch_info: &EpochInfo,
+        vote_accounts_whitelist: &Whitelist,
+        staking_account_whitelist: &Whitelist,
     ) -> anyhow::Result<HashMap<Pubkey, VoterApy>> {
         // Since during an epoch the APY cannot change, make sure that all information about an epoch
         // is only calculated once, and then written to database to prevent inconsistent exporting.
@@ -179,7 +414,12 @@ impl<'a> RewardsMonitor<'a> {
 
             // Fill current epoch and find APY
             let mapping = self
-                .fill_current_epoch_and_find_apy(current_epoch_info, &mut apys)
+                .fill_current_epoch_and_find_apy(
+                    current_epoch_info,
+                    &mut apys,
+                    vote_accounts_whitelist,
+                    staking_account_whitelist,
+                )
                 .await?;
 
             // Write to database
@@ -202,17 +442,21 @@ impl<'a> RewardsMonitor<'a> {
 
         // Each epoch's reward block is an independent, potentially slow `getBlock`
         // (the epoch-boundary block carries the whole network's rewards). Fetch
-        // them concurrently rather than serially, then merge in epoch order.
-        let historical_rewards = futures::future::try_join_all(
-            ((current_epoch - MAX_EPOCH_LOOKBACK)..current_epoch).map(|epoch| async move {
-                let rewards = self
-                    .get_rewards_for_epoch(epoch)
-                    .await?
-                    .ok_or_else(|| anyhow!("historical epoch has no rewards"))?;
-                anyhow::Ok((epoch, rewards))
-            }),
-        )
-        .await?;
+        // `backfill_concurrency` of them at once rather than serially, so a cold
+        // start neither crawls nor bursts past the RPC rate limits, then merge in
+        // epoch order.
+        let historical_rewards: Vec<_> =
+            futures::stream::iter(lookback_start(current_epoch.saturating_sub(1))..current_epoch)
+                .map(|epoch| async move {
+                    let rewards = self
+                        .get_rewards_for_epoch(epoch)
+                        .await?
+                        .ok_or_else(|| anyhow!("historical epoch has no rewards"))?;
+                    anyhow::Ok((epoch, rewards))
+                })
+                .buffered(self.backfill_concurrency)
+                .try_collect()
+                .await?;
 
         for (epoch, epoch_rewards) in historical_rewards {
             for reward in epoch_rewards {
@@ -237,6 +481,8 @@ impl<'a> RewardsMonitor<'a> {
         &self,
         current_epoch_info: &EpochInfo,
         apys: &mut VoterEpochApyMap,
+        vote_accounts_whitelist: &Whitelist,
+        staking_account_whitelist: &Whitelist,
     ) -> anyhow::Result<HashMap<Pubkey, VoterApy>> {
         let current_epoch = current_epoch_info.epoch;
 
@@ -248,7 +494,7 @@ impl<'a> RewardsMonitor<'a> {
         // Extract into staking rewards and validator rewards.
         let staking_rewards = current_rewards.into_iter().filter_map(|r| {
             if r.reward_type == Some(RewardType::Staking)
-                && self.staking_account_whitelist.contains(&r.pubkey)
+                && staking_account_whitelist.contains(&r.pubkey)
             {
                 if let Ok(pubkey) = r.pubkey.parse() {
                     Some(StakingReward {
@@ -270,14 +516,27 @@ impl<'a> RewardsMonitor<'a> {
             .get_epoch_apy(current_epoch)?
             .unwrap_or_default()
             .into_iter()
-            .filter(|(_, (voter, _))| self.vote_accounts_whitelist.contains(&voter.to_string()))
+            .filter(|(_, (voter, _))| vote_accounts_whitelist.contains(&voter.to_string()))
             .collect::<PubkeyVoterApyMapping>();
 
         // Use cached pubkeys to find what keys we need to query
         let cached_pubkeys: BTreeSet<_> = cached_apys.keys().collect();
-        let to_query: Vec<_> = staking_rewards
+        // Dust stake accounts are skipped before their accounts are fetched.
+        let (to_query, dust): (Vec<_>, Vec<_>) = staking_rewards
             .filter(|r| !cached_pubkeys.contains(&r.pubkey))
-            .collect();
+            .partition(|r| !self.outlier_rules.is_dust(r));
+        if !dust.is_empty() {
+            debug!(
+                "Skipping {} stake accounts below the minimum stake",
+                dust.len()
+            );
+            self.baselines.inc_by_for_epoch(
+                self.staking_apy_outliers,
+                &["min_stake"],
+                current_epoch,
+                dust.len() as u64,
+            )?;
+        }
 
         // Move cached pubkeys into APYs by voter
         apys.extend(
@@ -292,11 +551,15 @@ impl<'a> RewardsMonitor<'a> {
             // Seen voters are added here so that an APY calculation occurs is done only once
             // for a given voter.
             let mut seen_voters = BTreeSet::new();
+            // Numbers of outlier APYs by reason.
+            let mut outliers: BTreeMap<&'static str, u64> = BTreeMap::new();
+            // Numbers of stake accounts that are missing or cannot be parsed.
+            let mut account_errors: BTreeMap<&'static str, u64> = BTreeMap::new();
 
             // The epoch duration is constant across every reward in this loop, so
             // resolve it once instead of on each `calculate_staking_apy` call.
             let epoch_duration = self
-                .epoch_duration_days(current_epoch - 1, current_epoch_info)
+                .epoch_duration_days(current_epoch.saturating_sub(1), current_epoch_info)
                 .await?
                 .unwrap_or(DEFAULT_EPOCH_LENGTH);
 
@@ -304,33 +567,61 @@ impl<'a> RewardsMonitor<'a> {
             for chunk in to_query.chunks(100) {
                 let pubkeys: Vec<_> = chunk.iter().map(|r| r.pubkey).collect();
                 debug!("Getting {} accounts", chunk.len());
-                let account_infos = self
-                    .client
-                    .get_multiple_accounts(pubkeys.as_slice())
-                    .await?;
+                let account_infos = self.rpc.get_multiple_accounts(pubkeys.as_slice()).await?;
 
                 // For each response in chunk
-                for (reward, account_info) in chunk
-                    .iter()
-                    .zip(account_infos)
-                    .flat_map(|(r, oa)| oa.map(|a| (r, a)))
-                {
+                for (reward, account_info) in chunk.iter().zip(account_infos) {
+                    let account_info = match account_info {
+                        Some(account_info) => account_info,
+                        None => {
+                            debug!("Stake account {} not found", reward.pubkey);
+                            *account_errors.entry("missing").or_default() += 1;
+                            continue;
+                        }
+                    };
                     // Calculate APY
-                    if let Some(StakingApy { voter, percent }) = calculate_staking_apy(
+                    match calculate_staking_apy(
                         &account_info,
                         &mut seen_voters,
                         epoch_duration,
                         reward.lamports as u64,
                         reward.post_balance,
-                    )? {
+                        &self.outlier_rules,
+                        &mut outliers,
+                    ) {
                         // Insert reward pubkey and voter
-                        queried.insert(reward.pubkey, (voter, percent));
+                        Ok(Some(StakingApy { voter, percent })) => {
+                            queried.insert(reward.pubkey, (voter, percent));
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            debug!("Could not parse stake account {}: {e:#}", reward.pubkey);
+                            *account_errors.entry("unparsable").or_default() += 1;
+                        }
                     }
                 }
 
                 // Write to cache in chunks of 100 at a time.
                 self.cache.add_epoch_data(current_epoch, queried.clone())?;
             }
+            // Only the APYs are cached, so a calculation retried after a failure sees the same
+            // outliers and account errors again, and they are only counted once per epoch.
+            for (reason, count) in outliers {
+                self.baselines.inc_by_for_epoch(
+                    self.staking_apy_outliers,
+                    &[reason],
+                    current_epoch,
+                    count,
+                )?;
+            }
+            for (reason, count) in account_errors {
+                self.baselines.inc_by_for_epoch(
+                    self.stake_account_errors,
+                    &[reason],
+                    current_epoch,
+                    count,
+                )?;
+            }
 
             // Extend accounts by voter
             apys.extend(
@@ -354,35 +645,18 @@ impl<'a> RewardsMonitor<'a> {
 
         // Epoch durations up to lookback
         let mut epoch_durations = BTreeMap::new();
-        for epoch in current_epoch - MAX_EPOCH_LOOKBACK + 1..=current_epoch {
+        for epoch in lookback_start(current_epoch)..=current_epoch {
             let duration = self
                 .epoch_duration_days(epoch - 1, current_epoch_info)
                 .await?
                 .unwrap_or(DEFAULT_EPOCH_LENGTH);
             epoch_durations.insert(epoch, duration);
         }
-        let duration_max_epoch_lookback: f64 = epoch_durations.values().sum();
-
-        let mut voter_apys = HashMap::new();
-
-        // Calculate the current and average APY
-        for (voter, epoch_apys) in voter_epoch_apys {
-            let mut total_apy = 0.0;
-            for (epoch, duration) in &epoch_durations {
-                let apy = *epoch_apys.get(epoch).unwrap_or(&0.0);
-                total_apy += apy * duration;
-            }
-            let average_apy = total_apy / duration_max_epoch_lookback;
-            let current_apy = *epoch_apys.get(&current_epoch).unwrap_or(&0.0);
-            voter_apys.insert(
-                voter,
-                VoterApy {
-                    current_apy,
-                    average_apy,
-                },
-            );
-        }
-        Ok(voter_apys)
+        Ok(average_voter_apys(
+            voter_epoch_apys,
+            &epoch_durations,
+            current_epoch,
+        ))
     }
 
     /// Calculates the duration of the epoch in days. May or may not use a cached result if the
@@ -399,9 +673,20 @@ impl<'a> RewardsMonitor<'a> {
         // If it's the current epoch then we must extrapolate
         if epoch == epoch_info.epoch {
             let first_slot = epoch_info.absolute_slot - epoch_info.slot_index;
-            return if let Some(first_slot_time) =
-                self.client.get_block(first_slot).await?.block_time
-            {
+            let block = self
+                .rpc
+                .get_block_with_config(
+                    first_slot,
+                    RpcBlockConfig {
+                        encoding: Some(UiTransactionEncoding::Base64),
+                        transaction_details: Some(TransactionDetails::None),
+                        rewards: Some(false),
+                        commitment: None,
+                        max_supported_transaction_version: Some(0),
+                    },
+                )
+                .await?;
+            return if let Some(first_slot_time) = block.block_time {
                 let average_slot_time = (OffsetDateTime::now_utc().unix_timestamp()
                     - first_slot_time) as f64
                     / (epoch_info.slot_index) as f64;
@@ -413,94 +698,468 @@ impl<'a> RewardsMonitor<'a> {
             };
         }
 
-        if let Some(length) = self.cache.get_epoch_length(epoch)? {
-            Ok(Some(length))
-        } else {
-            debug!("Finding epoch {}", epoch);
-
-            // Timestamp of the first block in `ep`, or `None` if the epoch has no
-            // first block or that block carries no block time.
-            let first_block_timestamp = |ep| async move {
-                if let Some(block) = first_block_in_epoch(self.client, ep).await? {
-                    let ui_confirmed_block = self
-                        .client
-                        .get_block_with_config(
-                            block,
-                            RpcBlockConfig {
-                                encoding: Some(UiTransactionEncoding::Base64),
-                                transaction_details: Some(TransactionDetails::None),
-                                rewards: Some(false),
-                                commitment: None,
-                                max_supported_transaction_version: Some(0),
-                            },
-                        )
-                        .await?;
-                    anyhow::Ok(ui_confirmed_block.block_time)
-                } else {
-                    anyhow::Ok(None)
-                }
-            };
+        cached_epoch_length(self.rpc, self.cache, epoch).await
+    }
 
-            let start_timestamp = first_block_timestamp(epoch).await?;
-            let end_timestamp = first_block_timestamp(epoch + 1).await?;
+    /// Gets the rewards for `epoch`, either from RPC or cache. The cache will be updated.
+    /// Returns `Ok(None)` if there haven't been any rewards in the given epoch yet, `Ok(Some(rewards))` if there have, and
+    /// otherwise returns an error.
+    async fn get_rewards_for_epoch(&self, epoch: Epoch) -> anyhow::Result<Option<Rewards>> {
+        cached_epoch_rewards(self.rpc, self.cache, epoch).await
+    }
+}
 
-            // Timestamps must exist for start and end block
-            let days_in_epoch = if let (Some(start_timestamp), Some(end_timestamp)) =
-                (start_timestamp, end_timestamp)
-            {
-                (end_timestamp - start_timestamp) as f64 / SECONDS_IN_DAY as f64
+/// Gets the rewards for `epoch` from `cache`, or from RPC if they are not cached, caching them.
+/// Returns `Ok(None)` if they haven't been paid yet.
+pub(crate) async fn cached_epoch_rewards(
+    rpc: &dyn RpcApi,
+    cache: &RewardsCache,
+    epoch: Epoch,
+) -> anyhow::Result<Option<Rewards>> {
+    if let Some(rewards) = cache.get_epoch_rewards(epoch)? {
+        Ok(Some(rewards))
+    } else if let Some(rewards) = fetch_epoch_rewards(rpc, epoch).await? {
+        cache.add_epoch_rewards(epoch, &rewards)?;
+        Ok(Some(rewards))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Gets the duration in days of the past `epoch` from `cache`, or from the block times of the
+/// first blocks of it and the next epoch if it is not cached, caching it. Returns `Ok(None)` if
+/// either block has no block time.
+pub(crate) async fn cached_epoch_length(
+    rpc: &dyn RpcApi,
+    cache: &RewardsCache,
+    epoch: Epoch,
+) -> anyhow::Result<Option<f64>> {
+    if let Some(length) = cache.get_epoch_length(epoch)? {
+        Ok(Some(length))
+    } else {
+        debug!("Finding epoch {}", epoch);
+
+        // Timestamp of the first block in `ep`, or `None` if the epoch has no
+        // first block or that block carries no block time.
+        let first_block_timestamp = |ep| async move {
+            if let Some(block) = first_block_in_epoch(rpc, ep).await? {
+                let ui_confirmed_block = rpc
+                    .get_block_with_config(
+                        block,
+                        RpcBlockConfig {
+                            encoding: Some(UiTransactionEncoding::Base64),
+                            transaction_details: Some(TransactionDetails::None),
+                            rewards: Some(false),
+                            commitment: None,
+                            max_supported_transaction_version: Some(0),
+                        },
+                    )
+                    .await?;
+                anyhow::Ok(ui_confirmed_block.block_time)
             } else {
-                // Otherwise return early, do not update cache.
-                return Ok(None);
+                anyhow::Ok(None)
+            }
+        };
+
+        let start_timestamp = first_block_timestamp(epoch).await?;
+        let end_timestamp = first_block_timestamp(epoch + 1).await?;
+
+        // Timestamps must exist for start and end block
+        let days_in_epoch = if let (Some(start_timestamp), Some(end_timestamp)) =
+            (start_timestamp, end_timestamp)
+        {
+            (end_timestamp - start_timestamp) as f64 / SECONDS_IN_DAY as f64
+        } else {
+            // Otherwise return early, do not update cache.
+            return Ok(None);
+        };
+
+        cache.add_epoch_length(epoch, days_in_epoch)?;
+        Ok(Some(days_in_epoch))
+    }
+}
+
+/// Fetches the rewards paid for `epoch` from RPC, without the cache. Returns `Ok(None)` if they
+/// haven't been paid yet.
+///
+/// With partitioned epoch rewards, the first block of the epoch only pays the voting rewards,
+/// and the staking rewards are paid in the blocks following it, one partition per block.
+/// Rewards are only returned once all partitions have been paid.
+pub(crate) async fn fetch_epoch_rewards(
+    rpc: &dyn RpcApi,
+    epoch: Epoch,
+) -> anyhow::Result<Option<Rewards>> {
+    let block = match first_block_in_epoch(rpc, epoch).await? {
+        Some(block) => block,
+        None => return Ok(None),
+    };
+    let (mut rewards, partitions) = get_block_rewards(rpc, block).await?;
+    if let Some(partitions) = partitions {
+        let partitions = partitions as usize;
+        let partition_blocks = rpc.get_blocks_with_limit(block + 1, partitions).await?;
+        if partition_blocks.len() < partitions {
+            debug!(
+                "Epoch {} rewards paid in {} of {} partitions so far",
+                epoch,
+                partition_blocks.len(),
+                partitions
+            );
+            return Ok(None);
+        }
+        let partition_rewards: Vec<_> = futures::stream::iter(partition_blocks)
+            .map(|slot| get_block_rewards(rpc, slot))
+            .buffered(PARTITION_BLOCK_CONCURRENCY)
+            .try_collect()
+            .await?;
+        // Partition blocks also carry the fee rewards of their leaders, which are not
+        // epoch rewards.
+        rewards.extend(
+            partition_rewards
+                .into_iter()
+                .flat_map(|(rewards, _)| rewards)
+                .filter(|r| r.reward_type == Some(RewardType::Staking)),
+        );
+    }
+    Ok(Some(rewards))
+}
+
+/// Gets the rewards of the block at `slot` and its number of reward partitions, if it starts
+/// a partitioned reward distribution.
+async fn get_block_rewards(rpc: &dyn RpcApi, slot: Slot) -> anyhow::Result<(Rewards, Option<u64>)> {
+    let block = rpc
+        .get_block_with_config(
+            slot,
+            RpcBlockConfig {
+                encoding: Some(UiTransactionEncoding::Base64),
+                transaction_details: Some(TransactionDetails::None),
+                rewards: Some(true),
+                commitment: None,
+                max_supported_transaction_version: Some(0),
+            },
+        )
+        .await?;
+    Ok((
+        block.rewards.unwrap_or_default(),
+        block.num_reward_partitions,
+    ))
+}
+
+#[async_trait]
+impl Monitor for RewardsMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "rewards"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(self.current_staking_apy),
+            family_name(self.average_staking_apy),
+            family_name(self.current_staking_apr),
+            family_name(self.average_staking_apr),
+            family_name(self.expected_annual_staking_rewards),
+            family_name(self.validator_rewards),
+            family_name(self.whitelist_validator_rewards),
+            family_name(self.whitelist_current_staking_apy),
+            family_name(self.whitelist_average_staking_apy),
+            family_name(self.cluster_staking_apy),
+            family_name(self.cluster_staking_apy_voters),
+            family_name(self.staking_apy_outliers),
+            family_name(self.stake_account_errors),
+        ]
+    }
+
+    /// Restores the validator rewards, APY outlier and stake account error counter totals.
+    async fn setup(&mut self) -> anyhow::Result<()> {
+        self.baselines.restore(self.validator_rewards)?;
+        self.baselines.restore(self.staking_apy_outliers)?;
+        self.baselines.restore(self.stake_account_errors)?;
+        if let Some(snapshot) = self.snapshot {
+            snapshot.restore(self.current_staking_apy)?;
+            snapshot.restore(self.average_staking_apy)?;
+            snapshot.restore(self.current_staking_apr)?;
+            snapshot.restore(self.average_staking_apr)?;
+            snapshot.restore(self.expected_annual_staking_rewards)?;
+            snapshot.restore(self.whitelist_validator_rewards)?;
+            snapshot.restore(self.whitelist_current_staking_apy)?;
+            snapshot.restore(self.whitelist_average_staking_apy)?;
+            snapshot.restore(self.cluster_staking_apy)?;
+            snapshot.restore(self.cluster_staking_apy_voters)?;
+            self.restored = true;
+        }
+        Ok(())
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        // Rather than fetching the same past epochs, wait for the backfill to cache them.
+        if self.warmer.is_some_and(CacheWarmer::rewards_warming) {
+            debug!("Waiting for the rewards of past epochs to be backfilled");
+            self.warming_up = true;
+            return Ok(());
+        }
+        let epoch = cycle.epoch_info.epoch;
+        // The rewards only change at epoch boundaries, so the exported ones are kept until then.
+        if !export_due(self.exported, epoch, self.recheck_interval) {
+            debug!("Rewards of epoch {} are already exported", epoch);
+            return Ok(());
+        }
+        let exported = self
+            .export_rewards(
+                &cycle.epoch_info,
+                &cycle.vote_accounts,
+                &cycle.vote_accounts_whitelist,
+                &cycle.staking_account_whitelist,
+            )
+            .await?;
+        self.restored = false;
+        self.exported = exported.then(|| (epoch, Instant::now()));
+        self.warming_up = !self.lookback_cached(epoch)?;
+        Ok(())
+    }
+
+    /// Until the APYs of the earlier epochs of the lookback are cached, which only happens for
+    /// the epochs the exporter ran in, they would count as zero and drag the averages down. The
+    /// averages restored from the snapshot are served until first recomputed.
+    fn warming_up_metrics(&self) -> Vec<String> {
+        if !self.warming_up || self.restored {
+            return Vec::new();
+        }
+        vec![
+            family_name(self.average_staking_apy),
+            family_name(self.average_staking_apr),
+            family_name(self.whitelist_average_staking_apy),
+        ]
+    }
+}
+
+impl RewardsMonitor<'_> {
+    /// Whether the APYs of the epochs before `epoch` that its average APY is taken over are all
+    /// cached.
+    fn lookback_cached(&self, epoch: Epoch) -> anyhow::Result<bool> {
+        for epoch in lookback_start(epoch)..epoch {
+            if !self.cache.has_epoch_apy(epoch)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Returns the first epoch of the `MAX_EPOCH_LOOKBACK` epochs up to `epoch` that the average APYs
+/// are taken over. Epoch 0 is left out: it pays no rewards and has no previous epoch to measure,
+/// so a new cluster averages over fewer epochs.
+fn lookback_start(epoch: Epoch) -> Epoch {
+    epoch.saturating_sub(MAX_EPOCH_LOOKBACK - 1).max(1)
+}
+
+/// Whether the rewards of `epoch` are to be exported, given the epoch whose rewards were last
+/// `exported` and when. They are until they have shown up, and then again every
+/// `recheck_interval`.
+fn export_due(
+    exported: Option<(Epoch, Instant)>,
+    epoch: Epoch,
+    recheck_interval: Duration,
+) -> bool {
+    match exported {
+        Some((exported_epoch, at)) => exported_epoch != epoch || at.elapsed() >= recheck_interval,
+        None => true,
+    }
+}
+
+/// Calculates the current APY and APR in `current_epoch` and the average APY and APR over the
+/// epochs of `epoch_durations`, weighted by their durations, of every voter in `voter_epoch_apys`.
+/// Epochs without an APY count as zero.
+fn average_voter_apys(
+    voter_epoch_apys: HashMap<Pubkey, BTreeMap<Epoch, f64>>,
+    epoch_durations: &BTreeMap<Epoch, f64>,
+    current_epoch: Epoch,
+) -> HashMap<Pubkey, VoterApy> {
+    let total_duration: f64 = epoch_durations.values().sum();
+    voter_epoch_apys
+        .into_iter()
+        .map(|(voter, epoch_apys)| {
+            let apy_of = |epoch: &Epoch| *epoch_apys.get(epoch).unwrap_or(&0.0);
+            let apr_of = |epoch: &Epoch| {
+                let duration = epoch_durations
+                    .get(epoch)
+                    .copied()
+                    .unwrap_or(DEFAULT_EPOCH_LENGTH);
+                apr_of_apy(apy_of(epoch), duration)
+            };
+            let total_apy: f64 = epoch_durations
+                .iter()
+                .map(|(epoch, duration)| apy_of(epoch) * duration)
+                .sum();
+            let total_apr: f64 = epoch_durations
+                .iter()
+                .map(|(epoch, duration)| apr_of(epoch) * duration)
+                .sum();
+            let apy = VoterApy {
+                current_apy: apy_of(&current_epoch),
+                average_apy: total_apy / total_duration,
+                current_apr: apr_of(&current_epoch),
+                average_apr: total_apr / total_duration,
             };
+            (voter, apy)
+        })
+        .collect()
+}
 
-            self.cache.add_epoch_length(epoch, days_in_epoch)?;
-            Ok(Some(days_in_epoch))
+/// Returns the distribution of `apys`, or `None` if there are none.
+fn apy_distribution(mut apys: Vec<f64>) -> Option<ApyDistribution> {
+    if apys.is_empty() {
+        return None;
+    }
+    apys.sort_by(|a, b| a.total_cmp(b));
+    let n = apys.len();
+    let median = if n.is_multiple_of(2) {
+        (apys[n / 2 - 1] + apys[n / 2]) / 2.0
+    } else {
+        apys[n / 2]
+    };
+    let buckets = APY_BUCKETS
+        .iter()
+        .map(|bound| apys.partition_point(|apy| apy <= bound))
+        .chain(std::iter::once(n))
+        .collect();
+    Some(ApyDistribution {
+        mean: apys.iter().sum::<f64>() / n as f64,
+        median,
+        buckets,
+    })
+}
+
+/// Averages the APYs of the voters in `stakes`, weighted by their stake. Voters without an APY
+/// are left out. Returns `None` if none of them has both an APY and a stake.
+fn stake_weighted_apy(
+    apys: &HashMap<Pubkey, VoterApy>,
+    stakes: &HashMap<Pubkey, u64>,
+) -> Option<VoterApy> {
+    let mut total_stake = 0.0;
+    let mut weighted = VoterApy::default();
+    for (voter, stake) in stakes {
+        if let Some(apy) = apys.get(voter) {
+            let stake = *stake as f64;
+            total_stake += stake;
+            weighted.current_apy += apy.current_apy * stake;
+            weighted.average_apy += apy.average_apy * stake;
+            weighted.current_apr += apy.current_apr * stake;
+            weighted.average_apr += apy.average_apr * stake;
         }
     }
+    (total_stake > 0.0).then(|| VoterApy {
+        current_apy: weighted.current_apy / total_stake,
+        average_apy: weighted.average_apy / total_stake,
+        current_apr: weighted.current_apr / total_stake,
+        average_apr: weighted.average_apr / total_stake,
+    })
+}
 
-    /// Gets the rewards for `epoch`, either from RPC or cache. The cache will be updated.
-    /// Returns `Ok(None)` if there haven't been any rewards in the given epoch yet, `Ok(Some(rewards))` if there have, and
-    /// otherwise returns an error.
-    async fn get_rewards_for_epoch(&self, epoch: Epoch) -> anyhow::Result<Option<Rewards>> {
-        if let Some(rewards) = self.cache.get_epoch_rewards(epoch)? {
-            Ok(Some(rewards))
-        } else if let Some(block) = first_block_in_epoch(self.client, epoch).await? {
-            let rewards = self.client.get_block(block).await?.rewards;
-            self.cache.add_epoch_rewards(epoch, &rewards)?;
-            Ok(Some(rewards))
-        } else {
-            Ok(None)
+/// Returns the stake accounts of `staking_account_whitelist` paid a staking reward in `rewards`,
+/// their voters in `stake_voters`, and the SOL they are expected to earn in a year at the current
+/// APY of their voter in `staking_apys`, given their balance after the reward.
+fn expected_annual_rewards(
+    rewards: &Rewards,
+    stake_voters: &PubkeyVoterApyMapping,
+    staking_apys: &HashMap<Pubkey, VoterApy>,
+    staking_account_whitelist: &Whitelist,
+) -> Vec<(String, String, f64)> {
+    rewards
+        .iter()
+        .filter(|r| {
+            r.reward_type == Some(RewardType::Staking)
+                && staking_account_whitelist.contains(&r.pubkey)
+        })
+        .filter_map(|r| {
+            let (voter, _) = stake_voters.get(&r.pubkey.parse().ok()?)?;
+            let apy = staking_apys.get(voter)?.current_apy;
+            let balance = r.post_balance as f64 / LAMPORTS_PER_SOL;
+            Some((r.pubkey.clone(), voter.to_string(), balance * apy / 100.0))
+        })
+        .collect()
+}
+
+/// Calculates the current APY in `epoch` and the average APY over the `lookback` epochs up to and
+/// including `epoch` of every voter, using only cached data. Epochs of unknown length are assumed
+/// to last `DEFAULT_EPOCH_LENGTH` days.
+pub fn cached_voter_apys(
+    cache: &RewardsCache,
+    epoch: Epoch,
+    lookback: u64,
+) -> anyhow::Result<HashMap<Pubkey, VoterApy>> {
+    let mut voter_epoch_apys: HashMap<Pubkey, BTreeMap<Epoch, f64>> = HashMap::new();
+    let mut epoch_durations = BTreeMap::new();
+    for ep in (epoch + 1).saturating_sub(lookback.max(1))..=epoch {
+        for (_, (voter, apy)) in cache.get_epoch_apy(ep)?.unwrap_or_default() {
+            voter_epoch_apys.entry(voter).or_default().insert(ep, apy);
         }
+        // The rewards paid out in `ep` were earned in the previous epoch.
+        let duration = match ep.checked_sub(1) {
+            Some(prev) => cache.get_epoch_length(prev)?,
+            None => None,
+        };
+        epoch_durations.insert(ep, duration.unwrap_or(DEFAULT_EPOCH_LENGTH));
     }
+    Ok(average_voter_apys(
+        voter_epoch_apys,
+        &epoch_durations,
+        epoch,
+    ))
+}
+
+/// Returns the APR in percent that compounds to `apy` in percent over a year of epochs of
+/// `epoch_duration` days, the inverse of the compounding in [`calculate_staking_apy`].
+pub(crate) fn apr_of_apy(apy: f64, epoch_duration: f64) -> f64 {
+    let epochs_in_year = DAYS_IN_YEAR as f64 / epoch_duration;
+    (f64::powf(1.0 + apy / 100.0, 1.0 / epochs_in_year) - 1.0) * epochs_in_year * 100.0
+}
+
+/// Returns the APY and APR in percent of a stake account that earned `lamports` in an epoch of
+/// `epoch_duration` days, up to `post_balance`.
+pub(crate) fn staking_apy(lamports: u64, post_balance: u64, epoch_duration: f64) -> (f64, f64) {
+    let prev_balance = post_balance - lamports;
+    let epoch_rate = lamports as f64 / prev_balance as f64;
+    let apr = epoch_rate / epoch_duration * (DAYS_IN_YEAR as f64);
+    let epochs_in_year = (DAYS_IN_YEAR as f64) / epoch_duration;
+    let apy = f64::powf(1.0 + apr / epochs_in_year, epochs_in_year) - 1.0;
+    (apy * 100.0, apr * 100.0)
 }
 
 /// Calculates the staking APY of an `AccountInfo` containing a `StakeState`.
 /// Returns the calculated APY while registering the delegated voter in `seen_voters`
-/// for later reference.
+/// for later reference. Outliers by `rules` are counted in `outliers` by reason, and those that
+/// are excluded leave the voter to its next stake account.
+#[allow(clippy::too_many_arguments)]
 fn calculate_staking_apy(
     account_info: &Account,
     seen_voters: &mut BTreeSet<Pubkey>,
     epoch_duration: f64,
     lamports: u64,
     post_balance: u64,
+    rules: &ApyOutlierRules,
+    outliers: &mut BTreeMap<&'static str, u64>,
 ) -> anyhow::Result<Option<StakingApy>> {
     let stake_state: StakeStateV2 = bincode::deserialize(&account_info.data)?;
     if let Some(delegation) = stake_state.delegation() {
         let percent = if !seen_voters.contains(&delegation.voter_pubkey) && lamports > 0 {
-            let prev_balance = post_balance - lamports;
-            let epoch_rate = lamports as f64 / prev_balance as f64;
-            let apr = epoch_rate / epoch_duration * (DAYS_IN_YEAR as f64);
-            let epochs_in_year = (DAYS_IN_YEAR as f64) / epoch_duration;
-            let apy = f64::powf(1.0 + apr / epochs_in_year, epochs_in_year) - 1.0;
+            let (apy, apr) = staking_apy(lamports, post_balance, epoch_duration);
+            let (plausible_apy, outlier) = rules.apply(apy);
+            if let Some(reason) = outlier {
+                debug!(
+                    "Staking APY {:.4} of a stake account of {} is an outlier ({})",
+                    apy, delegation.voter_pubkey, reason
+                );
+                *outliers.entry(reason).or_default() += 1;
+            }
+            let apy = match plausible_apy {
+                Some(apy) => apy,
+                None => return Ok(None),
+            };
             debug!(
                 "Staking APY of {} is {:.4} (APR {:.4})",
-                delegation.voter_pubkey,
-                apy * 100.0,
-                apr * 100.0
+                delegation.voter_pubkey, apy, apr
             );
             seen_voters.insert(delegation.voter_pubkey);
-            apy * 100.0
+            apy
         } else {
             return Ok(None);
         };
@@ -512,3 +1171,544 @@ fn calculate_staking_apy(
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_api::mock::MockRpc;
+    use prometheus_exporter::prometheus::Opts;
+    use solana_client::rpc_response::RpcVoteAccountInfo;
+    use solana_epoch_schedule::EpochSchedule;
+    use solana_stake_interface::stake_flags::StakeFlags;
+    use solana_stake_interface::state::{Delegation, Meta, Stake};
+
+    const SLOTS_PER_EPOCH: u64 = 32;
+    const EPOCH_DAYS: i64 = 2;
+
+    fn reward(
+        pubkey: &Pubkey,
+        lamports: i64,
+        post_balance: u64,
+        reward_type: RewardType,
+    ) -> Reward {
+        Reward {
+            pubkey: pubkey.to_string(),
+            lamports,
+            post_balance,
+            reward_type: Some(reward_type),
+            commission: None,
+            commission_bps: None,
+        }
+    }
+
+    /// The metrics of a [`RewardsMonitor`] under test, unregistered.
+    struct Gauges {
+        current_staking_apy: GaugeVec,
+        average_staking_apy: GaugeVec,
+        current_staking_apr: GaugeVec,
+        average_staking_apr: GaugeVec,
+        expected_annual_staking_rewards: GaugeVec,
+        validator_rewards: IntCounterVec,
+        whitelist_validator_rewards: IntGauge,
+        whitelist_current_staking_apy: Gauge,
+        whitelist_average_staking_apy: Gauge,
+        cluster_staking_apy: GaugeVec,
+        cluster_staking_apy_voters: IntGaugeVec,
+        staking_apy_outliers: IntCounterVec,
+        stake_account_errors: IntCounterVec,
+    }
+
+    impl Gauges {
+        fn new() -> Self {
+            let pubkey_gauge = |name, help| GaugeVec::new(Opts::new(name, help), &["pubkey"]);
+            Self {
+                current_staking_apy: pubkey_gauge("current_apy", "Current APY").unwrap(),
+                average_staking_apy: pubkey_gauge("average_apy", "Average APY").unwrap(),
+                current_staking_apr: pubkey_gauge("current_apr", "Current APR").unwrap(),
+                average_staking_apr: pubkey_gauge("average_apr", "Average APR").unwrap(),
+                expected_annual_staking_rewards: GaugeVec::new(
+                    Opts::new("expected_rewards", "Expected rewards"),
+                    &["pubkey", "voter"],
+                )
+                .unwrap(),
+                validator_rewards: IntCounterVec::new(Opts::new("rewards", "Rewards"), &["pubkey"])
+                    .unwrap(),
+                whitelist_validator_rewards: IntGauge::new(
+                    "whitelist_rewards",
+                    "Whitelist rewards",
+                )
+                .unwrap(),
+                whitelist_current_staking_apy: Gauge::new(
+                    "whitelist_current_apy",
+                    "Whitelist current APY",
+                )
+                .unwrap(),
+                whitelist_average_staking_apy: Gauge::new(
+                    "whitelist_average_apy",
+                    "Whitelist average APY",
+                )
+                .unwrap(),
+                cluster_staking_apy: GaugeVec::new(
+                    Opts::new("cluster_apy", "Cluster APY"),
+                    &["statistic"],
+                )
+                .unwrap(),
+                cluster_staking_apy_voters: IntGaugeVec::new(
+                    Opts::new("cluster_apy_voters", "Cluster APY voters"),
+                    &["le"],
+                )
+                .unwrap(),
+                staking_apy_outliers: IntCounterVec::new(
+                    Opts::new("apy_outliers", "APY outliers"),
+                    &["reason"],
+                )
+                .unwrap(),
+                stake_account_errors: IntCounterVec::new(
+                    Opts::new("account_errors", "Account errors"),
+                    &["reason"],
+                )
+                .unwrap(),
+            }
+        }
+
+        /// Makes a monitor of these metrics.
+        fn monitor<'a>(
+            &'a self,
+            rpc: &'a MockRpc,
+            baselines: &'a CounterBaselines,
+            cache: &'a RewardsCache,
+        ) -> RewardsMonitor<'a> {
+            RewardsMonitor::new(
+                rpc,
+                &self.current_staking_apy,
+                &self.average_staking_apy,
+                &self.current_staking_apr,
+                &self.average_staking_apr,
+                &self.expected_annual_staking_rewards,
+                &self.validator_rewards,
+                &self.whitelist_validator_rewards,
+                &self.whitelist_current_staking_apy,
+                &self.whitelist_average_staking_apy,
+                &self.cluster_staking_apy,
+                &self.cluster_staking_apy_voters,
+                &self.staking_apy_outliers,
+                &self.stake_account_errors,
+                baselines,
+                cache,
+                2,
+                Duration::from_secs(3600),
+                ApyOutlierRules::default(),
+                None,
+                None,
+            )
+        }
+    }
+
+    /// A stake account of `lamports` delegated to `voter`.
+    fn stake_account(voter: &Pubkey, lamports: u64) -> Account {
+        let stake_state = StakeStateV2::Stake(
+            Meta::default(),
+            Stake {
+                delegation: Delegation::new(voter, lamports, 0),
+                credits_observed: 0,
+            },
+            StakeFlags::empty(),
+        );
+        Account {
+            lamports,
+            data: bincode::serialize(&stake_state).unwrap(),
+            owner: Pubkey::default(),
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn calculates_staking_apy_in_the_first_epochs() {
+        let voter = Pubkey::new_unique();
+        let stake_account_pubkey = Pubkey::new_unique();
+        // A new cluster pays the first staking rewards in epoch 1.
+        let mut rpc = MockRpc {
+            epoch_schedule: Some(EpochSchedule::custom(
+                SLOTS_PER_EPOCH,
+                SLOTS_PER_EPOCH,
+                false,
+            )),
+            ..MockRpc::default()
+        };
+        rpc.add_block(0, Some(0), vec![]);
+        rpc.add_block(
+            SLOTS_PER_EPOCH,
+            Some(EPOCH_DAYS * SECONDS_IN_DAY as i64),
+            vec![reward(
+                &stake_account_pubkey,
+                1_000,
+                1_001_000,
+                RewardType::Staking,
+            )],
+        );
+        rpc.accounts
+            .insert(stake_account_pubkey, stake_account(&voter, 1_001_000));
+        let gauges = Gauges::new();
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let baselines = CounterBaselines::new(db.open_tree("counters").unwrap());
+        let cache = RewardsCache::temporary();
+        let monitor = gauges.monitor(&rpc, &baselines, &cache);
+        let epoch_info = |epoch| EpochInfo {
+            epoch,
+            slot_index: 5,
+            slots_in_epoch: SLOTS_PER_EPOCH,
+            absolute_slot: epoch * SLOTS_PER_EPOCH + 5,
+            block_height: 0,
+            transaction_count: None,
+        };
+        let vote_accounts = RpcVoteAccountStatus {
+            current: vec![],
+            delinquent: vec![],
+        };
+        let whitelist = Whitelist::default();
+
+        for epoch in 0..=1 {
+            monitor
+                .export_rewards(&epoch_info(epoch), &vote_accounts, &whitelist, &whitelist)
+                .await
+                .unwrap();
+        }
+        let expected_apy = (1.001f64.powf(DAYS_IN_YEAR as f64 / EPOCH_DAYS as f64) - 1.0) * 100.0;
+        let voter_label = voter.to_string();
+        let current_apy = gauges
+            .current_staking_apy
+            .with_label_values(&[&voter_label])
+            .get();
+        assert!((current_apy - expected_apy).abs() < 1e-9, "{}", current_apy);
+        // Epoch 1 is the whole lookback so far.
+        let average_apy = gauges
+            .average_staking_apy
+            .with_label_values(&[&voter_label])
+            .get();
+        assert!((average_apy - expected_apy).abs() < 1e-9, "{}", average_apy);
+        assert!(monitor.lookback_cached(1).unwrap());
+    }
+
+    #[tokio::test]
+    async fn calculates_staking_apy_and_counts_validator_rewards() {
+        let voter = Pubkey::new_unique();
+        let stake_account = Pubkey::new_unique();
+        let missing_account = Pubkey::new_unique();
+        let unparsable_account = Pubkey::new_unique();
+        let current_epoch = 10;
+
+        // The first block of every epoch in the lookback is two days after the previous one. The
+        // current epoch's staking rewards are paid in a partition after a skipped slot.
+        let mut rpc = MockRpc {
+            epoch_schedule: Some(EpochSchedule::custom(
+                SLOTS_PER_EPOCH,
+                SLOTS_PER_EPOCH,
+                false,
+            )),
+            ..MockRpc::default()
+        };
+        for epoch in current_epoch - MAX_EPOCH_LOOKBACK..=current_epoch {
+            let rewards = if epoch == current_epoch {
+                vec![reward(&voter, 5_000, 7_000_000, RewardType::Voting)]
+            } else {
+                vec![]
+            };
+            rpc.add_block(
+                epoch * SLOTS_PER_EPOCH,
+                Some(epoch as i64 * EPOCH_DAYS * SECONDS_IN_DAY as i64),
+                rewards,
+            );
+        }
+        let current_slot = current_epoch * SLOTS_PER_EPOCH;
+        rpc.blocks
+            .get_mut(&current_slot)
+            .unwrap()
+            .num_reward_partitions = Some(1);
+        rpc.add_block(
+            current_slot + 2,
+            None,
+            vec![
+                reward(&stake_account, 1_000, 1_001_000, RewardType::Staking),
+                reward(&missing_account, 1_000, 1_001_000, RewardType::Staking),
+                reward(&unparsable_account, 1_000, 1_001_000, RewardType::Staking),
+                reward(&Pubkey::new_unique(), 5_000, 1_000_000, RewardType::Fee),
+            ],
+        );
+        let stake_state = StakeStateV2::Stake(
+            Meta::default(),
+            Stake {
+                delegation: Delegation::new(&voter, 1_000_000, 0),
+                credits_observed: 0,
+            },
+            StakeFlags::empty(),
+        );
+        rpc.accounts.insert(
+            stake_account,
+            Account {
+                lamports: 1_001_000,
+                data: bincode::serialize(&stake_state).unwrap(),
+                owner: Pubkey::default(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        rpc.accounts.insert(
+            unparsable_account,
+            Account {
+                lamports: 1_001_000,
+                data: vec![0xff; 3],
+                owner: Pubkey::default(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let baselines = CounterBaselines::new(db.open_tree("counters").unwrap());
+        let cache = RewardsCache::temporary();
+        let whitelist = Whitelist::default();
+        let staking_whitelist = Whitelist::new(
+            [stake_account, missing_account, unparsable_account]
+                .iter()
+                .map(Pubkey::to_string)
+                .collect(),
+        );
+        let gauges = Gauges::new();
+        let monitor = gauges.monitor(&rpc, &baselines, &cache);
+
+        let epoch_info = EpochInfo {
+            epoch: current_epoch,
+            slot_index: 5,
+            slots_in_epoch: SLOTS_PER_EPOCH,
+            absolute_slot: current_epoch * SLOTS_PER_EPOCH + 5,
+            block_height: 0,
+            transaction_count: None,
+        };
+        let vote_accounts = RpcVoteAccountStatus {
+            current: vec![RpcVoteAccountInfo {
+                vote_pubkey: voter.to_string(),
+                node_pubkey: Pubkey::new_unique().to_string(),
+                activated_stake: 1_000_000,
+                commission: 0,
+                epoch_vote_account: true,
+                epoch_credits: vec![],
+                last_vote: 0,
+                root_slot: 0,
+            }],
+            delinquent: vec![],
+        };
+        monitor
+            .export_rewards(&epoch_info, &vote_accounts, &whitelist, &staking_whitelist)
+            .await
+            .unwrap();
+        // Exporting again within the epoch neither recalculates nor double-counts.
+        monitor
+            .export_rewards(&epoch_info, &vote_accounts, &whitelist, &staking_whitelist)
+            .await
+            .unwrap();
+
+        // 0.1% per two-day epoch, compounded over 182.5 epochs a year.
+        let epochs_in_year = DAYS_IN_YEAR as f64 / EPOCH_DAYS as f64;
+        let expected_apy = (1.001f64.powf(epochs_in_year) - 1.0) * 100.0;
+        let voter_label = voter.to_string();
+        let current_apy = gauges
+            .current_staking_apy
+            .with_label_values(&[&voter_label])
+            .get();
+        assert!((current_apy - expected_apy).abs() < 1e-9, "{}", current_apy);
+        // There are no staking rewards in the earlier epochs of the lookback.
+        let average_apy = gauges
+            .average_staking_apy
+            .with_label_values(&[&voter_label])
+            .get();
+        assert!(
+            (average_apy - expected_apy / MAX_EPOCH_LOOKBACK as f64).abs() < 1e-9,
+            "{}",
+            average_apy
+        );
+        // The APR is the epoch rate annualized without compounding.
+        let current_apr = gauges
+            .current_staking_apr
+            .with_label_values(&[&voter_label])
+            .get();
+        let expected_apr = 0.001 / EPOCH_DAYS as f64 * DAYS_IN_YEAR as f64 * 100.0;
+        assert!((current_apr - expected_apr).abs() < 1e-9, "{}", current_apr);
+        assert_eq!(
+            gauges
+                .validator_rewards
+                .with_label_values(&[&voter_label])
+                .get(),
+            5_000
+        );
+        // The whitelisted stake account earns the APY on its balance after the reward.
+        let expected_rewards = gauges
+            .expected_annual_staking_rewards
+            .with_label_values(&[&stake_account.to_string(), &voter_label])
+            .get();
+        assert!(
+            (expected_rewards - 0.001_001 * expected_apy / 100.0).abs() < 1e-12,
+            "{}",
+            expected_rewards
+        );
+
+        // The missing and unparsable stake accounts are counted once, even by a retried
+        // calculation.
+        monitor
+            .fill_current_epoch_and_find_apy(
+                &epoch_info,
+                &mut HashMap::new(),
+                &whitelist,
+                &staking_whitelist,
+            )
+            .await
+            .unwrap();
+        for reason in ["missing", "unparsable"].iter() {
+            assert_eq!(
+                gauges
+                    .stake_account_errors
+                    .with_label_values(&[reason])
+                    .get(),
+                1
+            );
+        }
+
+        // The only whitelisted validator makes up the whole whitelist.
+        assert_eq!(gauges.whitelist_validator_rewards.get(), 5_000);
+        assert!((gauges.whitelist_current_staking_apy.get() - current_apy).abs() < 1e-9);
+        assert!((gauges.whitelist_average_staking_apy.get() - average_apy).abs() < 1e-9);
+        assert!(
+            (gauges
+                .cluster_staking_apy
+                .with_label_values(&["median"])
+                .get()
+                - current_apy)
+                .abs()
+                < 1e-9
+        );
+        assert_eq!(
+            gauges
+                .cluster_staking_apy_voters
+                .with_label_values(&["+Inf"])
+                .get(),
+            1
+        );
+        // The average over the partial lookback is withheld until the earlier epochs are cached.
+        assert!(!monitor.lookback_cached(current_epoch).unwrap());
+        for epoch in current_epoch - MAX_EPOCH_LOOKBACK + 1..current_epoch {
+            let mapping = std::iter::once((Pubkey::new_unique(), (voter, 1.0))).collect();
+            cache.add_epoch_data(epoch, mapping).unwrap();
+        }
+        assert!(monitor.lookback_cached(current_epoch).unwrap());
+    }
+
+    #[test]
+    fn skips_dust_and_excludes_or_clamps_outlier_apys() {
+        let mut rules = ApyOutlierRules {
+            max_apy: Some(20.0),
+            clamp: false,
+            min_stake_lamports: 1_000,
+        };
+        let reward = |lamports, post_balance| StakingReward {
+            pubkey: Pubkey::new_unique(),
+            lamports,
+            post_balance,
+        };
+        assert!(!rules.is_dust(&reward(10, 1_010)));
+        assert!(rules.is_dust(&reward(11, 1_010)));
+        assert_eq!(rules.apply(7.0), (Some(7.0), None));
+        assert_eq!(rules.apply(3_000.0), (None, Some("max_apy")));
+        rules.clamp = true;
+        assert_eq!(rules.apply(3_000.0), (Some(20.0), Some("max_apy")));
+        let no_rules = ApyOutlierRules::default();
+        assert_eq!(no_rules.apply(3_000.0), (Some(3_000.0), None));
+        assert!(!no_rules.is_dust(&reward(10, 10)));
+    }
+
+    #[test]
+    fn computes_apy_distribution() {
+        assert_eq!(apy_distribution(vec![]), None);
+        let distribution = apy_distribution(vec![7.5, 0.5, 6.0, 30.0]).unwrap();
+        assert_eq!(distribution.mean, 11.0);
+        assert_eq!(distribution.median, 6.75);
+        let expected: Vec<usize> = vec![1, 1, 1, 1, 1, 2, 2, 3, 3, 3, 3, 3, 3, 4];
+        assert_eq!(distribution.buckets, expected);
+        assert_eq!(apy_distribution(vec![6.0, 2.0, 4.0]).unwrap().median, 4.0);
+    }
+
+    #[test]
+    fn exports_rewards_once_an_epoch() {
+        let hour = Duration::from_secs(3600);
+        assert!(export_due(None, 10, hour));
+        let now = Instant::now();
+        assert!(!export_due(Some((10, now)), 10, hour));
+        assert!(export_due(Some((9, now)), 10, hour));
+        assert!(export_due(Some((10, now)), 10, Duration::ZERO));
+    }
+
+    #[test]
+    fn leaves_epoch_0_out_of_the_lookback() {
+        assert_eq!(lookback_start(0), 1);
+        assert_eq!(lookback_start(1), 1);
+        assert_eq!(lookback_start(3), 1);
+        assert_eq!(lookback_start(10), 10 - MAX_EPOCH_LOOKBACK + 1);
+    }
+
+    #[test]
+    fn weights_apys_by_stake() {
+        let (a, b, c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let apys = vec![(a, 6.0, 3.0), (b, 9.0, 6.0), (c, 100.0, 100.0)]
+            .into_iter()
+            .map(|(voter, current_apy, average_apy)| {
+                let apy = VoterApy {
+                    current_apy,
+                    average_apy,
+                    current_apr: current_apy / 2.0,
+                    average_apr: average_apy / 2.0,
+                };
+                (voter, apy)
+            })
+            .collect();
+        // `c` has no stake in the whitelist, and the stake of `d` has no APY.
+        let d = Pubkey::new_unique();
+        let stakes = vec![(a, 2), (b, 1), (d, 5)].into_iter().collect();
+
+        assert_eq!(
+            stake_weighted_apy(&apys, &stakes),
+            Some(VoterApy {
+                current_apy: 7.0,
+                average_apy: 4.0,
+                current_apr: 3.5,
+                average_apr: 2.0,
+            })
+        );
+        assert_eq!(stake_weighted_apy(&apys, &HashMap::new()), None);
+    }
+
+    #[test]
+    fn averages_cached_apys_weighted_by_epoch_length() {
+        let cache = RewardsCache::temporary();
+        let voter = Pubkey::new_unique();
+        for (epoch, apy) in [(8, 6.0), (10, 3.0)] {
+            let mapping = std::iter::once((Pubkey::new_unique(), (voter, apy))).collect();
+            cache.add_epoch_data(epoch, mapping).unwrap();
+        }
+        for (epoch, days) in [(7, 1.0), (8, 2.0), (9, 3.0)] {
+            cache.add_epoch_length(epoch, days).unwrap();
+        }
+
+        assert_eq!(cache.latest_apy_epoch().unwrap(), Some(10));
+        let apys = cached_voter_apys(&cache, 10, 3).unwrap();
+        assert_eq!(apys[&voter].current_apy, 3.0);
+        assert_eq!(apys[&voter].average_apy, (6.0 * 1.0 + 3.0 * 3.0) / 6.0);
+        assert_eq!(apys[&voter].current_apr, apr_of_apy(3.0, 3.0));
+        assert_eq!(
+            apys[&voter].average_apr,
+            (apr_of_apy(6.0, 1.0) * 1.0 + apr_of_apy(3.0, 3.0) * 3.0) / 6.0
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! An estimate of the staking APY of the current epoch, before its rewards are paid.
+
+use super::{DAYS_IN_YEAR, SECONDS_IN_DAY};
+use crate::config::Whitelist;
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::rpc_api::RpcApi;
+use async_trait::async_trait;
+use prometheus_exporter::prometheus::GaugeVec;
+use solana_client::rpc_response::{RpcVoteAccountInfo, RpcVoteAccountStatus};
+use solana_clock::{Epoch, DEFAULT_MS_PER_SLOT};
+use solana_epoch_info::EpochInfo;
+use std::collections::HashMap;
+
+/// The monitor of the projected staking APY of the current epoch.
+///
+/// Inflation rewards are split among vote accounts by points, the vote credits earned in the
+/// epoch times the delegated stake. The share of a vote account's delegators is projected from
+/// the credits earned so far, which grow at about the same rate for all vote accounts, so the
+/// projection settles as the epoch progresses. The inflation rate and supply are fetched once per
+/// epoch.
+pub struct ProjectedApyMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Prometheus gauge.
+    projected_staking_apy: &'a GaugeVec,
+    /// The epoch and the lamports paid to stakers per year at its inflation rate.
+    annual_rewards: Option<(Epoch, f64)>,
+}
+
+impl<'a> ProjectedApyMonitor<'a> {
+    /// Constructs a monitor given `rpc`.
+    pub fn new(rpc: &'a dyn RpcApi, projected_staking_apy: &'a GaugeVec) -> Self {
+        Self {
+            rpc,
+            projected_staking_apy,
+            annual_rewards: None,
+        }
+    }
+
+    /// Exports the projected staking APY of the current epoch of the vote accounts of
+    /// `vote_accounts_whitelist`.
+    async fn export_projected_apys(
+        &mut self,
+        epoch_info: &EpochInfo,
+        vote_accounts: &RpcVoteAccountStatus,
+        vote_accounts_whitelist: &Whitelist,
+    ) -> anyhow::Result<()> {
+        let annual_rewards = match self.annual_rewards {
+            Some((epoch, lamports)) if epoch == epoch_info.epoch => lamports,
+            _ => {
+                let inflation = self.rpc.get_inflation_rate().await?;
+                let supply = self.rpc.get_supply().await?;
+                let lamports = inflation.validator * supply.total as f64;
+                self.annual_rewards = Some((epoch_info.epoch, lamports));
+                lamports
+            }
+        };
+        let epochs_in_year = (DAYS_IN_YEAR * SECONDS_IN_DAY * 1000) as f64
+            / (epoch_info.slots_in_epoch * DEFAULT_MS_PER_SLOT) as f64;
+
+        for (voter, apy) in projected_apys(
+            vote_accounts,
+            epoch_info.epoch,
+            annual_rewards,
+            epochs_in_year,
+        )
+        .iter()
+        .filter(|(voter, _)| vote_accounts_whitelist.contains(voter))
+        {
+            self.projected_staking_apy
+                .get_metric_with_label_values(&[voter])
+                .map(|m| m.set(*apy))?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Monitor for ProjectedApyMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "projected APY"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![family_name(self.projected_staking_apy)]
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.export_projected_apys(
+            &cycle.epoch_info,
+            &cycle.vote_accounts,
+            &cycle.vote_accounts_whitelist,
+        )
+        .await
+    }
+}
+
+/// Projects the staking APY in percent of every staked vote account in `epoch`, given the
+/// lamports paid to stakers per year and the number of epochs in a year. Empty before any credits
+/// are earned in the epoch.
+fn projected_apys(
+    vote_accounts: &RpcVoteAccountStatus,
+    epoch: Epoch,
+    annual_rewards: f64,
+    epochs_in_year: f64,
+) -> HashMap<String, f64> {
+    let credits = |v: &RpcVoteAccountInfo| {
+        v.epoch_credits
+            .iter()
+            .find(|(e, _, _)| *e == epoch)
+            .map_or(0, |(_, credits, previous)| {
+                credits.saturating_sub(*previous)
+            })
+    };
+    let voters: Vec<_> = vote_accounts
+        .current
+        .iter()
+        .chain(&vote_accounts.delinquent)
+        .filter(|v| v.activated_stake > 0)
+        .collect();
+    let total_points: f64 = voters
+        .iter()
+        .map(|v| credits(v) as f64 * v.activated_stake as f64)
+        .sum();
+    if total_points == 0.0 {
+        return HashMap::new();
+    }
+
+    voters
+        .iter()
+        .map(|v| {
+            let apr = annual_rewards * credits(v) as f64 * (1.0 - v.commission as f64 / 100.0)
+                / total_points;
+            let apy = f64::powf(1.0 + apr / epochs_in_year, epochs_in_year) - 1.0;
+            (v.vote_pubkey.clone(), apy * 100.0)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projects_apy_from_credits() {
+        let vote_account = |voter: &str, commission, epoch_credits| RpcVoteAccountInfo {
+            vote_pubkey: voter.to_string(),
+            node_pubkey: String::new(),
+            activated_stake: 100,
+            commission,
+            epoch_vote_account: true,
+            epoch_credits,
+            last_vote: 0,
+            root_slot: 0,
+        };
+        let vote_accounts = RpcVoteAccountStatus {
+            current: vec![
+                vote_account("a", 0, vec![(9, 50, 0), (10, 60, 50)]),
+                vote_account("b", 10, vec![(10, 10, 0)]),
+            ],
+            delinquent: vec![vote_account("c", 0, vec![(9, 40, 0)])],
+        };
+        assert!(projected_apys(&vote_accounts, 11, 10.0, 1.0).is_empty());
+
+        let apys = projected_apys(&vote_accounts, 10, 10.0, 1.0);
+        let apy = |voter| (apys[voter] * 1e9_f64).round() / 1e9;
+        assert_eq!(apy("a"), 5.0);
+        assert_eq!(apy("b"), 4.5);
+        assert_eq!(apy("c"), 0.0);
+    }
+}
@@ -0,0 +1,327 @@
+//! An [`RpcApi`] recording the duration and failures of every call.
+
+use super::RpcApi;
+use async_trait::async_trait;
+use prometheus_exporter::prometheus::{
+    register_histogram_vec, register_int_counter_vec, HistogramVec, IntCounterVec,
+};
+use serde_json::Value;
+use solana_account::Account;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcBlockConfig, RpcBlockProductionConfig, RpcLeaderScheduleConfig,
+    RpcProgramAccountsConfig, RpcSignaturesForAddressConfig, RpcTransactionConfig,
+};
+use solana_client::rpc_response::{
+    RpcAccountBalance, RpcBlockProduction, RpcConfirmedTransactionStatusWithSignature,
+    RpcInflationRate, RpcLeaderSchedule, RpcSupply, RpcVoteAccountStatus,
+};
+use solana_clock::Slot;
+use solana_commitment_config::CommitmentConfig;
+use solana_epoch_info::EpochInfo;
+use solana_epoch_schedule::EpochSchedule;
+use solana_pubkey::Pubkey;
+use solana_transaction_status_client_types::{
+    EncodedConfirmedTransactionWithStatusMeta, UiConfirmedBlock,
+};
+use std::future::Future;
+
+/// Label used for the RPC method name
+const METHOD_LABEL: &str = "method";
+
+/// Upper bounds of the request duration buckets in seconds, up to the RPC client timeout.
+const DURATION_BUCKETS: &[f64] = &[
+    0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0,
+];
+
+/// Wraps an [`RpcApi`], observing the duration of every call and counting failed calls per RPC
+/// method.
+pub struct InstrumentedRpc<R> {
+    inner: R,
+    /// Prometheus histogram.
+    request_duration: HistogramVec,
+    /// Prometheus counter.
+    request_errors: IntCounterVec,
+}
+
+impl<R: RpcApi> InstrumentedRpc<R> {
+    /// Wraps `inner` and registers the metrics.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            request_duration: register_histogram_vec!(
+                "solana_exporter_rpc_request_duration_seconds",
+                "Duration of RPC requests",
+                &[METHOD_LABEL],
+                DURATION_BUCKETS.to_vec()
+            )
+            .unwrap(),
+            request_errors: register_int_counter_vec!(
+                "solana_exporter_rpc_request_errors",
+                "Number of failed RPC requests",
+                &[METHOD_LABEL]
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Awaits `call` of `method`, recording its duration and whether it failed.
+    async fn observe<T>(
+        &self,
+        method: &str,
+        call: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        let timer = self
+            .request_duration
+            .with_label_values(&[method])
+            .start_timer();
+        let result = call.await;
+        timer.observe_duration();
+        if result.is_err() {
+            self.request_errors.with_label_values(&[method]).inc();
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl<R: RpcApi> RpcApi for InstrumentedRpc<R> {
+    async fn get_epoch_info(&self) -> anyhow::Result<EpochInfo> {
+        self.observe("getEpochInfo", self.inner.get_epoch_info())
+            .await
+    }
+
+    async fn get_epoch_schedule(&self) -> anyhow::Result<EpochSchedule> {
+        self.observe("getEpochSchedule", self.inner.get_epoch_schedule())
+            .await
+    }
+
+    async fn get_vote_accounts(&self) -> anyhow::Result<RpcVoteAccountStatus> {
+        self.observe("getVoteAccounts", self.inner.get_vote_accounts())
+            .await
+    }
+
+    async fn get_cluster_nodes_json(&self) -> anyhow::Result<Value> {
+        self.observe("getClusterNodes", self.inner.get_cluster_nodes_json())
+            .await
+    }
+
+    async fn get_balance(&self, pubkey: &Pubkey) -> anyhow::Result<u64> {
+        self.observe("getBalance", self.inner.get_balance(pubkey))
+            .await
+    }
+
+    async fn get_inflation_rate(&self) -> anyhow::Result<RpcInflationRate> {
+        self.observe("getInflationRate", self.inner.get_inflation_rate())
+            .await
+    }
+
+    async fn get_supply(&self) -> anyhow::Result<RpcSupply> {
+        self.observe("getSupply", self.inner.get_supply()).await
+    }
+
+    async fn get_largest_accounts(&self) -> anyhow::Result<Vec<RpcAccountBalance>> {
+        self.observe("getLargestAccounts", self.inner.get_largest_accounts())
+            .await
+    }
+
+    /// Observes the duration of the calls to all endpoints at once, and counts every failed one.
+    async fn get_endpoint_slots(
+        &self,
+        commitment: CommitmentConfig,
+    ) -> Vec<(String, anyhow::Result<Slot>)> {
+        let timer = self
+            .request_duration
+            .with_label_values(&["getSlot"])
+            .start_timer();
+        let slots = self.inner.get_endpoint_slots(commitment).await;
+        timer.observe_duration();
+        for _ in slots.iter().filter(|(_, slot)| slot.is_err()) {
+            self.request_errors.with_label_values(&["getSlot"]).inc();
+        }
+        slots
+    }
+
+    async fn get_slot_with_commitment(&self, commitment: CommitmentConfig) -> anyhow::Result<Slot> {
+        self.observe("getSlot", self.inner.get_slot_with_commitment(commitment))
+            .await
+    }
+
+    async fn get_max_retransmit_slot(&self) -> anyhow::Result<Slot> {
+        self.observe("getMaxRetransmitSlot", self.inner.get_max_retransmit_slot())
+            .await
+    }
+
+    async fn get_max_shred_insert_slot(&self) -> anyhow::Result<Slot> {
+        self.observe(
+            "getMaxShredInsertSlot",
+            self.inner.get_max_shred_insert_slot(),
+        )
+        .await
+    }
+
+    async fn get_multiple_accounts(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> anyhow::Result<Vec<Option<Account>>> {
+        self.observe(
+            "getMultipleAccounts",
+            self.inner.get_multiple_accounts(pubkeys),
+        )
+        .await
+    }
+
+    async fn get_multiple_accounts_with_config(
+        &self,
+        pubkeys: &[Pubkey],
+        config: RpcAccountInfoConfig,
+    ) -> anyhow::Result<Vec<Option<Account>>> {
+        self.observe(
+            "getMultipleAccounts",
+            self.inner
+                .get_multiple_accounts_with_config(pubkeys, config),
+        )
+        .await
+    }
+
+    async fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> anyhow::Result<Vec<(Pubkey, Account)>> {
+        self.observe(
+            "getProgramAccounts",
+            self.inner
+                .get_program_accounts_with_config(program_id, config),
+        )
+        .await
+    }
+
+    async fn get_blocks(
+        &self,
+        start_slot: Slot,
+        end_slot: Option<Slot>,
+    ) -> anyhow::Result<Vec<Slot>> {
+        self.observe("getBlocks", self.inner.get_blocks(start_slot, end_slot))
+            .await
+    }
+
+    async fn get_blocks_with_commitment(
+        &self,
+        start_slot: Slot,
+        end_slot: Option<Slot>,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Vec<Slot>> {
+        self.observe(
+            "getBlocks",
+            self.inner
+                .get_blocks_with_commitment(start_slot, end_slot, commitment),
+        )
+        .await
+    }
+
+    async fn get_blocks_with_limit(
+        &self,
+        start_slot: Slot,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Slot>> {
+        self.observe(
+            "getBlocksWithLimit",
+            self.inner.get_blocks_with_limit(start_slot, limit),
+        )
+        .await
+    }
+
+    async fn get_block_with_config(
+        &self,
+        slot: Slot,
+        config: RpcBlockConfig,
+    ) -> anyhow::Result<UiConfirmedBlock> {
+        self.observe("getBlock", self.inner.get_block_with_config(slot, config))
+            .await
+    }
+
+    async fn get_block_production_with_config(
+        &self,
+        config: RpcBlockProductionConfig,
+    ) -> anyhow::Result<RpcBlockProduction> {
+        self.observe(
+            "getBlockProduction",
+            self.inner.get_block_production_with_config(config),
+        )
+        .await
+    }
+
+    async fn get_leader_schedule_with_config(
+        &self,
+        slot: Option<Slot>,
+        config: RpcLeaderScheduleConfig,
+    ) -> anyhow::Result<Option<RpcLeaderSchedule>> {
+        self.observe(
+            "getLeaderSchedule",
+            self.inner.get_leader_schedule_with_config(slot, config),
+        )
+        .await
+    }
+
+    async fn get_signatures_for_address_with_config(
+        &self,
+        address: &Pubkey,
+        config: RpcSignaturesForAddressConfig,
+    ) -> anyhow::Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        self.observe(
+            "getSignaturesForAddress",
+            self.inner
+                .get_signatures_for_address_with_config(address, config),
+        )
+        .await
+    }
+
+    async fn get_transaction_with_config(
+        &self,
+        signature: &str,
+        config: RpcTransactionConfig,
+    ) -> anyhow::Result<EncodedConfirmedTransactionWithStatusMeta> {
+        self.observe(
+            "getTransaction",
+            self.inner.get_transaction_with_config(signature, config),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_api::mock::MockRpc;
+    use prometheus_exporter::prometheus::{HistogramOpts, Opts};
+
+    #[tokio::test]
+    async fn records_durations_and_errors_per_method() {
+        let rpc = InstrumentedRpc {
+            inner: MockRpc::default(),
+            request_duration: HistogramVec::new(
+                HistogramOpts::new("duration", "Duration"),
+                &[METHOD_LABEL],
+            )
+            .unwrap(),
+            request_errors: IntCounterVec::new(Opts::new("errors", "Errors"), &[METHOD_LABEL])
+                .unwrap(),
+        };
+
+        rpc.get_balance(&Pubkey::new_unique()).await.unwrap();
+        rpc.get_balance(&Pubkey::new_unique()).await.unwrap();
+        // Not mocked, so it fails.
+        assert!(rpc.get_epoch_info().await.is_err());
+
+        let count = |method| {
+            rpc.request_duration
+                .with_label_values(&[method])
+                .get_sample_count()
+        };
+        assert_eq!(count("getBalance"), 2);
+        assert_eq!(count("getEpochInfo"), 1);
+        let errors = |method| rpc.request_errors.with_label_values(&[method]).get();
+        assert_eq!(errors("getBalance"), 0);
+        assert_eq!(errors("getEpochInfo"), 1);
+    }
+}
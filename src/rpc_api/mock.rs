@@ -0,0 +1,309 @@
+//! An in-memory [`RpcApi`] serving canned responses.
+
+use super::RpcApi;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde_json::Value;
+use solana_account::Account;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcBlockConfig, RpcBlockProductionConfig, RpcLeaderScheduleConfig,
+    RpcProgramAccountsConfig, RpcSignaturesForAddressConfig, RpcTransactionConfig,
+    UiDataSliceConfig,
+};
+use solana_client::rpc_filter::RpcFilterType;
+use solana_client::rpc_response::{
+    RpcAccountBalance, RpcBlockProduction, RpcConfirmedTransactionStatusWithSignature,
+    RpcInflationRate, RpcLeaderSchedule, RpcSupply, RpcVoteAccountStatus,
+};
+use solana_clock::Slot;
+use solana_commitment_config::CommitmentConfig;
+use solana_epoch_info::EpochInfo;
+use solana_epoch_schedule::EpochSchedule;
+use solana_pubkey::Pubkey;
+use solana_transaction_status_client_types::{
+    EncodedConfirmedTransactionWithStatusMeta, Rewards, UiConfirmedBlock,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// Canned RPC responses. Calls whose response is not set fail.
+#[derive(Default)]
+pub struct MockRpc {
+    pub epoch_info: Option<EpochInfo>,
+    pub epoch_schedule: Option<EpochSchedule>,
+    pub vote_accounts: Option<RpcVoteAccountStatus>,
+    pub cluster_nodes: Option<Value>,
+    /// Existing accounts, also answering `getProgramAccounts`. Missing accounts have a zero
+    /// balance.
+    pub accounts: HashMap<Pubkey, Account>,
+    /// Confirmed blocks, also answering `getBlocks`. The config of `getBlock` is ignored.
+    pub blocks: BTreeMap<Slot, UiConfirmedBlock>,
+    /// Slots of blocks that are confirmed but not finalized, also answering `getBlocks` at a
+    /// commitment other than `finalized`.
+    pub confirmed_blocks: BTreeSet<Slot>,
+    /// `getBlockProduction` responses, returned in order.
+    pub block_production: Mutex<VecDeque<RpcBlockProduction>>,
+    pub leader_schedule: Option<RpcLeaderSchedule>,
+    pub inflation_rate: Option<RpcInflationRate>,
+    pub supply: Option<RpcSupply>,
+    pub largest_accounts: Option<Vec<RpcAccountBalance>>,
+    /// Slots by endpoint URL at any commitment; `None` fails.
+    pub endpoint_slots: Vec<(String, Option<Slot>)>,
+    /// Slot of the primary endpoint at any commitment.
+    pub slot: Option<Slot>,
+    pub max_retransmit_slot: Option<Slot>,
+    pub max_shred_insert_slot: Option<Slot>,
+    /// Transaction signatures by address, newest first. Other addresses have none.
+    pub signatures: HashMap<Pubkey, Vec<RpcConfirmedTransactionStatusWithSignature>>,
+    /// Transactions by signature. The config of `getTransaction` is ignored.
+    pub transactions: HashMap<String, EncodedConfirmedTransactionWithStatusMeta>,
+}
+
+impl MockRpc {
+    /// Adds a block at `slot` with the given time and rewards.
+    pub fn add_block(&mut self, slot: Slot, block_time: Option<i64>, rewards: Rewards) {
+        self.blocks.insert(
+            slot,
+            UiConfirmedBlock {
+                previous_blockhash: String::new(),
+                blockhash: String::new(),
+                parent_slot: slot.saturating_sub(1),
+                transactions: None,
+                signatures: None,
+                rewards: Some(rewards),
+                num_reward_partitions: None,
+                block_time,
+                block_height: Some(slot),
+            },
+        );
+    }
+}
+
+/// Returns `response` or an error naming the unmocked `method`.
+fn canned<T: Clone>(response: &Option<T>, method: &str) -> anyhow::Result<T> {
+    response
+        .clone()
+        .ok_or_else(|| anyhow!("{} is not mocked", method))
+}
+
+/// Returns `account` with only the `data_slice` of its data, if set.
+fn sliced(account: &Account, data_slice: Option<UiDataSliceConfig>) -> Account {
+    let mut account = account.clone();
+    if let Some(slice) = data_slice {
+        let start = slice.offset.min(account.data.len());
+        let end = (slice.offset + slice.length).min(account.data.len());
+        account.data = account.data[start..end].to_vec();
+    }
+    account
+}
+
+#[async_trait]
+impl RpcApi for MockRpc {
+    async fn get_epoch_info(&self) -> anyhow::Result<EpochInfo> {
+        canned(&self.epoch_info, "getEpochInfo")
+    }
+
+    async fn get_epoch_schedule(&self) -> anyhow::Result<EpochSchedule> {
+        canned(&self.epoch_schedule, "getEpochSchedule")
+    }
+
+    async fn get_vote_accounts(&self) -> anyhow::Result<RpcVoteAccountStatus> {
+        canned(&self.vote_accounts, "getVoteAccounts")
+    }
+
+    async fn get_cluster_nodes_json(&self) -> anyhow::Result<Value> {
+        canned(&self.cluster_nodes, "getClusterNodes")
+    }
+
+    async fn get_balance(&self, pubkey: &Pubkey) -> anyhow::Result<u64> {
+        Ok(self
+            .accounts
+            .get(pubkey)
+            .map(|a| a.lamports)
+            .unwrap_or_default())
+    }
+
+    async fn get_inflation_rate(&self) -> anyhow::Result<RpcInflationRate> {
+        canned(&self.inflation_rate, "getInflationRate")
+    }
+
+    async fn get_supply(&self) -> anyhow::Result<RpcSupply> {
+        canned(&self.supply, "getSupply")
+    }
+
+    async fn get_largest_accounts(&self) -> anyhow::Result<Vec<RpcAccountBalance>> {
+        canned(&self.largest_accounts, "getLargestAccounts")
+    }
+
+    async fn get_endpoint_slots(
+        &self,
+        _commitment: CommitmentConfig,
+    ) -> Vec<(String, anyhow::Result<Slot>)> {
+        self.endpoint_slots
+            .iter()
+            .map(|(url, slot)| (url.clone(), canned(slot, "getSlot")))
+            .collect()
+    }
+
+    async fn get_slot_with_commitment(
+        &self,
+        _commitment: CommitmentConfig,
+    ) -> anyhow::Result<Slot> {
+        canned(&self.slot, "getSlot")
+    }
+
+    async fn get_max_retransmit_slot(&self) -> anyhow::Result<Slot> {
+        canned(&self.max_retransmit_slot, "getMaxRetransmitSlot")
+    }
+
+    async fn get_max_shred_insert_slot(&self) -> anyhow::Result<Slot> {
+        canned(&self.max_shred_insert_slot, "getMaxShredInsertSlot")
+    }
+
+    async fn get_multiple_accounts(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> anyhow::Result<Vec<Option<Account>>> {
+        Ok(pubkeys
+            .iter()
+            .map(|p| self.accounts.get(p).cloned())
+            .collect())
+    }
+
+    async fn get_multiple_accounts_with_config(
+        &self,
+        pubkeys: &[Pubkey],
+        config: RpcAccountInfoConfig,
+    ) -> anyhow::Result<Vec<Option<Account>>> {
+        Ok(pubkeys
+            .iter()
+            .map(|p| {
+                self.accounts
+                    .get(p)
+                    .map(|account| sliced(account, config.data_slice))
+            })
+            .collect())
+    }
+
+    async fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> anyhow::Result<Vec<(Pubkey, Account)>> {
+        let data_slice = config.account_config.data_slice;
+        let filters = config.filters.unwrap_or_default();
+        let matches = |account: &Account| {
+            filters.iter().all(|filter| match filter {
+                RpcFilterType::DataSize(size) => account.data.len() as u64 == *size,
+                RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(&account.data),
+                RpcFilterType::TokenAccountState => false,
+            })
+        };
+        Ok(self
+            .accounts
+            .iter()
+            .filter(|(_, account)| account.owner == *program_id && matches(account))
+            .map(|(pubkey, account)| (*pubkey, sliced(account, data_slice)))
+            .collect())
+    }
+
+    async fn get_blocks(
+        &self,
+        start_slot: Slot,
+        end_slot: Option<Slot>,
+    ) -> anyhow::Result<Vec<Slot>> {
+        Ok(self
+            .blocks
+            .range(start_slot..=end_slot.unwrap_or(Slot::MAX))
+            .map(|(slot, _)| *slot)
+            .collect())
+    }
+
+    async fn get_blocks_with_commitment(
+        &self,
+        start_slot: Slot,
+        end_slot: Option<Slot>,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Vec<Slot>> {
+        let mut blocks = self.get_blocks(start_slot, end_slot).await?;
+        if !commitment.is_finalized() {
+            let range = start_slot..=end_slot.unwrap_or(Slot::MAX);
+            blocks.extend(self.confirmed_blocks.range(range));
+            blocks.sort_unstable();
+        }
+        Ok(blocks)
+    }
+
+    async fn get_blocks_with_limit(
+        &self,
+        start_slot: Slot,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Slot>> {
+        Ok(self
+            .blocks
+            .range(start_slot..)
+            .map(|(slot, _)| *slot)
+            .take(limit)
+            .collect())
+    }
+
+    async fn get_block_with_config(
+        &self,
+        slot: Slot,
+        _config: RpcBlockConfig,
+    ) -> anyhow::Result<UiConfirmedBlock> {
+        self.blocks
+            .get(&slot)
+            .cloned()
+            .ok_or_else(|| anyhow!("block {} is not mocked", slot))
+    }
+
+    async fn get_block_production_with_config(
+        &self,
+        _config: RpcBlockProductionConfig,
+    ) -> anyhow::Result<RpcBlockProduction> {
+        self.block_production
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow!("getBlockProduction is not mocked"))
+    }
+
+    async fn get_leader_schedule_with_config(
+        &self,
+        _slot: Option<Slot>,
+        _config: RpcLeaderScheduleConfig,
+    ) -> anyhow::Result<Option<RpcLeaderSchedule>> {
+        Ok(self.leader_schedule.clone())
+    }
+
+    async fn get_signatures_for_address_with_config(
+        &self,
+        address: &Pubkey,
+        config: RpcSignaturesForAddressConfig,
+    ) -> anyhow::Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        let signatures = self.signatures.get(address).into_iter().flatten();
+        let mut before = config.before.is_some();
+        Ok(signatures
+            .skip_while(|s| {
+                let skip = before;
+                before &= config.before.as_ref() != Some(&s.signature);
+                skip
+            })
+            .take_while(|s| config.until.as_ref() != Some(&s.signature))
+            .take(config.limit.unwrap_or(1_000))
+            .cloned()
+            .collect())
+    }
+
+    async fn get_transaction_with_config(
+        &self,
+        signature: &str,
+        _config: RpcTransactionConfig,
+    ) -> anyhow::Result<EncodedConfirmedTransactionWithStatusMeta> {
+        self.transactions
+            .get(signature)
+            .cloned()
+            .ok_or_else(|| anyhow!("transaction {} not found", signature))
+    }
+}
@@ -0,0 +1,466 @@
+//! The RPC calls the exporter makes, abstracted so monitors can be tested without a cluster.
+
+use crate::rpc_pool::RpcPool;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use solana_account::Account;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcBlockConfig, RpcBlockProductionConfig, RpcLargestAccountsConfig,
+    RpcLargestAccountsFilter, RpcLeaderScheduleConfig, RpcProgramAccountsConfig,
+    RpcSignaturesForAddressConfig, RpcTransactionConfig,
+};
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_response::{
+    RpcAccountBalance, RpcBlockProduction, RpcConfirmedTransactionStatusWithSignature,
+    RpcInflationRate, RpcLeaderSchedule, RpcSupply, RpcVoteAccountStatus,
+};
+use solana_clock::Slot;
+use solana_commitment_config::CommitmentConfig;
+use solana_epoch_info::EpochInfo;
+use solana_epoch_schedule::EpochSchedule;
+use solana_pubkey::Pubkey;
+use solana_transaction_status_client_types::{
+    EncodedConfirmedTransactionWithStatusMeta, UiConfirmedBlock,
+};
+
+pub mod instrumented;
+#[cfg(test)]
+pub mod mock;
+
+/// Solana RPC methods used by the monitors. See the RPC API documentation of the same-named
+/// methods for details.
+#[async_trait]
+pub trait RpcApi: Send + Sync {
+    async fn get_epoch_info(&self) -> anyhow::Result<EpochInfo>;
+
+    async fn get_epoch_schedule(&self) -> anyhow::Result<EpochSchedule>;
+
+    async fn get_vote_accounts(&self) -> anyhow::Result<RpcVoteAccountStatus>;
+
+    /// Returns the untyped `getClusterNodes` response, see
+    /// [`crate::rpc_extra::get_cluster_nodes_raw`].
+    async fn get_cluster_nodes_json(&self) -> anyhow::Result<Value>;
+
+    async fn get_balance(&self, pubkey: &Pubkey) -> anyhow::Result<u64>;
+
+    async fn get_inflation_rate(&self) -> anyhow::Result<RpcInflationRate>;
+
+    async fn get_supply(&self) -> anyhow::Result<RpcSupply>;
+
+    /// Returns the largest circulating accounts.
+    async fn get_largest_accounts(&self) -> anyhow::Result<Vec<RpcAccountBalance>>;
+
+    /// Returns the `getSlot` of every endpoint at `commitment`, by endpoint URL.
+    async fn get_endpoint_slots(
+        &self,
+        commitment: CommitmentConfig,
+    ) -> Vec<(String, anyhow::Result<Slot>)>;
+
+    async fn get_slot_with_commitment(&self, commitment: CommitmentConfig) -> anyhow::Result<Slot>;
+
+    async fn get_max_retransmit_slot(&self) -> anyhow::Result<Slot>;
+
+    async fn get_max_shred_insert_slot(&self) -> anyhow::Result<Slot>;
+
+    async fn get_multiple_accounts(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> anyhow::Result<Vec<Option<Account>>>;
+
+    /// Returns the accounts of `pubkeys` as requested by `config`, e.g. only a slice of their
+    /// data. The accounts must be requested in a binary encoding.
+    async fn get_multiple_accounts_with_config(
+        &self,
+        pubkeys: &[Pubkey],
+        config: RpcAccountInfoConfig,
+    ) -> anyhow::Result<Vec<Option<Account>>>;
+
+    /// Returns the accounts owned by `program_id` matching the filters of `config`. The accounts
+    /// must be requested in a binary encoding.
+    async fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> anyhow::Result<Vec<(Pubkey, Account)>>;
+
+    async fn get_blocks(
+        &self,
+        start_slot: Slot,
+        end_slot: Option<Slot>,
+    ) -> anyhow::Result<Vec<Slot>>;
+
+    async fn get_blocks_with_commitment(
+        &self,
+        start_slot: Slot,
+        end_slot: Option<Slot>,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Vec<Slot>>;
+
+    async fn get_blocks_with_limit(
+        &self,
+        start_slot: Slot,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Slot>>;
+
+    async fn get_block_with_config(
+        &self,
+        slot: Slot,
+        config: RpcBlockConfig,
+    ) -> anyhow::Result<UiConfirmedBlock>;
+
+    async fn get_block_production_with_config(
+        &self,
+        config: RpcBlockProductionConfig,
+    ) -> anyhow::Result<RpcBlockProduction>;
+
+    async fn get_leader_schedule_with_config(
+        &self,
+        slot: Option<Slot>,
+        config: RpcLeaderScheduleConfig,
+    ) -> anyhow::Result<Option<RpcLeaderSchedule>>;
+
+    /// Returns the signatures of the transactions involving `address`, newest first.
+    async fn get_signatures_for_address_with_config(
+        &self,
+        address: &Pubkey,
+        config: RpcSignaturesForAddressConfig,
+    ) -> anyhow::Result<Vec<RpcConfirmedTransactionStatusWithSignature>>;
+
+    /// Returns the transaction of the base-58 `signature`.
+    async fn get_transaction_with_config(
+        &self,
+        signature: &str,
+        config: RpcTransactionConfig,
+    ) -> anyhow::Result<EncodedConfirmedTransactionWithStatusMeta>;
+}
+
+#[async_trait]
+impl RpcApi for RpcClient {
+    async fn get_epoch_info(&self) -> anyhow::Result<EpochInfo> {
+        Ok(RpcClient::get_epoch_info(self).await?)
+    }
+
+    async fn get_epoch_schedule(&self) -> anyhow::Result<EpochSchedule> {
+        Ok(RpcClient::get_epoch_schedule(self).await?)
+    }
+
+    async fn get_vote_accounts(&self) -> anyhow::Result<RpcVoteAccountStatus> {
+        Ok(RpcClient::get_vote_accounts(self).await?)
+    }
+
+    async fn get_cluster_nodes_json(&self) -> anyhow::Result<Value> {
+        Ok(self.send(RpcRequest::GetClusterNodes, Value::Null).await?)
+    }
+
+    async fn get_balance(&self, pubkey: &Pubkey) -> anyhow::Result<u64> {
+        Ok(RpcClient::get_balance(self, pubkey).await?)
+    }
+
+    async fn get_inflation_rate(&self) -> anyhow::Result<RpcInflationRate> {
+        Ok(RpcClient::get_inflation_rate(self).await?)
+    }
+
+    async fn get_supply(&self) -> anyhow::Result<RpcSupply> {
+        Ok(RpcClient::supply(self).await?.value)
+    }
+
+    async fn get_largest_accounts(&self) -> anyhow::Result<Vec<RpcAccountBalance>> {
+        let config = RpcLargestAccountsConfig {
+            commitment: None,
+            filter: Some(RpcLargestAccountsFilter::Circulating),
+            sort_results: None,
+        };
+        Ok(RpcClient::get_largest_accounts_with_config(self, config)
+            .await?
+            .value)
+    }
+
+    async fn get_endpoint_slots(
+        &self,
+        commitment: CommitmentConfig,
+    ) -> Vec<(String, anyhow::Result<Slot>)> {
+        let slot = RpcClient::get_slot_with_commitment(self, commitment).await;
+        vec![(self.url(), slot.map_err(Into::into))]
+    }
+
+    async fn get_slot_with_commitment(&self, commitment: CommitmentConfig) -> anyhow::Result<Slot> {
+        Ok(RpcClient::get_slot_with_commitment(self, commitment).await?)
+    }
+
+    async fn get_max_retransmit_slot(&self) -> anyhow::Result<Slot> {
+        Ok(RpcClient::get_max_retransmit_slot(self).await?)
+    }
+
+    async fn get_max_shred_insert_slot(&self) -> anyhow::Result<Slot> {
+        Ok(RpcClient::get_max_shred_insert_slot(self).await?)
+    }
+
+    async fn get_multiple_accounts(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> anyhow::Result<Vec<Option<Account>>> {
+        Ok(RpcClient::get_multiple_accounts(self, pubkeys).await?)
+    }
+
+    async fn get_multiple_accounts_with_config(
+        &self,
+        pubkeys: &[Pubkey],
+        config: RpcAccountInfoConfig,
+    ) -> anyhow::Result<Vec<Option<Account>>> {
+        RpcClient::get_multiple_ui_accounts_with_config(self, pubkeys, config)
+            .await?
+            .value
+            .into_iter()
+            .zip(pubkeys)
+            .map(|(account, pubkey)| {
+                account
+                    .map(|account| {
+                        account.to_account().ok_or_else(|| {
+                            anyhow!("could not decode the data of account {}", pubkey)
+                        })
+                    })
+                    .transpose()
+            })
+            .collect()
+    }
+
+    async fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> anyhow::Result<Vec<(Pubkey, Account)>> {
+        RpcClient::get_program_ui_accounts_with_config(self, program_id, config)
+            .await?
+            .into_iter()
+            .map(|(pubkey, account)| {
+                let account = account
+                    .to_account()
+                    .ok_or_else(|| anyhow!("could not decode the data of account {}", pubkey))?;
+                Ok((pubkey, account))
+            })
+            .collect()
+    }
+
+    async fn get_blocks(
+        &self,
+        start_slot: Slot,
+        end_slot: Option<Slot>,
+    ) -> anyhow::Result<Vec<Slot>> {
+        Ok(RpcClient::get_blocks(self, start_slot, end_slot).await?)
+    }
+
+    async fn get_blocks_with_commitment(
+        &self,
+        start_slot: Slot,
+        end_slot: Option<Slot>,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Vec<Slot>> {
+        Ok(RpcClient::get_blocks_with_commitment(self, start_slot, end_slot, commitment).await?)
+    }
+
+    async fn get_blocks_with_limit(
+        &self,
+        start_slot: Slot,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Slot>> {
+        Ok(RpcClient::get_blocks_with_limit(self, start_slot, limit).await?)
+    }
+
+    async fn get_block_with_config(
+        &self,
+        slot: Slot,
+        config: RpcBlockConfig,
+    ) -> anyhow::Result<UiConfirmedBlock> {
+        Ok(RpcClient::get_block_with_config(self, slot, config).await?)
+    }
+
+    async fn get_block_production_with_config(
+        &self,
+        config: RpcBlockProductionConfig,
+    ) -> anyhow::Result<RpcBlockProduction> {
+        Ok(RpcClient::get_block_production_with_config(self, config)
+            .await?
+            .value)
+    }
+
+    async fn get_leader_schedule_with_config(
+        &self,
+        slot: Option<Slot>,
+        config: RpcLeaderScheduleConfig,
+    ) -> anyhow::Result<Option<RpcLeaderSchedule>> {
+        Ok(RpcClient::get_leader_schedule_with_config(self, slot, config).await?)
+    }
+
+    async fn get_signatures_for_address_with_config(
+        &self,
+        address: &Pubkey,
+        config: RpcSignaturesForAddressConfig,
+    ) -> anyhow::Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        let params = json!([address.to_string(), config]);
+        Ok(self
+            .send(RpcRequest::GetSignaturesForAddress, params)
+            .await?)
+    }
+
+    async fn get_transaction_with_config(
+        &self,
+        signature: &str,
+        config: RpcTransactionConfig,
+    ) -> anyhow::Result<EncodedConfirmedTransactionWithStatusMeta> {
+        Ok(self
+            .send(RpcRequest::GetTransaction, json!([signature, config]))
+            .await?)
+    }
+}
+
+/// Heavy calls are distributed across the pool, all others go to the primary endpoint.
+#[async_trait]
+impl RpcApi for RpcPool {
+    async fn get_epoch_info(&self) -> anyhow::Result<EpochInfo> {
+        RpcApi::get_epoch_info(self.primary()).await
+    }
+
+    async fn get_epoch_schedule(&self) -> anyhow::Result<EpochSchedule> {
+        RpcApi::get_epoch_schedule(self.primary()).await
+    }
+
+    async fn get_vote_accounts(&self) -> anyhow::Result<RpcVoteAccountStatus> {
+        RpcApi::get_vote_accounts(self.primary()).await
+    }
+
+    async fn get_cluster_nodes_json(&self) -> anyhow::Result<Value> {
+        self.primary().get_cluster_nodes_json().await
+    }
+
+    async fn get_balance(&self, pubkey: &Pubkey) -> anyhow::Result<u64> {
+        RpcApi::get_balance(self.primary(), pubkey).await
+    }
+
+    async fn get_inflation_rate(&self) -> anyhow::Result<RpcInflationRate> {
+        RpcApi::get_inflation_rate(self.primary()).await
+    }
+
+    async fn get_supply(&self) -> anyhow::Result<RpcSupply> {
+        RpcApi::get_supply(self.primary()).await
+    }
+
+    async fn get_largest_accounts(&self) -> anyhow::Result<Vec<RpcAccountBalance>> {
+        RpcApi::get_largest_accounts(self.next()).await
+    }
+
+    async fn get_endpoint_slots(
+        &self,
+        commitment: CommitmentConfig,
+    ) -> Vec<(String, anyhow::Result<Slot>)> {
+        futures::future::join_all(
+            self.clients()
+                .iter()
+                .map(|client| client.get_endpoint_slots(commitment)),
+        )
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    async fn get_slot_with_commitment(&self, commitment: CommitmentConfig) -> anyhow::Result<Slot> {
+        RpcApi::get_slot_with_commitment(self.primary(), commitment).await
+    }
+
+    async fn get_max_retransmit_slot(&self) -> anyhow::Result<Slot> {
+        RpcApi::get_max_retransmit_slot(self.primary()).await
+    }
+
+    async fn get_max_shred_insert_slot(&self) -> anyhow::Result<Slot> {
+        RpcApi::get_max_shred_insert_slot(self.primary()).await
+    }
+
+    async fn get_multiple_accounts(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> anyhow::Result<Vec<Option<Account>>> {
+        RpcApi::get_multiple_accounts(self.next(), pubkeys).await
+    }
+
+    async fn get_multiple_accounts_with_config(
+        &self,
+        pubkeys: &[Pubkey],
+        config: RpcAccountInfoConfig,
+    ) -> anyhow::Result<Vec<Option<Account>>> {
+        RpcApi::get_multiple_accounts_with_config(self.next(), pubkeys, config).await
+    }
+
+    async fn get_program_accounts_with_config(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+    ) -> anyhow::Result<Vec<(Pubkey, Account)>> {
+        RpcApi::get_program_accounts_with_config(self.next(), program_id, config).await
+    }
+
+    async fn get_blocks(
+        &self,
+        start_slot: Slot,
+        end_slot: Option<Slot>,
+    ) -> anyhow::Result<Vec<Slot>> {
+        RpcApi::get_blocks(self.primary(), start_slot, end_slot).await
+    }
+
+    async fn get_blocks_with_commitment(
+        &self,
+        start_slot: Slot,
+        end_slot: Option<Slot>,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Vec<Slot>> {
+        RpcApi::get_blocks_with_commitment(self.primary(), start_slot, end_slot, commitment).await
+    }
+
+    async fn get_blocks_with_limit(
+        &self,
+        start_slot: Slot,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Slot>> {
+        RpcApi::get_blocks_with_limit(self.primary(), start_slot, limit).await
+    }
+
+    async fn get_block_with_config(
+        &self,
+        slot: Slot,
+        config: RpcBlockConfig,
+    ) -> anyhow::Result<UiConfirmedBlock> {
+        RpcApi::get_block_with_config(self.next(), slot, config).await
+    }
+
+    async fn get_block_production_with_config(
+        &self,
+        config: RpcBlockProductionConfig,
+    ) -> anyhow::Result<RpcBlockProduction> {
+        RpcApi::get_block_production_with_config(self.primary(), config).await
+    }
+
+    async fn get_leader_schedule_with_config(
+        &self,
+        slot: Option<Slot>,
+        config: RpcLeaderScheduleConfig,
+    ) -> anyhow::Result<Option<RpcLeaderSchedule>> {
+        RpcApi::get_leader_schedule_with_config(self.primary(), slot, config).await
+    }
+
+    async fn get_signatures_for_address_with_config(
+        &self,
+        address: &Pubkey,
+        config: RpcSignaturesForAddressConfig,
+    ) -> anyhow::Result<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        RpcApi::get_signatures_for_address_with_config(self.next(), address, config).await
+    }
+
+    async fn get_transaction_with_config(
+        &self,
+        signature: &str,
+        config: RpcTransactionConfig,
+    ) -> anyhow::Result<EncodedConfirmedTransactionWithStatusMeta> {
+        RpcApi::get_transaction_with_config(self.next(), signature, config).await
+    }
+}
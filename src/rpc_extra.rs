@@ -1,11 +1,100 @@
 use crate::config::Whitelist;
-use anyhow::Context;
+use crate::rpc_api::RpcApi;
+use crate::slots::caching::LeaderScheduleCache;
+use anyhow::{anyhow, Context};
+use log::warn;
 use serde::Deserialize;
 use serde_json::Value;
-use solana_client::{
-    nonblocking::rpc_client::RpcClient, rpc_request::RpcRequest, rpc_response::RpcVoteAccountStatus,
-};
-use solana_clock::Epoch;
+use solana_client::rpc_config::{RpcBlockConfig, RpcLeaderScheduleConfig};
+use solana_client::rpc_response::RpcVoteAccountStatus;
+use solana_clock::{Epoch, Slot};
+use solana_epoch_info::EpochInfo;
+use solana_transaction_status_client_types::{TransactionDetails, UiTransactionEncoding};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+/// Returns the absolute leader slots of the whitelisted identities in the epoch of `epoch_info`.
+/// The leader slots of each identity are only fetched if they are not in `cache` yet.
+pub async fn leader_slots(
+    rpc: &dyn RpcApi,
+    cache: &LeaderScheduleCache,
+    epoch_info: &EpochInfo,
+    node_whitelist: &Whitelist,
+) -> anyhow::Result<BTreeMap<Slot, String>> {
+    let first_slot = epoch_info.absolute_slot - epoch_info.slot_index;
+    let mut schedule = BTreeMap::new();
+    for identity in &node_whitelist.0 {
+        let slot_indices = match cache.get_leader_slots(epoch_info.epoch, identity)? {
+            Some(slot_indices) => slot_indices,
+            None => {
+                let slot_indices = rpc
+                    .get_leader_schedule_with_config(
+                        Some(first_slot),
+                        RpcLeaderScheduleConfig {
+                            identity: Some(identity.clone()),
+                            commitment: None,
+                        },
+                    )
+                    .await?
+                    .and_then(|mut schedule| schedule.remove(identity))
+                    .unwrap_or_default();
+                cache.add_leader_slots(epoch_info.epoch, identity, &slot_indices)?;
+                slot_indices
+            }
+        };
+        for index in slot_indices {
+            schedule.insert(first_slot + index as Slot, identity.clone());
+        }
+    }
+    Ok(schedule)
+}
+
+/// Returns the average slot time in seconds of the epoch of `epoch_info` so far, measured from
+/// the time of its first block, if it has one.
+pub async fn average_slot_time(
+    rpc: &dyn RpcApi,
+    epoch_info: &EpochInfo,
+) -> anyhow::Result<Option<f64>> {
+    Ok(epoch_start_time(rpc, epoch_info.epoch)
+        .await?
+        .map(|start_time| average_slot_time_since(start_time, epoch_info)))
+}
+
+/// Returns the average slot time in seconds of the epoch of `epoch_info` so far, given the Unix
+/// time it started at.
+pub fn average_slot_time_since(start_time: i64, epoch_info: &EpochInfo) -> f64 {
+    (OffsetDateTime::now_utc().unix_timestamp() - start_time) as f64 / epoch_info.slot_index as f64
+}
+
+/// Returns the Unix time of the first block of `epoch`, if it has one.
+pub async fn epoch_start_time(rpc: &dyn RpcApi, epoch: Epoch) -> anyhow::Result<Option<i64>> {
+    let block = match first_block_in_epoch(rpc, epoch).await? {
+        Some(block) => block,
+        None => return Ok(None),
+    };
+    let block_time = rpc
+        .get_block_with_config(
+            block,
+            RpcBlockConfig {
+                encoding: Some(UiTransactionEncoding::Base64),
+                transaction_details: Some(TransactionDetails::None),
+                rewards: Some(false),
+                commitment: None,
+                max_supported_transaction_version: Some(0),
+            },
+        )
+        .await?
+        .block_time
+        .ok_or_else(|| anyhow!("block {} has no time", block))?;
+    Ok(Some(block_time))
+}
+
+/// Maximum number of accounts `getMultipleAccounts` accepts per call.
+pub const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Lamports in a SOL.
+pub const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
 
 /// A cluster node as returned by `getClusterNodes`, preserving the gossip-table
 /// address fields that the typed `RpcContactInfo` (solana-client 4.0.0) drops —
@@ -51,9 +140,9 @@ pub fn parse_gossip_nodes(raw: &Value) -> Vec<GossipNode> {
 /// string form before returning the raw `Value`, so callers can deserialize it
 /// into multiple typed views (e.g. `RpcContactInfo` and [`GossipNode`]) without
 /// re-issuing the RPC call.
-pub async fn get_cluster_nodes_raw(client: &RpcClient) -> anyhow::Result<Value> {
-    let mut raw: Value = client
-        .send(RpcRequest::GetClusterNodes, Value::Null)
+pub async fn get_cluster_nodes_raw(rpc: &dyn RpcApi) -> anyhow::Result<Value> {
+    let mut raw: Value = rpc
+        .get_cluster_nodes_json()
         .await
         .context("getClusterNodes RPC call failed")?;
 
@@ -70,24 +159,74 @@ pub async fn get_cluster_nodes_raw(client: &RpcClient) -> anyhow::Result<Value>
     Ok(raw)
 }
 
+/// The raw `getClusterNodes` response, refetched once it is older than a time to live. The node
+/// list of a large cluster is big but changes slowly, so it need not be fetched every cycle, and
+/// the last response stands in for it while it cannot be fetched.
+pub struct ClusterNodesCache {
+    ttl: Duration,
+    /// The last response and when it was fetched.
+    nodes: Option<(Instant, Value)>,
+}
+
+impl ClusterNodesCache {
+    /// Makes an empty cache keeping responses for `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, nodes: None }
+    }
+
+    /// Returns the cached nodes, fetching them from `rpc` with [`get_cluster_nodes_raw`] if the
+    /// cache is empty or expired. If the fetch fails, expired nodes are returned, and fetched
+    /// again on the next call.
+    pub async fn get(&mut self, rpc: &dyn RpcApi) -> anyhow::Result<Value> {
+        let now = Instant::now();
+        if let Some((fetched, nodes)) = &self.nodes {
+            if now.duration_since(*fetched) < self.ttl {
+                return Ok(nodes.clone());
+            }
+        }
+        match get_cluster_nodes_raw(rpc).await {
+            Ok(nodes) => {
+                self.nodes = Some((now, nodes.clone()));
+                Ok(nodes)
+            }
+            Err(e) => match &self.nodes {
+                Some((fetched, nodes)) => {
+                    warn!(
+                        "Could not fetch the cluster nodes, reusing those of {:?} ago: {e:#}",
+                        now.duration_since(*fetched)
+                    );
+                    Ok(nodes.clone())
+                }
+                None => Err(e),
+            },
+        }
+    }
+}
+
 /// Returns the slot of the first confirmed block in `epoch`, if any.
-pub async fn first_block_in_epoch(client: &RpcClient, epoch: Epoch) -> anyhow::Result<Option<u64>> {
-    let epoch_schedule = client.get_epoch_schedule().await?;
+///
+/// `getBlocksWithLimit` finds the next block however many slots were skipped at the start of the
+/// epoch, e.g. after a cluster restart. A block found past the end of the epoch belongs to a later
+/// one, so the epoch has no block.
+pub async fn first_block_in_epoch(rpc: &dyn RpcApi, epoch: Epoch) -> anyhow::Result<Option<u64>> {
+    let epoch_schedule = rpc.get_epoch_schedule().await?;
     let first_slot = epoch_schedule.get_first_slot_in_epoch(epoch);
+    let last_slot = epoch_schedule.get_last_slot_in_epoch(epoch);
 
-    Ok(client
+    Ok(rpc
         .get_blocks_with_limit(first_slot, 1)
         .await?
         .first()
-        .cloned())
+        .cloned()
+        .filter(|&slot| slot <= last_slot))
 }
 
 /// Maps vote pubkeys to node pubkeys based on the information provided in `vote_accounts`.
 pub fn node_pubkeys(vote_pubkeys: &Whitelist, vote_accounts: &RpcVoteAccountStatus) -> Whitelist {
-    if vote_pubkeys.0.is_empty() {
+    if vote_pubkeys.is_all() {
         Whitelist::default()
     } else {
-        Whitelist(
+        Whitelist::only(
             vote_accounts
                 .current
                 .iter()
@@ -98,3 +237,65 @@ pub fn node_pubkeys(vote_pubkeys: &Whitelist, vote_accounts: &RpcVoteAccountStat
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_api::mock::MockRpc;
+    use solana_epoch_schedule::EpochSchedule;
+
+    #[tokio::test]
+    async fn reuses_cluster_nodes_until_expired() {
+        let rpc = |nodes: Option<Value>| MockRpc {
+            cluster_nodes: nodes,
+            ..MockRpc::default()
+        };
+        let first = serde_json::json!([{ "pubkey": "a" }]);
+        let second = serde_json::json!([{ "pubkey": "b" }]);
+
+        let mut cache = ClusterNodesCache::new(Duration::from_secs(3600));
+        assert_eq!(cache.get(&rpc(Some(first.clone()))).await.unwrap(), first);
+        assert_eq!(cache.get(&rpc(None)).await.unwrap(), first);
+
+        let mut cache = ClusterNodesCache::new(Duration::ZERO);
+        assert!(cache.get(&rpc(None)).await.is_err());
+        assert_eq!(cache.get(&rpc(Some(first.clone()))).await.unwrap(), first);
+        assert_eq!(cache.get(&rpc(Some(second.clone()))).await.unwrap(), second);
+        // Expired nodes stand in while they cannot be fetched.
+        assert_eq!(cache.get(&rpc(None)).await.unwrap(), second);
+    }
+
+    #[tokio::test]
+    async fn finds_first_block_after_skipped_slots() {
+        let mut rpc = MockRpc {
+            epoch_schedule: Some(EpochSchedule::without_warmup()),
+            ..MockRpc::default()
+        };
+        let slots_per_epoch = EpochSchedule::without_warmup().slots_per_epoch;
+        rpc.add_block(slots_per_epoch + 500, None, vec![]);
+
+        assert_eq!(
+            first_block_in_epoch(&rpc, 1).await.unwrap(),
+            Some(slots_per_epoch + 500)
+        );
+        assert_eq!(first_block_in_epoch(&rpc, 0).await.unwrap(), None);
+        assert_eq!(first_block_in_epoch(&rpc, 2).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn dates_epoch_start_from_its_first_block() {
+        let mut rpc = MockRpc {
+            epoch_schedule: Some(EpochSchedule::without_warmup()),
+            ..MockRpc::default()
+        };
+        let slots_per_epoch = EpochSchedule::without_warmup().slots_per_epoch;
+        rpc.add_block(slots_per_epoch + 2, Some(1_700_000_000), vec![]);
+        rpc.add_block(slots_per_epoch + 3, Some(1_700_000_001), vec![]);
+
+        assert_eq!(
+            epoch_start_time(&rpc, 1).await.unwrap(),
+            Some(1_700_000_000)
+        );
+        assert_eq!(epoch_start_time(&rpc, 2).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,114 @@
+//! Distributing heavy RPC calls across several endpoints.
+
+use crate::http;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::RpcClientConfig;
+use solana_commitment_config::CommitmentConfig;
+use solana_rpc_client::http_sender::HttpSender;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// A set of RPC clients with weights.
+///
+/// The primary client serves the cheap calls that every cycle depends on (epoch info, cluster
+/// nodes, vote accounts). Heavy calls such as `getBlock` and `getMultipleAccounts` are spread
+/// across all clients with a non-zero weight in smooth weighted round-robin order, so e.g. rewards
+/// backfilling is not limited by the rate limit of a single endpoint.
+pub struct RpcPool {
+    clients: Vec<RpcClient>,
+    /// Client indices in the order heavy calls are assigned to them, repeated cyclically.
+    schedule: Vec<usize>,
+    next: AtomicUsize,
+}
+
+impl RpcPool {
+    /// Makes a pool of `(url, weight)` endpoints, the first of which is the primary one. The
+    /// primary endpoint is only used for heavy calls if its weight is non-zero; if all weights
+    /// are zero, every call goes to the primary endpoint. Requests are sent through `proxy` if
+    /// set, see [`crate::http::client_builder`].
+    pub fn new(
+        endpoints: &[(String, u32)],
+        timeout: Duration,
+        proxy: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        assert!(
+            !endpoints.is_empty(),
+            "RPC pool needs at least one endpoint"
+        );
+        let clients = endpoints
+            .iter()
+            .map(|(url, _)| {
+                let http_client = http::client_builder(proxy)?
+                    .default_headers(HttpSender::default_headers())
+                    .timeout(timeout)
+                    .pool_idle_timeout(timeout)
+                    .build()?;
+                anyhow::Ok(RpcClient::new_sender(
+                    HttpSender::new_with_client(url, http_client),
+                    RpcClientConfig::with_commitment(CommitmentConfig::default()),
+                ))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let weights: Vec<_> = endpoints.iter().map(|(_, weight)| *weight).collect();
+        let mut schedule = weighted_schedule(&weights);
+        if schedule.is_empty() {
+            schedule.push(0);
+        }
+
+        Ok(Self {
+            clients,
+            schedule,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Returns the primary client.
+    pub fn primary(&self) -> &RpcClient {
+        &self.clients[0]
+    }
+
+    /// Returns the clients of all endpoints, the primary one first.
+    pub fn clients(&self) -> &[RpcClient] {
+        &self.clients
+    }
+
+    /// Returns the client the next heavy call should go to.
+    pub fn next(&self) -> &RpcClient {
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.schedule.len();
+        &self.clients[self.schedule[i]]
+    }
+}
+
+/// Returns one round of smooth weighted round-robin over `weights`: every index appears as many
+/// times as its weight, interleaved as evenly as possible.
+fn weighted_schedule(weights: &[u32]) -> Vec<usize> {
+    let total: i64 = weights.iter().map(|w| *w as i64).sum();
+    let mut current = vec![0i64; weights.len()];
+    (0..total)
+        .map(|_| {
+            for (c, w) in current.iter_mut().zip(weights) {
+                *c += *w as i64;
+            }
+            let (best, _) = current
+                .iter()
+                .enumerate()
+                .max_by_key(|(i, c)| (**c, std::cmp::Reverse(*i)))
+                .unwrap();
+            current[best] -= total;
+            best
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::weighted_schedule;
+
+    #[test]
+    fn interleaves_by_weight() {
+        assert_eq!(weighted_schedule(&[1, 1]), vec![0, 1]);
+        assert_eq!(weighted_schedule(&[5, 1, 1]), vec![0, 0, 1, 0, 2, 0, 0]);
+        assert_eq!(weighted_schedule(&[0, 2]), vec![1, 1]);
+        assert!(weighted_schedule(&[0, 0]).is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+//! Timing of the update cycles, so that a fleet of exporters does not hit a shared RPC endpoint
+//! at the same instant.
+
+use crate::config::SchedulingConfig;
+use anyhow::anyhow;
+use rand::Rng;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// When the update cycles start: an interval after the previous one, then at the next multiple
+/// of the alignment, if any, then after a random jitter.
+pub struct CycleSchedule {
+    /// Minimum time between the end of a cycle and the start of the next.
+    interval: Duration,
+    /// Period of wall-clock time the cycles start at multiples of.
+    align: Option<Duration>,
+    /// Largest random delay added to the start of every cycle.
+    jitter: Duration,
+}
+
+impl CycleSchedule {
+    /// Makes the schedule of `config`, by default a cycle every second.
+    pub fn new(config: Option<&SchedulingConfig>) -> anyhow::Result<Self> {
+        let config = config.cloned().unwrap_or_default();
+        let jitter = config.jitter.unwrap_or(0.0);
+        if !jitter.is_finite() || jitter < 0.0 {
+            return Err(anyhow!("invalid [scheduling] jitter {}", jitter));
+        }
+        Ok(Self {
+            interval: Duration::from_secs(config.interval.unwrap_or(1)),
+            align: config
+                .align
+                .filter(|&align| align > 0)
+                .map(Duration::from_secs),
+            jitter: Duration::from_secs_f64(jitter),
+        })
+    }
+
+    /// Returns the time to wait from now until the next cycle starts.
+    pub fn next_delay(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let jitter = self.jitter.mul_f64(rand::thread_rng().gen_range(0.0..=1.0));
+        self.delay(now, jitter)
+    }
+
+    /// Returns the time to wait from `now`, since the Unix epoch, until the next cycle starts
+    /// with a delay of `jitter`.
+    fn delay(&self, now: Duration, jitter: Duration) -> Duration {
+        let mut start = now + self.interval;
+        if let Some(align) = self.align {
+            let periods = start.as_nanos().div_ceil(align.as_nanos());
+            start = Duration::from_nanos((periods * align.as_nanos()) as u64);
+        }
+        start - now + jitter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_cycles_and_adds_jitter() {
+        let schedule = |interval, align, jitter| {
+            CycleSchedule::new(Some(&SchedulingConfig {
+                interval: Some(interval),
+                align,
+                jitter,
+            }))
+            .unwrap()
+        };
+        let now = Duration::from_millis(1_759_999_998_500);
+        let no_jitter = Duration::ZERO;
+
+        assert_eq!(
+            CycleSchedule::new(None).unwrap().delay(now, no_jitter),
+            Duration::from_secs(1)
+        );
+        // Aligned to :00/:15/:30/:45, 11.5 seconds after :03.5.
+        assert_eq!(
+            schedule(1, Some(15), None).delay(now, no_jitter),
+            Duration::from_millis(11_500)
+        );
+        // At least the interval, even if it ends just past an aligned start.
+        assert_eq!(
+            schedule(12, Some(15), None).delay(now, no_jitter),
+            Duration::from_millis(26_500)
+        );
+        assert_eq!(
+            schedule(1, Some(15), Some(5.0)).delay(now, Duration::from_secs(2)),
+            Duration::from_millis(13_500)
+        );
+        assert!(CycleSchedule::new(Some(&SchedulingConfig {
+            jitter: Some(-1.0),
+            ..SchedulingConfig::default()
+        }))
+        .is_err());
+    }
+}
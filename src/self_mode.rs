@@ -0,0 +1,113 @@
+//! The single-validator preset: a validator identity stands in for the vote account whitelist.
+
+use crate::config::whitelist::WhitelistSource;
+use crate::config::{ExporterConfig, Mode, Whitelist};
+use crate::rpc_api::RpcApi;
+use anyhow::{anyhow, Context};
+use log::info;
+use solana_client::rpc_response::RpcVoteAccountStatus;
+use solana_pubkey::Pubkey;
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+
+/// Returns the vote account whitelist source of `config`. In self mode, that is the vote account
+/// of the configured identity, looked up with `rpc`.
+pub async fn vote_account_whitelist(
+    config: &ExporterConfig,
+    config_dir: &Path,
+    rpc: &dyn RpcApi,
+) -> anyhow::Result<Option<WhitelistSource>> {
+    if config.mode.unwrap_or_default() != Mode::SelfValidator {
+        return Ok(config.vote_account_whitelist.clone());
+    }
+    if config.vote_account_whitelist.is_some() {
+        return Err(anyhow!(
+            "vote_account_whitelist cannot be set in self mode, the vote account is derived from the identity"
+        ));
+    }
+    let identity = match (&config.identity, &config.identity_keypair) {
+        (Some(identity), None) => identity
+            .parse::<Pubkey>()
+            .with_context(|| format!("invalid identity {}", identity))?,
+        (None, Some(keypair)) => {
+            let keypair = config_dir.join(keypair);
+            let contents = fs::read_to_string(&keypair)
+                .with_context(|| format!("could not read keypair {}", keypair.display()))?;
+            keypair_pubkey(&contents)
+                .with_context(|| format!("invalid keypair {}", keypair.display()))?
+        }
+        _ => {
+            return Err(anyhow!(
+                "self mode needs exactly one of identity and identity_keypair"
+            ))
+        }
+    };
+
+    let vote_account = vote_account_of(&identity.to_string(), &rpc.get_vote_accounts().await?)
+        .ok_or_else(|| anyhow!("no vote account found for identity {}", identity))?;
+    info!(
+        "Self mode: exporting vote account {} of identity {}",
+        vote_account, identity
+    );
+    Ok(Some(WhitelistSource::Inline(Whitelist::new(
+        std::iter::once(vote_account).collect(),
+    ))))
+}
+
+/// Reads the public key of a keypair file as written by `solana-keygen`: a JSON array of the 32
+/// secret key bytes followed by the 32 public key bytes.
+fn keypair_pubkey(contents: &str) -> anyhow::Result<Pubkey> {
+    let bytes: Vec<u8> = serde_json::from_str(contents)?;
+    if bytes.len() != 64 {
+        return Err(anyhow!("expected 64 bytes, found {}", bytes.len()));
+    }
+    let pubkey: [u8; 32] = bytes[32..].try_into()?;
+    Ok(Pubkey::from(pubkey))
+}
+
+/// Returns the vote account voting for `identity`, preferring a current one over a delinquent
+/// one.
+fn vote_account_of(identity: &str, vote_accounts: &RpcVoteAccountStatus) -> Option<String> {
+    vote_accounts
+        .current
+        .iter()
+        .chain(&vote_accounts.delinquent)
+        .find(|v| v.node_pubkey == identity)
+        .map(|v| v.vote_pubkey.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_client::rpc_response::RpcVoteAccountInfo;
+
+    #[test]
+    fn derives_vote_account_from_keypair() {
+        let mut bytes = vec![7u8; 32];
+        bytes.extend([1u8; 32]);
+        let identity = keypair_pubkey(&serde_json::to_string(&bytes).unwrap()).unwrap();
+        assert_eq!(identity, Pubkey::from([1u8; 32]));
+        assert!(keypair_pubkey("[1, 2, 3]").is_err());
+
+        let vote_account = |voter: &str, identity: &str| RpcVoteAccountInfo {
+            vote_pubkey: voter.to_string(),
+            node_pubkey: identity.to_string(),
+            activated_stake: 0,
+            commission: 0,
+            epoch_vote_account: true,
+            epoch_credits: vec![],
+            last_vote: 0,
+            root_slot: 0,
+        };
+        let vote_accounts = RpcVoteAccountStatus {
+            current: vec![vote_account("a", "other")],
+            delinquent: vec![vote_account("b", &identity.to_string())],
+        };
+        assert_eq!(
+            vote_account_of(&identity.to_string(), &vote_accounts),
+            Some("b".to_string())
+        );
+        assert_eq!(vote_account_of("unknown", &vote_accounts), None);
+    }
+}
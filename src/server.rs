@@ -0,0 +1,840 @@
+//! HTTP server exposing the metrics and debugging endpoints.
+
+use crate::gauges::{NAME_LABEL, OPTIONAL_LABELS, PER_NODE_LABELS, PUBKEY_LABEL};
+use crate::persistent_database::PersistentDatabase;
+use crate::rewards::caching::RewardsCache;
+use anyhow::anyhow;
+use log::{error, info};
+use prometheus_exporter::prometheus::{
+    gather,
+    proto::{LabelPair, Metric, MetricFamily, MetricType},
+    register_histogram, register_int_counter, register_int_gauge, Encoder, Histogram, IntCounter,
+    IntGauge, TextEncoder,
+};
+use serde::Serialize;
+use solana_clock::Epoch;
+use solana_pubkey::Pubkey;
+use solana_reward_info::RewardType;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tiny_http::{Header, Request, Response, Server};
+
+/// Path the Prometheus metrics are served at unless configured otherwise.
+pub const DEFAULT_METRICS_PATH: &str = "/metrics";
+
+/// Handles a request given its query string, returning the content type and body of the
+/// response.
+pub type Handler = Box<dyn Fn(&str) -> anyhow::Result<(&'static str, Vec<u8>)> + Send + Sync>;
+
+/// An error caused by the request rather than the server, answered with a 400 status.
+#[derive(Debug)]
+pub struct BadRequest(pub String);
+
+impl fmt::Display for BadRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for BadRequest {}
+
+/// Metrics of the server itself, named as by the `prometheus_exporter` crate it replaces.
+struct ServerMetrics {
+    requests: IntCounter,
+    response_size: IntGauge,
+    request_duration: Histogram,
+}
+
+/// The metrics served until the next update cycle is published.
+type Published = Arc<Mutex<Option<Arc<Vec<MetricFamily>>>>>;
+
+/// The metric families of every monitor by collector name, which scrapes can select with
+/// `collect[]` query parameters.
+type Collectors = Arc<Mutex<HashMap<String, HashSet<String>>>>;
+
+/// Which of the registered series and labels are served.
+#[derive(Debug, Default)]
+pub struct MetricFilter {
+    /// Whether series with any of the [`PER_NODE_LABELS`] are left out.
+    aggregates_only: bool,
+    /// The [`OPTIONAL_LABELS`] kept by metric family. The other optional labels of the listed
+    /// families are removed.
+    metric_labels: HashMap<String, HashSet<String>>,
+    /// Display names of whitelisted pubkeys by pubkey, added as a `name` label to their series.
+    names: HashMap<String, String>,
+    /// Vote accounts by node identity as of the last update cycle, so that the series of the
+    /// identity of a named vote account are labelled with its name too.
+    identities: Mutex<HashMap<String, String>>,
+}
+
+impl MetricFilter {
+    /// Constructs a filter, failing if `metric_labels` selects labels that are not optional.
+    /// Series of the pubkeys in `names` are labelled with their display names.
+    pub fn new(
+        aggregates_only: bool,
+        metric_labels: BTreeMap<String, Vec<String>>,
+        names: HashMap<String, String>,
+    ) -> anyhow::Result<Self> {
+        for (family, labels) in &metric_labels {
+            if let Some(label) = labels
+                .iter()
+                .find(|label| !OPTIONAL_LABELS.contains(&label.as_str()))
+            {
+                return Err(anyhow!(
+                    "label {:?} of {} is not optional, expected one of {:?}",
+                    label,
+                    family,
+                    OPTIONAL_LABELS
+                ));
+            }
+        }
+        Ok(Self {
+            aggregates_only,
+            metric_labels: metric_labels
+                .into_iter()
+                .map(|(family, labels)| (family, labels.into_iter().collect()))
+                .collect(),
+            names,
+            identities: Mutex::default(),
+        })
+    }
+
+    /// Returns all registered metrics, filtered.
+    fn gather(&self) -> Vec<MetricFamily> {
+        let mut families = gather();
+        if !self.names.is_empty() {
+            let identities = self
+                .identities
+                .lock()
+                .expect("poisoned mutex, should never happen");
+            families = with_names(families, &self.names, &identities);
+        }
+        if self.aggregates_only {
+            families = without_per_node_series(families);
+        }
+        if !self.metric_labels.is_empty() {
+            families = with_selected_labels(families, &self.metric_labels);
+        }
+        families
+    }
+}
+
+/// A running HTTP server serving the metrics and extra routes.
+///
+/// The metrics are served as published at the end of the last update cycle, so a scrape neither
+/// waits for a cycle nor sees one half done. Until the first publication, they are gathered on
+/// every request.
+///
+/// A scrape can select the metrics of some monitors only, like with the node exporter, e.g. with
+/// `?collect[]=rewards&collect[]=skipped_slots`.
+pub struct Exporter {
+    /// The last published metrics.
+    published: Published,
+    /// The series and labels served.
+    filter: Arc<MetricFilter>,
+    /// The metric families selectable by collector.
+    collectors: Collectors,
+}
+
+impl Exporter {
+    /// Starts serving the Prometheus metrics at `metrics_path` and `routes` by path on each of
+    /// `bindings`. Other paths are not found. The metrics are served as filtered by `filter`.
+    pub fn start(
+        bindings: &[SocketAddr],
+        metrics_path: String,
+        routes: HashMap<&'static str, Handler>,
+        filter: MetricFilter,
+    ) -> anyhow::Result<Self> {
+        if !metrics_path.starts_with('/') {
+            return Err(anyhow!(
+                "metrics path {:?} does not start with a slash",
+                metrics_path
+            ));
+        }
+        let servers = bindings
+            .iter()
+            .map(|&binding| {
+                Server::http(binding)
+                    .map(|server| (binding, server))
+                    .map_err(|e| anyhow!("could not start HTTP server on {}: {}", binding, e))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let metrics = Arc::new(ServerMetrics {
+            requests: register_int_counter!(
+                "prometheus_exporter_requests_total",
+                "Number of HTTP requests received."
+            )?,
+            response_size: register_int_gauge!(
+                "prometheus_exporter_response_size_bytes",
+                "The HTTP response sizes in bytes."
+            )?,
+            request_duration: register_histogram!(
+                "prometheus_exporter_request_duration_seconds",
+                "The HTTP request latencies in seconds."
+            )?,
+        });
+        let published: Published = Arc::new(Mutex::new(None));
+        let metrics_path = Arc::new(metrics_path);
+        let routes = Arc::new(routes);
+        let filter = Arc::new(filter);
+        let collectors: Collectors = Arc::new(Mutex::new(HashMap::new()));
+
+        for (binding, server) in servers {
+            let metrics_path = Arc::clone(&metrics_path);
+            let routes = Arc::clone(&routes);
+            let metrics = Arc::clone(&metrics);
+            let published = Arc::clone(&published);
+            let filter = Arc::clone(&filter);
+            let collectors = Arc::clone(&collectors);
+            thread::spawn(move || {
+                info!("Exporting metrics to http://{}{}", binding, metrics_path);
+                for request in server.incoming_requests() {
+                    let result = respond(
+                        request,
+                        &metrics_path,
+                        &routes,
+                        &filter,
+                        &metrics,
+                        &published,
+                        &collectors,
+                    );
+                    if let Err(e) = result {
+                        error!("Failed to respond to HTTP request: {}", e);
+                    }
+                }
+            });
+        }
+
+        Ok(Self {
+            published,
+            filter,
+            collectors,
+        })
+    }
+
+    /// Sets the metric families that scrapes can select by collector name, see
+    /// [`crate::monitor::MonitorRegistry::collectors`].
+    pub fn set_collectors(&self, collectors: HashMap<String, HashSet<String>>) {
+        *self
+            .collectors
+            .lock()
+            .expect("poisoned mutex, should never happen") = collectors;
+    }
+
+    /// Sets the vote accounts by node identity, whose series are labelled with the display name of
+    /// their vote account.
+    pub fn set_identities(&self, identities: HashMap<String, String>) {
+        if !self.filter.names.is_empty() {
+            *self
+                .filter
+                .identities
+                .lock()
+                .expect("poisoned mutex, should never happen") = identities;
+        }
+    }
+
+    /// Publishes the current metrics to be served until the next publication, and returns them.
+    /// The families named in `pending` keep their previously published series, if any, and those
+    /// named in `withheld` are left out.
+    pub fn publish(
+        &self,
+        pending: &HashSet<String>,
+        withheld: &HashSet<String>,
+    ) -> Arc<Vec<MetricFamily>> {
+        let mut families = self.filter.gather();
+        families.retain(|family| !withheld.contains(family.get_name()));
+        let mut published = self
+            .published
+            .lock()
+            .expect("poisoned mutex, should never happen");
+        let families = Arc::new(match published.as_deref() {
+            Some(previous) => with_pending_from(families, previous, pending),
+            None => families,
+        });
+        *published = Some(Arc::clone(&families));
+        families
+    }
+}
+
+/// Responds to `request` according to its path.
+fn respond(
+    request: Request,
+    metrics_path: &str,
+    routes: &HashMap<&'static str, Handler>,
+    filter: &MetricFilter,
+    metrics: &ServerMetrics,
+    published: &Published,
+    collectors: &Collectors,
+) -> std::io::Result<()> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    if path == metrics_path {
+        metrics.requests.inc();
+        let _timer = metrics.request_duration.start_timer();
+        let selected = selected_families(
+            query,
+            &collectors
+                .lock()
+                .expect("poisoned mutex, should never happen"),
+        );
+        let selected = match selected {
+            Ok(selected) => selected,
+            Err(e) => {
+                return request
+                    .respond(Response::from_string(format!("{}\n", e)).with_status_code(400))
+            }
+        };
+        let mut families = published
+            .lock()
+            .expect("poisoned mutex, should never happen")
+            .clone()
+            .unwrap_or_else(|| Arc::new(filter.gather()));
+        if let Some(selected) = selected {
+            families = Arc::new(
+                families
+                    .iter()
+                    .filter(|family| selected.contains(family.get_name()))
+                    .cloned()
+                    .collect(),
+            );
+        }
+        let mut buffer = vec![];
+        if let Err(e) = TextEncoder::new().encode(&families, &mut buffer) {
+            return request.respond(Response::from_string(e.to_string()).with_status_code(500));
+        }
+        metrics.response_size.set(buffer.len() as i64);
+        return request.respond(Response::from_data(buffer));
+    }
+
+    match routes.get(path) {
+        Some(handler) => match handler(query) {
+            Ok((content_type, body)) => request.respond(
+                Response::from_data(body).with_header(header("Content-Type", content_type)),
+            ),
+            Err(e) => {
+                let status = if e.is::<BadRequest>() { 400 } else { 500 };
+                request
+                    .respond(Response::from_string(format!("{:#}\n", e)).with_status_code(status))
+            }
+        },
+        None => request.respond(Response::from_string("not found\n").with_status_code(404)),
+    }
+}
+
+/// Returns the metric families of the collectors selected by the `collect[]` parameters of
+/// `query`, or `None` to serve all of them if there are none. Other parameters are ignored.
+fn selected_families(
+    query: &str,
+    collectors: &HashMap<String, HashSet<String>>,
+) -> Result<Option<HashSet<String>>, BadRequest> {
+    let mut selected: Option<HashSet<String>> = None;
+    for (name, value) in query_params(query) {
+        // Prometheus percent-encodes the brackets of the parameters in its scrape configs.
+        if name != "collect[]" && !name.eq_ignore_ascii_case("collect%5B%5D") {
+            continue;
+        }
+        let families = collectors.get(value).ok_or_else(|| {
+            let mut known: Vec<_> = collectors.keys().collect();
+            known.sort();
+            BadRequest(format!(
+                "unknown collector {:?}, expected one of {:?}",
+                value, known
+            ))
+        })?;
+        selected
+            .get_or_insert_with(HashSet::new)
+            .extend(families.iter().cloned());
+    }
+    Ok(selected)
+}
+
+/// Removes the series with any of the [`PER_NODE_LABELS`] from `families`, and the families left
+/// without series.
+fn without_per_node_series(mut families: Vec<MetricFamily>) -> Vec<MetricFamily> {
+    for family in &mut families {
+        family.mut_metric().retain(|metric| {
+            !metric
+                .get_label()
+                .iter()
+                .any(|label| PER_NODE_LABELS.contains(&label.get_name()))
+        });
+    }
+    families.retain(|family| !family.get_metric().is_empty());
+    families
+}
+
+/// Adds a `name` label with the display name in `names` of their pubkey, or of the vote account
+/// in `identities` of their node identity, to the series of `families` with a `pubkey` label,
+/// unless they have a `name` label already.
+fn with_names(
+    mut families: Vec<MetricFamily>,
+    names: &HashMap<String, String>,
+    identities: &HashMap<String, String>,
+) -> Vec<MetricFamily> {
+    for family in &mut families {
+        for metric in family.mut_metric().iter_mut() {
+            let labels = metric.get_label();
+            if labels.iter().any(|label| label.get_name() == NAME_LABEL) {
+                continue;
+            }
+            let name = labels
+                .iter()
+                .find(|label| label.get_name() == PUBKEY_LABEL)
+                .and_then(|label| {
+                    let pubkey = label.get_value();
+                    names.get(pubkey).or_else(|| {
+                        identities
+                            .get(pubkey)
+                            .and_then(|vote_pubkey| names.get(vote_pubkey))
+                    })
+                });
+            if let Some(name) = name {
+                let mut label = LabelPair::default();
+                label.set_name(NAME_LABEL.to_string());
+                label.set_value(name.clone());
+                let mut labels = metric.take_label();
+                labels.push(label);
+                labels.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+                metric.set_label(labels);
+            }
+        }
+    }
+    families
+}
+
+/// Removes the [`OPTIONAL_LABELS`] of the families of `families` listed in `metric_labels` that
+/// are not selected for them. Counter and gauge series left with the same labels are summed up,
+/// like with `sum without (...)` in PromQL.
+fn with_selected_labels(
+    mut families: Vec<MetricFamily>,
+    metric_labels: &HashMap<String, HashSet<String>>,
+) -> Vec<MetricFamily> {
+    for family in &mut families {
+        let selected = match metric_labels.get(family.get_name()) {
+            Some(selected) => selected,
+            None => continue,
+        };
+        let field_type = family.get_field_type();
+        let mut merged: Vec<Metric> = Vec::new();
+        let mut index = HashMap::new();
+        for mut metric in family.take_metric() {
+            let mut labels = metric.take_label();
+            labels.retain(|label| {
+                !OPTIONAL_LABELS.contains(&label.get_name()) || selected.contains(label.get_name())
+            });
+            metric.set_label(labels);
+            let key: Vec<_> = metric
+                .get_label()
+                .iter()
+                .map(|l| (l.get_name().to_string(), l.get_value().to_string()))
+                .collect();
+            match index.get(&key) {
+                Some(&i) => {
+                    let existing: &mut Metric = &mut merged[i];
+                    match field_type {
+                        MetricType::COUNTER => {
+                            let mut counter = existing.get_counter().clone();
+                            counter
+                                .set_value(counter.get_value() + metric.get_counter().get_value());
+                            existing.set_counter(counter);
+                        }
+                        MetricType::GAUGE => {
+                            let mut gauge = existing.get_gauge().clone();
+                            gauge.set_value(gauge.get_value() + metric.get_gauge().get_value());
+                            existing.set_gauge(gauge);
+                        }
+                        // No histogram or summary has optional labels.
+                        _ => {}
+                    }
+                }
+                None => {
+                    index.insert(key, merged.len());
+                    merged.push(metric);
+                }
+            }
+        }
+        family.set_metric(merged);
+    }
+    families
+}
+
+/// Replaces the families of `families` named in `pending` with those of `previous`, where it has
+/// them.
+fn with_pending_from(
+    families: Vec<MetricFamily>,
+    previous: &[MetricFamily],
+    pending: &HashSet<String>,
+) -> Vec<MetricFamily> {
+    if pending.is_empty() {
+        return families;
+    }
+    families
+        .into_iter()
+        .map(|family| {
+            if !pending.contains(family.get_name()) {
+                return family;
+            }
+            previous
+                .iter()
+                .find(|p| p.get_name() == family.get_name())
+                .cloned()
+                .unwrap_or(family)
+        })
+        .collect()
+}
+
+/// Makes a header from a static name and an ASCII value.
+fn header(name: &'static str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("invalid header, should never happen")
+}
+
+/// Summary of a tree of the database.
+#[derive(Debug, Serialize)]
+struct TreeStatus {
+    name: String,
+    entries: usize,
+    /// First and last cached epoch, for trees keyed by epoch.
+    first_epoch: Option<Epoch>,
+    last_epoch: Option<Epoch>,
+    /// Lookups since start that found an entry or not, for the rewards cache trees.
+    hits: Option<u64>,
+    misses: Option<u64>,
+}
+
+/// Returns a handler of `/debug/cache`, which lists the trees of `database` with their entry
+/// counts, the cached epochs of the rewards cache trees, and their lookup hits and misses since
+/// start, as JSON.
+pub fn debug_cache_handler(database: Arc<PersistentDatabase>, cache: Arc<RewardsCache>) -> Handler {
+    Box::new(move |_| {
+        let epoch_ranges: HashMap<_, _> = cache.epoch_ranges()?.into_iter().collect();
+        let lookups: HashMap<_, _> = cache.lookup_counts().into_iter().collect();
+        let mut names = database.tree_names();
+        names.sort();
+        let mut trees = Vec::with_capacity(names.len());
+        for name in names {
+            let range = epoch_ranges.get(name.as_str()).cloned().flatten();
+            let lookup = lookups.get(name.as_str());
+            trees.push(TreeStatus {
+                entries: database.tree(&name)?.len(),
+                first_epoch: range.as_ref().map(|r| *r.start()),
+                last_epoch: range.as_ref().map(|r| *r.end()),
+                hits: lookup.map(|l| l.hits()),
+                misses: lookup.map(|l| l.misses()),
+                name,
+            });
+        }
+        Ok(("application/json", serde_json::to_vec_pretty(&trees)?))
+    })
+}
+
+/// Rewards and APY of a vote account in an epoch.
+#[derive(Debug, PartialEq, Serialize)]
+struct EpochRewards {
+    /// Epoch at the start of which the rewards were paid.
+    epoch: Epoch,
+    /// Voting rewards in lamports.
+    validator_rewards: Option<i64>,
+    /// Commission the voting rewards were paid at, in percent.
+    commission: Option<u8>,
+    /// Staking APY in the epoch, and on average over the epochs before.
+    current_apy: Option<f64>,
+    average_apy: Option<f64>,
+    /// Staking APR in the epoch, and on average over the epochs before.
+    current_apr: Option<f64>,
+    average_apr: Option<f64>,
+}
+
+/// Returns a handler of `/api/v1/rewards?voter=...&from_epoch=...&to_epoch=...`, which lists the
+/// cached rewards and APYs of a vote account by epoch as JSON. The epochs default to all cached
+/// ones, and epochs without cached data are left out.
+pub fn rewards_api_handler(cache: Arc<RewardsCache>) -> Handler {
+    Box::new(move |query| {
+        let mut voter = None;
+        let (mut from_epoch, mut to_epoch) = (None, None);
+        for (name, value) in query_params(query) {
+            let epoch = || {
+                value
+                    .parse::<Epoch>()
+                    .map_err(|_| BadRequest(format!("invalid {}: {}", name, value)))
+            };
+            match name {
+                "voter" => {
+                    voter = Some(
+                        value
+                            .parse::<Pubkey>()
+                            .map_err(|_| BadRequest(format!("invalid voter: {}", value)))?,
+                    )
+                }
+                "from_epoch" => from_epoch = Some(epoch()?),
+                "to_epoch" => to_epoch = Some(epoch()?),
+                _ => return Err(BadRequest(format!("unknown parameter: {}", name)).into()),
+            }
+        }
+        let voter = voter.ok_or_else(|| BadRequest("missing voter".to_string()))?;
+        let rewards = voter_rewards(&cache, &voter, from_epoch, to_epoch)?;
+        Ok(("application/json", serde_json::to_vec_pretty(&rewards)?))
+    })
+}
+
+/// Returns the cached rewards and APYs of `voter` by epoch, from `from_epoch` to `to_epoch`
+/// within the cached epochs.
+fn voter_rewards(
+    cache: &RewardsCache,
+    voter: &Pubkey,
+    from_epoch: Option<Epoch>,
+    to_epoch: Option<Epoch>,
+) -> anyhow::Result<Vec<EpochRewards>> {
+    let cached = cache
+        .epoch_ranges()?
+        .into_iter()
+        .filter_map(|(_, range)| range)
+        .reduce(|a, b| *a.start().min(b.start())..=*a.end().max(b.end()));
+    let cached = match cached {
+        Some(cached) => cached,
+        None => return Ok(vec![]),
+    };
+    let from = from_epoch.unwrap_or(0).max(*cached.start());
+    let to = to_epoch.unwrap_or(u64::MAX).min(*cached.end());
+
+    let voter_key = voter.to_string();
+    let mut rewards = Vec::new();
+    for epoch in from..=to {
+        let reward = cache.get_epoch_rewards(epoch)?.and_then(|rewards| {
+            rewards
+                .into_iter()
+                .find(|r| r.reward_type == Some(RewardType::Voting) && r.pubkey == voter_key)
+        });
+        let apy = cache
+            .get_epoch_voter_apy(epoch)?
+            .and_then(|apys| apys.get(voter).cloned());
+        if reward.is_none() && apy.is_none() {
+            continue;
+        }
+        rewards.push(EpochRewards {
+            epoch,
+            validator_rewards: reward.as_ref().map(|r| r.lamports),
+            commission: reward.and_then(|r| r.commission),
+            current_apy: apy.as_ref().map(|a| a.current_apy),
+            average_apy: apy.as_ref().map(|a| a.average_apy),
+            current_apr: apy.as_ref().map(|a| a.current_apr),
+            average_apr: apy.map(|a| a.average_apr),
+        });
+    }
+    Ok(rewards)
+}
+
+/// Splits a query string into its names and values. Values are not percent-decoded, since none
+/// of the parameters needs it.
+fn query_params(query: &str) -> impl Iterator<Item = (&str, &str)> {
+    query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| param.split_once('=').unwrap_or((param, "")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gauges::PUBKEY_LABEL;
+    use prometheus_exporter::prometheus::{IntGaugeVec, Opts, Registry};
+
+    #[test]
+    fn leaves_out_per_node_series() {
+        let registry = Registry::new();
+        let active = IntGaugeVec::new(Opts::new("active", "Active"), &["status"]).unwrap();
+        let stake = IntGaugeVec::new(Opts::new("stake", "Stake"), &[PUBKEY_LABEL]).unwrap();
+        registry.register(Box::new(active.clone())).unwrap();
+        registry.register(Box::new(stake.clone())).unwrap();
+        active.with_label_values(&["current"]).set(2);
+        stake.with_label_values(&["a"]).set(1);
+
+        let families = without_per_node_series(registry.gather());
+        assert_eq!(families.len(), 1);
+        assert_eq!(families[0].get_name(), "active");
+    }
+
+    #[test]
+    fn sums_up_series_without_unselected_labels() {
+        let registry = Registry::new();
+        let info = IntGaugeVec::new(
+            Opts::new("info", "Info"),
+            &["identity", "gossip_ip", "version"],
+        )
+        .unwrap();
+        let versions = IntGaugeVec::new(Opts::new("versions", "Versions"), &["version"]).unwrap();
+        registry.register(Box::new(info.clone())).unwrap();
+        registry.register(Box::new(versions.clone())).unwrap();
+        info.with_label_values(&["a", "10.0.0.1", "2.0"]).set(1);
+        info.with_label_values(&["b", "10.0.0.2", "2.0"]).set(1);
+        versions.with_label_values(&["2.0"]).set(2);
+        versions.with_label_values(&["2.1"]).set(3);
+
+        let metric_labels = [("info", vec!["version".to_string()]), ("versions", vec![])]
+            .iter()
+            .cloned()
+            .map(|(family, labels)| (family.to_string(), labels))
+            .collect();
+        let filter = MetricFilter::new(false, metric_labels, HashMap::new()).unwrap();
+        let families = with_selected_labels(registry.gather(), &filter.metric_labels);
+        let labels = |metric: &Metric| -> Vec<_> {
+            metric
+                .get_label()
+                .iter()
+                .map(|l| format!("{}={}", l.get_name(), l.get_value()))
+                .collect()
+        };
+        let info = families[0].get_metric();
+        assert_eq!(info.len(), 2);
+        assert_eq!(labels(&info[0]), vec!["identity=a", "version=2.0"]);
+        let versions = families[1].get_metric();
+        assert_eq!(versions.len(), 1);
+        assert!(labels(&versions[0]).is_empty());
+        assert_eq!(versions[0].get_gauge().get_value(), 5.0);
+
+        let not_optional = std::iter::once(("info".to_string(), vec!["identity".to_string()]));
+        assert!(MetricFilter::new(false, not_optional.collect(), HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn labels_series_with_display_names() {
+        let registry = Registry::new();
+        let stake = IntGaugeVec::new(Opts::new("stake", "Stake"), &[PUBKEY_LABEL]).unwrap();
+        let balance =
+            IntGaugeVec::new(Opts::new("balance", "Balance"), &[NAME_LABEL, PUBKEY_LABEL]).unwrap();
+        registry.register(Box::new(stake.clone())).unwrap();
+        registry.register(Box::new(balance.clone())).unwrap();
+        stake.with_label_values(&["a"]).set(1);
+        stake.with_label_values(&["b"]).set(2);
+        stake.with_label_values(&["c"]).set(4);
+        balance.with_label_values(&["treasury", "a"]).set(3);
+
+        let names = std::iter::once(("a".to_string(), "Cogent".to_string())).collect();
+        // `c` is the node identity of `a`.
+        let identities = std::iter::once(("c".to_string(), "a".to_string())).collect();
+        let families = with_names(registry.gather(), &names, &identities);
+        let labels = |metric: &Metric| -> Vec<_> {
+            metric
+                .get_label()
+                .iter()
+                .map(|l| format!("{}={}", l.get_name(), l.get_value()))
+                .collect()
+        };
+        assert_eq!(
+            labels(&families[0].get_metric()[0]),
+            vec!["name=treasury", "pubkey=a"]
+        );
+        let stake = families[1].get_metric();
+        assert_eq!(labels(&stake[0]), vec!["name=Cogent", "pubkey=a"]);
+        assert_eq!(labels(&stake[1]), vec!["pubkey=b"]);
+        assert_eq!(labels(&stake[2]), vec!["name=Cogent", "pubkey=c"]);
+    }
+
+    #[test]
+    fn keeps_previous_pending_families() {
+        let registry = Registry::new();
+        let epoch = IntGaugeVec::new(Opts::new("epoch", "Epoch"), &["status"]).unwrap();
+        let skipped = IntGaugeVec::new(Opts::new("skipped", "Skipped"), &["status"]).unwrap();
+        registry.register(Box::new(epoch.clone())).unwrap();
+        registry.register(Box::new(skipped.clone())).unwrap();
+        epoch.with_label_values(&["a"]).set(1);
+        skipped.with_label_values(&["a"]).set(10);
+        let previous = registry.gather();
+
+        epoch.with_label_values(&["a"]).set(2);
+        skipped.with_label_values(&["a"]).set(0);
+        let pending = std::iter::once("skipped".to_string()).collect();
+        let families = with_pending_from(registry.gather(), &previous, &pending);
+        let value = |i: usize| families[i].get_metric()[0].get_gauge().get_value();
+        assert_eq!((value(0), value(1)), (2.0, 10.0));
+    }
+
+    #[test]
+    fn selects_families_by_collector() {
+        let collectors: HashMap<_, HashSet<_>> = [
+            ("rewards", vec!["solana_current_staking_apy"]),
+            (
+                "skipped_slots",
+                vec!["solana_leader_slots", "solana_skipped_slot_percent"],
+            ),
+        ]
+        .iter()
+        .map(|(name, families)| {
+            let families = families.iter().map(|f| f.to_string()).collect();
+            (name.to_string(), families)
+        })
+        .collect();
+
+        assert_eq!(selected_families("", &collectors).unwrap(), None);
+        let selected = selected_families("collect[]=rewards&foo=bar", &collectors)
+            .unwrap()
+            .unwrap();
+        assert_eq!(selected.len(), 1);
+        assert!(selected.contains("solana_current_staking_apy"));
+        let selected = selected_families(
+            "collect%5B%5D=rewards&collect%5b%5d=skipped_slots",
+            &collectors,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(selected.len(), 3);
+        assert!(selected_families("collect[]=slots", &collectors).is_err());
+    }
+
+    #[test]
+    fn serves_voter_rewards_by_epoch() {
+        use crate::rewards::caching::*;
+        use crate::rewards::VoterApy;
+        use solana_transaction_status_client_types::Reward;
+
+        let cache = Arc::new(RewardsCache::temporary());
+        let voter = Pubkey::new_unique();
+        let reward = Reward {
+            pubkey: voter.to_string(),
+            lamports: 5_000,
+            post_balance: 0,
+            reward_type: Some(RewardType::Voting),
+            commission: Some(10),
+            commission_bps: None,
+        };
+        cache.add_epoch_rewards(3, &[reward]).unwrap();
+        let apy = VoterApy {
+            current_apy: 7.0,
+            average_apy: 6.5,
+            current_apr: 6.8,
+            average_apr: 6.3,
+        };
+        for epoch in [3, 5] {
+            let apys = std::iter::once((voter, apy.clone())).collect();
+            cache.add_epoch_voter_apy(epoch, &apys).unwrap();
+        }
+
+        let rewards = voter_rewards(&cache, &voter, Some(4), None).unwrap();
+        assert_eq!(
+            rewards,
+            vec![EpochRewards {
+                epoch: 5,
+                validator_rewards: None,
+                commission: None,
+                current_apy: Some(7.0),
+                average_apy: Some(6.5),
+                current_apr: Some(6.8),
+                average_apr: Some(6.3),
+            }]
+        );
+        assert_eq!(
+            voter_rewards(&cache, &voter, None, None).unwrap()[0].epoch,
+            3
+        );
+
+        let handler = rewards_api_handler(cache);
+        let body = handler(&format!("voter={}&to_epoch=3", voter)).unwrap().1;
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json[0]["validator_rewards"], 5_000);
+        let e = handler("from_epoch=1").err().unwrap();
+        assert!(e.is::<BadRequest>());
+    }
+}
@@ -0,0 +1,551 @@
+//! The exporter service: the monitors a config enables, and the update loop driving them.
+
+use crate::address_activity::AddressActivityMonitor;
+use crate::blocks::BlockComputeUnitsMonitor;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::commission::CommissionRugMonitor;
+use crate::config::whitelist::ReloadableWhitelist;
+use crate::config::{ExporterConfig, Mode};
+use crate::credit_rate::CreditRateMonitor;
+use crate::delegators::caching::{
+    DelegationsCache, DelegatorSetsCache, DELEGATIONS_TREE_NAME, DELEGATOR_SETS_TREE_NAME,
+};
+use crate::delegators::DelegatorsMonitor;
+use crate::endpoint_slots::EndpointSlotsMonitor;
+use crate::forks::ForksMonitor;
+use crate::gauges::{
+    AddressBalancesMonitor, EpochInfoMonitor, GossipNodesMonitor, NodesMonitor, PrometheusGauges,
+    VoteAccountsMonitor,
+};
+use crate::geolocation::caching::{GeolocationCache, GEO_DB_CACHE_TREE_NAME};
+use crate::geolocation::GeolocationMonitor;
+use crate::heartbeat::Heartbeat;
+use crate::high_availability::LeaderLease;
+use crate::http;
+use crate::ingestion::IngestionMonitor;
+use crate::largest_accounts::LargestAccountsMonitor;
+use crate::last_vote::LastVoteMonitor;
+use crate::lockups::LockupsMonitor;
+use crate::monitor::{CycleFetcher, MonitorRegistry};
+use crate::persistent_database::counters::{CounterBaselines, COUNTERS_TREE_NAME};
+use crate::persistent_database::snapshot::GaugeSnapshot;
+use crate::persistent_database::stats::DatabaseMonitor;
+use crate::persistent_database::PersistentDatabase;
+use crate::price::PriceMonitor;
+use crate::redelegations::caching::{DelegationTargetsCache, DELEGATION_TARGETS_TREE_NAME};
+use crate::redelegations::RedelegationsMonitor;
+use crate::reverse_dns::{ReverseDns, REVERSE_DNS_TREE_NAME};
+use crate::rewards::caching::RewardsCache;
+use crate::rewards::projection::ProjectedApyMonitor;
+use crate::rewards::{ApyOutlierRules, RewardsMonitor};
+use crate::rpc_api::RpcApi;
+use crate::schedule::CycleSchedule;
+use crate::server::Exporter;
+use crate::sharding::Shard;
+use crate::sinks::influxdb::InfluxDbSink;
+use crate::sinks::plaintext::PlaintextSink;
+use crate::sinks::{self, Sink};
+use crate::slots::caching::{
+    LeaderScheduleCache, SlotsCache, BLOCK_PRODUCTION_TREE_NAME, LEADER_SCHEDULE_TREE_NAME,
+};
+use crate::slots::SkippedSlotsMonitor;
+use crate::stake_pool::StakePoolMonitor;
+use crate::uptime::caching::{
+    PresenceHistory, ProductionHistory, PRESENCE_HISTORY_TREE_NAME, PRODUCTION_HISTORY_TREE_NAME,
+};
+use crate::uptime::UptimeMonitor;
+use crate::validator_client::ValidatorClientMonitor;
+use crate::validator_scores::ValidatorScoresMonitor;
+use crate::vote_authorities::VoteAuthoritiesMonitor;
+use crate::vote_fees::VoteFeesMonitor;
+use crate::warmup::CacheWarmer;
+use log::{debug, warn};
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// The caches and histories of the monitors, in the trees of the persistent database.
+pub struct Stores {
+    pub database: Arc<PersistentDatabase>,
+    pub geolocation: Arc<GeolocationCache>,
+    pub rewards: Arc<RewardsCache>,
+    pub slots: SlotsCache,
+    pub leader_schedule: LeaderScheduleCache,
+    pub delegations: DelegationsCache,
+    pub delegator_sets: DelegatorSetsCache,
+    pub counter_baselines: CounterBaselines,
+    pub presence_history: PresenceHistory,
+    pub production_history: ProductionHistory,
+    pub delegation_targets: DelegationTargetsCache,
+    /// Cached host names of the gossip IPs, if they are looked up.
+    pub reverse_dns: Option<ReverseDns>,
+}
+
+impl Stores {
+    /// Opens the caches of the monitors `config` enables in `database`.
+    pub fn open(
+        database: Arc<PersistentDatabase>,
+        config: &ExporterConfig,
+    ) -> anyhow::Result<Self> {
+        let reverse_dns = if config.enable_reverse_dns.unwrap_or(false)
+            && config.enable_gossip_node_info.unwrap_or(false)
+        {
+            Some(ReverseDns::new(database.tree(REVERSE_DNS_TREE_NAME)?))
+        } else {
+            None
+        };
+        Ok(Self {
+            geolocation: Arc::new(GeolocationCache::new(
+                database.tree(GEO_DB_CACHE_TREE_NAME)?,
+            )),
+            rewards: Arc::new(RewardsCache::open(&database)?),
+            slots: SlotsCache::new(database.tree(BLOCK_PRODUCTION_TREE_NAME)?),
+            leader_schedule: LeaderScheduleCache::new(database.tree(LEADER_SCHEDULE_TREE_NAME)?),
+            delegations: DelegationsCache::new(database.tree(DELEGATIONS_TREE_NAME)?),
+            delegator_sets: DelegatorSetsCache::new(database.tree(DELEGATOR_SETS_TREE_NAME)?),
+            counter_baselines: CounterBaselines::new(database.tree(COUNTERS_TREE_NAME)?),
+            presence_history: PresenceHistory::new(database.tree(PRESENCE_HISTORY_TREE_NAME)?),
+            production_history: ProductionHistory::new(
+                database.tree(PRODUCTION_HISTORY_TREE_NAME)?,
+            ),
+            delegation_targets: DelegationTargetsCache::new(
+                database.tree(DELEGATION_TARGETS_TREE_NAME)?,
+            ),
+            reverse_dns,
+            database,
+        })
+    }
+}
+
+/// Registers the monitors that `config` enables in `monitors`, in the order they run in on every
+/// update cycle.
+pub fn register_monitors<'a>(
+    monitors: &mut MonitorRegistry<'a>,
+    config: &ExporterConfig,
+    rpc: &'a dyn RpcApi,
+    gauges: &'a PrometheusGauges,
+    stores: &'a Stores,
+    warmer: &'a CacheWarmer,
+    snapshot: &'a GaugeSnapshot,
+) -> anyhow::Result<()> {
+    let self_mode = config.mode.unwrap_or_default() == Mode::SelfValidator;
+    let address_watchlist = config.address_watchlist.clone().unwrap_or_default();
+    let enable_gossip_node_info = config.enable_gossip_node_info.unwrap_or(false);
+    let enable_tpu_geolocation = config.enable_tpu_geolocation.unwrap_or(false);
+    if config.enable_reverse_dns.unwrap_or(false) && !enable_gossip_node_info {
+        warn!("enable_reverse_dns has no effect without enable_gossip_node_info");
+    }
+    let shard = Shard::new(config.shard_index, config.shard_count)?;
+    let proxy = config.proxy.as_deref();
+
+    monitors.register(VoteAccountsMonitor::new(
+        gauges,
+        &stores.counter_baselines,
+        shard.is_primary(),
+    ));
+    monitors.register(LastVoteMonitor::new(&gauges.seconds_since_last_vote));
+    monitors.register(CreditRateMonitor::new(&gauges.vote_credit_rate));
+    monitors.register(DatabaseMonitor::new(gauges, Arc::clone(&stores.database)));
+    // Cluster-wide metrics are only exported by the first shard.
+    if shard.is_primary() {
+        monitors.register(ForksMonitor::new(
+            rpc,
+            &gauges.rolled_back_slots,
+            &gauges.forks,
+            &stores.counter_baselines,
+        ));
+        monitors.register(EpochInfoMonitor::new(gauges, rpc));
+        monitors.register(IngestionMonitor::new(
+            rpc,
+            &gauges.ingestion_slot,
+            &gauges.ingestion_gap,
+        ));
+    }
+    monitors.register(NodesMonitor::new(gauges, rpc, shard.is_primary()));
+    monitors.register(ValidatorClientMonitor::new(gauges, shard.is_primary()));
+    if config.rpc_endpoints.as_ref().is_some_and(|e| !e.is_empty()) {
+        monitors.register(EndpointSlotsMonitor::new(
+            rpc,
+            &gauges.rpc_endpoint_slot,
+            &gauges.rpc_endpoint_slot_lag,
+        ));
+    }
+    if shard.is_primary() && !address_watchlist.0.is_empty() {
+        monitors.register(AddressBalancesMonitor::new(
+            gauges,
+            rpc,
+            address_watchlist.clone(),
+        ));
+        monitors.register(AddressActivityMonitor::new(
+            rpc,
+            &gauges.address_transactions,
+            &gauges.address_failed_transactions,
+            &stores.counter_baselines,
+            address_watchlist,
+        ));
+    }
+    // Both only export the vote accounts of the whitelist, which may be filled by a reload.
+    monitors.register(UptimeMonitor::new(
+        &gauges.gossip_uptime,
+        &stores.presence_history,
+    ));
+    monitors.register(VoteAuthoritiesMonitor::new(
+        rpc,
+        &gauges.vote_authority,
+        &gauges.vote_authority_changes,
+        &gauges.authorized_voters,
+        &gauges.voter_rotation_epoch,
+        &stores.counter_baselines,
+    ));
+    monitors.register(LockupsMonitor::new(
+        rpc,
+        &gauges.stake_lockup_seconds_remaining,
+        &gauges.stake_lockup_epochs_remaining,
+    ));
+    monitors.register(RedelegationsMonitor::new(
+        rpc,
+        &gauges.stake_delegation,
+        &gauges.stake_redelegations,
+        &gauges.stake_last_redelegation,
+        &stores.counter_baselines,
+        &stores.delegation_targets,
+    ));
+    if shard.is_primary() {
+        monitors.register(CommissionRugMonitor::new(
+            &gauges.commission_rugs,
+            &stores.counter_baselines,
+            config.commission_rug_window.unwrap_or(10_000),
+            config.commission_rug_threshold.unwrap_or(10),
+        ));
+    }
+    if shard.is_primary() && enable_gossip_node_info {
+        monitors.register(GossipNodesMonitor::new(gauges, stores.reverse_dns.as_ref()));
+    }
+    // The other shards only compare the TPU and gossip ASNs of their validators.
+    let maxmind = config
+        .maxmind
+        .clone()
+        .filter(|_| shard.is_primary() || enable_tpu_geolocation);
+    if let Some(maxmind) = maxmind {
+        // If the MaxMind API is configured, submit queries for any uncached IPs.
+        monitors.register(GeolocationMonitor::new(
+            gauges,
+            &stores.geolocation,
+            maxmind,
+            enable_tpu_geolocation,
+            shard.is_primary(),
+            Some(warmer),
+            Some(snapshot),
+        ));
+    }
+    if config.enable_skipped_slots.unwrap_or(true) {
+        monitors.register(SkippedSlotsMonitor::new(
+            rpc,
+            &gauges.leader_slots,
+            &gauges.skipped_slot_percent,
+            &gauges.skipped_slot_percentile,
+            &gauges.skipped_slot_percent_vs_cluster,
+            &stores.counter_baselines,
+            &stores.slots,
+            &stores.production_history,
+        ));
+    }
+    if config.enable_vote_fees.unwrap_or(true) {
+        monitors.register(VoteFeesMonitor::new(
+            rpc,
+            &gauges.vote_fees,
+            &stores.leader_schedule,
+        ));
+    }
+    // A single validator's blocks are cheap enough to follow by default.
+    if config.enable_block_compute_units.unwrap_or(self_mode) {
+        monitors.register(BlockComputeUnitsMonitor::new(
+            rpc,
+            &gauges.block_compute_units_average,
+            &gauges.block_compute_units_percentile,
+            &gauges.block_transactions_average,
+            &stores.leader_schedule,
+        ));
+    }
+    if config.enable_delegator_count.unwrap_or(false) {
+        monitors.register(DelegatorsMonitor::new(
+            rpc,
+            &gauges.delegators,
+            &gauges.delegated_lamports,
+            &gauges.undelegated_lamports,
+            &gauges.delegated_stake,
+            &gauges.stake_operations,
+            &gauges.stake_operation_lamports,
+            &gauges.uninspected_stake_accounts,
+            &stores.counter_baselines,
+            &stores.delegations,
+            &stores.delegator_sets,
+        ));
+    }
+    if shard.is_primary() && config.enable_largest_accounts.unwrap_or(false) {
+        monitors.register(LargestAccountsMonitor::new(
+            rpc,
+            &gauges.largest_accounts_supply_share,
+            &gauges.largest_accounts_above_threshold,
+            config
+                .largest_account_thresholds
+                .clone()
+                .unwrap_or_else(|| vec![1_000_000, 10_000_000]),
+        ));
+    }
+    let stake_pools = config.stake_pools.as_deref().unwrap_or_default();
+    if shard.is_primary() && !stake_pools.is_empty() {
+        monitors.register(StakePoolMonitor::new(
+            rpc,
+            stake_pools,
+            &gauges.stake_pool_total_lamports,
+            &gauges.stake_pool_token_supply,
+            &gauges.stake_pool_exchange_rate,
+            &gauges.stake_pool_validator_count,
+            &gauges.stake_pool_validator_active_stake,
+            &gauges.stake_pool_validator_transient_stake,
+        )?);
+    }
+    if let Some(validator_scores) = config.validator_scores.clone() {
+        monitors.register(ValidatorScoresMonitor::new(
+            http::client_builder(proxy)?
+                .timeout(Duration::from_secs(60))
+                .build()?,
+            validator_scores,
+            &gauges.validator_score,
+            &gauges.validator_score_rank,
+        )?);
+    }
+    if config.enable_rewards.unwrap_or(true) {
+        monitors.register(RewardsMonitor::new(
+            rpc,
+            &gauges.current_staking_apy,
+            &gauges.average_staking_apy,
+            &gauges.current_staking_apr,
+            &gauges.average_staking_apr,
+            &gauges.expected_annual_staking_rewards,
+            &gauges.validator_rewards,
+            &gauges.whitelist_validator_rewards,
+            &gauges.whitelist_current_staking_apy,
+            &gauges.whitelist_average_staking_apy,
+            &gauges.cluster_staking_apy,
+            &gauges.cluster_staking_apy_voters,
+            &gauges.staking_apy_outliers,
+            &gauges.stake_account_errors,
+            &stores.counter_baselines,
+            &stores.rewards,
+            config.reward_backfill_concurrency.unwrap_or(2),
+            Duration::from_secs(config.reward_recheck_interval.unwrap_or(3600)),
+            ApyOutlierRules::new(config),
+            Some(warmer),
+            Some(snapshot),
+        ));
+        monitors.register(ProjectedApyMonitor::new(rpc, &gauges.projected_staking_apy));
+    }
+    // Last, so that the USD values follow the balances and rewards of the same cycle.
+    if let Some(price) = config.price.clone() {
+        monitors.register(PriceMonitor::new(
+            http::client_builder(proxy)?
+                .timeout(Duration::from_secs(10))
+                .build()?,
+            rpc,
+            gauges,
+            price,
+        )?);
+    }
+    Ok(())
+}
+
+/// The loop collecting the monitors on every update cycle and publishing their metrics to the
+/// exporter and the sinks.
+pub struct UpdateLoop<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    monitors: MonitorRegistry<'a>,
+    exporter: &'a Exporter,
+    warmer: &'a CacheWarmer,
+    vote_accounts_whitelist: ReloadableWhitelist,
+    staking_account_whitelist: ReloadableWhitelist,
+    schedule: CycleSchedule,
+    circuit_breaker: CircuitBreaker,
+    cycles: CycleFetcher,
+    sinks: Vec<Box<dyn Sink>>,
+    heartbeat: Option<Heartbeat>,
+    leader_lease: Option<LeaderLease>,
+    /// Number of past epochs whose rewards are backfilled at once, if the rewards are exported.
+    reward_backfill_concurrency: Option<usize>,
+}
+
+impl<'a> UpdateLoop<'a> {
+    /// Constructs the update loop of `config`, collecting `monitors` for the accounts of the
+    /// whitelists.
+    pub fn new(
+        config: &ExporterConfig,
+        rpc: &'a dyn RpcApi,
+        monitors: MonitorRegistry<'a>,
+        exporter: &'a Exporter,
+        warmer: &'a CacheWarmer,
+        vote_accounts_whitelist: ReloadableWhitelist,
+        staking_account_whitelist: ReloadableWhitelist,
+    ) -> anyhow::Result<Self> {
+        let proxy = config.proxy.as_deref();
+        let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+        if let Some(influxdb) = config.influxdb.clone() {
+            sinks.push(Box::new(InfluxDbSink::new(
+                http::client_builder(proxy)?
+                    .timeout(Duration::from_secs(10))
+                    .build()?,
+                influxdb,
+            )));
+        }
+        if let Some(plaintext_sink) = config.plaintext_sink.clone() {
+            sinks.push(Box::new(PlaintextSink::new(plaintext_sink)));
+        }
+        let heartbeat = match config.heartbeat.clone() {
+            Some(heartbeat) => Some(Heartbeat::new(
+                http::client_builder(proxy)?
+                    .timeout(Duration::from_secs(10))
+                    .build()?,
+                heartbeat,
+            )),
+            None => None,
+        };
+        Ok(Self {
+            rpc,
+            monitors,
+            exporter,
+            warmer,
+            vote_accounts_whitelist,
+            staking_account_whitelist,
+            schedule: CycleSchedule::new(config.scheduling.as_ref())?,
+            circuit_breaker: CircuitBreaker::new(
+                config.circuit_breaker_threshold.unwrap_or(5),
+                Duration::from_secs(config.circuit_breaker_cooldown.unwrap_or(30)),
+            ),
+            cycles: CycleFetcher::new(
+                Duration::from_secs(config.cluster_nodes_ttl.unwrap_or(60)),
+                Shard::new(config.shard_index, config.shard_count)?,
+            ),
+            sinks,
+            heartbeat,
+            leader_lease: config
+                .high_availability
+                .clone()
+                .map(LeaderLease::new)
+                .transpose()?,
+            reward_backfill_concurrency: config
+                .enable_rewards
+                .unwrap_or(true)
+                .then(|| config.reward_backfill_concurrency.unwrap_or(2)),
+        })
+    }
+
+    /// Sets up the monitors and runs update cycles until `shutdown` completes, which is only
+    /// waited for between cycles.
+    pub async fn run(mut self, shutdown: impl Future<Output = ()>) -> anyhow::Result<()> {
+        self.monitors.setup().await?;
+        let monitor_families: HashSet<String> =
+            self.monitors.collectors().into_values().flatten().collect();
+        self.exporter.set_collectors(self.monitors.collectors());
+
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                () = &mut shutdown => return Ok(()),
+                () = tokio::time::sleep(self.schedule.next_delay()) => {}
+            }
+            // Standbys make no RPC calls and serve none of the monitors' metrics, so that the
+            // series a demoted leader collected do not linger next to the new leader's.
+            if self
+                .leader_lease
+                .as_mut()
+                .is_some_and(|lease| !lease.poll())
+            {
+                self.warmer.pause_rewards();
+                self.exporter.publish(&HashSet::new(), &monitor_families);
+                continue;
+            }
+            // While the RPC endpoint keeps failing, skip cycles instead of
+            // hammering it; a probe cycle is let through once the cooldown is over.
+            if !self.circuit_breaker.allow() {
+                self.warmer.pause_rewards();
+                continue;
+            }
+            debug!("Updating metrics");
+            self.cycle().await;
+        }
+    }
+
+    /// Runs one update cycle.
+    async fn cycle(&mut self) {
+        self.vote_accounts_whitelist.reload();
+        self.staking_account_whitelist.reload();
+        // Base data every monitor depends on. If it cannot be fetched there is nothing
+        // meaningful to publish this cycle, so log and wait for the next tick instead of
+        // returning the error, which would drop every metric until the process is restarted.
+        let cycle = self
+            .cycles
+            .fetch(
+                self.rpc,
+                self.vote_accounts_whitelist.get(),
+                self.staking_account_whitelist.get(),
+            )
+            .await;
+        let cycle = match cycle {
+            Ok(cycle) => {
+                self.circuit_breaker.record_success();
+                if let Some(lease) = &mut self.leader_lease {
+                    lease.renew();
+                }
+                if let Some(concurrency) = self.reward_backfill_concurrency {
+                    self.warmer.warm_rewards(concurrency);
+                }
+                cycle
+            }
+            Err(e) => {
+                self.circuit_breaker.record_failure();
+                warn!("Skipping update cycle, base RPC fetch failed: {e:#}");
+                return;
+            }
+        };
+        self.exporter.set_identities(
+            cycle
+                .vote_accounts
+                .current
+                .iter()
+                .chain(&cycle.vote_accounts.delinquent)
+                .map(|v| (v.node_pubkey.clone(), v.vote_pubkey.clone()))
+                .collect(),
+        );
+
+        // Each monitor is isolated: a transient error on one (e.g. testnet
+        // `getBlockProduction` racing the node's slot history) is logged and the
+        // remaining monitors still publish.
+        self.monitors.collect(&cycle).await;
+
+        // Scrapes see the whole cycle at once. Around an epoch rollover, the
+        // epoch-derived metrics keep describing the previous epoch until every
+        // monitor has caught up with the new one, and metrics computed from
+        // partial history are withheld until it is complete.
+        let published = self.exporter.publish(
+            &self.monitors.pending_metrics(&cycle),
+            &self.monitors.warming_up_metrics(),
+        );
+
+        if !self.sinks.is_empty() {
+            let samples = sinks::samples(&published);
+            let timestamp = OffsetDateTime::now_utc();
+            for sink in &self.sinks {
+                if let Err(e) = sink.write(&samples, timestamp).await {
+                    warn!("Failed to write metrics to {}: {e:#}", sink.name());
+                }
+            }
+        }
+
+        if let (Some(heartbeat), true) = (&mut self.heartbeat, self.monitors.all_up()) {
+            heartbeat.beat().await;
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Splitting of the whitelisted accounts across exporter instances.
+
+use crate::config::Whitelist;
+use crate::rpc_api::RpcApi;
+use crate::rpc_extra::MAX_MULTIPLE_ACCOUNTS;
+use anyhow::anyhow;
+use solana_clock::Epoch;
+use solana_pubkey::Pubkey;
+use solana_stake_interface::state::StakeStateV2;
+use std::collections::HashMap;
+
+/// One of `count` instances, each exporting the vote accounts whose pubkeys hash to its index,
+/// and the stake accounts delegated to them.
+///
+/// The hash of a pubkey is fixed, so every instance can tell the accounts of the others apart
+/// without coordinating. Shard 0 also exports the cluster-wide metrics.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shard {
+    index: u32,
+    count: u32,
+}
+
+impl Shard {
+    /// Constructs shard `index` of `count`, or the only shard by default.
+    pub fn new(index: Option<u32>, count: Option<u32>) -> anyhow::Result<Self> {
+        let (index, count) = (index.unwrap_or(0), count.unwrap_or(1));
+        if index >= count {
+            return Err(anyhow!(
+                "shard_index {} is not below shard_count {}",
+                index,
+                count
+            ));
+        }
+        Ok(Self { index, count })
+    }
+
+    /// Whether this shard exports the cluster-wide metrics.
+    pub fn is_primary(&self) -> bool {
+        self.index == 0
+    }
+
+    /// Whether the accounts are split across more than one shard.
+    pub fn is_split(&self) -> bool {
+        self.count > 1
+    }
+
+    /// Whether `pubkey` belongs to this shard.
+    pub fn owns(&self, pubkey: &str) -> bool {
+        // 64-bit FNV-1a, stable across builds and platforms unlike the std hashers.
+        let hash = pubkey
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            });
+        hash % u64::from(self.count) == u64::from(self.index)
+    }
+
+    /// Returns the accounts of `whitelist` that belong to this shard, or of `all` if the
+    /// whitelist stands for all accounts. A single shard returns `whitelist` as is.
+    pub fn whitelist<'a>(
+        &self,
+        whitelist: &Whitelist,
+        all: impl IntoIterator<Item = &'a String>,
+    ) -> Whitelist {
+        if !self.is_split() {
+            return whitelist.clone();
+        }
+        let in_shard = |pubkey: &&String| self.owns(pubkey);
+        Whitelist::only(if whitelist.is_all() {
+            all.into_iter().filter(in_shard).cloned().collect()
+        } else {
+            whitelist.0.iter().filter(in_shard).cloned().collect()
+        })
+    }
+
+    /// Returns the stake accounts of `whitelist` delegated to vote accounts of this shard, as
+    /// resolved by `voters`. A stake account whose vote account is unknown, e.g. because it is
+    /// not delegated, goes to the shard of its own pubkey. A single shard, or a whitelist that
+    /// stands for all accounts, returns `whitelist` as is.
+    pub fn stake_whitelist(&self, whitelist: &Whitelist, voters: &StakeVoters) -> Whitelist {
+        if !self.is_split() || whitelist.is_all() {
+            return whitelist.clone();
+        }
+        Whitelist::only(
+            whitelist
+                .0
+                .iter()
+                .filter(|stake| self.owns(voters.voter(stake).unwrap_or(stake)))
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+/// The vote accounts whitelisted stake accounts are delegated to, by stake account pubkey.
+///
+/// They are resolved again once per epoch, since a redelegation only takes effect at an epoch
+/// boundary, and for stake accounts added to the whitelist in between.
+#[derive(Debug, Default)]
+pub struct StakeVoters {
+    /// Vote account of each resolved stake account, if it is delegated.
+    voters: HashMap<String, Option<String>>,
+    /// Epoch the stake accounts were resolved in.
+    epoch: Option<Epoch>,
+}
+
+impl StakeVoters {
+    /// Returns the vote account `stake` is delegated to, if resolved.
+    pub fn voter(&self, stake: &str) -> Option<&str> {
+        self.voters.get(stake)?.as_deref()
+    }
+
+    /// Resolves the vote accounts of the stake accounts of `whitelist` not yet resolved in
+    /// `epoch`. On error, the vote accounts resolved so far are kept.
+    pub async fn refresh(
+        &mut self,
+        rpc: &dyn RpcApi,
+        whitelist: &Whitelist,
+        epoch: Epoch,
+    ) -> anyhow::Result<()> {
+        let stale = self.epoch != Some(epoch);
+        if stale {
+            self.voters.retain(|stake, _| whitelist.0.contains(stake));
+        }
+        let mut stakes: Vec<Pubkey> = whitelist
+            .0
+            .iter()
+            .filter(|stake| stale || !self.voters.contains_key(*stake))
+            .filter_map(|stake| stake.parse().ok())
+            .collect();
+        stakes.sort_unstable();
+        for chunk in stakes.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let accounts = rpc.get_multiple_accounts(chunk).await?;
+            for (stake, account) in chunk.iter().zip(accounts) {
+                let voter = account
+                    .and_then(|a| bincode::deserialize::<StakeStateV2>(&a.data).ok())
+                    .and_then(|state| state.delegation())
+                    .map(|delegation| delegation.voter_pubkey.to_string());
+                self.voters.insert(stake.to_string(), voter);
+            }
+        }
+        self.epoch = Some(epoch);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_api::mock::MockRpc;
+    use solana_account::Account;
+    use solana_stake_interface::stake_flags::StakeFlags;
+    use solana_stake_interface::state::{Delegation, Meta, Stake};
+
+    #[test]
+    fn splits_whitelists_across_shards() {
+        let pubkeys: Vec<String> = (0..100).map(|i| format!("voter{}", i)).collect();
+        let whitelist = Whitelist::new(pubkeys.iter().cloned().collect());
+        let shards: Vec<Whitelist> = (0..3)
+            .map(|i| Shard::new(Some(i), Some(3)).unwrap())
+            .map(|shard| shard.whitelist(&whitelist, &[]))
+            .collect();
+        assert!(shards.iter().all(|w| w.0.len() > 20));
+        assert_eq!(shards.iter().map(|w| w.0.len()).sum::<usize>(), 100);
+        assert!(pubkeys
+            .iter()
+            .all(|p| shards.iter().filter(|w| w.0.contains(p)).count() == 1));
+
+        // An empty whitelist is split like all accounts.
+        let shard = Shard::new(Some(1), Some(3)).unwrap();
+        assert_eq!(
+            shard.whitelist(&Whitelist::default(), &pubkeys).0,
+            shards[1].0
+        );
+        // A shard owning none of the whitelist does not fall back to all accounts.
+        let other = Whitelist::new(pubkeys.iter().filter(|p| !shard.owns(p)).cloned().collect());
+        let owned = shard.whitelist(&other, &pubkeys);
+        assert!(owned.0.is_empty() && !owned.is_all());
+        assert!(!pubkeys.iter().any(|p| owned.contains(p)));
+        assert!(Shard::new(Some(3), Some(3)).is_err());
+    }
+
+    #[tokio::test]
+    async fn shards_stake_accounts_by_voter() {
+        let stake_account = |voter: &Pubkey| Account {
+            lamports: 1_000_000,
+            data: bincode::serialize(&StakeStateV2::Stake(
+                Meta::default(),
+                Stake {
+                    delegation: Delegation::new(voter, 1_000_000, 0),
+                    credits_observed: 0,
+                },
+                StakeFlags::empty(),
+            ))
+            .unwrap(),
+            owner: Pubkey::default(),
+            executable: false,
+            rent_epoch: 0,
+        };
+        let shard = Shard::new(Some(1), Some(2)).unwrap();
+        let voters: Vec<Pubkey> = (0..20).map(|_| Pubkey::new_unique()).collect();
+        let (owned, other) = (
+            voters.iter().find(|v| shard.owns(&v.to_string())).unwrap(),
+            voters.iter().find(|v| !shard.owns(&v.to_string())).unwrap(),
+        );
+        let mut rpc = MockRpc::default();
+        let mut stakes = Vec::new();
+        for voter in [owned, other] {
+            let stake = Pubkey::new_unique();
+            rpc.accounts.insert(stake, stake_account(voter));
+            stakes.push(stake.to_string());
+        }
+        let whitelist = Whitelist::new(stakes.iter().cloned().collect());
+
+        let mut stake_voters = StakeVoters::default();
+        stake_voters.refresh(&rpc, &whitelist, 10).await.unwrap();
+        assert_eq!(stake_voters.voter(&stakes[0]), Some(&*owned.to_string()));
+        let shard_whitelist = shard.stake_whitelist(&whitelist, &stake_voters);
+        assert!(shard_whitelist.contains(&stakes[0]) && !shard_whitelist.contains(&stakes[1]));
+        // The stakes of all accounts are not split.
+        assert!(shard
+            .stake_whitelist(&Whitelist::default(), &stake_voters)
+            .is_all());
+    }
+}
@@ -0,0 +1,248 @@
+//! The `simulate` subcommand: replays the cached reward and skip-rate history into the metrics.
+
+use log::{info, warn};
+use solana_clock::Epoch;
+use solana_exporter::config::Whitelist;
+use solana_exporter::gauges::PrometheusGauges;
+use solana_exporter::persistent_database::counters::CounterBaselines;
+use solana_exporter::rewards::caching::{RewardsCache, EPOCH_VOTER_APY_TREE_NAME};
+use solana_exporter::rewards::{
+    ApyOutlierRules, RewardsMonitor, DEFAULT_EPOCH_LENGTH, SECONDS_IN_DAY,
+};
+use solana_exporter::rpc_api::RpcApi;
+use solana_exporter::server::Exporter;
+use solana_exporter::uptime::caching::{ProductionHistory, BUCKET_SECS};
+use solana_pubkey::Pubkey;
+use solana_reward_info::RewardType;
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+/// `simulate [--interval SECS]`: exports the cached APYs, validator rewards and skip rates of one
+/// epoch after the other, every `interval`, from the first to the last cached epoch, then keeps
+/// serving the last one. No RPC calls are made; `rpc` is only needed to make the rewards monitor.
+///
+/// The stakes of past epochs are not cached, so the whitelist-wide APYs are weighted by the stake
+/// accounts whose rewards are cached, leaving out voters without any. The validator rewards
+/// counters count from zero, in a temporary database, rather than in the persisted counter
+/// totals.
+///
+/// The skip rates come from the hourly production history, which has no epoch boundaries: the
+/// epochs are laid out back from the end of the history by their cached lengths, so the skip rate
+/// of an epoch is approximate. They are labelled with the vote account, since the identity of a
+/// validator is not known without RPC calls.
+pub async fn simulate(
+    rpc: &dyn RpcApi,
+    gauges: &PrometheusGauges,
+    cache: &RewardsCache,
+    production: &ProductionHistory,
+    vote_accounts_whitelist: &Whitelist,
+    exporter: &Exporter,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    let baselines = CounterBaselines::new(
+        sled::Config::default()
+            .temporary(true)
+            .open()?
+            .open_tree("counters")?,
+    );
+    let monitor = RewardsMonitor::new(
+        rpc,
+        &gauges.current_staking_apy,
+        &gauges.average_staking_apy,
+        &gauges.current_staking_apr,
+        &gauges.average_staking_apr,
+        &gauges.expected_annual_staking_rewards,
+        &gauges.validator_rewards,
+        &gauges.whitelist_validator_rewards,
+        &gauges.whitelist_current_staking_apy,
+        &gauges.whitelist_average_staking_apy,
+        &gauges.cluster_staking_apy,
+        &gauges.cluster_staking_apy_voters,
+        &gauges.staking_apy_outliers,
+        &gauges.stake_account_errors,
+        &baselines,
+        cache,
+        1,
+        Duration::ZERO,
+        ApyOutlierRules::default(),
+        None,
+        None,
+    );
+
+    let epochs = cache
+        .epoch_ranges()?
+        .into_iter()
+        .find(|(name, _)| *name == EPOCH_VOTER_APY_TREE_NAME)
+        .and_then(|(_, range)| range);
+    let epochs = match epochs {
+        Some(epochs) => epochs,
+        None => {
+            warn!("No cached APYs to replay, run the exporter for an epoch first");
+            return Ok(());
+        }
+    };
+    let voters: Vec<Pubkey> = production
+        .vote_pubkeys()?
+        .into_iter()
+        .filter(|voter| vote_accounts_whitelist.contains(&voter.to_string()))
+        .collect();
+    let windows = epoch_windows(cache, production, &voters, epochs.clone())?;
+    info!(
+        "Replaying epochs {} to {}, one every {:?}",
+        epochs.start(),
+        epochs.end(),
+        interval
+    );
+
+    for epoch in epochs {
+        let staking_apys = match cache.get_epoch_voter_apy(epoch)? {
+            Some(staking_apys) => staking_apys,
+            None => continue,
+        };
+        let whitelist_stakes = cached_stakes(cache, epoch)?
+            .into_iter()
+            .filter(|(voter, _)| vote_accounts_whitelist.contains(&voter.to_string()))
+            .collect();
+        gauges.current_epoch.set(epoch as i64);
+        if let Err(e) = monitor.export_epoch_rewards(
+            epoch,
+            &staking_apys,
+            &whitelist_stakes,
+            vote_accounts_whitelist,
+        ) {
+            warn!("Failed to replay epoch {}: {e:#}", epoch);
+        }
+        gauges.skipped_slot_percent.reset();
+        if let Some((start, end)) = windows.get(&epoch) {
+            for voter in &voters {
+                if let Some(skip_rate) = production.total(voter, *start, *end)?.skip_rate_percent()
+                {
+                    gauges
+                        .skipped_slot_percent
+                        .get_metric_with_label_values(&[&voter.to_string()])
+                        .map(|c| c.set(skip_rate))?;
+                }
+            }
+        }
+        exporter.publish(&HashSet::new(), &HashSet::new());
+        info!("Replayed epoch {}", epoch);
+        tokio::time::sleep(interval).await;
+    }
+
+    info!("Replay finished, serving the last epoch until interrupted");
+    loop {
+        tokio::time::sleep(Duration::from_secs(3600)).await;
+    }
+}
+
+/// Returns the stakes of the voters in `epoch`, summed from the balances before the staking
+/// rewards of the stake accounts cached for it.
+fn cached_stakes(cache: &RewardsCache, epoch: Epoch) -> anyhow::Result<HashMap<Pubkey, u64>> {
+    let stake_voters = cache.get_epoch_apy(epoch)?.unwrap_or_default();
+    let mut stakes = HashMap::new();
+    for reward in cache.get_epoch_rewards(epoch)?.unwrap_or_default() {
+        if reward.reward_type != Some(RewardType::Staking) {
+            continue;
+        }
+        let voter = reward
+            .pubkey
+            .parse::<Pubkey>()
+            .ok()
+            .and_then(|stake_account| stake_voters.get(&stake_account));
+        if let Some((voter, _)) = voter {
+            *stakes.entry(*voter).or_default() += reward
+                .post_balance
+                .saturating_sub(reward.lamports.max(0) as u64);
+        }
+    }
+    Ok(stakes)
+}
+
+/// Returns the unix time ranges of `epochs`, laid out back to back from the end of the production
+/// history of `voters` by the cached epoch lengths. Empty if there is no history.
+fn epoch_windows(
+    cache: &RewardsCache,
+    production: &ProductionHistory,
+    voters: &[Pubkey],
+    epochs: RangeInclusive<Epoch>,
+) -> anyhow::Result<HashMap<Epoch, (i64, i64)>> {
+    let mut end = None;
+    for voter in voters {
+        end = end.max(
+            production
+                .last_start(voter)?
+                .map(|start| start + BUCKET_SECS),
+        );
+    }
+    let mut end = match end {
+        Some(end) => end,
+        None => return Ok(HashMap::new()),
+    };
+    let mut windows = HashMap::new();
+    for epoch in epochs.rev() {
+        let days = cache
+            .get_epoch_length(epoch)?
+            .unwrap_or(DEFAULT_EPOCH_LENGTH);
+        let start = end - (days * SECONDS_IN_DAY as f64) as i64;
+        windows.insert(epoch, (start, end));
+        end = start;
+    }
+    Ok(windows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_exporter::persistent_database::PersistentDatabase;
+    use solana_exporter::rewards::caching::PubkeyVoterApyMapping;
+    use solana_exporter::uptime::caching::{ProductionBucket, PRODUCTION_HISTORY_TREE_NAME};
+    use solana_transaction_status_client_types::Reward;
+
+    #[test]
+    fn derives_stakes_and_epoch_windows_from_the_cache() {
+        // The fixture of the library's tests is not built into the binary's.
+        let dir = tempfile::TempDir::new().unwrap();
+        let database = PersistentDatabase::new(dir.path()).unwrap();
+        let cache = RewardsCache::open(&database).unwrap();
+        let production =
+            ProductionHistory::new(database.tree(PRODUCTION_HISTORY_TREE_NAME).unwrap());
+        let (voter, stake_a, stake_b) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let reward = |pubkey: &Pubkey, lamports, post_balance| Reward {
+            pubkey: pubkey.to_string(),
+            lamports,
+            post_balance,
+            reward_type: Some(RewardType::Staking),
+            commission: None,
+            commission_bps: None,
+        };
+        cache
+            .add_epoch_rewards(
+                5,
+                &[reward(&stake_a, 10, 1_010), reward(&stake_b, 5, 2_005)],
+            )
+            .unwrap();
+        let apys: PubkeyVoterApyMapping =
+            HashMap::from([(stake_a, (voter, 7.0)), (stake_b, (voter, 7.0))]);
+        cache.add_epoch_data(5, apys).unwrap();
+        assert_eq!(
+            cached_stakes(&cache, 5).unwrap(),
+            HashMap::from([(voter, 3_000)])
+        );
+
+        cache.add_epoch_length(5, 2.0).unwrap();
+        let bucket = ProductionBucket {
+            leader_slots: 4,
+            skipped_slots: 1,
+        };
+        production.add(&voter, 10 * 86_400, bucket).unwrap();
+        let windows = epoch_windows(&cache, &production, &[voter], 4..=5).unwrap();
+        let end = 10 * 86_400 + BUCKET_SECS;
+        assert_eq!(windows[&5], (end - 2 * 86_400, end));
+        assert_eq!(windows[&4], (end - 5 * 86_400, end - 2 * 86_400));
+    }
+}
@@ -0,0 +1,106 @@
+//! A sink writing to InfluxDB v2 in line protocol.
+
+use crate::config::InfluxDbConfig;
+use crate::sinks::{Sample, Sink};
+use anyhow::Context;
+use async_trait::async_trait;
+use std::fmt::Write;
+use time::OffsetDateTime;
+
+/// Writes every sample as a point of the measurement named after the metric, with the labels as
+/// tags and the value in the `value` field.
+pub struct InfluxDbSink {
+    client: reqwest::Client,
+    config: InfluxDbConfig,
+}
+
+impl InfluxDbSink {
+    /// Makes a sink writing to the endpoint of `config` using `client`.
+    pub fn new(client: reqwest::Client, config: InfluxDbConfig) -> Self {
+        Self { client, config }
+    }
+}
+
+#[async_trait]
+impl Sink for InfluxDbSink {
+    fn name(&self) -> &'static str {
+        "InfluxDB"
+    }
+
+    async fn write(&self, samples: &[Sample], timestamp: OffsetDateTime) -> anyhow::Result<()> {
+        let url = format!("{}/api/v2/write", self.config.url.trim_end_matches('/'));
+        self.client
+            .post(&url)
+            .query(&[
+                ("org", self.config.org.as_str()),
+                ("bucket", self.config.bucket.as_str()),
+                ("precision", "ms"),
+            ])
+            .header("Authorization", format!("Token {}", self.config.token))
+            .body(line_protocol(samples, timestamp))
+            .send()
+            .await
+            .with_context(|| format!("could not write to {}", url))?
+            .error_for_status()
+            .with_context(|| format!("InfluxDB at {} rejected the write", url))?;
+        Ok(())
+    }
+}
+
+/// Formats `samples` as line protocol with millisecond timestamps. Non-finite values, which
+/// InfluxDB rejects, and empty label values, which mean an unset label, are left out.
+fn line_protocol(samples: &[Sample], timestamp: OffsetDateTime) -> String {
+    let timestamp = timestamp.unix_timestamp_nanos() / 1_000_000;
+    let mut lines = String::new();
+    for sample in samples.iter().filter(|s| s.value.is_finite()) {
+        lines.push_str(&escape(&sample.name, &[',', ' ']));
+        for (name, value) in sample.labels.iter().filter(|(_, v)| !v.is_empty()) {
+            lines.push(',');
+            lines.push_str(&escape(name, &[',', '=', ' ']));
+            lines.push('=');
+            lines.push_str(&escape(value, &[',', '=', ' ']));
+        }
+        // Writing to a string cannot fail.
+        let _ = writeln!(lines, " value={} {}", sample.value, timestamp);
+    }
+    lines
+}
+
+/// Escapes backslashes and `special` characters with a backslash.
+fn escape(s: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '\\' || special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_line_protocol() {
+        let sample = |labels: &[(&str, &str)], value| Sample {
+            name: "solana_node_versions".to_string(),
+            labels: labels
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            value,
+        };
+        let samples = vec![
+            sample(&[("version", "1.18.0 (src:abc, feat:1=2)")], 3.0),
+            sample(&[("version", "")], 0.5),
+            sample(&[], f64::NAN),
+        ];
+        assert_eq!(
+            line_protocol(&samples, OffsetDateTime::from_unix_timestamp(1_700_000_000)),
+            "solana_node_versions,version=1.18.0\\ (src:abc\\,\\ feat:1\\=2) value=3 1700000000000\n\
+             solana_node_versions value=0.5 1700000000000\n"
+        );
+    }
+}
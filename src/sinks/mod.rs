@@ -0,0 +1,147 @@
+//! Outputs the collected metrics are pushed to after every update cycle, in addition to being
+//! served to Prometheus.
+
+use async_trait::async_trait;
+use prometheus_exporter::prometheus::proto::{MetricFamily, MetricType};
+use time::OffsetDateTime;
+
+pub mod influxdb;
+pub mod plaintext;
+
+/// A single value of a metric series, as in the Prometheus text format: histograms and summaries
+/// are split into their `_bucket` or quantile, `_sum` and `_count` series.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    /// Metric name.
+    pub name: String,
+    /// Label names and values.
+    pub labels: Vec<(String, String)>,
+    /// Value of the series.
+    pub value: f64,
+}
+
+/// A destination the metrics are written to after every update cycle.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    /// Short name of the sink, used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Writes the `samples` collected at `timestamp`.
+    async fn write(&self, samples: &[Sample], timestamp: OffsetDateTime) -> anyhow::Result<()>;
+}
+
+/// Flattens metric families into samples.
+pub fn samples(families: &[MetricFamily]) -> Vec<Sample> {
+    let mut samples = Vec::new();
+    for family in families {
+        let name = family.get_name();
+        for metric in family.get_metric() {
+            let labels: Vec<_> = metric
+                .get_label()
+                .iter()
+                .map(|l| (l.get_name().to_string(), l.get_value().to_string()))
+                .collect();
+            let mut push = |suffix: &str, extra: Option<(&str, f64)>, value: f64| {
+                let mut labels = labels.clone();
+                if let Some((label, bound)) = extra {
+                    labels.push((label.to_string(), format_bound(bound)));
+                }
+                samples.push(Sample {
+                    name: format!("{}{}", name, suffix),
+                    labels,
+                    value,
+                });
+            };
+            match family.get_field_type() {
+                MetricType::COUNTER => push("", None, metric.get_counter().get_value()),
+                MetricType::GAUGE => push("", None, metric.get_gauge().get_value()),
+                // Only made by custom collectors, of which there are none.
+                MetricType::UNTYPED => {}
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    for bucket in histogram.get_bucket() {
+                        push(
+                            "_bucket",
+                            Some(("le", bucket.get_upper_bound())),
+                            bucket.get_cumulative_count() as f64,
+                        );
+                    }
+                    let count = histogram.get_sample_count();
+                    push("_bucket", Some(("le", f64::INFINITY)), count as f64);
+                    push("_sum", None, histogram.get_sample_sum());
+                    push("_count", None, count as f64);
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        push(
+                            "",
+                            Some(("quantile", quantile.get_quantile())),
+                            quantile.get_value(),
+                        );
+                    }
+                    push("_sum", None, summary.get_sample_sum());
+                    push("_count", None, summary.get_sample_count() as f64);
+                }
+            }
+        }
+    }
+    samples
+}
+
+/// Formats a bucket bound or quantile as in the Prometheus text format.
+fn format_bound(bound: f64) -> String {
+    if bound == f64::INFINITY {
+        "+Inf".to_string()
+    } else {
+        bound.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_exporter::prometheus::{HistogramOpts, HistogramVec, IntGauge, Registry};
+
+    #[test]
+    fn flattens_histograms() {
+        let registry = Registry::new();
+        let gauge = IntGauge::new("nodes", "Nodes").unwrap();
+        let histogram = HistogramVec::new(
+            HistogramOpts::new("duration", "Duration").buckets(vec![1.0]),
+            &["method"],
+        )
+        .unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+        gauge.set(3);
+        histogram.with_label_values(&["getBlock"]).observe(2.0);
+
+        let sample = |name: &str, labels: &[(&str, &str)], value| Sample {
+            name: name.to_string(),
+            labels: labels
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            value,
+        };
+        assert_eq!(
+            samples(&registry.gather()),
+            vec![
+                sample(
+                    "duration_bucket",
+                    &[("method", "getBlock"), ("le", "1")],
+                    0.0
+                ),
+                sample(
+                    "duration_bucket",
+                    &[("method", "getBlock"), ("le", "+Inf")],
+                    1.0
+                ),
+                sample("duration_sum", &[("method", "getBlock")], 2.0),
+                sample("duration_count", &[("method", "getBlock")], 1.0),
+                sample("nodes", &[], 3.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! A sink pushing gauge values in StatsD or Graphite plaintext format over UDP or TCP.
+
+use crate::config::{PlaintextFormat, PlaintextSinkConfig, Transport};
+use crate::sinks::{Sample, Sink};
+use anyhow::Context;
+use async_trait::async_trait;
+use std::fmt::Write;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::timeout;
+
+/// Largest UDP datagram sent, small enough not to be fragmented on common networks.
+const MAX_DATAGRAM_SIZE: usize = 1432;
+
+/// Time allowed for connecting to the receiver and sending the lines of a cycle.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pushes every sample as a gauge, named after the metric with the labels as tags: DogStatsD
+/// `|#name:value` tags for StatsD, and `;name=value` tags for Graphite.
+pub struct PlaintextSink {
+    config: PlaintextSinkConfig,
+}
+
+impl PlaintextSink {
+    /// Makes a sink pushing to the receiver of `config`.
+    pub fn new(config: PlaintextSinkConfig) -> Self {
+        Self { config }
+    }
+
+    /// Returns the lines of `samples`.
+    fn lines(&self, samples: &[Sample], timestamp: OffsetDateTime) -> Vec<String> {
+        let prefix = self.config.prefix.as_deref();
+        match self.config.format {
+            PlaintextFormat::Statsd => statsd_lines(samples, prefix),
+            PlaintextFormat::Graphite => graphite_lines(samples, prefix, timestamp),
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for PlaintextSink {
+    fn name(&self) -> &'static str {
+        match self.config.format {
+            PlaintextFormat::Statsd => "StatsD",
+            PlaintextFormat::Graphite => "Graphite",
+        }
+    }
+
+    async fn write(&self, samples: &[Sample], timestamp: OffsetDateTime) -> anyhow::Result<()> {
+        let lines = self.lines(samples, timestamp);
+        let address = self.config.address.as_str();
+        let send = async {
+            match self.config.transport.unwrap_or_default() {
+                Transport::Udp => {
+                    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+                    socket.connect(address).await?;
+                    for datagram in datagrams(&lines) {
+                        socket.send(datagram.as_bytes()).await?;
+                    }
+                }
+                Transport::Tcp => {
+                    let mut stream = TcpStream::connect(address).await?;
+                    stream.write_all(lines.concat().as_bytes()).await?;
+                    stream.shutdown().await?;
+                }
+            }
+            anyhow::Ok(())
+        };
+        timeout(SEND_TIMEOUT, send)
+            .await
+            .with_context(|| format!("timed out sending to {}", address))?
+            .with_context(|| format!("could not send to {}", address))
+    }
+}
+
+/// Formats `samples` as StatsD gauges. A negative value is sent after a zero, since a signed
+/// gauge value is otherwise taken as a change.
+fn statsd_lines(samples: &[Sample], prefix: Option<&str>) -> Vec<String> {
+    let mut lines = Vec::new();
+    for sample in samples.iter().filter(|s| s.value.is_finite()) {
+        let name = prefixed(
+            &sanitize(&sample.name, &[':', '|', '@', '#', ','], '_'),
+            prefix,
+        );
+        let mut tags = String::new();
+        for (label, value) in sample.labels.iter().filter(|(_, v)| !v.is_empty()) {
+            tags.push(if tags.is_empty() { '#' } else { ',' });
+            let _ = write!(
+                tags,
+                "{}:{}",
+                sanitize(label, &[':', '|', '@', '#', ','], '_'),
+                sanitize(value, &['|', '@', '#', ','], '_')
+            );
+        }
+        let line = |value: f64| {
+            if tags.is_empty() {
+                format!("{}:{}|g\n", name, value)
+            } else {
+                format!("{}:{}|g|{}\n", name, value, tags)
+            }
+        };
+        if sample.value < 0.0 {
+            lines.push(line(0.0));
+        }
+        lines.push(line(sample.value));
+    }
+    lines
+}
+
+/// Formats `samples` as tagged Graphite plaintext lines with second timestamps.
+fn graphite_lines(
+    samples: &[Sample],
+    prefix: Option<&str>,
+    timestamp: OffsetDateTime,
+) -> Vec<String> {
+    let timestamp = timestamp.unix_timestamp();
+    let mut lines = Vec::new();
+    for sample in samples.iter().filter(|s| s.value.is_finite()) {
+        let mut path = prefixed(&sanitize(&sample.name, &[';', '~', '='], '_'), prefix);
+        for (label, value) in sample.labels.iter().filter(|(_, v)| !v.is_empty()) {
+            let _ = write!(
+                path,
+                ";{}={}",
+                sanitize(label, &[';', '!', '^', '=', '~'], '_'),
+                sanitize(value, &[';', '~'], '_')
+            );
+        }
+        lines.push(format!("{} {} {}\n", path, sample.value, timestamp));
+    }
+    lines
+}
+
+/// Prepends `prefix` and a dot to `name`, if given.
+fn prefixed(name: &str, prefix: Option<&str>) -> String {
+    match prefix {
+        Some(prefix) => format!("{}.{}", prefix, name),
+        None => name.to_string(),
+    }
+}
+
+/// Replaces whitespace and `special` characters of `s` with `replacement`.
+fn sanitize(s: &str, special: &[char], replacement: char) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_whitespace() || special.contains(&c) {
+                replacement
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Packs `lines` into as few datagrams of at most `MAX_DATAGRAM_SIZE` bytes as possible. Longer
+/// lines are sent on their own.
+fn datagrams(lines: &[String]) -> Vec<String> {
+    let mut datagrams = Vec::new();
+    let mut datagram = String::new();
+    for line in lines {
+        if !datagram.is_empty() && datagram.len() + line.len() > MAX_DATAGRAM_SIZE {
+            datagrams.push(std::mem::take(&mut datagram));
+        }
+        datagram.push_str(line);
+    }
+    if !datagram.is_empty() {
+        datagrams.push(datagram);
+    }
+    datagrams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> Vec<Sample> {
+        vec![
+            Sample {
+                name: "solana_node_versions".to_string(),
+                labels: vec![("version".to_string(), "1.18.0 (src:abc)".to_string())],
+                value: 3.0,
+            },
+            Sample {
+                name: "solana_vote_fees".to_string(),
+                labels: vec![("pubkey".to_string(), String::new())],
+                value: -2.5,
+            },
+        ]
+    }
+
+    #[test]
+    fn formats_statsd_gauges() {
+        assert_eq!(
+            statsd_lines(&samples(), Some("validators")),
+            vec![
+                "validators.solana_node_versions:3|g|#version:1.18.0_(src:abc)\n",
+                "validators.solana_vote_fees:0|g\n",
+                "validators.solana_vote_fees:-2.5|g\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_graphite_lines() {
+        assert_eq!(
+            graphite_lines(
+                &samples(),
+                None,
+                OffsetDateTime::from_unix_timestamp(1_700_000_000)
+            ),
+            vec![
+                "solana_node_versions;version=1.18.0_(src:abc) 3 1700000000\n",
+                "solana_vote_fees -2.5 1700000000\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn packs_lines_into_datagrams() {
+        let line = "x".repeat(600) + "\n";
+        let lines = vec![line.clone(), line.clone(), line.clone(), "x".repeat(2000)];
+        let sizes: Vec<_> = datagrams(&lines).iter().map(String::len).collect();
+        assert_eq!(sizes, vec![1202, 601, 2000]);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::persistent_database::envelope::{self, Encoding};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use solana_clock::Epoch;
+use std::collections::HashMap;
+
+/// Name of the tree holding the last block production snapshot.
+pub const BLOCK_PRODUCTION_TREE_NAME: &str = "block_production";
+
+/// Name of the tree holding the leader slots of identities in the current epoch.
+pub const LEADER_SCHEDULE_TREE_NAME: &str = "leader_schedule";
+
+const SNAPSHOT_KEY: &str = "snapshot";
+
+/// The last observed epoch-to-date block production, used as the baseline for counter deltas.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProductionSnapshot {
+    /// First slot of the epoch the snapshot belongs to.
+    pub epoch_first_slot: u64,
+    /// `(leader slots, blocks produced)` per identity.
+    pub production: HashMap<String, (usize, usize)>,
+}
+
+/// A caching database for block production, so that a restart in the middle of an epoch does not
+/// count the epoch-to-date production twice.
+pub struct SlotsCache {
+    tree: sled::Tree,
+}
+
+impl SlotsCache {
+    /// Creates a new cache using a tree.
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+
+    /// Returns the last persisted production snapshot.
+    pub fn get_production_snapshot(&self) -> anyhow::Result<Option<ProductionSnapshot>> {
+        self.tree
+            .get(SNAPSHOT_KEY)
+            .context("could not fetch production snapshot from database")?
+            .map(|x| envelope::decode(&x))
+            .transpose()
+            .context("could not deserialize fetched production snapshot")
+    }
+
+    /// Persists a production snapshot.
+    pub fn set_production_snapshot(&self, snapshot: &ProductionSnapshot) -> anyhow::Result<()> {
+        self.tree
+            .insert(SNAPSHOT_KEY, envelope::encode(Encoding::Json, snapshot)?)
+            .context("could not insert production snapshot into database")?;
+        Ok(())
+    }
+}
+
+/// A caching database for the leader slots of identities, so that a restart in the middle of an
+/// epoch does not fetch the leader schedule again. Only the latest epoch is kept.
+pub struct LeaderScheduleCache {
+    tree: sled::Tree,
+}
+
+impl LeaderScheduleCache {
+    /// Creates a new cache using a tree.
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+
+    /// Returns the cached leader slot indices of `identity` in `epoch`.
+    pub fn get_leader_slots(
+        &self,
+        epoch: Epoch,
+        identity: &str,
+    ) -> anyhow::Result<Option<Vec<usize>>> {
+        self.tree
+            .get(leader_slots_key(epoch, identity))
+            .context("could not fetch leader slots from database")?
+            .map(|x| envelope::decode(&x))
+            .transpose()
+            .context("could not deserialize fetched leader slots")
+    }
+
+    /// Persists the leader slot indices of `identity` in `epoch`, removing those of all earlier
+    /// epochs.
+    pub fn add_leader_slots(
+        &self,
+        epoch: Epoch,
+        identity: &str,
+        slot_indices: &[usize],
+    ) -> anyhow::Result<()> {
+        for kv in self.tree.range(..epoch.to_be_bytes()) {
+            let (k, _) = kv.context("could not read leader slots from database")?;
+            self.tree
+                .remove(k)
+                .context("could not remove leader slots from database")?;
+        }
+        self.tree
+            .insert(
+                leader_slots_key(epoch, identity),
+                envelope::encode(Encoding::Bincode, slot_indices)?,
+            )
+            .context("could not insert leader slots into database")?;
+        Ok(())
+    }
+}
+
+/// The key of the leader slots of `identity` in `epoch`: the big-endian epoch, so that earlier
+/// epochs sort first, followed by the identity.
+fn leader_slots_key(epoch: Epoch, identity: &str) -> Vec<u8> {
+    let mut key = epoch.to_be_bytes().to_vec();
+    key.extend_from_slice(identity.as_bytes());
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_leader_slots_of_the_latest_epoch() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let cache = LeaderScheduleCache::new(db.open_tree(LEADER_SCHEDULE_TREE_NAME).unwrap());
+        cache.add_leader_slots(9, "a", &[4, 5]).unwrap();
+        cache.add_leader_slots(10, "a", &[0, 1]).unwrap();
+        cache.add_leader_slots(10, "b", &[]).unwrap();
+
+        assert_eq!(cache.get_leader_slots(10, "a").unwrap(), Some(vec![0, 1]));
+        assert_eq!(cache.get_leader_slots(10, "b").unwrap(), Some(vec![]));
+        assert_eq!(cache.get_leader_slots(10, "c").unwrap(), None);
+        assert_eq!(cache.get_leader_slots(9, "a").unwrap(), None);
+    }
+}
@@ -0,0 +1,577 @@
+//! Statistics of skipped and validated slots.
+
+use crate::config::Whitelist;
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::persistent_database::counters::CounterBaselines;
+use crate::rpc_api::RpcApi;
+use crate::slots::caching::{ProductionSnapshot, SlotsCache};
+use crate::uptime::caching::{bucket_start, ProductionBucket, ProductionHistory};
+use async_trait::async_trait;
+use log::debug;
+use prometheus_exporter::prometheus::{GaugeVec, IntCounterVec};
+use solana_client::rpc_config::RpcBlockProductionConfig;
+use solana_client::rpc_response::RpcVoteAccountStatus;
+use solana_commitment_config::CommitmentConfig;
+use solana_pubkey::Pubkey;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use time::OffsetDateTime;
+
+pub mod caching;
+
+/// The monitor of skipped and validated slots per validator with minimal internal state.
+///
+/// Each cycle issues a single unfiltered `getBlockProduction` call, which the
+/// RPC node answers from its own epoch-to-date production tracking: a map of
+/// node identity pubkey to `(leader slots, blocks produced)` for the current
+/// epoch. That one cheap call covers the whole epoch so far, so there is no
+/// leader-schedule download, no `getBlocks` range scanning, and no cold-start
+/// backfill against the RPC's long-term block store. The counter totals and the last snapshot are
+/// persisted, so the counters carry on across restarts rather than resetting to zero. The deltas
+/// of whitelisted validators are also added to their hourly production history, keyed by vote
+/// account, for uptime reports, but only between two responses of the same run: a delta spanning
+/// a restart, or the epoch so far when it was not seen from its start, would all be attributed to
+/// a single hour.
+pub struct SkippedSlotsMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Prometheus counter.
+    leader_slots: &'a IntCounterVec,
+    /// Prometheus gauge.
+    skipped_slot_percent: &'a GaugeVec,
+    /// Prometheus gauge of the skip rate relative to all leaders of the epoch.
+    skipped_slot_percentile: &'a GaugeVec,
+    /// Prometheus gauge of the skip rate minus the stake-weighted skip rate of all leaders.
+    skipped_slot_percent_vs_cluster: &'a GaugeVec,
+    /// Persisted counter totals.
+    baselines: &'a CounterBaselines,
+    /// Caching database for the production snapshot.
+    cache: &'a SlotsCache,
+    /// Persisted hourly production history.
+    history: &'a ProductionHistory,
+    /// The last `getBlockProduction` snapshot. Its `epoch_first_slot` is
+    /// `range.first_slot` of the response and identifies the epoch the
+    /// baseline belongs to; taken from the response itself so an epoch
+    /// rollover mid-cycle cannot skew the baseline. The last observed
+    /// `(leader slots, blocks produced)` per identity are used to increment
+    /// the counters by the per-cycle delta.
+    snapshot: ProductionSnapshot,
+    /// Whether the snapshot was taken by this run rather than restored from the cache.
+    snapshot_current: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum SlotStatus {
+    Skipped,
+    Validated,
+}
+
+impl Display for SlotStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            SlotStatus::Skipped => "skipped",
+            SlotStatus::Validated => "validated",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl<'a> SkippedSlotsMonitor<'a> {
+    /// Constructs a monitor given `rpc`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        rpc: &'a dyn RpcApi,
+        leader_slots: &'a IntCounterVec,
+        skipped_slot_percent: &'a GaugeVec,
+        skipped_slot_percentile: &'a GaugeVec,
+        skipped_slot_percent_vs_cluster: &'a GaugeVec,
+        baselines: &'a CounterBaselines,
+        cache: &'a SlotsCache,
+        history: &'a ProductionHistory,
+    ) -> Self {
+        Self {
+            rpc,
+            leader_slots,
+            skipped_slot_percent,
+            skipped_slot_percentile,
+            skipped_slot_percent_vs_cluster,
+            baselines,
+            cache,
+            history,
+            snapshot: ProductionSnapshot::default(),
+            snapshot_current: false,
+        }
+    }
+
+    /// Exports the skipped slot statistics for the current epoch. `vote_accounts` maps identities
+    /// to the vote accounts their production history is recorded under.
+    pub async fn export_skipped_slots(
+        &mut self,
+        node_whitelist: &Whitelist,
+        vote_accounts: &RpcVoteAccountStatus,
+    ) -> anyhow::Result<()> {
+        // Pin the query to `finalized`. With an unset commitment and `range: None`
+        // the node derives `last_slot = bank.slot()` for whichever (often
+        // unrooted, tip) bank it picks, then bound-checks that range against its
+        // own SlotHistory sysvar. On testnet that bank's slot routinely runs
+        // ahead of the newest slot in history (skipped slots, forks, snapshot
+        // restarts), so the node rejects its own computed range with
+        // "lastSlot ... is too large" / "No slot history". A finalized bank's
+        // slot is rooted and therefore present in that same bank's SlotHistory,
+        // so the self-check cannot trip. `range: None` still scopes the response
+        // to the current epoch.
+        let production = self
+            .rpc
+            .get_block_production_with_config(RpcBlockProductionConfig {
+                identity: None,
+                range: None,
+                commitment: Some(CommitmentConfig::finalized()),
+            })
+            .await?;
+
+        let new_epoch = production.range.first_slot != self.snapshot.epoch_first_slot;
+        let mut record_history = self.snapshot_current;
+        if new_epoch {
+            // New epoch: production numbers restart from zero, so the counter
+            // baseline must too. Its start was only seen if the previous snapshot
+            // was taken by this run in the epoch right before.
+            record_history = record_history
+                && self
+                    .follows_snapshot_epoch(production.range.first_slot)
+                    .await?;
+            self.snapshot.epoch_first_slot = production.range.first_slot;
+            self.snapshot.production.clear();
+            debug!(
+                "SkippedSlotsMonitor reset for epoch starting at slot {}",
+                self.snapshot.epoch_first_slot
+            );
+        }
+
+        // Skip rates of all leaders of the epoch so far, to rank the whitelisted ones against.
+        let mut skip_rates: Vec<f64> = production
+            .by_identity
+            .values()
+            .filter_map(|(leader_slots, blocks_produced)| {
+                skip_rate(*leader_slots, *blocks_produced)
+            })
+            .collect();
+        skip_rates.sort_by(|a, b| a.total_cmp(b));
+        let cluster_skip_rate = stake_weighted_skip_rate(&production.by_identity, vote_accounts);
+
+        // Without a whitelist every leader is exported, but the history is only kept for
+        // whitelisted validators.
+        let vote_pubkeys: HashMap<_, _> = if node_whitelist.0.is_empty() {
+            HashMap::new()
+        } else {
+            vote_accounts
+                .current
+                .iter()
+                .chain(&vote_accounts.delinquent)
+                .map(|v| (v.node_pubkey.as_str(), v.vote_pubkey.as_str()))
+                .collect()
+        };
+        let history_start = bucket_start(OffsetDateTime::now_utc().unix_timestamp());
+
+        let mut snapshot = HashMap::new();
+        for (identity, (leader_slots, blocks_produced)) in production.by_identity {
+            if !node_whitelist.contains(&identity) {
+                continue;
+            }
+
+            let (prev_leader_slots, prev_blocks_produced) = self
+                .snapshot
+                .production
+                .get(&identity)
+                .copied()
+                .unwrap_or_default();
+            // Saturating arithmetic absorbs the occasional regression when a
+            // load-balanced RPC pool answers consecutive calls from nodes with
+            // slightly different views of the epoch.
+            let delta_validated = blocks_produced.saturating_sub(prev_blocks_produced);
+            let delta_skipped = leader_slots
+                .saturating_sub(prev_leader_slots)
+                .saturating_sub(delta_validated);
+            self.baselines.inc_by(
+                self.leader_slots,
+                &[&identity, &SlotStatus::Validated.to_string()],
+                delta_validated as u64,
+            )?;
+            self.baselines.inc_by(
+                self.leader_slots,
+                &[&identity, &SlotStatus::Skipped.to_string()],
+                delta_skipped as u64,
+            )?;
+            if let (Some(vote_pubkey), true) = (
+                vote_pubkeys.get(identity.as_str()),
+                record_history && delta_validated + delta_skipped > 0,
+            ) {
+                self.history.add(
+                    &vote_pubkey.parse::<Pubkey>()?,
+                    history_start,
+                    ProductionBucket {
+                        leader_slots: (delta_validated + delta_skipped) as u64,
+                        skipped_slots: delta_skipped as u64,
+                    },
+                )?;
+            }
+
+            // The percentage is set from the epoch-to-date absolutes rather
+            // than the counters, so it is exact regardless of counter resets.
+            if let Some(skipped_percent) = skip_rate(leader_slots, blocks_produced) {
+                self.skipped_slot_percent
+                    .get_metric_with_label_values(&[&identity])
+                    .map(|c| c.set(skipped_percent))?;
+                self.skipped_slot_percentile
+                    .get_metric_with_label_values(&[&identity])
+                    .map(|c| c.set(percentile_rank(&skip_rates, skipped_percent)))?;
+                if let Some(cluster_skip_rate) = cluster_skip_rate {
+                    self.skipped_slot_percent_vs_cluster
+                        .get_metric_with_label_values(&[&identity])
+                        .map(|c| c.set(skipped_percent - cluster_skip_rate))?;
+                }
+            }
+
+            snapshot.insert(identity, (leader_slots, blocks_produced));
+        }
+        if new_epoch || snapshot != self.snapshot.production {
+            self.snapshot.production = snapshot;
+            self.cache.set_production_snapshot(&self.snapshot)?;
+        }
+        self.snapshot_current = true;
+
+        debug!("Exported leader slots");
+        Ok(())
+    }
+
+    /// Returns whether the epoch starting at `first_slot` directly follows the epoch of the
+    /// snapshot.
+    async fn follows_snapshot_epoch(&self, first_slot: u64) -> anyhow::Result<bool> {
+        let epoch_schedule = self.rpc.get_epoch_schedule().await?;
+        let snapshot_epoch = epoch_schedule.get_epoch(self.snapshot.epoch_first_slot);
+        Ok(epoch_schedule.get_last_slot_in_epoch(snapshot_epoch) + 1 == first_slot)
+    }
+}
+
+/// Returns the percentage of `leader_slots` that were skipped, or `None` if there were none.
+pub(crate) fn skip_rate(leader_slots: usize, blocks_produced: usize) -> Option<f64> {
+    if leader_slots == 0 {
+        return None;
+    }
+    let skipped = leader_slots - blocks_produced.min(leader_slots);
+    Some((skipped as f64 / leader_slots as f64) * 100.0)
+}
+
+/// Returns the average skip rate of the leaders of `by_identity`, weighted by the activated stake
+/// of their vote accounts in `vote_accounts`, or `None` if no staked leader had a leader slot yet.
+fn stake_weighted_skip_rate(
+    by_identity: &HashMap<String, (usize, usize)>,
+    vote_accounts: &RpcVoteAccountStatus,
+) -> Option<f64> {
+    let mut stakes: HashMap<&str, u64> = HashMap::new();
+    for v in vote_accounts
+        .current
+        .iter()
+        .chain(&vote_accounts.delinquent)
+    {
+        *stakes.entry(v.node_pubkey.as_str()).or_default() += v.activated_stake;
+    }
+    let (weighted, total_stake) = by_identity
+        .iter()
+        .filter_map(|(identity, (leader_slots, blocks_produced))| {
+            let stake = *stakes.get(identity.as_str())? as f64;
+            Some((skip_rate(*leader_slots, *blocks_produced)? * stake, stake))
+        })
+        .fold((0.0, 0.0), |(w, t), (rate, stake)| (w + rate, t + stake));
+    (total_stake > 0.0).then(|| weighted / total_stake)
+}
+
+/// Returns the percentage of `sorted` values below `value`, counting values equal to it as half
+/// below. 0 means `value` is lower than all others.
+fn percentile_rank(sorted: &[f64], value: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let below = sorted.partition_point(|v| *v < value);
+    let equal = sorted[below..].partition_point(|v| *v <= value);
+    (below as f64 + equal as f64 / 2.0) / sorted.len() as f64 * 100.0
+}
+
+#[async_trait]
+impl Monitor for SkippedSlotsMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "skipped slots"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(self.leader_slots),
+            family_name(self.skipped_slot_percent),
+            family_name(self.skipped_slot_percentile),
+            family_name(self.skipped_slot_percent_vs_cluster),
+        ]
+    }
+
+    /// Restores the counter totals and resumes from the persisted snapshot if there is one.
+    async fn setup(&mut self) -> anyhow::Result<()> {
+        self.baselines.restore(self.leader_slots)?;
+        self.snapshot = self.cache.get_production_snapshot()?.unwrap_or_default();
+        Ok(())
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.export_skipped_slots(&cycle.node_whitelist, &cycle.vote_accounts)
+            .await
+    }
+
+    fn epoch_metrics(&self) -> Vec<String> {
+        vec![
+            family_name(self.skipped_slot_percent),
+            family_name(self.skipped_slot_percentile),
+            family_name(self.skipped_slot_percent_vs_cluster),
+        ]
+    }
+
+    /// Block production is read at `finalized` commitment, so it reaches a new epoch a few dozen
+    /// slots after the epoch info does.
+    fn epoch_complete(&self, cycle: &Cycle) -> bool {
+        let epoch_info = &cycle.epoch_info;
+        self.snapshot.epoch_first_slot == epoch_info.absolute_slot - epoch_info.slot_index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_api::mock::MockRpc;
+    use crate::uptime::caching::PRODUCTION_HISTORY_TREE_NAME;
+    use prometheus_exporter::prometheus::Opts;
+    use solana_client::rpc_response::{
+        RpcBlockProduction, RpcBlockProductionRange, RpcVoteAccountInfo,
+    };
+    use solana_epoch_schedule::EpochSchedule;
+    use std::sync::Mutex;
+
+    fn production(first_slot: u64, by_identity: &[(&str, (usize, usize))]) -> RpcBlockProduction {
+        RpcBlockProduction {
+            by_identity: by_identity
+                .iter()
+                .map(|(identity, p)| (identity.to_string(), *p))
+                .collect(),
+            range: RpcBlockProductionRange {
+                first_slot,
+                last_slot: first_slot + 100,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_skipped_slots_across_cycles_and_epochs() {
+        let rpc = MockRpc {
+            block_production: Mutex::new(
+                vec![
+                    production(0, &[("a", (10, 8)), ("b", (4, 4))]),
+                    production(0, &[("a", (14, 11)), ("b", (4, 4))]),
+                    production(432_000, &[("a", (4, 3))]),
+                    production(864_000, &[("a", (6, 4))]),
+                ]
+                .into(),
+            ),
+            epoch_schedule: Some(EpochSchedule::without_warmup()),
+            ..MockRpc::default()
+        };
+        let leader_slots = IntCounterVec::new(
+            Opts::new("leader_slots", "Leader slots"),
+            &["pubkey", "status"],
+        )
+        .unwrap();
+        let skipped_slot_percent =
+            GaugeVec::new(Opts::new("skipped_percent", "Skipped percent"), &["pubkey"]).unwrap();
+        let skipped_slot_percentile = GaugeVec::new(
+            Opts::new("skipped_percentile", "Skipped percentile"),
+            &["pubkey"],
+        )
+        .unwrap();
+        let skipped_slot_percent_vs_cluster = GaugeVec::new(
+            Opts::new("skipped_percent_vs_cluster", "Skipped percent vs cluster"),
+            &["pubkey"],
+        )
+        .unwrap();
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let baselines = CounterBaselines::new(db.open_tree("counters").unwrap());
+        let cache = SlotsCache::new(db.open_tree("block_production").unwrap());
+        let history = ProductionHistory::new(db.open_tree(PRODUCTION_HISTORY_TREE_NAME).unwrap());
+        let mut monitor = SkippedSlotsMonitor::new(
+            &rpc,
+            &leader_slots,
+            &skipped_slot_percent,
+            &skipped_slot_percentile,
+            &skipped_slot_percent_vs_cluster,
+            &baselines,
+            &cache,
+            &history,
+        );
+        let whitelist = Whitelist::new(std::iter::once("a".to_string()).collect());
+        let vote_pubkey = Pubkey::new_unique();
+        let vote_accounts = RpcVoteAccountStatus {
+            current: vec![RpcVoteAccountInfo {
+                vote_pubkey: vote_pubkey.to_string(),
+                node_pubkey: "a".to_string(),
+                activated_stake: 0,
+                commission: 0,
+                epoch_vote_account: true,
+                epoch_credits: vec![],
+                last_vote: 0,
+                root_slot: 0,
+            }],
+            delinquent: vec![],
+        };
+        let count = |status: SlotStatus| {
+            leader_slots
+                .with_label_values(&["a", &status.to_string()])
+                .get()
+        };
+
+        monitor
+            .export_skipped_slots(&whitelist, &vote_accounts)
+            .await
+            .unwrap();
+        assert_eq!(count(SlotStatus::Validated), 8);
+        assert_eq!(count(SlotStatus::Skipped), 2);
+        assert_eq!(skipped_slot_percent.with_label_values(&["a"]).get(), 20.0);
+        // `a` skipped more than `b`, which is ranked without being exported.
+        assert_eq!(
+            skipped_slot_percentile.with_label_values(&["a"]).get(),
+            75.0
+        );
+
+        monitor
+            .export_skipped_slots(&whitelist, &vote_accounts)
+            .await
+            .unwrap();
+        assert_eq!(count(SlotStatus::Validated), 11);
+        assert_eq!(count(SlotStatus::Skipped), 3);
+        assert_eq!(
+            skipped_slot_percent.with_label_values(&["a"]).get(),
+            3.0 / 14.0 * 100.0
+        );
+
+        // Production restarts from zero in a new epoch, the counters carry on.
+        monitor
+            .export_skipped_slots(&whitelist, &vote_accounts)
+            .await
+            .unwrap();
+        assert_eq!(count(SlotStatus::Validated), 14);
+        assert_eq!(count(SlotStatus::Skipped), 4);
+        assert_eq!(skipped_slot_percent.with_label_values(&["a"]).get(), 25.0);
+
+        // Identities outside the whitelist are not exported.
+        assert_eq!(
+            leader_slots
+                .with_label_values(&["b", &SlotStatus::Validated.to_string()])
+                .get(),
+            0
+        );
+        assert_eq!(
+            cache
+                .get_production_snapshot()
+                .unwrap()
+                .unwrap()
+                .epoch_first_slot,
+            432_000
+        );
+        // The epoch so far at the first cycle is left out of the history, the new epoch was seen
+        // from its start.
+        assert_eq!(
+            history.total(&vote_pubkey, 0, i64::MAX).unwrap(),
+            ProductionBucket {
+                leader_slots: 8,
+                skipped_slots: 2,
+            }
+        );
+
+        // After a restart across an epoch boundary, the counters carry on but the history
+        // misses the downtime rather than attributing it to one hour.
+        let leader_slots = IntCounterVec::new(
+            Opts::new("leader_slots", "Leader slots"),
+            &["pubkey", "status"],
+        )
+        .unwrap();
+        let mut monitor = SkippedSlotsMonitor::new(
+            &rpc,
+            &leader_slots,
+            &skipped_slot_percent,
+            &skipped_slot_percentile,
+            &skipped_slot_percent_vs_cluster,
+            &baselines,
+            &cache,
+            &history,
+        );
+        monitor.setup().await.unwrap();
+        monitor
+            .export_skipped_slots(&whitelist, &vote_accounts)
+            .await
+            .unwrap();
+        let count = |status: SlotStatus| {
+            leader_slots
+                .with_label_values(&["a", &status.to_string()])
+                .get()
+        };
+        assert_eq!(count(SlotStatus::Validated), 18);
+        assert_eq!(count(SlotStatus::Skipped), 6);
+        assert_eq!(
+            history
+                .total(&vote_pubkey, 0, i64::MAX)
+                .unwrap()
+                .leader_slots,
+            8
+        );
+    }
+
+    #[test]
+    fn weighs_cluster_skip_rate_by_stake() {
+        let vote_account = |node_pubkey: &str, activated_stake| RpcVoteAccountInfo {
+            vote_pubkey: String::new(),
+            node_pubkey: node_pubkey.to_string(),
+            activated_stake,
+            commission: 0,
+            epoch_vote_account: true,
+            epoch_credits: vec![],
+            last_vote: 0,
+            root_slot: 0,
+        };
+        let vote_accounts = RpcVoteAccountStatus {
+            current: vec![vote_account("a", 300), vote_account("b", 100)],
+            delinquent: vec![vote_account("c", 400)],
+        };
+        let by_identity = |production: &[(&str, (usize, usize))]| {
+            production
+                .iter()
+                .map(|(identity, p)| (identity.to_string(), *p))
+                .collect()
+        };
+        assert_eq!(
+            stake_weighted_skip_rate(&by_identity(&[("d", (4, 0))]), &vote_accounts),
+            None
+        );
+        // `c` has no leader slots yet and `d` no stake, so neither counts.
+        let production = by_identity(&[
+            ("a", (10, 10)),
+            ("b", (10, 6)),
+            ("c", (0, 0)),
+            ("d", (4, 0)),
+        ]);
+        assert_eq!(
+            stake_weighted_skip_rate(&production, &vote_accounts),
+            Some(10.0)
+        );
+    }
+
+    #[test]
+    fn ranks_skip_rates_with_ties_counted_half() {
+        let sorted = [0.0, 1.0, 1.0, 5.0];
+        assert_eq!(percentile_rank(&sorted, 0.0), 12.5);
+        assert_eq!(percentile_rank(&sorted, 1.0), 50.0);
+        assert_eq!(percentile_rank(&sorted, 3.0), 75.0);
+        assert_eq!(percentile_rank(&[], 3.0), 0.0);
+    }
+}
@@ -0,0 +1,275 @@
+//! Metrics of SPL stake pools.
+
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::rpc_api::RpcApi;
+use crate::rpc_extra::MAX_MULTIPLE_ACCOUNTS;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use log::debug;
+use prometheus_exporter::prometheus::{GaugeVec, IntGaugeVec};
+use solana_pubkey::Pubkey;
+use std::convert::TryInto;
+
+/// Offset of `validator_list` in a borsh-serialized `StakePool`: account type, manager, staker,
+/// deposit authority and withdraw bump seed precede it.
+const POOL_VALIDATOR_LIST_OFFSET: usize = 1 + 32 * 3 + 1;
+/// Offset of `total_lamports` in a `StakePool`: validator list, reserve stake, pool mint, manager
+/// fee account and token program id precede it.
+const POOL_TOTAL_LAMPORTS_OFFSET: usize = POOL_VALIDATOR_LIST_OFFSET + 32 * 5;
+/// Offset of `pool_token_supply` in a `StakePool`.
+const POOL_TOKEN_SUPPLY_OFFSET: usize = POOL_TOTAL_LAMPORTS_OFFSET + 8;
+
+/// Offset of the validator vector length in a `ValidatorList`, after the account type and
+/// `max_validators`.
+const VALIDATOR_LIST_LEN_OFFSET: usize = 1 + 4;
+/// Size of a borsh-serialized `ValidatorStakeInfo`.
+const VALIDATOR_STAKE_INFO_LEN: usize = 8 * 4 + 4 + 4 + 1 + 32;
+/// Offset of `vote_account_address` in a `ValidatorStakeInfo`.
+const VALIDATOR_VOTE_ACCOUNT_OFFSET: usize = 8 * 4 + 4 + 4 + 1;
+
+/// The part of a `StakePool` account that is exported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct StakePool {
+    validator_list: Pubkey,
+    total_lamports: u64,
+    pool_token_supply: u64,
+}
+
+/// The part of a `ValidatorStakeInfo` entry in a `ValidatorList` account that is exported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ValidatorStakeInfo {
+    active_stake_lamports: u64,
+    transient_stake_lamports: u64,
+    vote_account: Pubkey,
+}
+
+/// The monitor of SPL stake pools listed in the config.
+pub struct StakePoolMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Stake pool addresses.
+    pools: Vec<Pubkey>,
+    /// Prometheus gauge.
+    total_lamports: &'a IntGaugeVec,
+    /// Prometheus gauge.
+    pool_token_supply: &'a IntGaugeVec,
+    /// Prometheus gauge.
+    exchange_rate: &'a GaugeVec,
+    /// Prometheus gauge.
+    validator_count: &'a IntGaugeVec,
+    /// Prometheus gauge.
+    validator_active_stake: &'a IntGaugeVec,
+    /// Prometheus gauge.
+    validator_transient_stake: &'a IntGaugeVec,
+}
+
+impl<'a> StakePoolMonitor<'a> {
+    /// Constructs a monitor of `pools` given `rpc`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        rpc: &'a dyn RpcApi,
+        pools: &[String],
+        total_lamports: &'a IntGaugeVec,
+        pool_token_supply: &'a IntGaugeVec,
+        exchange_rate: &'a GaugeVec,
+        validator_count: &'a IntGaugeVec,
+        validator_active_stake: &'a IntGaugeVec,
+        validator_transient_stake: &'a IntGaugeVec,
+    ) -> anyhow::Result<Self> {
+        let pools = pools
+            .iter()
+            .map(|p| {
+                p.parse()
+                    .with_context(|| format!("invalid stake pool address: {}", p))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            rpc,
+            pools,
+            total_lamports,
+            pool_token_supply,
+            exchange_rate,
+            validator_count,
+            validator_active_stake,
+            validator_transient_stake,
+        })
+    }
+
+    /// Exports the pool-level and per-validator metrics of the configured stake pools.
+    pub async fn export_stake_pools(&self) -> anyhow::Result<()> {
+        let mut pools = Vec::new();
+        for chunk in self.pools.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let accounts = self.rpc.get_multiple_accounts(chunk).await?;
+            for (address, account) in chunk.iter().zip(accounts) {
+                let account =
+                    account.ok_or_else(|| anyhow!("stake pool {} does not exist", address))?;
+                let pool = parse_stake_pool(&account.data)
+                    .with_context(|| format!("could not parse stake pool {}", address))?;
+                pools.push((address, pool));
+            }
+        }
+
+        let validator_list_addresses: Vec<_> =
+            pools.iter().map(|(_, p)| p.validator_list).collect();
+        let mut validator_lists = Vec::new();
+        for chunk in validator_list_addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            validator_lists.extend(self.rpc.get_multiple_accounts(chunk).await?);
+        }
+
+        // Reset so validators that left a pool do not linger.
+        self.validator_active_stake.reset();
+        self.validator_transient_stake.reset();
+
+        for ((address, pool), validator_list) in pools.iter().zip(validator_lists) {
+            let address = address.to_string();
+            self.total_lamports
+                .get_metric_with_label_values(&[&address])
+                .map(|c| c.set(pool.total_lamports as i64))?;
+            self.pool_token_supply
+                .get_metric_with_label_values(&[&address])
+                .map(|c| c.set(pool.pool_token_supply as i64))?;
+            if pool.pool_token_supply > 0 {
+                self.exchange_rate
+                    .get_metric_with_label_values(&[&address])
+                    .map(|c| c.set(pool.total_lamports as f64 / pool.pool_token_supply as f64))?;
+            }
+
+            let validator_list = validator_list
+                .ok_or_else(|| anyhow!("validator list {} does not exist", pool.validator_list))?;
+            let validators = parse_validator_list(&validator_list.data).with_context(|| {
+                format!("could not parse validator list {}", pool.validator_list)
+            })?;
+
+            self.validator_count
+                .get_metric_with_label_values(&[&address])
+                .map(|c| c.set(validators.len() as i64))?;
+            for validator in validators {
+                let vote_account = validator.vote_account.to_string();
+                self.validator_active_stake
+                    .get_metric_with_label_values(&[&address, &vote_account])
+                    .map(|c| c.set(validator.active_stake_lamports as i64))?;
+                self.validator_transient_stake
+                    .get_metric_with_label_values(&[&address, &vote_account])
+                    .map(|c| c.set(validator.transient_stake_lamports as i64))?;
+            }
+        }
+
+        debug!("Exported stake pools");
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Monitor for StakePoolMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "stake pool"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(self.total_lamports),
+            family_name(self.pool_token_supply),
+            family_name(self.exchange_rate),
+            family_name(self.validator_count),
+            family_name(self.validator_active_stake),
+            family_name(self.validator_transient_stake),
+        ]
+    }
+
+    async fn collect(&mut self, _cycle: &Cycle) -> anyhow::Result<()> {
+        self.export_stake_pools().await
+    }
+}
+
+/// Reads a little-endian `u64` at `offset`.
+fn read_u64(data: &[u8], offset: usize) -> anyhow::Result<u64> {
+    data.get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| anyhow!("account data too short"))
+}
+
+/// Reads a little-endian `u32` at `offset`.
+fn read_u32(data: &[u8], offset: usize) -> anyhow::Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| anyhow!("account data too short"))
+}
+
+/// Reads a pubkey at `offset`.
+fn read_pubkey(data: &[u8], offset: usize) -> anyhow::Result<Pubkey> {
+    data.get(offset..offset + 32)
+        .map(|b| Pubkey::new_from_array(b.try_into().unwrap()))
+        .ok_or_else(|| anyhow!("account data too short"))
+}
+
+/// Parses the leading fields of a `StakePool` account.
+fn parse_stake_pool(data: &[u8]) -> anyhow::Result<StakePool> {
+    Ok(StakePool {
+        validator_list: read_pubkey(data, POOL_VALIDATOR_LIST_OFFSET)?,
+        total_lamports: read_u64(data, POOL_TOTAL_LAMPORTS_OFFSET)?,
+        pool_token_supply: read_u64(data, POOL_TOKEN_SUPPLY_OFFSET)?,
+    })
+}
+
+/// Parses the validator entries of a `ValidatorList` account.
+fn parse_validator_list(data: &[u8]) -> anyhow::Result<Vec<ValidatorStakeInfo>> {
+    let len = read_u32(data, VALIDATOR_LIST_LEN_OFFSET)? as usize;
+    (0..len)
+        .map(|i| {
+            let offset = VALIDATOR_LIST_LEN_OFFSET + 4 + i * VALIDATOR_STAKE_INFO_LEN;
+            Ok(ValidatorStakeInfo {
+                active_stake_lamports: read_u64(data, offset)?,
+                transient_stake_lamports: read_u64(data, offset + 8)?,
+                vote_account: read_pubkey(data, offset + VALIDATOR_VOTE_ACCOUNT_OFFSET)?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_stake_pool_prefix() {
+        let validator_list = Pubkey::new_unique();
+        let mut data = vec![0; POOL_VALIDATOR_LIST_OFFSET];
+        data.extend_from_slice(validator_list.as_ref());
+        data.resize(POOL_TOTAL_LAMPORTS_OFFSET, 0);
+        data.extend_from_slice(&1_000u64.to_le_bytes());
+        data.extend_from_slice(&800u64.to_le_bytes());
+        // Trailing fields are ignored.
+        data.extend_from_slice(&[0xff; 64]);
+
+        assert_eq!(
+            parse_stake_pool(&data).unwrap(),
+            StakePool {
+                validator_list,
+                total_lamports: 1_000,
+                pool_token_supply: 800,
+            }
+        );
+        assert!(parse_stake_pool(&data[..POOL_TOKEN_SUPPLY_OFFSET]).is_err());
+    }
+
+    #[test]
+    fn parses_validator_list() {
+        let vote_accounts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut data = vec![1, 10, 0, 0, 0];
+        data.extend_from_slice(&2u32.to_le_bytes());
+        for (i, vote_account) in vote_accounts.iter().enumerate() {
+            data.extend_from_slice(&(100 * (i as u64 + 1)).to_le_bytes());
+            data.extend_from_slice(&(i as u64).to_le_bytes());
+            data.resize(data.len() + 8 * 2 + 4 + 4 + 1, 0);
+            data.extend_from_slice(vote_account.as_ref());
+        }
+
+        let validators = parse_validator_list(&data).unwrap();
+        assert_eq!(validators.len(), 2);
+        assert_eq!(validators[1].active_stake_lamports, 200);
+        assert_eq!(validators[1].transient_stake_lamports, 1);
+        assert_eq!(validators[1].vote_account, vote_accounts[1]);
+        assert!(parse_validator_list(&data[..data.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+//! The `top` subcommand: a live view of the whitelisted validators in the terminal.
+
+use console::{style, Key, Term};
+use prometheus_exporter::prometheus::core::Collector;
+use solana_client::rpc_response::RpcVoteAccountStatus;
+use solana_epoch_info::EpochInfo;
+use solana_exporter::config::Whitelist;
+use solana_exporter::gauges::{NodesMonitor, PrometheusGauges};
+use solana_exporter::monitor::{series, Cycle, MonitorRegistry, Throttled};
+use solana_exporter::persistent_database::counters::{CounterBaselines, COUNTERS_TREE_NAME};
+use solana_exporter::rewards::projection::ProjectedApyMonitor;
+use solana_exporter::rpc_api::RpcApi;
+use solana_exporter::rpc_extra::LAMPORTS_PER_SOL;
+use solana_exporter::slots::caching::{SlotsCache, BLOCK_PRODUCTION_TREE_NAME};
+use solana_exporter::slots::SkippedSlotsMonitor;
+use solana_exporter::uptime::caching::{ProductionHistory, PRODUCTION_HISTORY_TREE_NAME};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Lines above the table: the status line, a blank line and the table header.
+const HEADER_LINES: usize = 3;
+
+/// Time between two queries of the identity balances, which change slowly.
+const BALANCE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A validator's line of the table.
+#[derive(Debug, PartialEq)]
+struct TopRow {
+    /// Vote account pubkey.
+    voter: String,
+    /// Whether the vote account is delinquent.
+    delinquent: bool,
+    /// Activated stake in SOL.
+    stake: f64,
+    /// Slots between the last vote and the current slot.
+    vote_lag: u64,
+    /// Percentage of the leader slots of the epoch so far that were skipped.
+    skip_rate: Option<f64>,
+    /// Projected staking APY of the current epoch in percent.
+    apy: Option<f64>,
+    /// Balance of the node identity in SOL.
+    balance: Option<f64>,
+}
+
+/// `top [--interval SECS]`: shows the stake, vote lag, skip rate, projected APY and identity
+/// balance of the whitelisted validators, or all of them but the balances if there is no
+/// whitelist, refreshed every `interval` until `q` or Escape is pressed.
+///
+/// The values are collected by the exporter's own monitors. Their state is kept in a temporary
+/// database rather than the exporter's, which a running exporter holds locked.
+pub async fn top(
+    rpc: &dyn RpcApi,
+    vote_accounts_whitelist: &Whitelist,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    let scratch = sled::Config::default().temporary(true).open()?;
+    let baselines = CounterBaselines::new(scratch.open_tree(COUNTERS_TREE_NAME)?);
+    let slots_cache = SlotsCache::new(scratch.open_tree(BLOCK_PRODUCTION_TREE_NAME)?);
+    let history = ProductionHistory::new(scratch.open_tree(PRODUCTION_HISTORY_TREE_NAME)?);
+    let gauges = PrometheusGauges::default();
+    let mut monitors = MonitorRegistry::new();
+    monitors.register(SkippedSlotsMonitor::new(
+        rpc,
+        &gauges.leader_slots,
+        &gauges.skipped_slot_percent,
+        &gauges.skipped_slot_percentile,
+        &gauges.skipped_slot_percent_vs_cluster,
+        &baselines,
+        &slots_cache,
+        &history,
+    ));
+    monitors.register(ProjectedApyMonitor::new(rpc, &gauges.projected_staking_apy));
+    monitors.register(Throttled::new(
+        NodesMonitor::new(&gauges, rpc, false),
+        BALANCE_INTERVAL,
+    ));
+    monitors.setup().await?;
+
+    let term = Term::stdout();
+    let quit = Arc::new(AtomicBool::new(false));
+    {
+        let quit = Arc::clone(&quit);
+        // Reading a key blocks, so wait for it on a thread of its own. Without a terminal to
+        // read from, the view runs until interrupted.
+        thread::spawn(move || {
+            let term = Term::stdout();
+            while let Ok(key) = term.read_key() {
+                if matches!(key, Key::Char('q') | Key::Escape) {
+                    quit.store(true, Ordering::Relaxed);
+                    break;
+                }
+            }
+        });
+    }
+
+    term.hide_cursor()?;
+    let result = async {
+        while !quit.load(Ordering::Relaxed) {
+            let screen = match refresh(rpc, vote_accounts_whitelist, &mut monitors, &gauges).await {
+                Ok((epoch_info, rows)) => {
+                    let (height, _) = term.size();
+                    render(&epoch_info, &rows, height as usize)
+                }
+                Err(e) => format!("{}\n", style(format!("Refresh failed: {:#}", e)).red()),
+            };
+            term.clear_screen()?;
+            term.write_str(&screen)?;
+
+            let refreshed = Instant::now();
+            while !quit.load(Ordering::Relaxed) && refreshed.elapsed() < interval {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+        anyhow::Ok(())
+    }
+    .await;
+    term.show_cursor()?;
+    result
+}
+
+/// Fetches a cycle, runs `monitors` on it and makes the rows of the whitelisted validators from
+/// the `gauges` they export.
+async fn refresh(
+    rpc: &dyn RpcApi,
+    vote_accounts_whitelist: &Whitelist,
+    monitors: &mut MonitorRegistry<'_>,
+    gauges: &PrometheusGauges,
+) -> anyhow::Result<(EpochInfo, Vec<TopRow>)> {
+    let cycle = Cycle::fetch(rpc, vote_accounts_whitelist.clone(), Whitelist::default()).await?;
+    monitors.collect(&cycle).await;
+    let rows = rows(
+        cycle.epoch_info.absolute_slot,
+        &cycle.vote_accounts,
+        &by_pubkey(&gauges.skipped_slot_percent),
+        &by_pubkey(&gauges.node_pubkey_balances),
+        &by_pubkey(&gauges.projected_staking_apy),
+        vote_accounts_whitelist,
+    );
+    Ok((cycle.epoch_info, rows))
+}
+
+/// Returns the values of the series of `collector` by their first label, a pubkey.
+fn by_pubkey(collector: &dyn Collector) -> HashMap<String, f64> {
+    series(collector)
+        .into_iter()
+        .filter_map(|(labels, value)| Some((labels.into_iter().next()?, value)))
+        .collect()
+}
+
+/// Makes the rows of the whitelisted vote accounts at `slot`, by descending stake, given the skip
+/// rates and lamport balances by identity and the APYs by vote account.
+fn rows(
+    slot: u64,
+    vote_accounts: &RpcVoteAccountStatus,
+    skip_rates: &HashMap<String, f64>,
+    balances: &HashMap<String, f64>,
+    apys: &HashMap<String, f64>,
+    vote_accounts_whitelist: &Whitelist,
+) -> Vec<TopRow> {
+    let current = vote_accounts.current.iter().map(|v| (v, false));
+    let delinquent = vote_accounts.delinquent.iter().map(|v| (v, true));
+    let mut rows: Vec<_> = current
+        .chain(delinquent)
+        .filter(|(v, _)| vote_accounts_whitelist.contains(&v.vote_pubkey))
+        .map(|(v, delinquent)| TopRow {
+            voter: v.vote_pubkey.clone(),
+            delinquent,
+            stake: v.activated_stake as f64 / LAMPORTS_PER_SOL,
+            vote_lag: slot.saturating_sub(v.last_vote),
+            skip_rate: skip_rates.get(&v.node_pubkey).copied(),
+            apy: apys.get(&v.vote_pubkey).copied(),
+            balance: balances
+                .get(&v.node_pubkey)
+                .map(|lamports| lamports / LAMPORTS_PER_SOL),
+        })
+        .collect();
+    rows.sort_by(|a, b| b.stake.total_cmp(&a.stake));
+    rows
+}
+
+/// Renders the status line and as many `rows` as fit in `height` lines.
+fn render(epoch_info: &EpochInfo, rows: &[TopRow], height: usize) -> String {
+    let optional = |value: Option<f64>, precision: usize| {
+        value.map_or_else(|| "-".to_string(), |v| format!("{:.*}", precision, v))
+    };
+    let mut screen = String::new();
+    // Writing to a string cannot fail.
+    let _ = writeln!(
+        screen,
+        "Epoch {} at {:.1}%, slot {}, {} validators. Press q to quit.\n",
+        epoch_info.epoch,
+        epoch_info.slot_index as f64 / epoch_info.slots_in_epoch as f64 * 100.0,
+        epoch_info.absolute_slot,
+        rows.len()
+    );
+    let _ = writeln!(
+        screen,
+        "{}",
+        style(format!(
+            "{:<44}  {:>14}  {:>8}  {:>9}  {:>8}  {:>13}",
+            "voter", "stake (SOL)", "vote lag", "skip rate", "APY", "balance (SOL)"
+        ))
+        .bold()
+    );
+    for row in rows.iter().take(height.saturating_sub(HEADER_LINES + 1)) {
+        let line = format!(
+            "{:<44}  {:>14.0}  {:>8}  {:>8}%  {:>7}%  {:>13}",
+            row.voter,
+            row.stake,
+            row.vote_lag,
+            optional(row.skip_rate, 2),
+            optional(row.apy, 2),
+            optional(row.balance, 3)
+        );
+        let _ = if row.delinquent {
+            writeln!(screen, "{}", style(line).red())
+        } else {
+            writeln!(screen, "{}", line)
+        };
+    }
+    screen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_client::rpc_response::RpcVoteAccountInfo;
+
+    #[test]
+    fn makes_rows_by_stake() {
+        let vote_account = |voter: &str, identity: &str, stake, last_vote| RpcVoteAccountInfo {
+            vote_pubkey: voter.to_string(),
+            node_pubkey: identity.to_string(),
+            activated_stake: stake,
+            commission: 0,
+            epoch_vote_account: true,
+            epoch_credits: vec![],
+            last_vote,
+            root_slot: 0,
+        };
+        let vote_accounts = RpcVoteAccountStatus {
+            current: vec![vote_account("a", "ia", 1_000_000_000, 95)],
+            delinquent: vec![vote_account("b", "ib", 5_000_000_000, 10)],
+        };
+        let skip_rates = vec![("ia".to_string(), 25.0)].into_iter().collect();
+        let balances = vec![("ib".to_string(), 2_500_000_000.0)]
+            .into_iter()
+            .collect();
+
+        let rows = rows(
+            100,
+            &vote_accounts,
+            &skip_rates,
+            &balances,
+            &HashMap::new(),
+            &Whitelist::default(),
+        );
+        assert_eq!(
+            rows,
+            vec![
+                TopRow {
+                    voter: "b".to_string(),
+                    delinquent: true,
+                    stake: 5.0,
+                    vote_lag: 90,
+                    skip_rate: None,
+                    apy: None,
+                    balance: Some(2.5),
+                },
+                TopRow {
+                    voter: "a".to_string(),
+                    delinquent: false,
+                    stake: 1.0,
+                    vote_lag: 5,
+                    skip_rate: Some(25.0),
+                    apy: None,
+                    balance: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use crate::persistent_database::envelope::{self, Encoding};
+use anyhow::Context;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use solana_pubkey::Pubkey;
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use std::ops::AddAssign;
+
+/// Name of the tree holding the hourly presence history of validators.
+pub const PRESENCE_HISTORY_TREE_NAME: &str = "presence_history";
+
+/// Name of the tree holding the hourly block production history of validators.
+pub const PRODUCTION_HISTORY_TREE_NAME: &str = "production_history";
+
+/// Length of a history bucket in seconds.
+pub const BUCKET_SECS: i64 = 3600;
+
+/// Time a validator was observed, and present in gossip or delinquent, within a bucket.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceBucket {
+    /// Seconds the exporter observed the validator.
+    pub observed_secs: u64,
+    /// Seconds the validator was present in gossip.
+    pub present_secs: u64,
+    /// Seconds the vote account of the validator was delinquent or unknown.
+    pub delinquent_secs: u64,
+}
+
+impl AddAssign for PresenceBucket {
+    fn add_assign(&mut self, other: Self) {
+        self.observed_secs += other.observed_secs;
+        self.present_secs += other.present_secs;
+        self.delinquent_secs += other.delinquent_secs;
+    }
+}
+
+impl PresenceBucket {
+    /// Percentage of the observed time the validator was present in gossip, or `None` if it was
+    /// not observed.
+    pub fn uptime_percent(&self) -> Option<f64> {
+        (self.observed_secs > 0)
+            .then(|| self.present_secs as f64 / self.observed_secs as f64 * 100.0)
+    }
+}
+
+/// Leader slots of a validator within a bucket.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProductionBucket {
+    /// Leader slots, whether skipped or not.
+    pub leader_slots: u64,
+    /// Skipped leader slots.
+    pub skipped_slots: u64,
+}
+
+impl AddAssign for ProductionBucket {
+    fn add_assign(&mut self, other: Self) {
+        self.leader_slots += other.leader_slots;
+        self.skipped_slots += other.skipped_slots;
+    }
+}
+
+impl ProductionBucket {
+    /// Percentage of the leader slots that were skipped, or `None` if there were none.
+    pub fn skip_rate_percent(&self) -> Option<f64> {
+        (self.leader_slots > 0)
+            .then(|| self.skipped_slots as f64 / self.leader_slots as f64 * 100.0)
+    }
+}
+
+/// Returns the start of the bucket containing the unix `timestamp`.
+pub fn bucket_start(timestamp: i64) -> i64 {
+    timestamp - timestamp.rem_euclid(BUCKET_SECS)
+}
+
+/// An hourly history of validators, keyed by vote account and bucket start in time order. The
+/// history is kept indefinitely, at about 8760 small entries per validator and year.
+pub struct HourlyHistory<B> {
+    tree: sled::Tree,
+    _bucket: PhantomData<B>,
+}
+
+/// The hourly presence history of validators.
+pub type PresenceHistory = HourlyHistory<PresenceBucket>;
+
+/// The hourly block production history of validators.
+pub type ProductionHistory = HourlyHistory<ProductionBucket>;
+
+impl<B> HourlyHistory<B>
+where
+    B: AddAssign + Default + Serialize + DeserializeOwned,
+{
+    /// Creates a new history using a tree.
+    pub fn new(tree: sled::Tree) -> Self {
+        Self {
+            tree,
+            _bucket: PhantomData,
+        }
+    }
+
+    /// Adds `bucket` to the persisted bucket of `vote_pubkey` starting at `start`.
+    pub fn add(&self, vote_pubkey: &Pubkey, start: i64, bucket: B) -> anyhow::Result<()> {
+        let key = history_key(vote_pubkey, start);
+        let mut total = self
+            .tree
+            .get(key)
+            .context("could not fetch history bucket from database")?
+            .map(|x| envelope::decode::<B>(&x))
+            .transpose()
+            .context("could not deserialize fetched history bucket")?
+            .unwrap_or_default();
+        total += bucket;
+        self.tree
+            .insert(key, envelope::encode(Encoding::Json, &total)?)
+            .context("could not insert history bucket into database")?;
+        Ok(())
+    }
+
+    /// Returns the sum of the buckets of `vote_pubkey` starting in `start..end`.
+    pub fn total(&self, vote_pubkey: &Pubkey, start: i64, end: i64) -> anyhow::Result<B> {
+        let mut total = B::default();
+        for kv in self
+            .tree
+            .range(history_key(vote_pubkey, start)..history_key(vote_pubkey, end))
+        {
+            let (_, v) = kv.context("could not read history bucket from database")?;
+            total += envelope::decode(&v).context("could not deserialize history bucket")?;
+        }
+        Ok(total)
+    }
+
+    /// Returns the start of the last bucket of `vote_pubkey`, if it has any.
+    pub fn last_start(&self, vote_pubkey: &Pubkey) -> anyhow::Result<Option<i64>> {
+        let last = self
+            .tree
+            .range(history_key(vote_pubkey, i64::MIN)..=history_key(vote_pubkey, i64::MAX))
+            .next_back()
+            .transpose()
+            .context("could not read history key from database")?;
+        Ok(last.map(|(key, _)| {
+            let mut start = [0; 8];
+            start.copy_from_slice(&key[32..]);
+            (u64::from_be_bytes(start) ^ (1 << 63)) as i64
+        }))
+    }
+
+    /// Returns the vote accounts with any history, in key order.
+    pub fn vote_pubkeys(&self) -> anyhow::Result<Vec<Pubkey>> {
+        let mut vote_pubkeys: Vec<Pubkey> = Vec::new();
+        for key in self.tree.iter().keys() {
+            let key = key.context("could not read history key from database")?;
+            let vote_pubkey = Pubkey::try_from(&key[..32])
+                .map_err(|_| anyhow::anyhow!("invalid history key in database"))?;
+            if vote_pubkeys.last() != Some(&vote_pubkey) {
+                vote_pubkeys.push(vote_pubkey);
+            }
+        }
+        Ok(vote_pubkeys)
+    }
+}
+
+/// Returns the key of the bucket of `vote_pubkey` starting at `start`. Bucket starts are offset
+/// so that big-endian keys sort in time order, including before the unix epoch.
+fn history_key(vote_pubkey: &Pubkey, start: i64) -> [u8; 40] {
+    let mut key = [0; 40];
+    key[..32].copy_from_slice(vote_pubkey.as_ref());
+    key[32..].copy_from_slice(&((start as u64) ^ (1 << 63)).to_be_bytes());
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums_buckets_in_range() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let history = PresenceHistory::new(db.open_tree(PRESENCE_HISTORY_TREE_NAME).unwrap());
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let bucket = |observed_secs, present_secs| PresenceBucket {
+            observed_secs,
+            present_secs,
+            delinquent_secs: 0,
+        };
+        history.add(&a, 0, bucket(3600, 3600)).unwrap();
+        history.add(&a, 3600, bucket(1800, 900)).unwrap();
+        history.add(&a, 3600, bucket(1800, 900)).unwrap();
+        history.add(&a, 7200, bucket(3600, 0)).unwrap();
+        history.add(&b, 3600, bucket(3600, 0)).unwrap();
+
+        assert_eq!(history.total(&a, 0, 7200).unwrap(), bucket(7200, 5400));
+        assert_eq!(history.total(&a, 3600, 10800).unwrap(), bucket(7200, 1800));
+        assert_eq!(history.total(&a, -3600, 0).unwrap(), bucket(0, 0));
+        assert_eq!(bucket(7200, 5400).uptime_percent(), Some(75.0));
+        assert_eq!(bucket(0, 0).uptime_percent(), None);
+        let mut vote_pubkeys = vec![a, b];
+        vote_pubkeys.sort();
+        assert_eq!(history.vote_pubkeys().unwrap(), vote_pubkeys);
+        assert_eq!(history.last_start(&a).unwrap(), Some(7200));
+        assert_eq!(history.last_start(&Pubkey::new_unique()).unwrap(), None);
+    }
+}
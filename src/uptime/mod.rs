@@ -0,0 +1,231 @@
+//! Gossip presence history and uptime of whitelisted validators.
+
+use crate::config::Whitelist;
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::uptime::caching::{bucket_start, PresenceBucket, PresenceHistory, BUCKET_SECS};
+use anyhow::Context;
+use async_trait::async_trait;
+use prometheus_exporter::prometheus::GaugeVec;
+use solana_client::rpc_response::RpcVoteAccountStatus;
+use solana_pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
+use time::OffsetDateTime;
+
+pub mod caching;
+
+/// Longest time between two samples attributed to the later one. Longer gaps, e.g. while the
+/// exporter was down or collection was suspended, count as not observed.
+const MAX_SAMPLE_GAP_SECS: i64 = 60;
+
+/// Time between two writes of the accumulated samples to the database.
+const FLUSH_INTERVAL_SECS: i64 = 60;
+
+/// Rolling windows the uptime is exported over, by label value and length in seconds.
+const UPTIME_WINDOWS: [(&str, i64); 3] = [
+    ("24h", 24 * 3600),
+    ("7d", 7 * 24 * 3600),
+    ("30d", 30 * 24 * 3600),
+];
+
+/// The monitor of the presence of whitelisted validators in gossip.
+///
+/// Every cycle, the time since the previous one is attributed to each whitelisted vote account
+/// as observed, present if its node is in `getClusterNodes`, and delinquent if the vote account
+/// is delinquent or unknown. Samples are accumulated into hourly buckets and written to the
+/// database every `FLUSH_INTERVAL_SECS`, after which the uptime over each of `UPTIME_WINDOWS` is
+/// exported, approximated to whole buckets.
+pub struct UptimeMonitor<'a> {
+    /// Prometheus gossip uptime gauge.
+    gossip_uptime: &'a GaugeVec,
+    /// Persisted presence history.
+    history: &'a PresenceHistory,
+    /// Unix timestamp of the previous sample.
+    last_sample: Option<i64>,
+    /// Unix timestamp of the previous write to the database.
+    last_flush: Option<i64>,
+    /// Samples not yet written to the database, by vote account and bucket start.
+    pending: HashMap<(Pubkey, i64), PresenceBucket>,
+}
+
+impl<'a> UptimeMonitor<'a> {
+    pub fn new(gossip_uptime: &'a GaugeVec, history: &'a PresenceHistory) -> Self {
+        Self {
+            gossip_uptime,
+            history,
+            last_sample: None,
+            last_flush: None,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Samples the presence of the validators in `vote_accounts_whitelist` in `gossip`, a set of
+    /// node pubkeys, at unix timestamp `now`, and exports their uptime if the samples are due to
+    /// be written.
+    pub fn export_uptime(
+        &mut self,
+        vote_accounts_whitelist: &Whitelist,
+        gossip: &HashSet<&str>,
+        vote_accounts: &RpcVoteAccountStatus,
+        now: i64,
+    ) -> anyhow::Result<()> {
+        if vote_accounts_whitelist.0.is_empty() {
+            return Ok(());
+        }
+
+        let elapsed = self
+            .last_sample
+            .map_or(0, |last| (now - last).clamp(0, MAX_SAMPLE_GAP_SECS) as u64);
+        self.last_sample = Some(now);
+        if elapsed > 0 {
+            let current: HashMap<_, _> = vote_accounts
+                .current
+                .iter()
+                .map(|v| (v.vote_pubkey.as_str(), v.node_pubkey.as_str()))
+                .collect();
+            let delinquent: HashMap<_, _> = vote_accounts
+                .delinquent
+                .iter()
+                .map(|v| (v.vote_pubkey.as_str(), v.node_pubkey.as_str()))
+                .collect();
+            for vote_pubkey in &vote_accounts_whitelist.0 {
+                let pubkey = vote_pubkey
+                    .parse::<Pubkey>()
+                    .with_context(|| format!("invalid whitelisted vote account {}", vote_pubkey))?;
+                let node_pubkey = current
+                    .get(vote_pubkey.as_str())
+                    .or_else(|| delinquent.get(vote_pubkey.as_str()));
+                let present = node_pubkey.is_some_and(|node| gossip.contains(node));
+                let bucket = self.pending.entry((pubkey, bucket_start(now))).or_default();
+                bucket.observed_secs += elapsed;
+                if present {
+                    bucket.present_secs += elapsed;
+                }
+                if !current.contains_key(vote_pubkey.as_str()) {
+                    bucket.delinquent_secs += elapsed;
+                }
+            }
+        }
+
+        if self
+            .last_flush
+            .is_some_and(|last| now - last < FLUSH_INTERVAL_SECS)
+        {
+            return Ok(());
+        }
+        for ((pubkey, start), bucket) in self.pending.drain() {
+            self.history.add(&pubkey, start, bucket)?;
+        }
+        self.last_flush = Some(now);
+
+        let end = bucket_start(now) + BUCKET_SECS;
+        for vote_pubkey in &vote_accounts_whitelist.0 {
+            let pubkey = vote_pubkey.parse::<Pubkey>()?;
+            for (window, length) in UPTIME_WINDOWS {
+                let total = self.history.total(&pubkey, end - length, end)?;
+                if let Some(uptime) = total.uptime_percent() {
+                    self.gossip_uptime
+                        .with_label_values(&[vote_pubkey, window])
+                        .set(uptime);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Monitor for UptimeMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "gossip uptime"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![family_name(self.gossip_uptime)]
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        let gossip = cycle.nodes.iter().map(|n| n.pubkey.as_str()).collect();
+        self.export_uptime(
+            &cycle.vote_accounts_whitelist,
+            &gossip,
+            &cycle.vote_accounts,
+            OffsetDateTime::now_utc().unix_timestamp(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gauges::{PUBKEY_LABEL, WINDOW_LABEL};
+    use crate::uptime::caching::PRESENCE_HISTORY_TREE_NAME;
+    use prometheus_exporter::prometheus::Opts;
+    use solana_client::rpc_response::RpcVoteAccountInfo;
+
+    #[test]
+    fn exports_uptime_from_flushed_samples() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let history = PresenceHistory::new(db.open_tree(PRESENCE_HISTORY_TREE_NAME).unwrap());
+        let gauge =
+            GaugeVec::new(Opts::new("uptime", "Uptime"), &[PUBKEY_LABEL, WINDOW_LABEL]).unwrap();
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let whitelist = Whitelist::new(vec![a.to_string(), b.to_string()].into_iter().collect());
+        let mut monitor = UptimeMonitor::new(&gauge, &history);
+
+        let account = |vote_pubkey: &Pubkey, node_pubkey: &str| RpcVoteAccountInfo {
+            vote_pubkey: vote_pubkey.to_string(),
+            node_pubkey: node_pubkey.to_string(),
+            activated_stake: 0,
+            commission: 0,
+            epoch_vote_account: true,
+            epoch_credits: vec![],
+            last_vote: 0,
+            root_slot: 0,
+        };
+        let vote_accounts = RpcVoteAccountStatus {
+            current: vec![account(&a, "node-a")],
+            delinquent: vec![account(&b, "node-b")],
+        };
+        let both = vec!["node-a", "node-b"].into_iter().collect();
+        let only_a = vec!["node-a"].into_iter().collect();
+
+        let start = 1_700_000_000 - 1_700_000_000 % BUCKET_SECS;
+        monitor
+            .export_uptime(&whitelist, &both, &vote_accounts, start)
+            .unwrap();
+        monitor
+            .export_uptime(&whitelist, &both, &vote_accounts, start + 30)
+            .unwrap();
+        monitor
+            .export_uptime(&whitelist, &only_a, &vote_accounts, start + 60)
+            .unwrap();
+
+        let uptime = |pubkey: &Pubkey, window| {
+            gauge
+                .with_label_values(&[&pubkey.to_string(), window])
+                .get()
+        };
+        assert_eq!(uptime(&a, "24h"), 100.0);
+        assert_eq!(uptime(&b, "30d"), 50.0);
+        let total = history.total(&b, start, start + BUCKET_SECS).unwrap();
+        assert_eq!(
+            total,
+            PresenceBucket {
+                observed_secs: 60,
+                present_secs: 30,
+                delinquent_secs: 60,
+            }
+        );
+
+        // Samples are not written until the flush interval has elapsed.
+        monitor
+            .export_uptime(&whitelist, &only_a, &vote_accounts, start + 90)
+            .unwrap();
+        assert_eq!(uptime(&b, "7d"), 50.0);
+        // The gap since the previous sample is capped.
+        monitor
+            .export_uptime(&whitelist, &only_a, &vote_accounts, start + 600)
+            .unwrap();
+        assert_eq!(uptime(&b, "7d"), 20.0);
+    }
+}
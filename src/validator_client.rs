@@ -0,0 +1,224 @@
+//! Validator client implementations, inferred from gossip contact info.
+
+use crate::gauges::PrometheusGauges;
+use crate::monitor::{family_name, Cycle, Monitor};
+use async_trait::async_trait;
+use solana_client::rpc_response::RpcContactInfo;
+use std::collections::HashMap;
+
+/// A validator client implementation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Client {
+    /// Agave, or the Solana Labs client it was forked from.
+    Agave,
+    /// The Jito fork of Agave, including its BAM variant.
+    Jito,
+    /// Agave with the Paladin MEV extension.
+    Paladin,
+    /// The Firedancer networking stack on the Agave runtime.
+    Frankendancer,
+    /// Firedancer.
+    Firedancer,
+    /// Sig.
+    Sig,
+    /// A client that gossip does not tell.
+    Unknown,
+}
+
+impl Client {
+    /// Infers the client of a node from its `getClusterNodes` contact info: its client id if it
+    /// advertises one, and otherwise its version, Firedancer being the only client on major
+    /// version 0.
+    pub fn of(contact: &RpcContactInfo) -> Self {
+        if let Some(client_id) = &contact.client_id {
+            let client = Self::from_client_id(client_id);
+            if client != Self::Unknown {
+                return client;
+            }
+        }
+        match contact.version.as_deref().and_then(|v| v.split('.').next()) {
+            Some("0") => Self::Frankendancer,
+            Some(major) if major.parse::<u32>().is_ok() => Self::Agave,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Parses a client id of gossip, e.g. `JitoLabs` or `Frankendancer`.
+    fn from_client_id(client_id: &str) -> Self {
+        let id: String = client_id
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .collect::<String>()
+            .to_lowercase();
+        if id.contains("jito") || id == "agavebam" {
+            Self::Jito
+        } else if id.contains("paladin") {
+            Self::Paladin
+        } else if id.contains("frankendancer") {
+            Self::Frankendancer
+        } else if id.contains("firedancer") {
+            Self::Firedancer
+        } else if id == "sig" {
+            Self::Sig
+        } else if id.contains("agave") || id == "solanalabs" {
+            Self::Agave
+        } else {
+            Self::Unknown
+        }
+    }
+
+    /// Value of the `client` label.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Agave => "agave",
+            Self::Jito => "jito",
+            Self::Paladin => "paladin",
+            Self::Frankendancer => "frankendancer",
+            Self::Firedancer => "firedancer",
+            Self::Sig => "sig",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// The monitor of the client implementations of the cluster nodes, by number of nodes and by
+/// activated stake, and of the whitelisted validators.
+pub struct ValidatorClientMonitor<'a> {
+    gauges: &'a PrometheusGauges,
+    /// Whether the cluster-wide totals are exported, besides the whitelisted validators.
+    cluster_wide: bool,
+}
+
+impl<'a> ValidatorClientMonitor<'a> {
+    /// Constructs a monitor exporting into `gauges`, and the cluster-wide totals if
+    /// `cluster_wide`.
+    pub fn new(gauges: &'a PrometheusGauges, cluster_wide: bool) -> Self {
+        Self {
+            gauges,
+            cluster_wide,
+        }
+    }
+}
+
+#[async_trait]
+impl Monitor for ValidatorClientMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "validator client"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(&self.gauges.client_nodes),
+            family_name(&self.gauges.client_stake),
+            family_name(&self.gauges.validator_client),
+        ]
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        let clients: HashMap<&str, Client> = cycle
+            .nodes
+            .iter()
+            .map(|contact| (contact.pubkey.as_str(), Client::of(contact)))
+            .collect();
+        let vote_accounts = cycle
+            .vote_accounts
+            .current
+            .iter()
+            .chain(&cycle.vote_accounts.delinquent);
+
+        if self.cluster_wide {
+            let mut nodes: HashMap<Client, i64> = HashMap::new();
+            for client in clients.values() {
+                *nodes.entry(*client).or_default() += 1;
+            }
+            let mut stake: HashMap<Client, u64> = HashMap::new();
+            for vote in vote_accounts.clone() {
+                let client = clients
+                    .get(vote.node_pubkey.as_str())
+                    .copied()
+                    .unwrap_or(Client::Unknown);
+                *stake.entry(client).or_default() += vote.activated_stake;
+            }
+            // Clients that no node runs anymore are dropped.
+            self.gauges.client_nodes.reset();
+            self.gauges.client_stake.reset();
+            for (client, count) in nodes {
+                self.gauges
+                    .client_nodes
+                    .get_metric_with_label_values(&[client.label()])
+                    .map(|c| c.set(count))?;
+            }
+            for (client, lamports) in stake {
+                self.gauges
+                    .client_stake
+                    .get_metric_with_label_values(&[client.label()])
+                    .map(|c| c.set(lamports as i64))?;
+            }
+        }
+
+        // Validators switching clients keep a single series.
+        self.gauges.validator_client.reset();
+        for vote in vote_accounts.filter(|v| cycle.vote_accounts_whitelist.contains(&v.vote_pubkey))
+        {
+            if let Some(client) = clients.get(vote.node_pubkey.as_str()) {
+                self.gauges
+                    .validator_client
+                    .get_metric_with_label_values(&[&vote.vote_pubkey, client.label()])
+                    .map(|c| c.set(1))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_clients() {
+        let contact = |client_id: Option<&str>, version: Option<&str>| RpcContactInfo {
+            pubkey: String::new(),
+            gossip: None,
+            tvu: None,
+            tpu: None,
+            tpu_quic: None,
+            tpu_forwards: None,
+            tpu_forwards_quic: None,
+            tpu_vote: None,
+            serve_repair: None,
+            rpc: None,
+            pubsub: None,
+            version: version.map(str::to_string),
+            client_id: client_id.map(str::to_string),
+            feature_set: None,
+            shred_version: None,
+        };
+        assert_eq!(
+            Client::of(&contact(Some("JitoLabs"), Some("2.2.14"))),
+            Client::Jito
+        );
+        assert_eq!(
+            Client::of(&contact(Some("Agave"), Some("2.2.14"))),
+            Client::Agave
+        );
+        assert_eq!(
+            Client::of(&contact(Some("Frankendancer"), Some("0.503.20214"))),
+            Client::Frankendancer
+        );
+        assert_eq!(
+            Client::of(&contact(Some("AgavePaladin"), None)),
+            Client::Paladin
+        );
+        // Without a known client id, the version tells Agave from Firedancer.
+        assert_eq!(
+            Client::of(&contact(Some("Unknown(42)"), Some("2.1.0"))),
+            Client::Agave
+        );
+        assert_eq!(
+            Client::of(&contact(None, Some("0.406.20113"))),
+            Client::Frankendancer
+        );
+        assert_eq!(Client::of(&contact(None, None)), Client::Unknown);
+    }
+}
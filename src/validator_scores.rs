@@ -0,0 +1,259 @@
+//! Scores and ranks of validators from validators.app or Stakewiz.
+
+use crate::config::{ScoreProvider, ValidatorScoresConfig, Whitelist};
+use crate::monitor::{family_name, Cycle, Monitor};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use log::debug;
+use prometheus_exporter::prometheus::{GaugeVec, IntGaugeVec};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// URL of the validators.app validator list, by network.
+const VALIDATORS_APP_URI: &str = "https://www.validators.app/api/v1/validators";
+/// URL of the Stakewiz validator list.
+const STAKEWIZ_URI: &str = "https://api.stakewiz.com/validators";
+/// Time after which a failed fetch is retried, unless the `ttl` is shorter.
+const RETRY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// A validator in the validators.app list.
+#[derive(Deserialize)]
+struct ValidatorsAppValidator {
+    vote_account: Option<String>,
+    total_score: Option<f64>,
+}
+
+/// A validator in the Stakewiz list.
+#[derive(Deserialize)]
+struct StakewizValidator {
+    vote_identity: String,
+    wiz_score: Option<f64>,
+    rank: Option<i64>,
+}
+
+/// The score of a validator and its rank among all validators, 1 being the best.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Score {
+    score: f64,
+    rank: i64,
+}
+
+/// The monitor of the scores and ranks of the whitelisted validators at a third-party service.
+///
+/// The scores of all validators are fetched in a single request and reused for `ttl`, since
+/// they rarely change by the hour and the services are rate limited. If a fetch fails, the
+/// previous scores are kept and the fetch is retried after `RETRY_INTERVAL`, rather than on every
+/// cycle.
+pub struct ValidatorScoresMonitor<'a> {
+    /// HTTP client for the service.
+    client: reqwest::Client,
+    /// Service and its settings.
+    config: ValidatorScoresConfig,
+    /// Time the fetched scores are reused for.
+    ttl: Duration,
+    /// Prometheus gauge.
+    score: &'a GaugeVec,
+    /// Prometheus gauge.
+    rank: &'a IntGaugeVec,
+    /// Last fetched scores by vote account pubkey.
+    scores: HashMap<String, Score>,
+    /// When the scores are fetched next, if they have been fetched, or a fetch failed, before.
+    next_fetch: Option<Instant>,
+}
+
+impl<'a> ValidatorScoresMonitor<'a> {
+    /// Constructs a monitor of the service of `config`, queried with `client`.
+    pub fn new(
+        client: reqwest::Client,
+        config: ValidatorScoresConfig,
+        score: &'a GaugeVec,
+        rank: &'a IntGaugeVec,
+    ) -> anyhow::Result<Self> {
+        if config.provider == ScoreProvider::ValidatorsApp && config.api_key.is_none() {
+            return Err(anyhow!("validators.app scores need an api_key"));
+        }
+        Ok(Self {
+            client,
+            ttl: Duration::from_secs(config.ttl.unwrap_or(6 * 3600)),
+            config,
+            score,
+            rank,
+            scores: HashMap::new(),
+            next_fetch: None,
+        })
+    }
+
+    /// Fetches the scores of all validators from the service.
+    async fn fetch(&self) -> anyhow::Result<HashMap<String, Score>> {
+        Ok(match self.config.provider {
+            ScoreProvider::ValidatorsApp => {
+                let network = self.config.network.as_deref().unwrap_or("mainnet");
+                let validators = self
+                    .client
+                    .get(format!("{}/{}.json", VALIDATORS_APP_URI, network))
+                    .query(&[("limit", "9999")])
+                    .header("Token", self.config.api_key.as_deref().unwrap_or_default())
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<Vec<ValidatorsAppValidator>>()
+                    .await?;
+                validators_app_scores(validators)
+            }
+            ScoreProvider::Stakewiz => {
+                let validators = self
+                    .client
+                    .get(STAKEWIZ_URI)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<Vec<StakewizValidator>>()
+                    .await?;
+                stakewiz_scores(validators)
+            }
+        })
+    }
+
+    /// Exports the scores of the vote accounts of `vote_accounts_whitelist`, fetching them first
+    /// if they are older than `ttl` and no failed fetch was attempted within `RETRY_INTERVAL`.
+    async fn export_scores(&mut self, vote_accounts_whitelist: &Whitelist) -> anyhow::Result<()> {
+        if self.next_fetch.is_none_or(|t| Instant::now() >= t) {
+            match self.fetch().await {
+                Ok(scores) => {
+                    self.scores = scores;
+                    self.next_fetch = Some(Instant::now() + self.ttl);
+                    debug!("Fetched the scores of {} validators", self.scores.len());
+                }
+                Err(e) => {
+                    self.next_fetch = Some(Instant::now() + RETRY_INTERVAL.min(self.ttl));
+                    return Err(e);
+                }
+            }
+        }
+
+        let provider = match self.config.provider {
+            ScoreProvider::ValidatorsApp => "validators_app",
+            ScoreProvider::Stakewiz => "stakewiz",
+        };
+        for (voter, score) in self
+            .scores
+            .iter()
+            .filter(|(voter, _)| vote_accounts_whitelist.contains(voter))
+        {
+            self.score
+                .get_metric_with_label_values(&[voter, provider])
+                .map(|m| m.set(score.score))?;
+            self.rank
+                .get_metric_with_label_values(&[voter, provider])
+                .map(|m| m.set(score.rank))?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Monitor for ValidatorScoresMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "validator scores"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![family_name(self.score), family_name(self.rank)]
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.export_scores(&cycle.vote_accounts_whitelist).await
+    }
+}
+
+/// Returns the validators.app total scores by vote account, ranked by descending score with ties
+/// sharing a rank.
+fn validators_app_scores(validators: Vec<ValidatorsAppValidator>) -> HashMap<String, Score> {
+    let mut scores: Vec<(String, f64)> = validators
+        .into_iter()
+        .filter_map(|v| Some((v.vote_account?, v.total_score?)))
+        .collect();
+    scores.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    let mut rank = 0;
+    let mut previous = None;
+    scores
+        .into_iter()
+        .enumerate()
+        .map(|(i, (voter, score))| {
+            if previous != Some(score) {
+                rank = i as i64 + 1;
+                previous = Some(score);
+            }
+            (voter, Score { score, rank })
+        })
+        .collect()
+}
+
+/// Returns the Stakewiz scores and ranks by vote account.
+fn stakewiz_scores(validators: Vec<StakewizValidator>) -> HashMap<String, Score> {
+    validators
+        .into_iter()
+        .filter_map(|v| {
+            Some((
+                v.vote_identity,
+                Score {
+                    score: v.wiz_score?,
+                    rank: v.rank?,
+                },
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_exporter::prometheus::Opts;
+
+    #[test]
+    fn ranks_validators_app_scores() {
+        let validators = serde_json::from_str(
+            r#"[
+                {"account": "i1", "vote_account": "a", "total_score": 9},
+                {"account": "i2", "vote_account": "b", "total_score": 11},
+                {"account": "i3", "vote_account": "c", "total_score": 9},
+                {"account": "i4", "vote_account": null, "total_score": 12},
+                {"account": "i5", "vote_account": "d", "total_score": 2}
+            ]"#,
+        )
+        .unwrap();
+        let scores = validators_app_scores(validators);
+        let ranks = |voter: &str| scores.get(voter).map(|s| s.rank);
+        assert_eq!(ranks("b"), Some(1));
+        assert_eq!((ranks("a"), ranks("c")), (Some(2), Some(2)));
+        assert_eq!(ranks("d"), Some(4));
+        assert_eq!(scores.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn backs_off_after_a_failed_fetch() {
+        // Every request fails, through a proxy that refuses connections.
+        let client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::all("http://127.0.0.1:1").unwrap())
+            .build()
+            .unwrap();
+        let config = ValidatorScoresConfig {
+            provider: ScoreProvider::Stakewiz,
+            api_key: None,
+            network: None,
+            ttl: None,
+        };
+        let score = GaugeVec::new(Opts::new("score", "Score"), &["pubkey", "provider"]).unwrap();
+        let rank = IntGaugeVec::new(Opts::new("rank", "Rank"), &["pubkey", "provider"]).unwrap();
+        let mut monitor = ValidatorScoresMonitor::new(client, config, &score, &rank).unwrap();
+        let whitelist = Whitelist::default();
+
+        assert!(monitor.export_scores(&whitelist).await.is_err());
+        let next_fetch = monitor.next_fetch.unwrap();
+        assert!(next_fetch > Instant::now() + RETRY_INTERVAL - Duration::from_secs(60));
+        // Not retried before the interval is up.
+        assert!(monitor.export_scores(&whitelist).await.is_ok());
+        assert_eq!(monitor.next_fetch, Some(next_fetch));
+    }
+}
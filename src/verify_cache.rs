@@ -0,0 +1,270 @@
+//! The `verify-cache` subcommand: checks the reward cache for unreadable entries and missing
+//! epochs, and a sample of its rewards and APYs against RPC.
+
+use crate::rewards::caching::{
+    EpochRange, PubkeyVoterApyMapping, RewardsCache, EPOCH_REWARDS_TREE_NAME,
+};
+use crate::rewards::{fetch_epoch_rewards, staking_apy, ApyOutlierRules, DEFAULT_EPOCH_LENGTH};
+use crate::rpc_api::RpcApi;
+use anyhow::{anyhow, Context};
+use clap::ArgMatches;
+use solana_clock::Epoch;
+use solana_reward_info::RewardType;
+use solana_transaction_status_client_types::Reward;
+use std::collections::{BTreeSet, HashMap};
+
+/// Largest relative difference between a cached and a re-derived APY that is not a mismatch.
+const APY_TOLERANCE: f64 = 1e-9;
+
+/// Differences between the cached and fetched rewards of an epoch.
+#[derive(Debug, Default, PartialEq)]
+struct RewardsDiff {
+    /// Fetched rewards that are not cached.
+    missing: usize,
+    /// Cached rewards that were not fetched.
+    unexpected: usize,
+    /// Rewards cached with other lamports, balance or commission than fetched.
+    mismatched: usize,
+}
+
+impl RewardsDiff {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// `verify-cache [--epochs N]`: reports the unreadable entries of the reward cache and the
+/// epochs missing inside the cached range of each tree, then fetches the rewards of the last `N`
+/// (3) epochs with cached rewards from RPC, and compares them and the staking APYs re-derived
+/// from them under `outlier_rules` with the cache. The cache is not modified. Fails if any
+/// problem was found.
+pub async fn verify_cache(
+    rpc: &dyn RpcApi,
+    cache: &RewardsCache,
+    outlier_rules: ApyOutlierRules,
+    args: &ArgMatches<'_>,
+) -> anyhow::Result<()> {
+    let sample = args
+        .value_of("epochs")
+        .map(str::parse::<usize>)
+        .transpose()
+        .context("invalid number of epochs")?
+        .unwrap_or(3);
+
+    let mut problems = 0;
+    let mut reward_epochs = BTreeSet::new();
+    for tree in cache.tree_entries()? {
+        let missing = missing_epochs(&tree.epochs);
+        println!(
+            "{}: {} epochs, {} unreadable entries, {} missing epochs",
+            tree.name,
+            tree.epochs.len(),
+            tree.unreadable.len(),
+            missing
+                .iter()
+                .map(|r| r.end() - r.start() + 1)
+                .sum::<Epoch>()
+        );
+        for entry in &tree.unreadable {
+            println!("  unreadable: {}", entry);
+        }
+        for range in &missing {
+            println!("  missing: epochs {}..={}", range.start(), range.end());
+        }
+        problems += tree.unreadable.len() + missing.len();
+        if tree.name == EPOCH_REWARDS_TREE_NAME {
+            reward_epochs = tree.epochs;
+        }
+    }
+
+    for &epoch in reward_epochs.iter().rev().take(sample) {
+        let cached = match cache.get_epoch_rewards(epoch)? {
+            Some(cached) => cached,
+            // Reported as unreadable above.
+            None => continue,
+        };
+        let fetched = match fetch_epoch_rewards(rpc, epoch).await {
+            Ok(Some(fetched)) => fetched,
+            Ok(None) => {
+                println!("epoch {}: not checked, no rewards on RPC", epoch);
+                continue;
+            }
+            Err(e) => {
+                println!("epoch {}: not checked, {:#}", epoch, e);
+                continue;
+            }
+        };
+        let diff = compare_rewards(&cached, &fetched);
+        let duration = match epoch.checked_sub(1) {
+            Some(prev) => cache.get_epoch_length(prev)?,
+            None => None,
+        }
+        .unwrap_or(DEFAULT_EPOCH_LENGTH);
+        let cached_apys = cache.get_epoch_apy(epoch)?.unwrap_or_default();
+        let apy_mismatches = compare_apys(&cached_apys, &fetched, duration, outlier_rules);
+        println!(
+            "epoch {}: {} rewards ({} missing, {} unexpected, {} mismatched), {} APYs ({} mismatched)",
+            epoch,
+            cached.len(),
+            diff.missing,
+            diff.unexpected,
+            diff.mismatched,
+            cached_apys.len(),
+            apy_mismatches.len()
+        );
+        for stake in &apy_mismatches {
+            println!("  mismatched APY: stake {}", stake);
+        }
+        problems += usize::from(!diff.is_empty()) + apy_mismatches.len();
+    }
+
+    if problems > 0 {
+        return Err(anyhow!("found {} problems in the reward cache", problems));
+    }
+    println!("No problems found");
+    Ok(())
+}
+
+/// Returns the runs of epochs missing between the first and last of `epochs`.
+fn missing_epochs(epochs: &BTreeSet<Epoch>) -> Vec<EpochRange> {
+    epochs
+        .iter()
+        .zip(epochs.iter().skip(1))
+        .filter(|(a, b)| *b - *a > 1)
+        .map(|(a, b)| a + 1..=b - 1)
+        .collect()
+}
+
+/// Compares the `cached` rewards of an epoch with the `fetched` ones, by account and type.
+fn compare_rewards(cached: &[Reward], fetched: &[Reward]) -> RewardsDiff {
+    let by_key = |rewards: &[Reward]| -> HashMap<(String, Option<String>), Reward> {
+        rewards
+            .iter()
+            .map(|r| {
+                let key = (r.pubkey.clone(), r.reward_type.map(|t| t.to_string()));
+                (key, r.clone())
+            })
+            .collect()
+    };
+    let cached = by_key(cached);
+    let fetched = by_key(fetched);
+    let mut diff = RewardsDiff {
+        unexpected: cached.keys().filter(|k| !fetched.contains_key(k)).count(),
+        ..RewardsDiff::default()
+    };
+    for (key, f) in &fetched {
+        match cached.get(key) {
+            None => diff.missing += 1,
+            Some(c) => {
+                if (c.lamports, c.post_balance, c.commission)
+                    != (f.lamports, f.post_balance, f.commission)
+                {
+                    diff.mismatched += 1;
+                }
+            }
+        }
+    }
+    diff
+}
+
+/// Returns the stake accounts of `cached_apys` whose APY differs from the one re-derived from
+/// their `fetched` staking reward in an epoch of `epoch_duration` days and clamped by
+/// `outlier_rules`, or that have none or are excluded by the rules.
+fn compare_apys(
+    cached_apys: &PubkeyVoterApyMapping,
+    fetched: &[Reward],
+    epoch_duration: f64,
+    outlier_rules: ApyOutlierRules,
+) -> Vec<String> {
+    let rewards: HashMap<_, _> = fetched
+        .iter()
+        .filter(|r| r.reward_type == Some(RewardType::Staking) && r.lamports > 0)
+        .map(|r| (r.pubkey.as_str(), r))
+        .collect();
+    let mut mismatched: Vec<_> = cached_apys
+        .iter()
+        .filter(|(stake, (_, apy))| {
+            rewards.get(stake.to_string().as_str()).is_none_or(|r| {
+                let (derived, _) = staking_apy(r.lamports as u64, r.post_balance, epoch_duration);
+                outlier_rules.apply(derived).0.is_none_or(|expected| {
+                    (expected - apy).abs() > APY_TOLERANCE * apy.abs().max(1.0)
+                })
+            })
+        })
+        .map(|(stake, _)| stake.to_string())
+        .collect();
+    mismatched.sort();
+    mismatched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_pubkey::Pubkey;
+
+    fn reward(pubkey: &str, lamports: i64, post_balance: u64, reward_type: RewardType) -> Reward {
+        Reward {
+            pubkey: pubkey.to_string(),
+            lamports,
+            post_balance,
+            reward_type: Some(reward_type),
+            commission: None,
+            commission_bps: None,
+        }
+    }
+
+    #[test]
+    fn compares_cached_rewards_and_apys() {
+        let epochs = BTreeSet::from([3, 4, 6, 9]);
+        assert_eq!(missing_epochs(&epochs), vec![5..=5, 7..=8]);
+
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let fetched = vec![
+            reward(&a.to_string(), 100, 100_100, RewardType::Staking),
+            reward(&b.to_string(), 200, 100_200, RewardType::Staking),
+            reward("v", 10, 1_000, RewardType::Voting),
+            reward("w", 20, 1_000, RewardType::Voting),
+        ];
+        let mut cached = fetched.clone();
+        cached[1].lamports = 300;
+        cached.remove(3);
+        cached.push(reward("v", 10, 1_000, RewardType::Fee));
+        assert_eq!(
+            compare_rewards(&cached, &fetched),
+            RewardsDiff {
+                missing: 1,
+                unexpected: 1,
+                mismatched: 1
+            }
+        );
+        assert!(compare_rewards(&fetched, &fetched).is_empty());
+
+        let voter = Pubkey::new_unique();
+        let (apy, _) = staking_apy(100, 100_100, 2.0);
+        let cached_apys: PubkeyVoterApyMapping = vec![
+            (a, (voter, apy)),
+            (b, (voter, apy)),
+            (Pubkey::new_unique(), (voter, apy)),
+        ]
+        .into_iter()
+        .collect();
+        let mismatched = compare_apys(&cached_apys, &fetched, 2.0, ApyOutlierRules::default());
+        assert_eq!(mismatched.len(), 2);
+        assert!(!mismatched.contains(&a.to_string()));
+        assert!(mismatched.contains(&b.to_string()));
+
+        // Cached APYs were clamped to the maximum.
+        let clamp = ApyOutlierRules {
+            max_apy: Some(5.0),
+            clamp: true,
+            ..ApyOutlierRules::default()
+        };
+        assert!(apy > 5.0);
+        let cached_apys: PubkeyVoterApyMapping = std::iter::once((a, (voter, 5.0))).collect();
+        assert!(compare_apys(&cached_apys, &fetched, 2.0, clamp).is_empty());
+        assert_eq!(
+            compare_apys(&cached_apys, &fetched, 2.0, ApyOutlierRules::default()),
+            vec![a.to_string()]
+        );
+    }
+}
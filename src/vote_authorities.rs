@@ -0,0 +1,284 @@
+//! Authorized voters and withdrawers of vote accounts, and their changes.
+
+use crate::config::Whitelist;
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::persistent_database::counters::CounterBaselines;
+use crate::rpc_api::RpcApi;
+use crate::rpc_extra::MAX_MULTIPLE_ACCOUNTS;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use log::{debug, error, warn};
+use prometheus_exporter::prometheus::{IntCounterVec, IntGaugeVec};
+use solana_clock::Epoch;
+use solana_pubkey::Pubkey;
+use solana_vote_interface::state::VoteStateVersions;
+use std::collections::HashMap;
+
+/// Value of the authority label for the authorized voter.
+const VOTER: &str = "voter";
+/// Value of the authority label for the authorized withdrawer.
+const WITHDRAWER: &str = "withdrawer";
+
+/// The authorities of a vote account.
+#[derive(Clone, Debug, PartialEq)]
+struct Authorities {
+    /// Authorized voter in the current epoch.
+    voter: String,
+    /// Authorized withdrawer.
+    withdrawer: String,
+    /// Number of authorized voters the vote account holds, for the current and later epochs.
+    voter_count: usize,
+    /// The first later epoch an authorized voter is scheduled for.
+    rotation_epoch: Option<Epoch>,
+}
+
+/// The monitor of the authorities of whitelisted vote accounts.
+///
+/// The vote accounts are fetched every cycle. Their current authorized voter and withdrawer are
+/// exported as info series, and changes between cycles are counted. A withdrawer change nobody
+/// made on purpose means the vote account, and the rewards it collects, has been taken over. The
+/// authorities seen in the first cycle after a start are the baseline. The number of authorized
+/// voters and the epoch of the next scheduled voter rotation are exported too, so that
+/// rotations are visible before they take effect at an epoch boundary.
+pub struct VoteAuthoritiesMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Prometheus gauge.
+    vote_authority: &'a IntGaugeVec,
+    /// Prometheus counter.
+    vote_authority_changes: &'a IntCounterVec,
+    /// Prometheus gauge.
+    authorized_voters: &'a IntGaugeVec,
+    /// Prometheus gauge.
+    voter_rotation_epoch: &'a IntGaugeVec,
+    /// Persisted counter totals.
+    baselines: &'a CounterBaselines,
+    /// The last seen authorities per vote account.
+    authorities: HashMap<String, Authorities>,
+}
+
+impl<'a> VoteAuthoritiesMonitor<'a> {
+    pub fn new(
+        rpc: &'a dyn RpcApi,
+        vote_authority: &'a IntGaugeVec,
+        vote_authority_changes: &'a IntCounterVec,
+        authorized_voters: &'a IntGaugeVec,
+        voter_rotation_epoch: &'a IntGaugeVec,
+        baselines: &'a CounterBaselines,
+    ) -> Self {
+        Self {
+            rpc,
+            vote_authority,
+            vote_authority_changes,
+            authorized_voters,
+            voter_rotation_epoch,
+            baselines,
+            authorities: HashMap::new(),
+        }
+    }
+
+    /// Exports the authorities of the vote accounts of `vote_accounts_whitelist` in `epoch` and
+    /// counts their changes since the previous cycle.
+    pub async fn export_vote_authorities(
+        &mut self,
+        vote_accounts_whitelist: &Whitelist,
+        epoch: Epoch,
+    ) -> anyhow::Result<()> {
+        let vote_pubkeys = vote_accounts_whitelist
+            .0
+            .iter()
+            .map(|vote_pubkey| anyhow::Ok((vote_pubkey, vote_pubkey.parse::<Pubkey>()?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        for chunk in vote_pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let pubkeys: Vec<_> = chunk.iter().map(|(_, pubkey)| *pubkey).collect();
+            let accounts = self.rpc.get_multiple_accounts(&pubkeys).await?;
+
+            for ((vote_pubkey, _), account) in chunk.iter().zip(accounts) {
+                let account = match account {
+                    Some(account) => account,
+                    None => {
+                        debug!("Vote account {} does not exist", vote_pubkey);
+                        continue;
+                    }
+                };
+                let current = authorities(&account.data, epoch)?;
+                let previous = self
+                    .authorities
+                    .insert(vote_pubkey.to_string(), current.clone());
+                for (authority, old, new) in changes(previous.as_ref(), &current) {
+                    if authority == WITHDRAWER {
+                        error!(
+                            "Authorized withdrawer of vote account {} changed from {} to {}",
+                            vote_pubkey, old, new
+                        );
+                    } else {
+                        warn!(
+                            "Authorized voter of vote account {} changed from {} to {}",
+                            vote_pubkey, old, new
+                        );
+                    }
+                    self.baselines.inc_by(
+                        self.vote_authority_changes,
+                        &[vote_pubkey, authority],
+                        1,
+                    )?;
+                    let _ = self
+                        .vote_authority
+                        .remove_label_values(&[vote_pubkey, authority, old]);
+                }
+                for (authority, pubkey) in
+                    [(VOTER, &current.voter), (WITHDRAWER, &current.withdrawer)]
+                {
+                    self.vote_authority
+                        .get_metric_with_label_values(&[vote_pubkey, authority, pubkey])
+                        .map(|c| c.set(1))?;
+                }
+                self.authorized_voters
+                    .get_metric_with_label_values(&[vote_pubkey])
+                    .map(|c| c.set(current.voter_count as i64))?;
+                match current.rotation_epoch {
+                    Some(rotation_epoch) => self
+                        .voter_rotation_epoch
+                        .get_metric_with_label_values(&[vote_pubkey])
+                        .map(|c| c.set(rotation_epoch as i64))?,
+                    None => {
+                        let _ = self
+                            .voter_rotation_epoch
+                            .remove_label_values(&[vote_pubkey]);
+                    }
+                }
+            }
+        }
+
+        debug!("Exported vote authorities");
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Monitor for VoteAuthoritiesMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "vote authorities"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![
+            family_name(self.vote_authority),
+            family_name(self.vote_authority_changes),
+            family_name(self.authorized_voters),
+            family_name(self.voter_rotation_epoch),
+        ]
+    }
+
+    /// Restores the authority change counter totals.
+    async fn setup(&mut self) -> anyhow::Result<()> {
+        self.baselines.restore(self.vote_authority_changes)
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.export_vote_authorities(&cycle.vote_accounts_whitelist, cycle.epoch_info.epoch)
+            .await
+    }
+}
+
+/// Reads the authorities in `epoch` from the `data` of a vote account.
+fn authorities(data: &[u8], epoch: Epoch) -> anyhow::Result<Authorities> {
+    let state = VoteStateVersions::deserialize(data)
+        .map_err(|e| anyhow!("could not deserialize vote account: {}", e))?;
+    let (voters, withdrawer) = match &state {
+        VoteStateVersions::V1_14_11(state) => {
+            (&state.authorized_voters, state.authorized_withdrawer)
+        }
+        VoteStateVersions::V3(state) => (&state.authorized_voters, state.authorized_withdrawer),
+        VoteStateVersions::V4(state) => (&state.authorized_voters, state.authorized_withdrawer),
+        VoteStateVersions::Uninitialized => return Err(anyhow!("vote account is uninitialized")),
+    };
+    // The voter of the current epoch is set before the epoch starts, except in accounts that
+    // have not voted since, which keep an older one.
+    let voter = voters
+        .get_authorized_voter(epoch)
+        .or_else(|| voters.last().map(|(_, voter)| *voter))
+        .ok_or_else(|| anyhow!("vote account has no authorized voter"))?;
+    Ok(Authorities {
+        voter: voter.to_string(),
+        withdrawer: withdrawer.to_string(),
+        voter_count: voters.len(),
+        rotation_epoch: voters
+            .iter()
+            .map(|(voter_epoch, _)| *voter_epoch)
+            .find(|voter_epoch| *voter_epoch > epoch),
+    })
+}
+
+/// Returns the authorities that differ between `previous` and `current`, with their previous and
+/// current pubkeys.
+fn changes<'a>(
+    previous: Option<&'a Authorities>,
+    current: &'a Authorities,
+) -> Vec<(&'static str, &'a str, &'a str)> {
+    let previous = match previous {
+        Some(previous) => previous,
+        None => return vec![],
+    };
+    [
+        (VOTER, &previous.voter, &current.voter),
+        (WITHDRAWER, &previous.withdrawer, &current.withdrawer),
+    ]
+    .iter()
+    .filter(|(_, old, new)| old != new)
+    .map(|(authority, old, new)| (*authority, old.as_str(), new.as_str()))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_clock::Clock;
+    use solana_vote_interface::state::{VoteInit, VoteStateV3};
+
+    #[test]
+    fn detects_authority_changes() {
+        let vote_init = VoteInit {
+            node_pubkey: [1; 32].into(),
+            authorized_voter: [2; 32].into(),
+            authorized_withdrawer: [3; 32].into(),
+            commission: 0,
+        };
+        let clock = Clock {
+            epoch: 7,
+            ..Clock::default()
+        };
+        let mut state = VoteStateV3::new(&vote_init, &clock);
+        let next_voter = Pubkey::new_unique();
+        state.authorized_voters.insert(10, next_voter);
+        let data = bincode::serialize(&VoteStateVersions::new_v3(state)).unwrap();
+
+        let first = authorities(&data, 8).unwrap();
+        assert_eq!(first.voter, vote_init.authorized_voter.to_string());
+        assert_eq!(
+            first.withdrawer,
+            vote_init.authorized_withdrawer.to_string()
+        );
+        assert_eq!((first.voter_count, first.rotation_epoch), (2, Some(10)));
+        assert!(changes(None, &first).is_empty());
+
+        // The rotation takes effect in its epoch.
+        let rotated = authorities(&data, 10).unwrap();
+        assert_eq!(rotated.voter, next_voter.to_string());
+        assert_eq!(rotated.rotation_epoch, None);
+
+        let second = Authorities {
+            withdrawer: Pubkey::new_unique().to_string(),
+            ..first.clone()
+        };
+        assert_eq!(
+            changes(Some(&first), &second),
+            vec![(
+                WITHDRAWER,
+                first.withdrawer.as_str(),
+                second.withdrawer.as_str()
+            )]
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! Estimated vote transaction fee expenditure of node identities.
+
+use crate::config::Whitelist;
+use crate::monitor::{family_name, Cycle, Monitor};
+use crate::rpc_api::RpcApi;
+use crate::rpc_extra::leader_slots;
+use crate::slots::caching::LeaderScheduleCache;
+use async_trait::async_trait;
+use futures::future::try_join_all;
+use log::debug;
+use prometheus_exporter::prometheus::IntGaugeVec;
+use solana_client::rpc_config::RpcBlockConfig;
+use solana_clock::{Epoch, Slot};
+use solana_epoch_info::EpochInfo;
+use solana_pubkey::Pubkey;
+use solana_transaction_status_client_types::TransactionDetails;
+use std::collections::{BTreeMap, HashMap};
+
+/// The monitor of lamports spent by node identities on vote transactions in the current epoch.
+///
+/// Vote fees are paid from the node identity account, whose balance is otherwise credited by the
+/// rewards of the blocks it produces, and by top-ups. Balances are sampled every cycle, and the
+/// block rewards credited to each identity since the previous sample are added back before the
+/// decrease is attributed to vote fees. Other credits do not offset the expenditure, and the
+/// estimate slightly over-counts if the identity also pays for non-vote transactions.
+pub struct VoteFeesMonitor<'a> {
+    /// Shared Solana RPC client.
+    rpc: &'a dyn RpcApi,
+    /// Prometheus gauge.
+    vote_fees: &'a IntGaugeVec,
+    /// Caching database for the leader slots.
+    cache: &'a LeaderScheduleCache,
+    /// The epoch that `spent` is accumulated for.
+    epoch: Option<Epoch>,
+    /// Leader slots of whitelisted identities that have not been checked yet, mapped to the
+    /// identity.
+    schedule: BTreeMap<Slot, String>,
+    /// The first slot whose block rewards have not been checked yet, once balances are sampled.
+    next_slot: Option<Slot>,
+    /// Last observed balance per identity.
+    last_balances: HashMap<String, u64>,
+    /// Block rewards credited per identity since its last observed balance.
+    credits: HashMap<String, u64>,
+    /// Lamports spent per identity in `epoch` so far.
+    spent: HashMap<String, u64>,
+}
+
+impl<'a> VoteFeesMonitor<'a> {
+    /// Constructs a monitor given `rpc`.
+    pub fn new(
+        rpc: &'a dyn RpcApi,
+        vote_fees: &'a IntGaugeVec,
+        cache: &'a LeaderScheduleCache,
+    ) -> Self {
+        Self {
+            rpc,
+            vote_fees,
+            cache,
+            epoch: None,
+            schedule: BTreeMap::new(),
+            next_slot: None,
+            last_balances: HashMap::new(),
+            credits: HashMap::new(),
+            spent: HashMap::new(),
+        }
+    }
+
+    /// Exports the estimated vote fees of the whitelisted node identities for the current epoch.
+    /// Only exported if a whitelist is set.
+    pub async fn export_vote_fees(
+        &mut self,
+        epoch_info: &EpochInfo,
+        node_whitelist: &Whitelist,
+    ) -> anyhow::Result<()> {
+        if node_whitelist.0.is_empty() {
+            return Ok(());
+        }
+
+        if self.epoch != Some(epoch_info.epoch) {
+            // New epoch: fees accumulate from zero. The last balances, and the leader slots of the
+            // old epoch that were not checked yet, are kept so the spend between the final sample
+            // of the old epoch and the first of the new one is not lost.
+            let schedule = leader_slots(self.rpc, self.cache, epoch_info, node_whitelist).await?;
+            self.schedule.extend(schedule);
+            self.epoch = Some(epoch_info.epoch);
+            self.spent.clear();
+            self.vote_fees.reset();
+            debug!("VoteFeesMonitor reset for epoch {}", epoch_info.epoch);
+        }
+
+        // Block rewards are collected up to the slot that balances are sampled at.
+        self.collect_credits(epoch_info.absolute_slot).await?;
+
+        let rpc = self.rpc;
+        let balances = try_join_all(node_whitelist.0.iter().map(|identity| async move {
+            let balance = rpc.get_balance(&identity.parse::<Pubkey>()?).await?;
+            anyhow::Ok((identity, balance))
+        }))
+        .await?;
+
+        for (identity, balance) in balances {
+            let credits = self.credits.remove(identity).unwrap_or_default();
+            let spent = self.spent.entry(identity.clone()).or_default();
+            if let Some(last_balance) = self.last_balances.insert(identity.clone(), balance) {
+                *spent += (last_balance + credits).saturating_sub(balance);
+            }
+
+            self.vote_fees
+                .get_metric_with_label_values(&[identity])
+                .map(|c| c.set(*spent as i64))?;
+        }
+
+        debug!("Exported vote fees");
+        Ok(())
+    }
+
+    /// Adds up the rewards of the blocks that whitelisted identities produced up to `last_slot`
+    /// into `credits`.
+    async fn collect_credits(&mut self, last_slot: Slot) -> anyhow::Result<()> {
+        // Before the first sample, no balance is there to offset.
+        let next_slot = match self.next_slot {
+            Some(next_slot) => next_slot,
+            None => last_slot + 1,
+        };
+        self.schedule = self.schedule.split_off(&next_slot);
+        if last_slot < next_slot || self.schedule.range(..=last_slot).next().is_none() {
+            self.next_slot = Some(next_slot.max(last_slot + 1));
+            return Ok(());
+        }
+
+        let produced = self.rpc.get_blocks(next_slot, Some(last_slot)).await?;
+        for slot in produced {
+            if let Some(identity) = self.schedule.get(&slot).cloned() {
+                let credit = self.block_credit(slot, &identity).await?;
+                *self.credits.entry(identity).or_default() += credit;
+                // Advance per block so that a failed fetch is retried without counting twice.
+                self.next_slot = Some(slot + 1);
+            }
+        }
+        self.next_slot = Some(last_slot + 1);
+        Ok(())
+    }
+
+    /// Fetches the rewards of the block at `slot` and returns the lamports credited to
+    /// `identity`.
+    async fn block_credit(&self, slot: Slot, identity: &str) -> anyhow::Result<u64> {
+        let block = self
+            .rpc
+            .get_block_with_config(
+                slot,
+                RpcBlockConfig {
+                    encoding: None,
+                    transaction_details: Some(TransactionDetails::None),
+                    rewards: Some(true),
+                    commitment: None,
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await?;
+
+        Ok(block
+            .rewards
+            .unwrap_or_default()
+            .iter()
+            .filter(|reward| reward.pubkey == identity)
+            .map(|reward| reward.lamports.max(0) as u64)
+            .sum())
+    }
+}
+
+#[async_trait]
+impl Monitor for VoteFeesMonitor<'_> {
+    fn name(&self) -> &'static str {
+        "vote fees"
+    }
+
+    fn metrics(&self) -> Vec<String> {
+        vec![family_name(self.vote_fees)]
+    }
+
+    async fn collect(&mut self, cycle: &Cycle) -> anyhow::Result<()> {
+        self.export_vote_fees(&cycle.epoch_info, &cycle.node_whitelist)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_api::mock::MockRpc;
+    use prometheus_exporter::prometheus::Opts;
+    use solana_account::Account;
+    use solana_reward_info::RewardType;
+    use solana_transaction_status_client_types::Reward;
+
+    #[tokio::test]
+    async fn offsets_fees_by_block_rewards() {
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let cache = LeaderScheduleCache::new(db.open_tree("leader_schedule").unwrap());
+        let vote_fees = IntGaugeVec::new(Opts::new("vote_fees", "Vote fees"), &["pubkey"]).unwrap();
+        let identity = Pubkey::new_unique();
+        let whitelist = Whitelist::new(std::iter::once(identity.to_string()).collect());
+        let epoch_info = |absolute_slot| EpochInfo {
+            epoch: 1,
+            slot_index: absolute_slot - 100,
+            slots_in_epoch: 432_000,
+            absolute_slot,
+            block_height: 0,
+            transaction_count: None,
+        };
+        let rpc = |balance| {
+            let mut rpc = MockRpc {
+                leader_schedule: Some(HashMap::from([(identity.to_string(), vec![5, 7])])),
+                ..MockRpc::default()
+            };
+            rpc.accounts.insert(
+                identity,
+                Account {
+                    lamports: balance,
+                    ..Account::default()
+                },
+            );
+            rpc
+        };
+
+        // 20 lamports of fees, and a block reward of 50 in slot 105; slot 107 was skipped.
+        let first = rpc(1_000);
+        let mut second = rpc(1_030);
+        second.add_block(
+            105,
+            None,
+            vec![Reward {
+                pubkey: identity.to_string(),
+                lamports: 50,
+                post_balance: 1_050,
+                reward_type: Some(RewardType::Fee),
+                commission: None,
+                commission_bps: None,
+            }],
+        );
+        let mut monitor = VoteFeesMonitor::new(&first, &vote_fees, &cache);
+
+        monitor
+            .export_vote_fees(&epoch_info(101), &whitelist)
+            .await
+            .unwrap();
+        let spent = || vote_fees.with_label_values(&[&identity.to_string()]).get();
+        assert_eq!(spent(), 0);
+
+        monitor.rpc = &second;
+        monitor
+            .export_vote_fees(&epoch_info(110), &whitelist)
+            .await
+            .unwrap();
+        assert_eq!(spent(), 20);
+    }
+}
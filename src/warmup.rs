@@ -0,0 +1,214 @@
+//! Background warming of the caches that a cold start would otherwise fill within the first
+//! update cycles, holding up every monitor after the one filling them.
+
+use crate::geolocation::api::{self, MaxMindAPIKey};
+use crate::geolocation::caching::GeolocationCache;
+use crate::rewards::caching::RewardsCache;
+use crate::rewards::{cached_epoch_length, cached_epoch_rewards, MAX_EPOCH_LOOKBACK};
+use crate::rpc_api::RpcApi;
+use futures::{StreamExt, TryStreamExt};
+use log::{debug, info, warn};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+/// Number of IP addresses geolocated at once.
+const GEOLOCATION_CONCURRENCY: usize = 8;
+
+/// Fills the rewards and geolocation caches in background tasks. The monitors reading these
+/// caches skip their collections while they are warming, so that all other metrics are served
+/// from the first update cycle on.
+pub struct CacheWarmer {
+    rpc: Arc<dyn RpcApi>,
+    rewards_cache: Arc<RewardsCache>,
+    geolocation_cache: Arc<GeolocationCache>,
+    /// HTTP client for MaxMind.
+    client: reqwest::Client,
+    /// The task filling the rewards cache, once started.
+    rewards: Mutex<Option<JoinHandle<()>>>,
+    /// Whether the rewards cache has been filled.
+    rewards_warm: Arc<AtomicBool>,
+    /// The task filling the geolocation cache, once started.
+    geolocation: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl CacheWarmer {
+    /// Constructs a warmer filling the caches from `rpc` and from MaxMind using `client`.
+    pub fn new(
+        rpc: Arc<dyn RpcApi>,
+        rewards_cache: Arc<RewardsCache>,
+        geolocation_cache: Arc<GeolocationCache>,
+        client: reqwest::Client,
+    ) -> Self {
+        Self {
+            rpc,
+            rewards_cache,
+            geolocation_cache,
+            client,
+            rewards: Mutex::new(None),
+            rewards_warm: Arc::new(AtomicBool::new(false)),
+            geolocation: Mutex::new(None),
+        }
+    }
+
+    /// Starts fetching the rewards and durations of the past epochs that the average APYs are
+    /// taken over, `concurrency` epochs at once, unless they are being fetched or are cached
+    /// already. A failed backfill is started over by the next call.
+    pub fn warm_rewards(&self, concurrency: usize) {
+        let mut rewards = self
+            .rewards
+            .lock()
+            .expect("poisoned mutex, should never happen");
+        if self.rewards_warm.load(Ordering::Relaxed)
+            || rewards.as_ref().is_some_and(|task| !task.is_finished())
+        {
+            return;
+        }
+        let rpc = Arc::clone(&self.rpc);
+        let cache = Arc::clone(&self.rewards_cache);
+        let warm = Arc::clone(&self.rewards_warm);
+        *rewards = Some(tokio::spawn(async move {
+            match warm_rewards(&*rpc, &cache, concurrency.max(1)).await {
+                Ok(()) => {
+                    warm.store(true, Ordering::Relaxed);
+                    info!("Rewards of past epochs are cached");
+                }
+                Err(e) => warn!("Failed to backfill the rewards of past epochs: {e:#}"),
+            }
+        }));
+    }
+
+    /// Stops fetching the rewards of past epochs, while this instance is a standby or the RPC
+    /// endpoint is failing. The epochs fetched so far stay cached for the next `warm_rewards`.
+    pub fn pause_rewards(&self) {
+        if let Some(task) = self
+            .rewards
+            .lock()
+            .expect("poisoned mutex, should never happen")
+            .take()
+        {
+            task.abort();
+        }
+    }
+
+    /// Whether the rewards cache is still being filled.
+    pub fn rewards_warming(&self) -> bool {
+        running(&self.rewards)
+    }
+
+    /// Starts geolocating `ips`, which are not cached, with `maxmind`.
+    pub fn warm_geolocation(&self, maxmind: MaxMindAPIKey, ips: Vec<IpAddr>) {
+        let client = self.client.clone();
+        let cache = Arc::clone(&self.geolocation_cache);
+        let task = tokio::spawn(async move {
+            debug!("Geolocating {} uncached IP addresses", ips.len());
+            futures::stream::iter(ips)
+                .for_each_concurrent(GEOLOCATION_CONCURRENCY, |ip| {
+                    let (client, cache, maxmind) = (&client, &cache, &maxmind);
+                    async move {
+                        let result = async {
+                            let geo = api::lookup(client, maxmind, ip).await?;
+                            cache.add_ip_address(&ip, &geo)
+                        };
+                        if let Err(e) = result.await {
+                            warn!("Could not geolocate {}: {e:#}", ip);
+                        }
+                    }
+                })
+                .await;
+            info!("Geolocations of the validators are cached");
+        });
+        *self
+            .geolocation
+            .lock()
+            .expect("poisoned mutex, should never happen") = Some(task);
+    }
+
+    /// Whether the geolocation cache is still being filled.
+    pub fn geolocation_warming(&self) -> bool {
+        running(&self.geolocation)
+    }
+}
+
+/// Whether the task of `handle` was started and has not finished yet.
+fn running(handle: &Mutex<Option<JoinHandle<()>>>) -> bool {
+    handle
+        .lock()
+        .expect("poisoned mutex, should never happen")
+        .as_ref()
+        .is_some_and(|task| !task.is_finished())
+}
+
+/// Caches the rewards and durations of the `MAX_EPOCH_LOOKBACK` epochs before the current one.
+async fn warm_rewards(
+    rpc: &dyn RpcApi,
+    cache: &RewardsCache,
+    concurrency: usize,
+) -> anyhow::Result<()> {
+    let epoch = rpc.get_epoch_info().await?.epoch;
+    futures::stream::iter(epoch.saturating_sub(MAX_EPOCH_LOOKBACK)..epoch)
+        .map(|epoch| async move {
+            cached_epoch_rewards(rpc, cache, epoch).await?;
+            cached_epoch_length(rpc, cache, epoch).await
+        })
+        .buffer_unordered(concurrency)
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_api::mock::MockRpc;
+    use solana_epoch_info::EpochInfo;
+    use solana_epoch_schedule::EpochSchedule;
+
+    #[tokio::test]
+    async fn warms_rewards_in_the_background() {
+        const SLOTS_PER_EPOCH: u64 = 32;
+        let db = sled::Config::default().temporary(true).open().unwrap();
+        let rewards_cache = Arc::new(RewardsCache::temporary());
+        // The first block of every epoch is a day after the previous one.
+        let mut rpc = MockRpc {
+            epoch_info: Some(EpochInfo {
+                epoch: 10,
+                slot_index: 0,
+                slots_in_epoch: SLOTS_PER_EPOCH,
+                absolute_slot: 10 * SLOTS_PER_EPOCH,
+                block_height: 0,
+                transaction_count: None,
+            }),
+            epoch_schedule: Some(EpochSchedule::custom(
+                SLOTS_PER_EPOCH,
+                SLOTS_PER_EPOCH,
+                false,
+            )),
+            ..MockRpc::default()
+        };
+        for epoch in 10 - MAX_EPOCH_LOOKBACK..=10 {
+            rpc.add_block(epoch * SLOTS_PER_EPOCH, Some(epoch as i64 * 86_400), vec![]);
+        }
+        let warmer = CacheWarmer::new(
+            Arc::new(rpc),
+            Arc::clone(&rewards_cache),
+            Arc::new(GeolocationCache::new(db.open_tree("geolocation").unwrap())),
+            reqwest::Client::new(),
+        );
+        assert!(!warmer.rewards_warming());
+
+        warmer.warm_rewards(2);
+        let task = warmer.rewards.lock().unwrap().take().unwrap();
+        task.await.unwrap();
+        for epoch in 10 - MAX_EPOCH_LOOKBACK..10 {
+            assert!(rewards_cache.get_epoch_rewards(epoch).unwrap().is_some());
+            assert_eq!(rewards_cache.get_epoch_length(epoch).unwrap(), Some(1.0));
+        }
+        assert!(rewards_cache.get_epoch_rewards(10).unwrap().is_none());
+
+        // Once cached, the rewards are not fetched again.
+        warmer.warm_rewards(2);
+        assert!(warmer.rewards.lock().unwrap().is_none());
+    }
+}
@@ -0,0 +1,319 @@
+//! End-to-end tests of the exporter against a local `solana-test-validator`, which must be on the
+//! `PATH` along with the `solana` and `solana-keygen` CLIs. Run with
+//! `cargo test --features integration-tests --test test_validator`.
+#![cfg(feature = "integration-tests")]
+
+use anyhow::{anyhow, Context};
+use serde_json::{json, Value};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+/// Slots per epoch of the test validator, about 13 seconds.
+const SLOTS_PER_EPOCH: u64 = 32;
+/// Time the whole scenario is given.
+const TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A child process killed when dropped.
+struct Process(Child);
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// A `solana-test-validator` with its ledger in a fresh directory.
+struct TestValidator {
+    ledger: PathBuf,
+    rpc_url: String,
+    _process: Process,
+}
+
+impl TestValidator {
+    /// Starts a validator with short epochs and waits for its RPC endpoint to be healthy.
+    async fn start(dir: &Path) -> anyhow::Result<Self> {
+        let ledger = dir.join("ledger");
+        let rpc_port = free_port()?;
+        let faucet_port = free_port()?;
+        let process = Process(
+            Command::new("solana-test-validator")
+                .arg("--ledger")
+                .arg(&ledger)
+                .args(["--rpc-port", &rpc_port.to_string()])
+                .args(["--faucet-port", &faucet_port.to_string()])
+                .args(["--slots-per-epoch", &SLOTS_PER_EPOCH.to_string()])
+                .args(["--reset", "--quiet"])
+                .stdout(Stdio::null())
+                .spawn()
+                .context("could not start solana-test-validator, is it on the PATH?")?,
+        );
+        let validator = Self {
+            ledger,
+            rpc_url: format!("http://127.0.0.1:{}", rpc_port),
+            _process: process,
+        };
+        wait_for("a healthy test validator", || async {
+            Ok(validator.rpc("getHealth", json!([])).await.ok() == Some(json!("ok")))
+        })
+        .await?;
+        Ok(validator)
+    }
+
+    /// Calls the RPC `method` of the validator and returns its result.
+    async fn rpc(&self, method: &str, params: Value) -> anyhow::Result<Value> {
+        let response: Value = reqwest::Client::new()
+            .post(&self.rpc_url)
+            .json(&json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}))
+            .send()
+            .await?
+            .json()
+            .await?;
+        response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| anyhow!("{} failed: {}", method, response))
+    }
+
+    /// Returns the current epoch of the validator.
+    async fn epoch(&self) -> anyhow::Result<u64> {
+        self.rpc("getEpochInfo", json!([]))
+            .await?
+            .get("epoch")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| anyhow!("getEpochInfo returned no epoch"))
+    }
+
+    /// Returns the vote account of the validator.
+    fn vote_account(&self) -> anyhow::Result<String> {
+        run(Command::new("solana-keygen")
+            .arg("pubkey")
+            .arg(self.ledger.join("vote-account-keypair.json")))
+    }
+
+    /// Creates a stake account of `sol` from the faucet keypair, delegated to `vote_account`, and
+    /// returns its pubkey.
+    fn delegate_stake(&self, dir: &Path, vote_account: &str, sol: u64) -> anyhow::Result<String> {
+        let stake_keypair = dir.join("stake-keypair.json");
+        run(Command::new("solana-keygen")
+            .args(["new", "--no-bip39-passphrase", "--silent", "--force"])
+            .arg("--outfile")
+            .arg(&stake_keypair))?;
+        let solana = || {
+            let mut command = Command::new("solana");
+            command
+                .args(["--url", &self.rpc_url, "--commitment", "confirmed"])
+                .arg("--keypair")
+                .arg(self.ledger.join("faucet-keypair.json"));
+            command
+        };
+        run(solana()
+            .arg("create-stake-account")
+            .arg(&stake_keypair)
+            .arg(sol.to_string()))?;
+        run(solana()
+            .arg("delegate-stake")
+            .arg(&stake_keypair)
+            .arg(vote_account))?;
+        run(Command::new("solana-keygen")
+            .arg("pubkey")
+            .arg(&stake_keypair))
+    }
+}
+
+/// The exporter binary, serving its metrics on a free port.
+struct Exporter {
+    url: String,
+    _process: Process,
+}
+
+impl Exporter {
+    /// Starts the exporter with its database in `dir`, monitoring `vote_account` and the APY of
+    /// `stake_account` on `validator`.
+    fn start(
+        dir: &Path,
+        validator: &TestValidator,
+        vote_account: &str,
+        stake_account: &str,
+    ) -> anyhow::Result<Self> {
+        let target = format!("127.0.0.1:{}", free_port()?);
+        let config = dir.join("config.toml");
+        std::fs::write(
+            &config,
+            format!(
+                "rpc = '{}'\n\
+                 target = '{}'\n\
+                 vote_account_whitelist = ['{}']\n\
+                 staking_account_whitelist = ['{}']\n\
+                 enable_rewards = true\n",
+                validator.rpc_url, target, vote_account, stake_account
+            ),
+        )?;
+        let process = Process(
+            Command::new(env!("CARGO_BIN_EXE_solana-exporter"))
+                .arg("--config")
+                .arg(&config)
+                .arg("--database")
+                .arg(dir.join("database"))
+                .args(["--log-level", "info"])
+                .spawn()
+                .context("could not start the exporter")?,
+        );
+        Ok(Self {
+            url: format!("http://{}", target),
+            _process: process,
+        })
+    }
+
+    /// Returns the body of `path`, e.g. `/metrics`.
+    async fn get(&self, path: &str) -> anyhow::Result<String> {
+        Ok(reqwest::get(format!("{}{}", self.url, path))
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)
+    }
+
+    /// Returns the value of the series `series`, e.g. `solana_current_epoch`, if it is served.
+    async fn metric(&self, series: &str) -> anyhow::Result<Option<f64>> {
+        let metrics = self.get("/metrics").await?;
+        Ok(metrics.lines().find_map(|line| {
+            let value = line.strip_prefix(series)?.strip_prefix(' ')?;
+            value.parse().ok()
+        }))
+    }
+
+    /// Returns the number of entries of the database tree `name`, from `/debug/cache`.
+    async fn tree_entries(&self, name: &str) -> anyhow::Result<u64> {
+        let trees: Value = serde_json::from_str(&self.get("/debug/cache").await?)?;
+        Ok(trees
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|tree| tree["name"] == name)
+            .and_then(|tree| tree["entries"].as_u64())
+            .unwrap_or(0))
+    }
+}
+
+/// Returns a TCP port that is free at the time of the call.
+fn free_port() -> anyhow::Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+/// Runs `command` to completion, and returns its trimmed standard output.
+fn run(command: &mut Command) -> anyhow::Result<String> {
+    let output = command
+        .output()
+        .with_context(|| format!("could not run {:?}", command))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{:?} failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// Polls `done` every second until it returns `true`, failing after `TIMEOUT`.
+async fn wait_for<F, Fut>(what: &str, mut done: F) -> anyhow::Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<bool>>,
+{
+    let start = Instant::now();
+    loop {
+        if done().await.unwrap_or(false) {
+            return Ok(());
+        }
+        if start.elapsed() > TIMEOUT {
+            return Err(anyhow!("timed out waiting for {}", what));
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+#[tokio::test]
+async fn exports_metrics_of_a_test_validator() -> anyhow::Result<()> {
+    let dir = TempDir::new()?;
+    let dir = dir.path();
+    let validator = TestValidator::start(dir).await?;
+    let vote_account = validator.vote_account()?;
+    let stake_account = validator.delegate_stake(dir, &vote_account, 100)?;
+    let exporter = Exporter::start(dir, &validator, &vote_account, &stake_account)?;
+
+    // Epoch info follows the validator.
+    let start_epoch = validator.epoch().await?;
+    wait_for("the exporter to serve the current epoch", || async {
+        let epoch = exporter.metric("solana_current_epoch").await?;
+        Ok(epoch.is_some_and(|e| e >= start_epoch as f64))
+    })
+    .await?;
+
+    // The validator votes with its bootstrap stake.
+    let current = exporter
+        .metric("solana_active_validators{status=\"current\"}")
+        .await?;
+    assert_eq!(current, Some(1.0));
+    let stake = exporter
+        .metric(&format!(
+            "solana_validator_activated_stake{{pubkey=\"{}\"}}",
+            vote_account
+        ))
+        .await?;
+    assert!(stake.is_some_and(|s| s > 0.0), "{:?}", stake);
+
+    // Once the delegated stake has earned rewards at an epoch boundary, they are cached.
+    wait_for("an epoch past the stake activation", || async {
+        Ok(validator.epoch().await? >= start_epoch + 3)
+    })
+    .await?;
+    wait_for("the rewards to be cached", || async {
+        Ok(exporter.tree_entries("epoch_rewards").await? > 0)
+    })
+    .await?;
+    let epoch = exporter.metric("solana_current_epoch").await?;
+    assert!(
+        epoch.is_some_and(|e| e >= (start_epoch + 3) as f64),
+        "{:?}",
+        epoch
+    );
+
+    // The rewards of the stake account make up the APY of the vote account it is delegated to.
+    let apy_series = format!("solana_current_staking_apy{{pubkey=\"{}\"}}", vote_account);
+    wait_for("the exporter to serve the staking APY", || async {
+        Ok(exporter
+            .metric(&apy_series)
+            .await?
+            .is_some_and(|apy| apy > 0.0))
+    })
+    .await?;
+    let rewards = exporter
+        .metric(&format!(
+            "solana_validator_rewards{{pubkey=\"{}\"}}",
+            vote_account
+        ))
+        .await?;
+    assert!(rewards.is_some_and(|r| r >= 0.0), "{:?}", rewards);
+    let whitelist_rewards = exporter
+        .metric("solana_whitelist_validator_rewards")
+        .await?;
+    assert!(
+        whitelist_rewards.is_some_and(|r| r >= 0.0),
+        "{:?}",
+        whitelist_rewards
+    );
+    let whitelist_apy = exporter
+        .metric("solana_whitelist_current_staking_apy")
+        .await?;
+    assert!(
+        whitelist_apy.is_some_and(|apy| apy > 0.0),
+        "{:?}",
+        whitelist_apy
+    );
+    Ok(())
+}